@@ -0,0 +1,11 @@
+//! build.rs
+//!
+//! Generates the types of the protobuf wire format from `proto/*.proto`. A
+//! vendored `protoc` is used, so building does not need one installed.
+
+fn main() -> std::io::Result<()> {
+    let protoc = protoc_bin_vendored::protoc_bin_path().map_err(std::io::Error::other)?;
+    std::env::set_var("PROTOC", protoc);
+    println!("cargo:rerun-if-changed=proto");
+    prost_build::compile_protos(&["proto/domain.proto", "proto/wire.proto"], &["proto"])
+}
@@ -1,5 +1,5 @@
 // src/lib/networkState.svelte.ts
-import type { NodeId, NodeInfo, WebSocketMessage, UpdatePayload } from './types';
+import type { Ban, BanTarget, MemberStatus, NodeHealth, NodeId, PersistentPeer, NodeInfo, WebSocketMessage, UpdatePayload } from './types';
 
 export interface LogEntry {
     id: number;
@@ -12,6 +12,10 @@ export const networkState = $state({
     isConnected: false,
     selfId: null as NodeId | null,
     nodes: {} as Record<NodeId, NodeInfo>,
+    statuses: {} as Record<NodeId, MemberStatus>,
+    health: {} as Record<NodeId, NodeHealth>,
+    persistentPeers: {} as Record<string, PersistentPeer>,
+    bans: [] as Ban[],
     activeConnections: new Set<NodeId>(),
     currentPulsePeers: new Set<NodeId>(),
     log: [] as LogEntry[],
@@ -23,6 +27,8 @@ let pulseTimerId: number | null = null;
 
 export const truncateNodeId = (id: NodeId) => `${id.substring(0, 8)}...`;
 
+const formatBanTarget = (target: BanTarget) => 'addr' in target ? target.addr : truncateNodeId(target.node);
+
 function addLogEntry(message: string, type: LogEntry['type']) {
     // MODIFICATION: Use `push` to add new entries to the end of the array (chronological order).
     networkState.log.push({ id: logCounter++, timestamp: new Date(), message, type });
@@ -38,13 +44,25 @@ function formatUpdateMessage(payload: UpdatePayload): string {
         case 'node_added':
             return `Discovered new node: ${truncateNodeId(data.id)} (Community ${data.info.community_id})`;
         case 'node_updated':
-            return `Received telemetry update for node: ${truncateNodeId(data.id)}`;
+            return `Received ${Object.keys(data.info.payloads).join(', ')} update for node: ${truncateNodeId(data.id)}`;
         case 'node_removed':
             return `Node considered stale and removed: ${truncateNodeId(data.id)}`;
         case 'connection_status':
             return `Peer connection ${data.is_connected ? 'established with' : 'lost from'} ${truncateNodeId(data.peer_id)}`;
         case 'animate_edge':
             return `[Animation] Edge from ${truncateNodeId(data.from_peer)} pulsed.`;
+        case 'node_status':
+            return `Node ${truncateNodeId(data.id)} is now ${data.status}`;
+        case 'node_health':
+            return `Node ${truncateNodeId(data.id)} ${data.suspected ? 'is suspected' : 'is healthy'} (phi ${data.phi})`;
+        case 'persistent_peer':
+            return data.state === 'backing_off'
+                ? `Persistent peer ${data.addr} unreachable after ${data.failures} attempt(s), retrying`
+                : `Persistent peer ${data.addr} is ${data.state}`;
+        case 'bans':
+            return data.length === 0
+                ? 'No peers are banned'
+                : `Banned peers: ${data.map(ban => `${formatBanTarget(ban.target)} (${ban.reason})`).join(', ')}`;
     }
 }
 
@@ -61,6 +79,10 @@ export function connect() {
         networkState.isConnected = false;
         networkState.selfId = null;
         networkState.nodes = {};
+        networkState.statuses = {};
+        networkState.health = {};
+        networkState.persistentPeers = {};
+        networkState.bans = [];
         networkState.activeConnections.clear();
         networkState.currentPulsePeers.clear();
         addLogEntry('Disconnected from WebSocket server. Retrying in 3s...', 'error');
@@ -75,13 +97,22 @@ export function connect() {
                 const payload = data.payload;
                 networkState.selfId = payload.self_id;
                 networkState.nodes = payload.nodes;
+                networkState.statuses = payload.statuses ?? {};
+                networkState.health = payload.health ?? {};
+                networkState.persistentPeers = Object.fromEntries(
+                    (payload.persistent_peers ?? []).map(peer => [peer.addr, peer]),
+                );
+                networkState.bans = payload.bans ?? [];
                 networkState.activeConnections = new Set(payload.active_connections);
                 addLogEntry(`Received initial state snapshot with ${Object.keys(networkState.nodes).length} nodes.`, 'info');
             } else if (data.type === 'update') {
                 const payload = data.payload;
                 const { event, data: eventData } = payload;
 
-                if (event !== 'animate_edge') {
+                // Phi changes on every tick; only suspicion changes are logged.
+                const isRoutineHealth = event === 'node_health'
+                    && eventData.suspected === (networkState.health[eventData.id]?.suspected ?? false);
+                if (event !== 'animate_edge' && !isRoutineHealth) {
                     addLogEntry(formatUpdateMessage(payload), 'info');
                 }
 
@@ -95,6 +126,8 @@ export function connect() {
                     case 'node_removed':
                         delete networkState.nodes[eventData.id];
                         networkState.nodes = { ...networkState.nodes };
+                        delete networkState.health[eventData.id];
+                        networkState.health = { ...networkState.health };
                         break;
                     case 'connection_status':
                         if (eventData.is_connected) {
@@ -104,6 +137,21 @@ export function connect() {
                         }
                         networkState.activeConnections = new Set(networkState.activeConnections);
                         break;
+                    case 'node_status':
+                        networkState.statuses = { ...networkState.statuses, [eventData.id]: eventData.status };
+                        break;
+                    case 'persistent_peer':
+                        networkState.persistentPeers = { ...networkState.persistentPeers, [eventData.addr]: eventData };
+                        break;
+                    case 'bans':
+                        networkState.bans = eventData;
+                        break;
+                    case 'node_health':
+                        networkState.health = {
+                            ...networkState.health,
+                            [eventData.id]: { phi: eventData.phi, suspected: eventData.suspected },
+                        };
+                        break;
                     case 'animate_edge':
                         pendingPulsePeers.add(eventData.from_peer);
                         if (!pulseTimerId) {
@@ -2,14 +2,73 @@
 
 export type NodeId = string; // A hex-encoded string
 
-export interface TelemetryData {
-    timestamp_ms: number;
+// Orders the messages of a single originator: by epoch, then by sequence.
+export interface Version {
+    epoch: number;
+    sequence: number;
+}
+
+// A single measurement, e.g. { value: 12.5, unit: 'ms', labels: { quantile: '0.99' } }.
+export interface Metric {
     value: number;
+    unit: string;
+    // Omitted when the metric has no labels.
+    labels?: Record<string, string>;
+}
+
+// The payload of the built-in `telemetry` kind: metrics by name.
+export interface TelemetryData {
+    metrics: Record<string, Metric>;
 }
 
 export interface NodeInfo {
-    telemetry: TelemetryData;
+    // The node's latest payload of each kind it gossips, e.g. 'telemetry'.
+    payloads: Record<string, unknown>;
+    timestamp_ms: number;
     community_id: number;
+    version: Version;
+}
+
+// Single-value records of earlier releases show their value as the unitless
+// `value` metric, as the node decodes them.
+export function telemetryOf(info: NodeInfo): TelemetryData | undefined {
+    const telemetry = info.payloads['telemetry'] as TelemetryData | { value: number } | undefined;
+    if (telemetry && 'value' in telemetry) {
+        return { metrics: { value: { value: telemetry.value, unit: '' } } };
+    }
+    return telemetry;
+}
+
+// The SWIM failure detector's view of a node.
+export type MemberStatus = 'alive' | 'suspect' | 'dead';
+
+// The phi-accrual failure detector's suspicion level for a node.
+export interface NodeHealth {
+    phi: number;
+    suspected: boolean;
+}
+
+// Where the transport stands with a peer it keeps connected to.
+export type DialState = 'connecting' | 'connected' | 'backing_off';
+
+export interface PersistentPeer {
+    addr: string;
+    state: DialState;
+    failures: number;
+    // While backing off, when the next attempt is due (ms since the Unix epoch).
+    retry_at_ms: number | null;
+}
+
+// Whom a ban applies to: an IP address, or a node.
+export type BanTarget = { addr: string } | { node: NodeId };
+
+export type BanReason = 'invalid_signature' | 'malformed_message' | 'rate_limited' | 'manual';
+
+export interface Ban {
+    target: BanTarget;
+    reason: BanReason;
+    // When the ban is lifted (ms since the Unix epoch).
+    expires_at_ms: number;
 }
 
 // --- WebSocket Message Protocol ---
@@ -18,6 +77,10 @@ export interface SnapshotPayload {
     self_id: NodeId;
     nodes: Record<NodeId, NodeInfo>;
     active_connections: NodeId[];
+    statuses: Record<NodeId, MemberStatus>;
+    health: Record<NodeId, NodeHealth>;
+    persistent_peers: PersistentPeer[];
+    bans: Ban[];
 }
 
 export type UpdatePayload =
@@ -26,7 +89,11 @@ export type UpdatePayload =
     | { event: 'node_removed'; data: { id: NodeId } }
     | { event: 'connection_status'; data: { peer_id: NodeId; is_connected: boolean } }
     // NEW: Add the explicit animation event type.
-    | { event: 'animate_edge'; data: { from_peer: NodeId } };
+    | { event: 'animate_edge'; data: { from_peer: NodeId } }
+    | { event: 'node_status'; data: { id: NodeId; status: MemberStatus } }
+    | { event: 'node_health'; data: { id: NodeId } & NodeHealth }
+    | { event: 'persistent_peer'; data: PersistentPeer }
+    | { event: 'bans'; data: Ban[] };
 
 
 export type WebSocketMessage =
@@ -0,0 +1,93 @@
+//! src/api/bans.rs
+//!
+//! HTTP endpoints for listing, placing and lifting bans. Bans are enforced by
+//! the transport, so changes are sent to it as commands, and show up in the
+//! list once it has applied them. Changes must bear the configured admin token,
+//! and are refused outright when none is configured.
+
+use crate::{
+    api::ApiState,
+    domain::{Ban, BanTarget},
+    transport::TransportCommand,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+/// Query parameters for placing a ban.
+#[derive(Debug, Deserialize)]
+pub struct BanParams {
+    /// How long the ban lasts. Defaults to `ban_duration_ms`.
+    pub duration_ms: Option<u64>,
+}
+
+/// `GET /api/bans`: the active bans.
+pub async fn list_bans(State(state): State<ApiState>) -> Json<Vec<Ban>> {
+    Json(state.state_rx.borrow().bans.clone())
+}
+
+/// `POST /api/bans/:target`: bans an IP address or a hex-encoded `NodeId`.
+pub async fn ban(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(target): Path<String>,
+    Query(params): Query<BanParams>,
+) -> Response {
+    if let Err(refusal) = authorize(&state, &headers) {
+        return refusal.into_response();
+    }
+    match target.parse::<BanTarget>() {
+        Ok(target) => {
+            let duration = params.duration_ms.map(Duration::from_millis);
+            send_command(&state, TransportCommand::Ban { target, duration }).await
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// `DELETE /api/bans/:target`: lifts the ban on an IP address or `NodeId`.
+pub async fn unban(State(state): State<ApiState>, headers: HeaderMap, Path(target): Path<String>) -> Response {
+    if let Err(refusal) = authorize(&state, &headers) {
+        return refusal.into_response();
+    }
+    match target.parse::<BanTarget>() {
+        Ok(target) => send_command(&state, TransportCommand::Unban(target)).await,
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// Checks that the request bears the admin token in an
+/// `Authorization: Bearer` header.
+fn authorize(state: &ApiState, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    let Some(token) = &state.admin_token else {
+        return Err((StatusCode::FORBIDDEN, "bans cannot be changed without an admin token"));
+    };
+    let presented = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if tokens_match(presented, token) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "invalid admin token")),
+    }
+}
+
+/// Compares the tokens' digests in full, so the time taken does not reveal
+/// how much of the token was guessed right.
+fn tokens_match(presented: &str, token: &str) -> bool {
+    let (presented, token) = (Sha256::digest(presented), Sha256::digest(token));
+    presented.iter().zip(token.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn send_command(state: &ApiState, command: TransportCommand) -> Response {
+    match state.transport_tx.send(command).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "transport is not running").into_response(),
+    }
+}
@@ -4,13 +4,20 @@
 //! Defines the `ApiServer` service, which provides the web frontend and
 //! a WebSocket endpoint for real-time visualization.
 
-use crate::domain::{NetworkState, NodeId}; // MODIFICATION: Import NodeId
-use axum::{routing::get, Router};
-use std::net::SocketAddr;
-use tokio::sync::{broadcast, watch}; // MODIFICATION: Import broadcast
+use crate::{
+    domain::{NetworkState, NodeId}, // MODIFICATION: Import NodeId
+    transport::TransportCommand,
+};
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::sync::{broadcast, mpsc, watch}; // MODIFICATION: Import broadcast
 use tokio_util::sync::CancellationToken;
 use tower_http::services::ServeDir;
 
+pub mod bans;
 pub mod protocol;
 pub mod ws;
 
@@ -20,6 +27,10 @@ pub struct ApiState {
     pub state_rx: watch::Receiver<NetworkState>,
     // FIX: Store the clonable Sender, not the Receiver.
     pub animation_tx: broadcast::Sender<NodeId>,
+    /// Used to place and lift bans, which the transport enforces.
+    pub transport_tx: mpsc::Sender<TransportCommand>,
+    /// The bearer token required to place and lift bans.
+    pub admin_token: Option<Arc<str>>,
 }
 
 pub struct ApiServer {
@@ -27,6 +38,8 @@ pub struct ApiServer {
     state_rx: watch::Receiver<NetworkState>,
     // FIX: Store the Sender here as well.
     animation_tx: broadcast::Sender<NodeId>,
+    transport_tx: mpsc::Sender<TransportCommand>,
+    admin_token: Option<Arc<str>>,
 }
 
 impl ApiServer {
@@ -35,23 +48,36 @@ impl ApiServer {
         state_rx: watch::Receiver<NetworkState>,
         // FIX: Accept the Sender in the constructor.
         animation_tx: broadcast::Sender<NodeId>,
+        transport_tx: mpsc::Sender<TransportCommand>,
     ) -> Self {
         Self {
             bind_addr,
             state_rx,
             animation_tx,
+            transport_tx,
+            admin_token: None,
         }
     }
 
+    /// Allows bans to be placed and lifted by requests bearing `token`.
+    pub fn with_admin_token(mut self, token: Option<String>) -> Self {
+        self.admin_token = token.map(Arc::from);
+        self
+    }
+
     pub async fn run(self, shutdown_token: CancellationToken) -> crate::error::Result<()> {
         let app_state = ApiState {
             state_rx: self.state_rx,
             // FIX: Pass the sender to the shared state.
             animation_tx: self.animation_tx,
+            transport_tx: self.transport_tx,
+            admin_token: self.admin_token,
         };
 
         let app = Router::new()
             .route("/ws", get(ws::websocket_handler))
+            .route("/api/bans", get(bans::list_bans))
+            .route("/api/bans/:target", post(bans::ban).delete(bans::unban))
             .nest_service("/", ServeDir::new("dist"))
             .with_state(app_state);
 
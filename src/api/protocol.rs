@@ -3,7 +3,7 @@
 //! Defines the data contract for the WebSocket API, ensuring a clear separation
 //! between backend state and the frontend's data model.
 
-use crate::domain::{NetworkState, NodeId, NodeInfo};
+use crate::domain::{Ban, MemberStatus, NetworkState, NodeHealth, NodeId, NodeInfo, PersistentPeer};
 // NEW: Import Deserialize
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -26,6 +26,18 @@ pub struct SnapshotPayload {
     pub self_id: NodeId,
     pub nodes: HashMap<NodeId, NodeInfo>,
     pub active_connections: Vec<NodeId>,
+    /// The failure detector's status of each known node.
+    #[serde(default)]
+    pub statuses: HashMap<NodeId, MemberStatus>,
+    /// The phi-accrual suspicion level of each node with enough history.
+    #[serde(default)]
+    pub health: HashMap<NodeId, NodeHealth>,
+    /// The connection state of each persistent peer.
+    #[serde(default)]
+    pub persistent_peers: Vec<PersistentPeer>,
+    /// The active bans.
+    #[serde(default)]
+    pub bans: Vec<Ban>,
 }
 
 impl From<&NetworkState> for SnapshotPayload {
@@ -34,6 +46,10 @@ impl From<&NetworkState> for SnapshotPayload {
             self_id: state.self_id.unwrap_or_default(),
             nodes: state.nodes.clone(),
             active_connections: state.active_connections.clone(),
+            statuses: state.statuses.clone(),
+            health: state.health.clone(),
+            persistent_peers: state.persistent_peers.clone(),
+            bans: state.bans.clone(),
         }
     }
 }
@@ -55,4 +71,16 @@ pub enum UpdatePayload {
     },
     #[serde(rename = "animate_edge")]
     AnimateEdge { from_peer: NodeId },
+    /// A node's failure detector status changed, e.g. it became suspected.
+    #[serde(rename = "node_status")]
+    NodeStatus { id: NodeId, status: MemberStatus },
+    /// A node's phi changed.
+    #[serde(rename = "node_health")]
+    NodeHealth { id: NodeId, phi: f64, suspected: bool },
+    /// A persistent peer connected, disconnected, or failed another attempt.
+    #[serde(rename = "persistent_peer")]
+    PersistentPeer(PersistentPeer),
+    /// A ban was placed, lifted or expired. Carries every active ban.
+    #[serde(rename = "bans")]
+    Bans(Vec<Ban>),
 }
\ No newline at end of file
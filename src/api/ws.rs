@@ -67,6 +67,28 @@ fn calculate_delta(old: &NetworkState, new: &NetworkState) -> Vec<UpdatePayload>
             is_connected: true,
         });
     }
+    for (id, status) in &new.statuses {
+        if old.statuses.get(id) != Some(status) {
+            updates.push(UpdatePayload::NodeStatus { id: *id, status: *status });
+        }
+    }
+    for (id, health) in &new.health {
+        if old.health.get(id) != Some(health) {
+            updates.push(UpdatePayload::NodeHealth {
+                id: *id,
+                phi: health.phi,
+                suspected: health.suspected,
+            });
+        }
+    }
+    for peer in &new.persistent_peers {
+        if !old.persistent_peers.contains(peer) {
+            updates.push(UpdatePayload::PersistentPeer(peer.clone()));
+        }
+    }
+    if new.bans != old.bans {
+        updates.push(UpdatePayload::Bans(new.bans.clone()));
+    }
     updates
 }
 
@@ -168,7 +190,10 @@ async fn handle_socket(mut socket: WebSocket, state: ApiState) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{NodeId, NodeInfo, TelemetryData};
+    use crate::domain::{
+        AppPayload, Ban, BanReason, BanTarget, DialState, MemberStatus, NodeHealth, NodeId, NodeInfo, PersistentPeer,
+        TelemetryData, Version,
+    };
 
     // Helper to create a dummy NodeId for testing.
     fn create_node_id(id: u8) -> NodeId {
@@ -180,8 +205,10 @@ mod tests {
     // Helper to create dummy NodeInfo.
     fn create_node_info(timestamp_ms: u64) -> NodeInfo {
         NodeInfo {
-            telemetry: TelemetryData { timestamp_ms, value: 0.0 },
+            payloads: [(TelemetryData::KIND.to_string(), serde_json::json!({ "value": 0.0 }))].into(),
+            timestamp_ms,
             community_id: 0,
+            version: Version::default(),
         }
     }
 
@@ -228,11 +255,15 @@ mod tests {
         let node2 = create_node_id(2);
         let node3 = create_node_id(3);
 
-        let mut old_state = NetworkState::default();
-        old_state.active_connections = vec![node1, node2];
+        let old_state = NetworkState {
+            active_connections: vec![node1, node2],
+            ..Default::default()
+        };
 
-        let mut new_state = NetworkState::default();
-        new_state.active_connections = vec![node2, node3];
+        let new_state = NetworkState {
+            active_connections: vec![node2, node3],
+            ..Default::default()
+        };
 
         let delta = calculate_delta(&old_state, &new_state);
         assert_eq!(delta.len(), 2);
@@ -251,6 +282,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn delta_detects_status_change() {
+        let node1 = create_node_id(1);
+        let mut old_state = NetworkState::default();
+        old_state.statuses.insert(node1, MemberStatus::Alive);
+        let mut new_state = old_state.clone();
+        new_state.statuses.insert(node1, MemberStatus::Suspect);
+
+        let delta = calculate_delta(&old_state, &new_state);
+        assert_eq!(delta.len(), 1);
+        assert!(matches!(
+            delta[0],
+            UpdatePayload::NodeStatus { id, status: MemberStatus::Suspect } if id == node1
+        ));
+    }
+
+    #[test]
+    fn delta_detects_health_change() {
+        let node1 = create_node_id(1);
+        let mut old_state = NetworkState::default();
+        old_state.health.insert(node1, NodeHealth { phi: 0.4, suspected: false });
+        let mut new_state = old_state.clone();
+        new_state.health.insert(node1, NodeHealth { phi: 9.1, suspected: true });
+
+        let delta = calculate_delta(&old_state, &new_state);
+        assert_eq!(delta.len(), 1);
+        assert!(matches!(
+            delta[0],
+            UpdatePayload::NodeHealth { id, suspected: true, .. } if id == node1
+        ));
+    }
+
+    #[test]
+    fn delta_detects_persistent_peer_change() {
+        let peer = PersistentPeer {
+            addr: "127.0.0.1:5000".parse().unwrap(),
+            state: DialState::Connecting,
+            failures: 0,
+            retry_at_ms: None,
+        };
+        let mut old_state = NetworkState::default();
+        old_state.persistent_peers.push(peer.clone());
+        let mut new_state = NetworkState::default();
+        new_state.persistent_peers.push(PersistentPeer { state: DialState::BackingOff, failures: 1, retry_at_ms: Some(1_500), ..peer });
+
+        let delta = calculate_delta(&old_state, &new_state);
+        assert_eq!(delta.len(), 1);
+        assert!(matches!(
+            &delta[0],
+            UpdatePayload::PersistentPeer(PersistentPeer { state: DialState::BackingOff, failures: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn delta_detects_ban_change() {
+        let ban = Ban { target: BanTarget::Node(create_node_id(1)), reason: BanReason::RateLimited, expires_at_ms: 60_000 };
+        let old_state = NetworkState::default();
+        let mut new_state = NetworkState::default();
+        new_state.bans.push(ban.clone());
+
+        let delta = calculate_delta(&old_state, &new_state);
+        assert_eq!(delta.len(), 1);
+        assert!(matches!(&delta[0], UpdatePayload::Bans(bans) if *bans == vec![ban.clone()]));
+
+        // Lifting the last ban is reported as an empty list.
+        let delta = calculate_delta(&new_state, &old_state);
+        assert!(matches!(&delta[0], UpdatePayload::Bans(bans) if bans.is_empty()));
+    }
+
     #[test]
     fn delta_is_empty_when_states_are_identical() {
         let node1 = create_node_id(1);
@@ -6,11 +6,18 @@
 use crate::{
     api::ApiServer,
     config::Config,
-    domain::{Identity, NetworkState, NodeId}, // MODIFICATION: Import NodeId
-    engine::Engine,
+    domain::{Identity, NetworkState, NodeId, PayloadRegistry}, // MODIFICATION: Import NodeId
+    engine::{
+        payload::{PayloadSource, SyntheticTelemetry},
+        Engine,
+    },
     error::Result,
     // MODIFICATION: Import new types.
-    transport::{ConnectionEvent, InboundMessage, Transport, TransportCommand},
+    transport::{
+        guard::{BanPolicy, RateLimits},
+        manager::{Backoff, ConnectionLimits},
+        ConnectionEvent, InboundMessage, Transport, TransportCommand,
+    },
 };
 use tokio::sync::{broadcast, mpsc, watch}; // MODIFICATION: Import broadcast
 use tokio_util::sync::CancellationToken;
@@ -20,6 +27,8 @@ use tokio_util::sync::CancellationToken;
 pub struct App {
     config: Config,
     shutdown_token: CancellationToken,
+    payload_sources: Vec<Box<dyn PayloadSource>>,
+    payloads: PayloadRegistry,
 }
 
 impl App {
@@ -28,9 +37,40 @@ impl App {
         Ok(Self {
             config,
             shutdown_token: CancellationToken::new(),
+            payload_sources: vec![Box::new(SyntheticTelemetry)],
+            payloads: PayloadRegistry::default(),
         })
     }
 
+    /// Sets the source of the application payload this node gossips, in place
+    /// of the synthetic telemetry it publishes by default.
+    pub fn with_payload_source(mut self, source: Box<dyn PayloadSource>) -> Self {
+        self.payload_sources = vec![source];
+        self
+    }
+
+    /// Like `with_payload_source`, but gossips a payload from each of
+    /// `sources`, such as telemetry alongside service metadata. Each source
+    /// should produce a different kind, as peers keep the latest payload of
+    /// each kind from every node.
+    pub fn with_payload_sources(mut self, sources: Vec<Box<dyn PayloadSource>>) -> Self {
+        self.payload_sources = sources;
+        self
+    }
+
+    /// Sets the payload kinds this node accepts from others. Register every
+    /// kind the network's nodes publish; other messages are rejected.
+    pub fn with_payload_registry(mut self, payloads: PayloadRegistry) -> Self {
+        self.payloads = payloads;
+        self
+    }
+
+    /// Returns a handle to the token that triggers graceful shutdown of the
+    /// application. Cancelling it has the same effect as receiving Ctrl+C.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown_token.clone()
+    }
+
     /// The main run loop for the application.
     ///
     /// This function performs the following steps:
@@ -61,12 +101,19 @@ impl App {
 
         // Transport: The network I/O layer.
         let transport = Transport::new(
+            identity.clone(),
+            &self.config.tls,
             self.config.p2p_addr,
             self.config.bootstrap_peers.clone(),
             transport_command_rx,
             inbound_message_tx,
             conn_event_tx,
-        )?;
+        )?
+        .with_advertised_addrs(self.config.listen_addrs())
+        .with_legacy_peers(self.config.accept_legacy_signatures)
+        .with_reconnect_backoff(Backoff::from_config(&self.config))
+        .with_connection_limits(ConnectionLimits::from_config(&self.config))
+        .with_peer_guard(RateLimits::from_config(&self.config), BanPolicy::from_config(&self.config));
         let transport_task = tokio::spawn(transport.run(self.shutdown_token.clone()));
         tracing::debug!("Transport service spawned.");
 
@@ -76,11 +123,13 @@ impl App {
             self.config.clone(),
             inbound_message_rx,
             conn_event_rx,
-            transport_command_tx,
+            transport_command_tx.clone(),
             network_state_tx,
             // MODIFICATION: Pass a clone of the sender to the Engine.
             animation_event_tx.clone(),
-        );
+        )
+        .with_payload_sources(self.payload_sources)
+        .with_payload_registry(self.payloads);
         let engine_task = tokio::spawn(engine.run(self.shutdown_token.clone()));
         tracing::debug!("Engine service spawned.");
 
@@ -89,7 +138,8 @@ impl App {
             tracing::info!("Visualizer is enabled. Starting API server.");
             // MODIFICATION: Pass the animation event sender to the ApiServer.
             let api_server =
-                ApiServer::new(viz_config.bind_addr, network_state_rx, animation_event_tx);
+                ApiServer::new(viz_config.bind_addr, network_state_rx, animation_event_tx, transport_command_tx)
+                    .with_admin_token(viz_config.admin_token);
             let api_server_task = tokio::spawn(api_server.run(self.shutdown_token.clone()));
             Some(api_server_task)
         } else {
@@ -3,6 +3,11 @@
 //! Defines the strongly-typed `Config` struct for all runtime parameters,
 //! loaded from files and environment variables via `figment`.
 
+use crate::{
+    domain::NodeId,
+    error::Result,
+    transport::envelope::{ALPN_BINCODE, ALPN_PROTOBUF},
+};
 use figment::{
     providers::{Env, Format, Toml},
     Figment,
@@ -11,34 +16,236 @@ use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 
-/// Top-level struct holding all application configuration.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Top-level struct holding all application configuration. Fields missing
+/// from the configuration sources fall back to `Config::default()`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub identity_path: PathBuf,
     pub p2p_addr: SocketAddr,
+    /// The addresses peers should dial this node at, announced in connection
+    /// handshakes and peer advertisements. Defaults to `p2p_addr`; set when
+    /// that is not reachable by peers, e.g. behind NAT or when bound to `0.0.0.0`.
+    pub advertised_addrs: Vec<SocketAddr>,
     pub bootstrap_peers: Vec<SocketAddr>,
+    /// How long to wait before redialing a bootstrap peer whose connection
+    /// was lost. Doubles with every failed attempt, with jitter.
+    pub reconnect_initial_backoff_ms: u64,
+    /// The longest wait between two attempts to dial a bootstrap peer.
+    pub reconnect_max_backoff_ms: u64,
+    /// The number of outbound connections below which advertised peers are dialed.
+    pub min_outbound_connections: usize,
+    /// The maximum number of outbound connections. Opening one more evicts
+    /// an existing one, picked by `eviction_policy`.
+    pub max_outbound_connections: usize,
+    /// The maximum number of inbound connections. Further ones are refused.
+    pub max_inbound_connections: usize,
+    /// How the outbound connection to close is picked when over the limit.
+    pub eviction_policy: EvictionPolicy,
+    /// The number of streams each peer may open per second, with bursts of
+    /// up to one second's worth.
+    pub peer_streams_per_sec: u32,
+    /// The number of bytes each peer may send per second, with bursts of up
+    /// to one second's worth or one maximum-size message, whichever is larger.
+    pub peer_bytes_per_sec: u64,
+    /// The number of each peer's streams that are read at once. Further
+    /// streams are dropped.
+    pub peer_max_concurrent_streams: usize,
+    /// The number of strikes within `strike_window_ms` that gets a peer
+    /// banned. Strikes are given for exceeding the rate limits, malformed
+    /// messages, and invalid signatures.
+    pub ban_strike_threshold: usize,
+    /// The number of strikes within `strike_window_ms`, by any peers
+    /// connecting from it, that gets an IP address banned.
+    pub ban_addr_strike_threshold: usize,
+    pub strike_window_ms: u64,
+    /// How long a ban lasts, unless given a duration through the API.
+    pub ban_duration_ms: u64,
     pub gossip_interval_ms: u64,
     pub gossip_factor: usize,
+    /// The maximum number of neighbours gossip is exchanged with.
+    pub active_view_size: usize,
+    /// The maximum number of backup peers kept to replace lost neighbours.
+    pub passive_view_size: usize,
+    /// How often to exchange passive view samples with a random neighbour.
+    pub shuffle_interval_ms: u64,
+    /// How often to send peer advertisements to neighbours and dial
+    /// advertised peers if short of outbound connections.
+    pub pex_interval_ms: u64,
+    /// The maximum number of peer advertisements kept as dial candidates.
+    pub pex_candidate_pool_size: usize,
+    /// How messages are propagated through the network.
+    pub propagation: Propagation,
+    /// How peers are chosen when gossiping a message.
+    pub peer_selection: PeerSelection,
+    /// With `PeerSelection::Community`, the fraction of the fanout sent to
+    /// peers outside the message's community, between 0.0 and 1.0.
+    pub community_bridge_fraction: f64,
+    /// How long to keep a node with too short an update history for phi
+    /// accrual, since its last update.
     pub node_ttl_ms: u64,
+    /// The phi above which a node is reported as suspected.
+    pub phi_suspect_threshold: f64,
+    /// The phi above which a node is evicted from the state.
+    pub phi_evict_threshold: f64,
+    /// A floor for the standard deviation of update intervals used by phi accrual.
+    pub phi_min_std_deviation_ms: u64,
+    /// Inbound messages dated more than this far ahead of the local clock are rejected.
+    pub max_future_skew_ms: u64,
+    /// Inbound messages dated more than this far behind the local clock are rejected.
+    pub max_past_skew_ms: u64,
+    /// Also accept nodes of protocol version 1: their handshakes, envelopes
+    /// and telemetry, signed over the `gossip-network/telemetry/v1` encoding
+    /// or the bincode encoding that predates it. Turn off once every node has
+    /// been upgraded.
+    pub accept_legacy_signatures: bool,
     // NEW: Make cleanup interval configurable for better testability.
     pub cleanup_interval_ms: u64,
+    /// In Plumtree mode, how long to wait for an announced message before
+    /// requesting it from the peer that announced it.
+    pub plumtree_graft_timeout_ms: u64,
+    /// How often to synchronize state with a random peer via anti-entropy.
+    pub anti_entropy_interval_ms: u64,
+    /// The SWIM protocol period: how often a random member is probed.
+    pub swim_probe_interval_ms: u64,
+    /// How long to wait for a direct ack before probing through other members.
+    pub swim_probe_timeout_ms: u64,
+    /// The number of members asked to probe a target that did not answer.
+    pub swim_indirect_probes: usize,
+    /// How long a suspected node has to refute before it is declared dead.
+    pub swim_suspicion_timeout_ms: u64,
     pub community_id: u32,
+    /// Maximum number of message digests held by the seen-message cache.
+    pub seen_cache_capacity: usize,
+    /// How long a message digest stays in the seen-message cache.
+    pub seen_cache_ttl_ms: u64,
     pub visualizer: Option<VisualizerConfig>,
+    pub tls: TlsConfig,
+}
+
+/// How new messages are pushed to peers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Propagation {
+    /// Each message is forwarded to peers chosen by the `peer_selection` strategy.
+    #[default]
+    Gossip,
+    /// Plumtree: messages are pushed along a spanning tree of eager peers, and
+    /// only announced to the remaining, lazy peers.
+    Plumtree,
+}
+
+/// The strategy used to pick the peers a message is forwarded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerSelection {
+    /// Peers are picked uniformly at random.
+    #[default]
+    Random,
+    /// Most of the fanout goes to peers in the message's community, with a
+    /// fraction reserved for peers in other communities.
+    Community,
+    /// Peers are cycled through in a fixed order, so each is picked equally often.
+    RoundRobin,
+}
+
+/// Which outbound connection is closed when the node has too many.
+/// Connections to bootstrap peers are never evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// The connection established first.
+    Oldest,
+    /// The connection that has gone the longest without a message being sent over it.
+    #[default]
+    LeastRecentlyUsed,
+    /// The connection with the highest round-trip time.
+    HighestLatency,
 }
 
 /// Configuration for the optional visualizer web server.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VisualizerConfig {
     pub bind_addr: SocketAddr,
+    /// The bearer token required to place and lift bans through the API.
+    /// Without one, bans can only be listed.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+}
+
+/// How the node authenticates itself and its peers at the TLS layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsMode {
+    /// Certificates issued by a private CA, read from the configured paths.
+    #[default]
+    Pki,
+    /// A self-signed certificate derived from the node's `Identity`. Peers are
+    /// accepted if their certificate key is a valid `NodeId`.
+    Identity,
+}
+
+/// Configuration for the transport's TLS layer. Certificate and key files may
+/// be PEM or DER encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub mode: TlsMode,
+    /// The CA certificate(s) that peer certificates must chain to (`pki` mode).
+    pub ca_cert_path: PathBuf,
+    /// This node's certificate, optionally followed by intermediates (`pki` mode).
+    pub cert_path: PathBuf,
+    /// The private key for `cert_path` (`pki` mode).
+    pub key_path: PathBuf,
+    /// The name sent as SNI and checked against peer certificates when dialing.
+    pub server_name: String,
+    /// The ALPN protocols offered and accepted, in order of preference. The
+    /// negotiated one picks the wire format: `gossip/2.0-pb` for protobuf,
+    /// anything else (normally `gossip/1.0`) for bincode.
+    pub alpn_protocols: Vec<String>,
+    /// In `pki` mode, require connecting peers to present a client certificate
+    /// verified against the CA. `identity` mode always requires one.
+    pub client_auth: bool,
+    /// In `identity` mode, restricts accepted peers to these `NodeId`s.
+    /// An empty list accepts any peer.
+    pub allowed_peers: Vec<NodeId>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            mode: TlsMode::default(),
+            ca_cert_path: PathBuf::from("certs/ca.cert"),
+            cert_path: PathBuf::from("certs/node.cert"),
+            key_path: PathBuf::from("certs/node.key"),
+            server_name: "localhost".to_string(),
+            alpn_protocols: vec![ALPN_PROTOBUF.to_string(), ALPN_BINCODE.to_string()],
+            client_auth: false,
+            allowed_peers: Vec::new(),
+        }
+    }
 }
 
 impl Config {
     /// Loads configuration from `config.toml` and environment variables.
-    pub fn load() -> Result<Self, figment::Error> {
-        Figment::new()
+    pub fn load() -> Result<Self> {
+        let config = Figment::new()
             .merge(Toml::file("config.toml"))
             .merge(Env::prefixed("GOSSIP_"))
-            .extract()
+            .extract()?;
+        Ok(config)
+    }
+
+    /// The addresses announced to peers: `advertised_addrs` if set, otherwise
+    /// `p2p_addr` unless it is unspecified and so of no use to a peer.
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        if !self.advertised_addrs.is_empty() {
+            self.advertised_addrs.clone()
+        } else if self.p2p_addr.ip().is_unspecified() {
+            Vec::new()
+        } else {
+            vec![self.p2p_addr]
+        }
     }
 }
 
@@ -47,13 +254,50 @@ impl Default for Config {
         Self {
             identity_path: PathBuf::from("identity.key"),
             p2p_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 5000),
+            advertised_addrs: Vec::new(),
             bootstrap_peers: Vec::new(),
+            reconnect_initial_backoff_ms: 500,
+            reconnect_max_backoff_ms: 30_000, // 30 seconds
+            min_outbound_connections: 4,
+            max_outbound_connections: 16,
+            max_inbound_connections: 64,
+            eviction_policy: EvictionPolicy::default(),
+            peer_streams_per_sec: 100,
+            peer_bytes_per_sec: 4 * 1_024 * 1_024, // 4 MiB
+            peer_max_concurrent_streams: 32,
+            ban_strike_threshold: 10,
+            ban_addr_strike_threshold: 40,
+            strike_window_ms: 60_000, // 1 minute
+            ban_duration_ms: 600_000, // 10 minutes
             gossip_interval_ms: 5000,
             gossip_factor: 2,
+            active_view_size: 5,
+            passive_view_size: 30,
+            shuffle_interval_ms: 10_000, // 10 seconds
+            pex_interval_ms: 30_000, // 30 seconds
+            pex_candidate_pool_size: 100,
+            propagation: Propagation::default(),
+            peer_selection: PeerSelection::default(),
+            community_bridge_fraction: 0.1,
             node_ttl_ms: 300000, // 5 minutes
+            phi_suspect_threshold: 8.0,
+            phi_evict_threshold: 16.0,
+            phi_min_std_deviation_ms: 500,
+            max_future_skew_ms: 30_000, // 30 seconds
+            max_past_skew_ms: 300_000,  // 5 minutes
+            accept_legacy_signatures: true,
             cleanup_interval_ms: 60000, // 1 minute
+            plumtree_graft_timeout_ms: 1_000, // 1 second
+            anti_entropy_interval_ms: 10_000, // 10 seconds
+            swim_probe_interval_ms: 1_000, // 1 second
+            swim_probe_timeout_ms: 300,
+            swim_indirect_probes: 3,
+            swim_suspicion_timeout_ms: 5_000, // 5 seconds
             community_id: 0,
+            seen_cache_capacity: 10_000,
+            seen_cache_ttl_ms: 120_000, // 2 minutes
             visualizer: None,
+            tls: TlsConfig::default(),
         }
     }
 }
\ No newline at end of file
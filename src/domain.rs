@@ -5,53 +5,129 @@
 //! the concepts of data representation (model) and identity (crypto).
 
 use crate::error::{Error, Result};
-use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use ed25519_dalek::{
+    pkcs8::EncodePrivateKey, Signature, Signer, SigningKey, Verifier, VerifyingKey,
+};
 use rand::{rngs::OsRng, RngCore};
-use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde::{de::DeserializeOwned, de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
 use std::{
-    collections::{HashMap},
-    fmt, fs, io,
-    path::Path,
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    io::{self, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 // --- Cryptographic Identity ---
 #[derive(Debug, Clone)] // MODIFICATION: Added Clone
 pub struct Identity {
     keypair: SigningKey,
     pub node_id: NodeId,
+    epoch: u64,
 }
 
 impl Identity {
+    /// Generates a fresh, ephemeral identity. Its epoch is always 0.
     pub fn new() -> Self {
         let mut csprng = OsRng;
         let mut secret_key_bytes = [0u8; 32];
         csprng.fill_bytes(&mut secret_key_bytes);
         let keypair = SigningKey::from_bytes(&secret_key_bytes);
         let node_id = NodeId(keypair.verifying_key().to_bytes());
-        Self { keypair, node_id }
+        Self { keypair, node_id, epoch: 0 }
     }
 
+    /// Restores an identity from its 32-byte ed25519 secret key. Its epoch is 0.
+    pub fn from_secret_key(secret_key: [u8; 32]) -> Self {
+        let keypair = SigningKey::from_bytes(&secret_key);
+        let node_id = NodeId(keypair.verifying_key().to_bytes());
+        Self { keypair, node_id, epoch: 0 }
+    }
+
+    /// Loads the identity stored at `path`, generating it if missing.
+    ///
+    /// Every call also advances the epoch persisted next to the key file (the
+    /// same path with an `epoch` extension), so that messages produced after a
+    /// restart always order after those produced before it.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        match fs::read(path.as_ref()) {
+        let mut identity = match fs::read(path.as_ref()) {
             Ok(bytes) => {
                 let keypair_bytes: [u8; 32] =
                     bytes.try_into().map_err(|_| Error::InvalidKeyFile)?;
-                let keypair = SigningKey::from_bytes(&keypair_bytes);
-                let node_id = NodeId(keypair.verifying_key().to_bytes());
-                Ok(Self { keypair, node_id })
+                Self::from_secret_key(keypair_bytes)
             }
             Err(e) if e.kind() == io::ErrorKind::NotFound => {
                 let identity = Self::new();
-                fs::write(path.as_ref(), identity.keypair.to_bytes())?;
-                Ok(identity)
+                Self::write_atomically(path.as_ref(), &identity.keypair.to_bytes())?;
+                identity
+            }
+            Err(e) => return Err(e.into()),
+        };
+        identity.epoch = Self::advance_epoch(&path.as_ref().with_extension("epoch"))?;
+        Ok(identity)
+    }
+
+    /// Reads the last persisted epoch, increments it, and writes it back.
+    fn advance_epoch(path: &Path) -> Result<u64> {
+        let previous = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| Error::InvalidEpochFile)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        let epoch = previous.checked_add(1).ok_or(Error::InvalidEpochFile)?;
+        Self::write_atomically(path, epoch.to_string().as_bytes())?;
+        Ok(epoch)
+    }
+
+    /// Replaces the file at `path` with `contents` so that a crash leaves either
+    /// the old or the new contents in place, never a truncated file: the data
+    /// is written and synced to a temporary file, which is then renamed over
+    /// `path`.
+    fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        // Persist the rename itself. Directories cannot be opened on every
+        // platform, so this step is best effort.
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            if let Ok(dir) = fs::File::open(dir) {
+                let _ = dir.sync_all();
             }
-            Err(e) => Err(e.into()),
         }
+        Ok(())
+    }
+
+    /// The epoch of this identity's current incarnation.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Encodes this identity's key as a PKCS#8 document, the format expected
+    /// by the TLS certificate tooling.
+    pub(crate) fn to_pkcs8_der(&self) -> Result<Vec<u8>> {
+        let document = self
+            .keypair
+            .to_pkcs8_der()
+            .map_err(|e| Error::TlsConfig(format!("Failed to encode identity key: {}", e)))?;
+        Ok(document.as_bytes().to_vec())
     }
 
+    /// Signs arbitrary bytes with this identity's key.
+    pub fn sign_bytes(&self, bytes: &[u8]) -> Signature {
+        self.keypair.sign(bytes)
+    }
+
+    /// Signs the canonical encoding of `message_data`.
     pub fn sign(&self, message_data: GossipPayload) -> SignedMessage {
-        let message_bytes =
-            bincode::serialize(&message_data).expect("GossipPayload is serializable");
-        let signature = self.keypair.sign(&message_bytes);
+        let signature = self.keypair.sign(&message_data.signing_bytes());
 
         SignedMessage {
             message: message_data,
@@ -59,6 +135,19 @@ impl Identity {
             signature,
         }
     }
+
+    /// Signs an advertisement of the addresses this node can be dialed at.
+    pub fn advertise(&self, listen_addrs: Vec<SocketAddr>, version: Version) -> SignedPeerAdvertisement {
+        let advertisement = PeerAdvertisement { node_id: self.node_id, listen_addrs, version };
+        let signature = self.keypair.sign(&advertisement.signing_bytes());
+        SignedPeerAdvertisement { advertisement, signature }
+    }
+}
+
+impl Default for Identity {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // --- Domain Models ---
@@ -93,6 +182,13 @@ impl NodeId {
     pub fn as_bytes(&self) -> &[u8; 32] {
         &self.0
     }
+
+    /// Verifies that `signature` over `bytes` was produced by this node's key.
+    pub fn verify(&self, bytes: &[u8], signature: &Signature) -> Result<()> {
+        let public_key = VerifyingKey::from_bytes(self.as_bytes())?;
+        public_key.verify(bytes, signature)?;
+        Ok(())
+    }
 }
 
 impl fmt::Display for NodeId {
@@ -101,17 +197,392 @@ impl fmt::Display for NodeId {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+// --- Application Payloads ---
+
+/// A type of application data gossiped by nodes, such as telemetry, service
+/// metadata or feature flags. Values are encoded as JSON, so that they can be
+/// produced and read by other languages and shown as-is by the API.
+pub trait AppPayload: Serialize + DeserializeOwned {
+    /// Names the type on the wire. Must be unique among the registered types.
+    const KIND: &'static str;
+
+    /// Checks constraints the type system cannot express, such as size
+    /// bounds. Payloads that fail are rejected by receiving nodes.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The longest payload kind accepted, in bytes.
+pub const MAX_PAYLOAD_KIND_LEN: usize = 64;
+/// The largest payload data accepted, in bytes, whatever its kind.
+pub const MAX_PAYLOAD_DATA_LEN: usize = 16 * 1024;
+
+/// Application data in transit: its kind and its JSON encoding.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payload {
+    pub kind: String,
+    pub data: Vec<u8>,
+}
+
+impl Payload {
+    pub fn new<T: AppPayload>(value: &T) -> Self {
+        Self {
+            kind: T::KIND.to_string(),
+            data: serde_json::to_vec(value).expect("AppPayload is serializable to JSON"),
+        }
+    }
+
+    /// Decodes the payload as a `T`, failing if it is of another kind.
+    pub fn decode<T: AppPayload>(&self) -> Result<T> {
+        if self.kind != T::KIND {
+            return Err(Error::Payload(format!("expected kind {:?}, found {:?}", T::KIND, self.kind)));
+        }
+        serde_json::from_slice(&self.data).map_err(|e| Error::Payload(format!("invalid {:?} data: {}", self.kind, e)))
+    }
+
+    /// Checks the kind and data against `MAX_PAYLOAD_KIND_LEN` and
+    /// `MAX_PAYLOAD_DATA_LEN`, so that no node can gossip oversized payloads.
+    pub fn check_size(&self) -> Result<()> {
+        if self.kind.len() > MAX_PAYLOAD_KIND_LEN {
+            return Err(Error::Payload(format!("kind is {} bytes, over {}", self.kind.len(), MAX_PAYLOAD_KIND_LEN)));
+        }
+        if self.data.len() > MAX_PAYLOAD_DATA_LEN {
+            return Err(Error::Payload(format!(
+                "{:?} data is {} bytes, over {}",
+                self.kind,
+                self.data.len(),
+                MAX_PAYLOAD_DATA_LEN
+            )));
+        }
+        Ok(())
+    }
+
+    /// The data as a JSON value, or `null` if it is not valid JSON.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.data).unwrap_or_default()
+    }
+}
+
+/// The built-in application payload: a record of named metrics, gossiped by
+/// nodes that are not given another `PayloadSource`.
+/// Single-value records of earlier releases decode with their value as the
+/// `LEGACY_METRIC` metric.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "TelemetryRecord")]
 pub struct TelemetryData {
-    pub timestamp_ms: u64,
+    /// Metrics by name, e.g. `cpu` or `latency_p99`.
+    pub metrics: BTreeMap<String, Metric>,
+}
+
+/// The shapes a `TelemetryData` record decodes from. Anything else is
+/// rejected, rather than decoding as an empty record.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TelemetryRecord {
+    Metrics { metrics: BTreeMap<String, Metric> },
+    /// `{"value": 42.5}`, as gossiped by releases before named metrics.
+    SingleValue { value: f64 },
+}
+
+impl From<TelemetryRecord> for TelemetryData {
+    fn from(record: TelemetryRecord) -> Self {
+        match record {
+            TelemetryRecord::Metrics { metrics } => Self { metrics },
+            TelemetryRecord::SingleValue { value } => {
+                Self::default().with_metric(Self::LEGACY_METRIC, Metric::new(value, ""))
+            }
+        }
+    }
+}
+
+/// A single measurement in a `TelemetryData` record.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Metric {
+    /// Must be finite, as JSON cannot carry NaN or infinities.
     pub value: f64,
+    /// The unit of `value`, e.g. `percent`, `bytes` or `ms`.
+    pub unit: String,
+    /// Optional dimensions of the measurement, e.g. `queue = "ingest"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Metric {
+    pub fn new(value: f64, unit: impl Into<String>) -> Self {
+        Self { value, unit: unit.into(), labels: BTreeMap::new() }
+    }
+
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl TelemetryData {
+    /// The most metrics a record may carry.
+    pub const MAX_METRICS: usize = 32;
+    /// The most labels a metric may carry.
+    pub const MAX_LABELS: usize = 8;
+    /// The longest metric name, unit, label key or label value, in bytes.
+    pub const MAX_NAME_LEN: usize = 64;
+    /// The metric the value of a single-value record of an earlier release
+    /// decodes as. It has no unit.
+    pub const LEGACY_METRIC: &'static str = "value";
+
+    pub fn with_metric(mut self, name: impl Into<String>, metric: Metric) -> Self {
+        self.metrics.insert(name.into(), metric);
+        self
+    }
+}
+
+impl AppPayload for TelemetryData {
+    const KIND: &'static str = "telemetry";
+
+    fn validate(&self) -> Result<()> {
+        let check_len = |what: &str, text: &str| {
+            if text.len() > Self::MAX_NAME_LEN {
+                return Err(Error::Payload(format!("{} {:?} is over {} bytes", what, text, Self::MAX_NAME_LEN)));
+            }
+            Ok(())
+        };
+        if self.metrics.len() > Self::MAX_METRICS {
+            return Err(Error::Payload(format!("{} metrics, over {}", self.metrics.len(), Self::MAX_METRICS)));
+        }
+        for (name, metric) in &self.metrics {
+            if name.is_empty() {
+                return Err(Error::Payload("empty metric name".to_string()));
+            }
+            check_len("metric name", name)?;
+            check_len("unit", &metric.unit)?;
+            if !metric.value.is_finite() {
+                return Err(Error::Payload(format!("metric {:?} has non-finite value {}", name, metric.value)));
+            }
+            if metric.labels.len() > Self::MAX_LABELS {
+                return Err(Error::Payload(format!(
+                    "metric {:?} has {} labels, over {}",
+                    name,
+                    metric.labels.len(),
+                    Self::MAX_LABELS
+                )));
+            }
+            for (key, value) in &metric.labels {
+                check_len("label key", key)?;
+                check_len("label value", value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodes and validates payload data of one registered kind.
+type PayloadValidator = fn(&[u8]) -> Result<()>;
+
+/// The application payload types a node accepts. Messages carrying a payload
+/// of another kind, over the size bounds, or with data that does not decode
+/// and validate as its kind, are rejected.
+#[derive(Clone)]
+pub struct PayloadRegistry {
+    validators: HashMap<String, PayloadValidator>,
+}
+
+impl PayloadRegistry {
+    /// A registry accepting no payload types.
+    pub fn empty() -> Self {
+        Self { validators: HashMap::new() }
+    }
+
+    pub fn register<T: AppPayload>(mut self) -> Self {
+        self.validators.insert(T::KIND.to_string(), |data| {
+            let value: T = serde_json::from_slice(data)
+                .map_err(|e| Error::Payload(format!("invalid {:?} data: {}", T::KIND, e)))?;
+            value.validate()
+        });
+        self
+    }
+
+    /// Checks that `payload` is within the size bounds, of a registered kind,
+    /// and decodes and validates as it.
+    pub fn validate(&self, payload: &Payload) -> Result<()> {
+        payload.check_size()?;
+        let validate = self
+            .validators
+            .get(&payload.kind)
+            .ok_or_else(|| Error::Payload(format!("unregistered kind {:?}", payload.kind)))?;
+        validate(&payload.data)
+    }
+
+    /// The registered kinds, in alphabetical order.
+    pub fn kinds(&self) -> Vec<&str> {
+        let mut kinds: Vec<_> = self.validators.keys().map(String::as_str).collect();
+        kinds.sort_unstable();
+        kinds
+    }
+}
+
+/// Accepts `TelemetryData` only.
+impl Default for PayloadRegistry {
+    fn default() -> Self {
+        Self::empty().register::<TelemetryData>()
+    }
+}
+
+impl fmt::Debug for PayloadRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PayloadRegistry").field("kinds", &self.kinds()).finish()
+    }
+}
+
+/// A logical version ordering the messages originated by a single node.
+///
+/// Versions compare by `epoch` first, then by `sequence`. The epoch advances on
+/// every restart and the sequence on every message within an epoch, so the
+/// ordering is independent of the originator's wall clock.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Version {
+    pub epoch: u64,
+    pub sequence: u64,
 }
 
 /// The data payload that is signed and gossiped across the network.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GossipPayload {
-    pub telemetry: TelemetryData,
+    /// When the payload was produced, by the originator's clock. Checked
+    /// against the accepted skew window; freshness is decided by `version`.
+    pub timestamp_ms: u64,
     pub community_id: u32,
+    pub version: Version,
+    /// The application data, of a kind registered with the `PayloadRegistry`.
+    pub payload: Payload,
+}
+
+/// Prefixed to the canonical encoding of a `GossipPayload` before signing.
+/// The version suffix changes whenever the encoding does.
+pub const PAYLOAD_SIGNING_CONTEXT: &[u8] = b"gossip-network/payload/v1";
+
+impl GossipPayload {
+    /// The canonical encoding signed by the originator, which any language can
+    /// reproduce: `PAYLOAD_SIGNING_CONTEXT` followed by the fields as
+    /// big-endian integers, the payload kind and data each preceded by their
+    /// length in bytes, with no padding.
+    ///
+    /// ```text
+    /// "gossip-network/payload/v1" | timestamp_ms (u64) | community_id (u32) | epoch (u64)
+    ///     | sequence (u64) | kind length (u32) | kind (UTF-8) | data length (u32) | data
+    /// ```
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let Payload { kind, data } = &self.payload;
+        let mut bytes = Vec::with_capacity(PAYLOAD_SIGNING_CONTEXT.len() + 36 + kind.len() + data.len());
+        bytes.extend_from_slice(PAYLOAD_SIGNING_CONTEXT);
+        bytes.extend_from_slice(&self.timestamp_ms.to_be_bytes());
+        bytes.extend_from_slice(&self.community_id.to_be_bytes());
+        bytes.extend_from_slice(&self.version.epoch.to_be_bytes());
+        bytes.extend_from_slice(&self.version.sequence.to_be_bytes());
+        bytes.extend_from_slice(&(kind.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(kind.as_bytes());
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+}
+
+/// Prefixed to the canonical encoding of a `GossipPayloadV1` before signing.
+pub const TELEMETRY_SIGNING_CONTEXT: &[u8] = b"gossip-network/telemetry/v1";
+
+/// The single metric gossiped by nodes of protocol version 1.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryV1 {
+    pub timestamp_ms: u64,
+    pub value: f64,
+}
+
+/// The signed payload of protocol version 1, which predates application
+/// payloads. It is carried as a `GossipPayload` of kind `telemetry` whose data
+/// is `{"value": <value>}`, and its signatures are accepted while
+/// `accept_legacy_signatures` is on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GossipPayloadV1 {
+    pub telemetry: TelemetryV1,
+    pub community_id: u32,
+    pub version: Version,
+}
+
+impl GossipPayloadV1 {
+    /// The canonical encoding signed by nodes of protocol version 1:
+    /// `TELEMETRY_SIGNING_CONTEXT` followed by the fields in declaration order
+    /// as fixed-width big-endian integers, with no padding or length prefixes.
+    ///
+    /// ```text
+    /// "gossip-network/telemetry/v1" | timestamp_ms (u64) | value (f64 as IEEE 754 bits, u64)
+    ///     | community_id (u32) | epoch (u64) | sequence (u64)
+    /// ```
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(TELEMETRY_SIGNING_CONTEXT.len() + 36);
+        bytes.extend_from_slice(TELEMETRY_SIGNING_CONTEXT);
+        bytes.extend_from_slice(&self.telemetry.timestamp_ms.to_be_bytes());
+        bytes.extend_from_slice(&self.telemetry.value.to_bits().to_be_bytes());
+        bytes.extend_from_slice(&self.community_id.to_be_bytes());
+        bytes.extend_from_slice(&self.version.epoch.to_be_bytes());
+        bytes.extend_from_slice(&self.version.sequence.to_be_bytes());
+        bytes
+    }
+
+    /// The bincode encoding signed by nodes that predate the canonical one.
+    pub fn legacy_signing_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("GossipPayloadV1 is serializable")
+    }
+}
+
+impl TryFrom<GossipPayloadV1> for GossipPayload {
+    type Error = Error;
+
+    fn try_from(payload: GossipPayloadV1) -> Result<Self> {
+        let TelemetryV1 { timestamp_ms, value } = payload.telemetry;
+        if !value.is_finite() {
+            return Err(Error::Payload(format!("non-finite telemetry value {}", value)));
+        }
+        Ok(Self {
+            timestamp_ms,
+            community_id: payload.community_id,
+            version: payload.version,
+            payload: Payload {
+                kind: TelemetryData::KIND.to_string(),
+                data: serde_json::to_vec(&serde_json::json!({ "value": value })).expect("JSON is serializable"),
+            },
+        })
+    }
+}
+
+impl GossipPayload {
+    /// The version 1 payload this one was converted from, if it is exactly
+    /// such a conversion, so that a version 1 signature verifies one payload
+    /// only.
+    pub fn to_v1(&self) -> Option<GossipPayloadV1> {
+        if self.payload.kind != TelemetryData::KIND {
+            return None;
+        }
+        let value = serde_json::from_slice::<serde_json::Value>(&self.payload.data).ok()?.get("value")?.as_f64()?;
+        let v1 = GossipPayloadV1 {
+            telemetry: TelemetryV1 { timestamp_ms: self.timestamp_ms, value },
+            community_id: self.community_id,
+            version: self.version,
+        };
+        (GossipPayload::try_from(v1.clone()).ok()? == *self).then_some(v1)
+    }
+}
+
+/// The encoding of a payload that a signature was made over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigningEncoding {
+    /// The canonical encoding of the current protocol version.
+    Canonical,
+    /// `GossipPayloadV1::signing_bytes`, signed by nodes of protocol version 1.
+    TelemetryV1,
+    /// The bincode encoding signed by nodes that predate the canonical ones,
+    /// accepted during migration.
+    LegacyBincode,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -122,19 +593,316 @@ pub struct SignedMessage {
 }
 
 impl SignedMessage {
+    /// Verifies the signature over the canonical encoding of the payload.
     pub fn verify(&self) -> Result<()> {
-        let public_key = VerifyingKey::from_bytes(self.originator.as_bytes())?;
-        let message_bytes = bincode::serialize(&self.message)?;
-        public_key.verify(&message_bytes, &self.signature)?;
-        Ok(())
+        self.originator.verify(&self.message.signing_bytes(), &self.signature)
     }
+
+    /// Verifies the signature over the canonical encoding or, if
+    /// `accept_legacy`, over the encodings signed by nodes of protocol
+    /// version 1. Returns the encoding that verified.
+    pub fn verify_with(&self, accept_legacy: bool) -> Result<SigningEncoding> {
+        let error = match self.verify() {
+            Ok(()) => return Ok(SigningEncoding::Canonical),
+            Err(e) => e,
+        };
+        let Some(v1) = self.message.to_v1().filter(|_| accept_legacy) else {
+            return Err(error);
+        };
+        if self.originator.verify(&v1.signing_bytes(), &self.signature).is_ok() {
+            return Ok(SigningEncoding::TelemetryV1);
+        }
+        self.originator.verify(&v1.legacy_signing_bytes(), &self.signature)?;
+        Ok(SigningEncoding::LegacyBincode)
+    }
+
+    /// Computes the message's digest, a SHA-256 hash over the originator and
+    /// the signature. Cheap to compute and does not require verification.
+    pub fn id(&self) -> MessageId {
+        let mut hasher = Sha256::new();
+        hasher.update(self.originator.as_bytes());
+        hasher.update(self.signature.to_bytes());
+        MessageId(hasher.finalize().into())
+    }
+}
+
+/// Prefixed to the canonical encoding of a `PeerAdvertisement` before signing,
+/// so that a signature over an advertisement can never be mistaken for one
+/// over another kind of message. The version suffix changes whenever the
+/// encoding does.
+pub const PEER_ADVERTISEMENT_SIGNING_CONTEXT: &[u8] = b"gossip-network/peer-advertisement/v1";
+
+/// The addresses a node can be dialed at, as claimed by the node itself.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerAdvertisement {
+    pub node_id: NodeId,
+    pub listen_addrs: Vec<SocketAddr>,
+    /// Orders the advertisements of a node, so newer addresses win.
+    pub version: Version,
 }
 
+impl PeerAdvertisement {
+    /// The canonical encoding signed by the advertised node:
+    /// `PEER_ADVERTISEMENT_SIGNING_CONTEXT`, the node id, the version as
+    /// big-endian integers, and the addresses as encoded by
+    /// `encode_socket_addrs`.
+    ///
+    /// ```text
+    /// "gossip-network/peer-advertisement/v1" | node_id (32 bytes) | epoch (u64) | sequence (u64)
+    ///     | address count (u32) | addresses
+    /// ```
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(PEER_ADVERTISEMENT_SIGNING_CONTEXT.len() + 52 + 19 * self.listen_addrs.len());
+        bytes.extend_from_slice(PEER_ADVERTISEMENT_SIGNING_CONTEXT);
+        bytes.extend_from_slice(&self.node_id.0);
+        bytes.extend_from_slice(&self.version.epoch.to_be_bytes());
+        bytes.extend_from_slice(&self.version.sequence.to_be_bytes());
+        encode_socket_addrs(&mut bytes, &self.listen_addrs);
+        bytes
+    }
+
+    /// The encoding signed by nodes of protocol version 1: the unversioned
+    /// prefix `gossip-network/peer-advertisement` and the bincode encoding.
+    pub fn legacy_signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = b"gossip-network/peer-advertisement".to_vec();
+        bytes.extend(bincode::serialize(self).expect("PeerAdvertisement is serializable"));
+        bytes
+    }
+}
+
+/// Appends the canonical encoding of `addrs`, as signed in peer advertisements
+/// and connection handshakes: their count as a big-endian u32, then for each
+/// its family (4 or 6) as one byte, its IP address in network byte order (4 or
+/// 16 bytes) and its port as a big-endian u16. IPv6 flow and scope ids are not
+/// covered.
+///
+/// ```text
+/// count (u32) | family (u8) | ip (4 or 16 bytes) | port (u16) | family (u8) | ...
+/// ```
+pub fn encode_socket_addrs(bytes: &mut Vec<u8>, addrs: &[SocketAddr]) {
+    bytes.extend_from_slice(&(addrs.len() as u32).to_be_bytes());
+    for addr in addrs {
+        match addr.ip() {
+            IpAddr::V4(ip) => {
+                bytes.push(4);
+                bytes.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                bytes.push(6);
+                bytes.extend_from_slice(&ip.octets());
+            }
+        }
+        bytes.extend_from_slice(&addr.port().to_be_bytes());
+    }
+}
+
+/// A `PeerAdvertisement` signed by the node it advertises, so that peers
+/// relaying it cannot alter the addresses or attribute them to another node.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedPeerAdvertisement {
+    pub advertisement: PeerAdvertisement,
+    pub signature: Signature,
+}
+
+impl SignedPeerAdvertisement {
+    pub fn verify(&self) -> Result<()> {
+        let advertisement = &self.advertisement;
+        advertisement.node_id.verify(&advertisement.signing_bytes(), &self.signature)
+    }
+
+    /// Verifies the signature over the canonical encoding or, if
+    /// `accept_legacy`, over `PeerAdvertisement::legacy_signing_bytes`.
+    /// Returns the encoding that verified.
+    pub fn verify_with(&self, accept_legacy: bool) -> Result<SigningEncoding> {
+        match self.verify() {
+            Ok(()) => Ok(SigningEncoding::Canonical),
+            Err(_) if accept_legacy => {
+                let advertisement = &self.advertisement;
+                advertisement.node_id.verify(&advertisement.legacy_signing_bytes(), &self.signature)?;
+                Ok(SigningEncoding::LegacyBincode)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A compact identifier for a `SignedMessage`, used to detect duplicates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MessageId(pub [u8; 32]);
+
 /// Information about a node, as held by the Engine.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct NodeInfo {
-    pub telemetry: TelemetryData,
+    /// The node's latest application payload of each kind, as JSON.
+    pub payloads: BTreeMap<String, serde_json::Value>,
+    /// When the node's latest message, of any kind, was produced.
+    pub timestamp_ms: u64,
     pub community_id: u32,
+    /// The version of the node's latest message, of any kind.
+    pub version: Version,
+}
+
+impl NodeInfo {
+    /// Summarizes the records of a node, or returns `None` if it has none.
+    pub fn from_records(records: &NodeRecords) -> Option<Self> {
+        let latest = &latest_record(records)?.message.message;
+        Some(Self {
+            payloads: records.iter().map(|(kind, record)| (kind.clone(), record.message.message.payload.to_json())).collect(),
+            timestamp_ms: latest.timestamp_ms,
+            community_id: latest.community_id,
+            version: latest.version,
+        })
+    }
+}
+
+/// The latest message accepted from an originator for each payload kind it
+/// gossips. Each kind is ordered by version independently of the others, so
+/// a node can gossip several kinds at once.
+pub type NodeRecords = BTreeMap<String, MessageRecord>;
+
+/// The most recent of a node's records, of any kind.
+pub fn latest_record(records: &NodeRecords) -> Option<&MessageRecord> {
+    records.values().max_by_key(|record| record.message.message.version)
+}
+
+/// The Engine's record of the latest message accepted from an originator for
+/// one payload kind.
+/// Keeping the full `SignedMessage` lets the record be re-served to peers
+/// and independently verified by consumers.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MessageRecord {
+    pub message: SignedMessage,
+    /// When this node accepted the message, in milliseconds since the Unix epoch.
+    pub received_at_ms: u64,
+    /// The authenticated peer that delivered the message; this node's own id
+    /// for messages it originated.
+    pub from_peer: NodeId,
+    /// The number of hops the message travelled from its originator; 0 for
+    /// messages this node originated. Reported by relaying peers, not signed.
+    pub hops: u32,
+}
+
+/// The liveness of a node, as judged by the SWIM failure detector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberStatus {
+    /// The node answered a recent probe, or refuted a suspicion.
+    Alive,
+    /// The node failed a probe and will be declared dead unless it refutes.
+    Suspect,
+    /// The node stayed suspected past the suspicion timeout.
+    Dead,
+}
+
+/// The phi-accrual failure detector's view of a node.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeHealth {
+    /// The suspicion level, rounded to one decimal place.
+    pub phi: f64,
+    /// Whether phi exceeds `phi_suspect_threshold`.
+    pub suspected: bool,
+}
+
+/// Where the transport stands with a persistent peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DialState {
+    /// A connection attempt is in progress.
+    Connecting,
+    /// The peer is connected.
+    Connected,
+    /// The last attempt failed or the connection was lost; waiting to retry.
+    BackingOff,
+}
+
+/// A peer the transport keeps connected to, such as a bootstrap peer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistentPeer {
+    pub addr: SocketAddr,
+    pub state: DialState,
+    /// Consecutive failed attempts since the peer was last connected.
+    pub failures: u32,
+    /// While backing off, when the next attempt is due, in milliseconds since
+    /// the Unix epoch.
+    pub retry_at_ms: Option<u64>,
+}
+
+/// Whom a ban applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BanTarget {
+    /// Every connection from this IP address, whatever node it authenticates as.
+    Addr(IpAddr),
+    /// Every connection authenticated as this node.
+    Node(NodeId),
+}
+
+/// Parses an IP address, or a `NodeId` in hex.
+impl FromStr for BanTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Ok(addr) = s.parse() {
+            return Ok(BanTarget::Addr(addr));
+        }
+        let bytes = hex::decode(s).map_err(|_| format!("{s:?} is neither an IP address nor a node id"))?;
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| format!("{s:?} is not 32 bytes long"))?;
+        Ok(BanTarget::Node(NodeId(bytes)))
+    }
+}
+
+/// Why a peer was struck or banned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BanReason {
+    /// The peer relayed a message or advertisement whose signature is invalid.
+    InvalidSignature,
+    /// The peer sent a message that could not be read or deserialized.
+    MalformedMessage,
+    /// The peer opened streams or sent bytes faster than its rate limits.
+    RateLimited,
+    /// The ban was placed through the API.
+    Manual,
+}
+
+/// A timed ban, enforced by the transport.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ban {
+    pub target: BanTarget,
+    /// The reason for the last strike, for automatic bans.
+    pub reason: BanReason,
+    /// When the ban is lifted, in milliseconds since the Unix epoch.
+    pub expires_at_ms: u64,
+}
+
+/// Running counters describing how the Engine has handled inbound gossip.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EngineStats {
+    /// Inbound messages discarded because their digest was already cached.
+    pub seen_cache_hits: u64,
+    /// Inbound messages not found in the cache and therefore fully processed.
+    pub seen_cache_misses: u64,
+    /// Inbound messages whose signature failed to verify.
+    pub rejected_invalid_signature: u64,
+    /// Inbound messages dated further ahead than `max_future_skew_ms`.
+    pub rejected_future_timestamp: u64,
+    /// Inbound messages dated further back than `max_past_skew_ms`.
+    pub rejected_stale_timestamp: u64,
+    /// Peer advertisements whose signature failed to verify.
+    pub rejected_invalid_advertisement: u64,
+    /// Inbound messages whose application payload is of an unregistered kind
+    /// or does not decode as its kind.
+    #[serde(default)]
+    pub rejected_invalid_payload: u64,
+    /// Inbound messages accepted with a signature of protocol version 1,
+    /// which should drop to zero before `accept_legacy_signatures` is turned
+    /// off.
+    #[serde(default)]
+    pub accepted_legacy_signature: u64,
+    /// Sync digests ignored because the same peer had one answered less than
+    /// half an anti-entropy interval earlier.
+    #[serde(default)]
+    pub ignored_sync_digests: u64,
 }
 
 /// A snapshot of the network state, for use by the visualizer.
@@ -143,6 +911,23 @@ pub struct NetworkState {
     pub self_id: Option<NodeId>,
     pub nodes: HashMap<NodeId, NodeInfo>,
     pub active_connections: Vec<NodeId>,
+    #[serde(default)]
+    pub stats: EngineStats,
+    /// The signed messages behind each entry of `nodes`, by payload kind.
+    #[serde(default)]
+    pub messages: HashMap<NodeId, NodeRecords>,
+    /// The failure detector's status of each known node, including this one.
+    #[serde(default)]
+    pub statuses: HashMap<NodeId, MemberStatus>,
+    /// The phi-accrual suspicion level of each node with enough update history.
+    #[serde(default)]
+    pub health: HashMap<NodeId, NodeHealth>,
+    /// The connection state of each persistent peer, by address.
+    #[serde(default)]
+    pub persistent_peers: Vec<PersistentPeer>,
+    /// The peers the transport currently refuses, soonest to expire first.
+    #[serde(default)]
+    pub bans: Vec<Ban>,
 }
 
 #[cfg(test)]
@@ -163,8 +948,10 @@ mod tests {
 
         fn sign(&self, timestamp_ms: u64) -> SignedMessage {
             let payload = GossipPayload {
-                telemetry: TelemetryData { timestamp_ms, value: 42.0 },
+                timestamp_ms,
                 community_id: 1,
+                version: Version { epoch: 1, sequence: timestamp_ms },
+                payload: Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(42.0, "percent"))),
             };
             self.identity.sign(payload)
         }
@@ -183,7 +970,7 @@ mod tests {
         let mut message = peer.sign(1000);
 
         // Mutate the payload after signing.
-        message.message.telemetry.value = 999.0;
+        message.message.payload = Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(999.0, "percent")));
 
         assert!(message.verify().is_err());
     }
@@ -200,6 +987,257 @@ mod tests {
         assert!(message.verify().is_err());
     }
 
+    #[test]
+    fn signature_covers_payload_kind() {
+        let peer = TestPeer::new();
+        let mut message = peer.sign(1000);
+        message.message.payload.kind = "flags".to_string();
+        assert!(message.verify().is_err());
+    }
+
+    #[test]
+    fn version_1_signatures_are_accepted_only_in_compatibility_mode() {
+        let peer = TestPeer::new();
+        let v1 = GossipPayloadV1 {
+            telemetry: TelemetryV1 { timestamp_ms: 1000, value: 123.456 },
+            community_id: 1,
+            version: Version { epoch: 1, sequence: 7 },
+        };
+        for (bytes, encoding) in [
+            (v1.signing_bytes(), SigningEncoding::TelemetryV1),
+            (v1.legacy_signing_bytes(), SigningEncoding::LegacyBincode),
+        ] {
+            let mut message = SignedMessage {
+                message: v1.clone().try_into().unwrap(),
+                originator: peer.identity.node_id,
+                signature: peer.identity.sign_bytes(&bytes),
+            };
+            assert_eq!(message.message.to_v1(), Some(v1.clone()));
+            assert!(message.verify().is_err());
+            assert!(message.verify_with(false).is_err());
+            assert_eq!(message.verify_with(true).unwrap(), encoding);
+
+            message.message.community_id = 2;
+            assert!(message.verify_with(true).is_err(), "Compatibility mode must still detect tampering");
+        }
+        assert_eq!(peer.sign(1000).verify_with(true).unwrap(), SigningEncoding::Canonical);
+    }
+
+    #[test]
+    fn only_exact_conversions_of_version_1_payloads_carry_their_signatures() {
+        let v1 = GossipPayloadV1 {
+            telemetry: TelemetryV1 { timestamp_ms: 1000, value: 42.0 },
+            community_id: 1,
+            version: Version { epoch: 1, sequence: 7 },
+        };
+        let mut payload = GossipPayload::try_from(v1).unwrap();
+        assert_eq!(payload.payload.data, br#"{"value":42.0}"#);
+        payload.payload.data = br#"{"value":42}"#.to_vec();
+        assert_eq!(payload.to_v1(), None);
+
+        let non_finite = GossipPayloadV1 {
+            telemetry: TelemetryV1 { timestamp_ms: 1000, value: f64::NAN },
+            community_id: 1,
+            version: Version { epoch: 1, sequence: 7 },
+        };
+        assert!(GossipPayload::try_from(non_finite).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct FeatureFlags {
+        dark_mode: bool,
+    }
+
+    impl AppPayload for FeatureFlags {
+        const KIND: &'static str = "feature_flags";
+    }
+
+    #[test]
+    fn payloads_decode_only_as_their_kind() {
+        let payload = Payload::new(&FeatureFlags { dark_mode: true });
+        assert_eq!(payload.decode::<FeatureFlags>().unwrap(), FeatureFlags { dark_mode: true });
+        assert!(payload.decode::<TelemetryData>().is_err());
+        assert_eq!(payload.to_json(), serde_json::json!({ "dark_mode": true }));
+    }
+
+    #[test]
+    fn registry_accepts_only_registered_kinds_with_valid_data() {
+        let flags = Payload::new(&FeatureFlags { dark_mode: false });
+        let telemetry = Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(1.0, "percent")));
+        let default = PayloadRegistry::default();
+        assert!(default.validate(&telemetry).is_ok());
+        assert!(default.validate(&flags).is_err());
+
+        let registry = PayloadRegistry::empty().register::<FeatureFlags>();
+        assert_eq!(registry.kinds(), vec!["feature_flags"]);
+        assert!(registry.validate(&flags).is_ok());
+        assert!(registry.validate(&telemetry).is_err());
+        let garbled = Payload { kind: FeatureFlags::KIND.to_string(), data: b"{\"dark_mode\": 1}".to_vec() };
+        assert!(registry.validate(&garbled).is_err());
+    }
+
+    #[test]
+    fn telemetry_metrics_round_trip_with_units_and_labels() {
+        let telemetry = TelemetryData::default()
+            .with_metric("cpu", Metric::new(37.5, "percent"))
+            .with_metric("latency_p99", Metric::new(12.0, "ms").with_label("route", "/api"));
+        let payload = Payload::new(&telemetry);
+        assert_eq!(payload.decode::<TelemetryData>().unwrap(), telemetry);
+        assert_eq!(
+            payload.to_json(),
+            serde_json::json!({ "metrics": {
+                "cpu": { "value": 37.5, "unit": "percent" },
+                "latency_p99": { "value": 12.0, "unit": "ms", "labels": { "route": "/api" } },
+            }})
+        );
+    }
+
+    #[test]
+    fn registry_rejects_telemetry_over_its_bounds() {
+        let registry = PayloadRegistry::default();
+        let metrics = |count: usize| {
+            (0..count).fold(TelemetryData::default(), |telemetry, i| {
+                telemetry.with_metric(format!("metric_{}", i), Metric::new(i as f64, "count"))
+            })
+        };
+        assert!(registry.validate(&Payload::new(&metrics(TelemetryData::MAX_METRICS))).is_ok());
+        assert!(registry.validate(&Payload::new(&metrics(TelemetryData::MAX_METRICS + 1))).is_err());
+
+        let long = "x".repeat(TelemetryData::MAX_NAME_LEN + 1);
+        for metric in [
+            Metric::new(1.0, long.as_str()),
+            Metric::new(1.0, "ms").with_label(long.as_str(), "value"),
+            Metric::new(1.0, "ms").with_label("key", long.as_str()),
+            (0..=TelemetryData::MAX_LABELS).fold(Metric::new(1.0, "ms"), |metric, i| metric.with_label(i.to_string(), "v")),
+        ] {
+            let telemetry = TelemetryData::default().with_metric("cpu", metric);
+            assert!(registry.validate(&Payload::new(&telemetry)).is_err());
+        }
+        let unnamed = TelemetryData::default().with_metric("", Metric::new(1.0, "ms"));
+        assert!(registry.validate(&Payload::new(&unnamed)).is_err());
+    }
+
+    #[test]
+    fn telemetry_rejects_non_finite_values() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let telemetry = TelemetryData::default().with_metric("cpu", Metric::new(value, "percent"));
+            assert!(telemetry.validate().is_err());
+            // They would be encoded as `null`, which does not decode as a value.
+            assert!(PayloadRegistry::default().validate(&Payload::new(&telemetry)).is_err());
+        }
+    }
+
+    #[test]
+    fn telemetry_decodes_single_value_records_of_earlier_releases() {
+        let payload = Payload { kind: TelemetryData::KIND.to_string(), data: br#"{"value":42.5}"#.to_vec() };
+        assert!(PayloadRegistry::default().validate(&payload).is_ok());
+        assert_eq!(
+            payload.decode::<TelemetryData>().unwrap(),
+            TelemetryData::default().with_metric(TelemetryData::LEGACY_METRIC, Metric::new(42.5, ""))
+        );
+    }
+
+    #[test]
+    fn telemetry_rejects_records_of_neither_shape() {
+        for data in [&br#"{}"#[..], br#"{"cpu":1.0}"#, br#"{"value":"high"}"#, br#"{"metrics":[]}"#] {
+            let payload = Payload { kind: TelemetryData::KIND.to_string(), data: data.to_vec() };
+            assert!(PayloadRegistry::default().validate(&payload).is_err(), "{}", String::from_utf8_lossy(data));
+        }
+    }
+
+    #[test]
+    fn payloads_over_the_size_bounds_are_rejected_whatever_their_kind() {
+        let registry = PayloadRegistry::empty().register::<FeatureFlags>();
+        let mut padded = serde_json::to_vec(&FeatureFlags { dark_mode: true }).unwrap();
+        padded.resize(MAX_PAYLOAD_DATA_LEN, b' ');
+        let payload = Payload { kind: FeatureFlags::KIND.to_string(), data: padded };
+        assert!(registry.validate(&payload).is_ok());
+
+        let mut oversized = payload.clone();
+        oversized.data.push(b' ');
+        assert!(oversized.check_size().is_err());
+        assert!(registry.validate(&oversized).is_err());
+
+        let long_kind = Payload { kind: "k".repeat(MAX_PAYLOAD_KIND_LEN + 1), data: b"{}".to_vec() };
+        assert!(long_kind.check_size().is_err());
+    }
+
+    #[test]
+    fn version_orders_by_epoch_before_sequence() {
+        let before_restart = Version { epoch: 1, sequence: 500 };
+        let after_restart = Version { epoch: 2, sequence: 0 };
+
+        assert!(after_restart > before_restart);
+        assert!(Version { epoch: 2, sequence: 1 } > after_restart);
+    }
+
+    #[test]
+    fn identity_from_file_persists_key_and_advances_epoch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let key_path = temp_dir.path().join("identity.key");
+
+        let first = Identity::from_file(&key_path).unwrap();
+        let second = Identity::from_file(&key_path).unwrap();
+
+        assert_eq!(first.node_id, second.node_id);
+        assert_eq!(first.epoch(), 1);
+        assert_eq!(second.epoch(), 2);
+        assert_eq!(fs::read_to_string(temp_dir.path().join("identity.epoch")).unwrap(), "2");
+    }
+
+    #[test]
+    fn identity_from_file_ignores_leftovers_of_an_interrupted_epoch_write() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let key_path = temp_dir.path().join("identity.key");
+        Identity::from_file(&key_path).unwrap();
+
+        // A crash between writing the temporary file and renaming it leaves the
+        // previous epoch intact.
+        let temp_path = temp_dir.path().join("identity.epoch.tmp");
+        fs::write(&temp_path, "").unwrap();
+
+        assert_eq!(Identity::from_file(&key_path).unwrap().epoch(), 2);
+        assert!(!temp_path.exists());
+    }
+
+    #[test]
+    fn message_id_is_stable_and_distinguishes_messages() {
+        let peer = TestPeer::new();
+        let message = peer.sign(1000);
+
+        assert_eq!(message.id(), message.clone().id());
+        assert_ne!(message.id(), peer.sign(2000).id());
+    }
+
+    #[test]
+    fn peer_advertisement_verification_rejects_forged_addresses() {
+        let peer = TestPeer::new();
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let advertisement = peer.identity.advertise(vec![addr], Version { epoch: 1, sequence: 0 });
+        assert!(advertisement.verify().is_ok());
+
+        // Redirecting the advertised node to another address invalidates it.
+        let mut forged = advertisement.clone();
+        forged.advertisement.listen_addrs = vec!["10.0.0.1:5000".parse().unwrap()];
+        assert!(forged.verify().is_err());
+
+        // As does attributing the addresses to another node.
+        let mut forged = advertisement;
+        forged.advertisement.node_id = TestPeer::new().identity.node_id;
+        assert!(forged.verify().is_err());
+    }
+
+    #[test]
+    fn version_1_peer_advertisements_are_accepted_only_in_compatibility_mode() {
+        let peer = TestPeer::new();
+        let mut advertisement = peer.identity.advertise(vec!["127.0.0.1:5000".parse().unwrap()], Version { epoch: 1, sequence: 0 });
+        assert_eq!(advertisement.verify_with(true).unwrap(), SigningEncoding::Canonical);
+
+        advertisement.signature = peer.identity.sign_bytes(&advertisement.advertisement.legacy_signing_bytes());
+        assert!(advertisement.verify_with(false).is_err());
+        assert_eq!(advertisement.verify_with(true).unwrap(), SigningEncoding::LegacyBincode);
+    }
+
     #[test]
     fn signature_verification_fails_for_corrupted_signature() {
         let peer = TestPeer::new();
@@ -212,4 +1250,14 @@ mod tests {
 
         assert!(message.verify().is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ban_target_parses_addresses_and_node_ids() {
+        let node_id = TestPeer::new().identity.node_id;
+        assert_eq!(hex::encode(node_id.0).parse(), Ok(BanTarget::Node(node_id)));
+        assert_eq!("10.0.0.1".parse(), Ok(BanTarget::Addr([10, 0, 0, 1].into())));
+        assert_eq!("::1".parse(), Ok(BanTarget::Addr(std::net::Ipv6Addr::LOCALHOST.into())));
+        assert!("abcd".parse::<BanTarget>().is_err());
+        assert!("10.0.0.1:5000".parse::<BanTarget>().is_err());
+    }
+}
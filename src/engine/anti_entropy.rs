@@ -0,0 +1,127 @@
+//! src/engine/anti_entropy.rs
+//!
+//! Implements push-pull anti-entropy. Periodically, a node sends a digest of
+//! the latest version it holds for every originator and payload kind to a
+//! random peer, which
+//! replies with the signed messages the requester is missing or behind on.
+//! This repairs state that push gossip failed to deliver, e.g. to a node that
+//! joined late or was partitioned.
+
+use crate::{
+    domain::{NodeId, NodeRecords, Version},
+    transport::RelayedMessage,
+};
+use std::collections::HashMap;
+
+/// The maximum number of messages carried by a single sync reply, keeping each
+/// reply well below the transport's message size limit.
+pub const MAX_MESSAGES_PER_REPLY: usize = 256;
+
+/// Summarizes the latest version held for each originator and payload kind.
+pub fn digest(latest: &HashMap<NodeId, NodeRecords>) -> Vec<(NodeId, String, Version)> {
+    latest
+        .iter()
+        .flat_map(|(id, records)| records.iter().map(|(kind, record)| (*id, kind.clone(), record.message.message.version)))
+        .collect()
+}
+
+/// Returns the messages in `latest` that are absent from `digest`, or newer
+/// than the version it lists, split into batches of at most
+/// `MAX_MESSAGES_PER_REPLY` messages.
+pub fn missing_from(
+    latest: &HashMap<NodeId, NodeRecords>,
+    digest: &[(NodeId, String, Version)],
+) -> Vec<Vec<RelayedMessage>> {
+    let known: HashMap<(&NodeId, &str), &Version> =
+        digest.iter().map(|(id, kind, version)| ((id, kind.as_str()), version)).collect();
+    let missing: Vec<RelayedMessage> = latest
+        .iter()
+        .flat_map(|(id, records)| records.iter().map(move |(kind, record)| (id, kind, record)))
+        .filter(|(id, kind, record)| {
+            known
+                .get(&(*id, kind.as_str()))
+                .is_none_or(|version| record.message.message.version > **version)
+        })
+        .map(|(_, _, record)| RelayedMessage {
+            message: record.message.clone(),
+            hops: record.hops,
+            status_updates: Vec::new(),
+        })
+        .collect();
+    missing
+        .chunks(MAX_MESSAGES_PER_REPLY)
+        .map(<[RelayedMessage]>::to_vec)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{AppPayload, GossipPayload, Identity, MessageRecord, Payload, TelemetryData};
+
+    fn create_message(identity: &Identity, sequence: u64) -> MessageRecord {
+        let message = identity.sign(GossipPayload {
+            timestamp_ms: 0,
+            community_id: 0,
+            version: Version { epoch: 1, sequence },
+            payload: Payload::new(&TelemetryData::default()),
+        });
+        MessageRecord { message, received_at_ms: 0, from_peer: identity.node_id, hops: 1 }
+    }
+
+    fn create_records(identity: &Identity, sequence: u64) -> NodeRecords {
+        [(TelemetryData::KIND.to_string(), create_message(identity, sequence))].into_iter().collect()
+    }
+
+    fn entry(identity: &Identity, kind: &str, sequence: u64) -> (NodeId, String, Version) {
+        (identity.node_id, kind.to_string(), Version { epoch: 1, sequence })
+    }
+
+    #[test]
+    fn test_missing_from_returns_unknown_and_newer_messages() {
+        let (a, b, c, d) = (Identity::new(), Identity::new(), Identity::new(), Identity::new());
+        let latest: HashMap<_, _> = [&a, &b, &c, &d]
+            .into_iter()
+            .map(|identity| (identity.node_id, create_records(identity, 5)))
+            .collect();
+
+        // The requester is behind on A, up to date on B, ahead on C, and has
+        // only heard of another kind of payload from D.
+        let requester_digest = vec![
+            entry(&a, TelemetryData::KIND, 4),
+            entry(&b, TelemetryData::KIND, 5),
+            (c.node_id, TelemetryData::KIND.to_string(), Version { epoch: 2, sequence: 0 }),
+            entry(&d, "feature_flags", 9),
+        ];
+
+        let batches = missing_from(&latest, &requester_digest);
+        assert_eq!(batches.len(), 1);
+        let mut originators: Vec<_> = batches[0].iter().map(|m| m.message.originator.0).collect();
+        originators.sort();
+        let mut expected = vec![a.node_id.0, d.node_id.0];
+        expected.sort();
+        assert_eq!(originators, expected);
+    }
+
+    #[test]
+    fn test_missing_from_is_empty_for_identical_state() {
+        let identity = Identity::new();
+        let latest: HashMap<_, _> = [(identity.node_id, create_records(&identity, 0))].into_iter().collect();
+        assert!(missing_from(&latest, &digest(&latest)).is_empty());
+    }
+
+    #[test]
+    fn test_missing_from_splits_large_replies() {
+        let latest: HashMap<_, _> = (0..MAX_MESSAGES_PER_REPLY + 1)
+            .map(|_| {
+                let identity = Identity::new();
+                (identity.node_id, create_records(&identity, 0))
+            })
+            .collect();
+
+        let batches = missing_from(&latest, &[]);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), MAX_MESSAGES_PER_REPLY);
+        assert_eq!(batches[1].len(), 1);
+    }
+}
@@ -0,0 +1,559 @@
+//! src/engine/membership.rs
+//!
+//! Implements HyParView-style partial view membership. Each node keeps a small
+//! active view of neighbours that gossip is exchanged with, and a larger
+//! passive view of backup peers. Joins are spread through the network with
+//! random walks (FORWARDJOIN), passive views are refreshed by periodic
+//! shuffles, and a neighbour that leaves the active view is replaced by a
+//! peer promoted from the passive view.
+//!
+//! Addresses learned from other peers are unauthenticated hints. A peer is
+//! only trusted to be who it claims once a connection handshake verifies it,
+//! so peers found by a random walk or promoted from the passive view are kept
+//! pending, and only join the active view once they have authenticated.
+
+use crate::{domain::NodeId, transport::MembershipMessage};
+use rand::{
+    seq::{IteratorRandom, SliceRandom},
+    thread_rng,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+};
+
+/// The number of hops a FORWARDJOIN travels before the joining node is
+/// added to an active view.
+pub const ACTIVE_RANDOM_WALK_LENGTH: u32 = 6;
+/// The remaining hop count at which a FORWARDJOIN also adds the joining node
+/// to the passive view. Also the number of hops a SHUFFLE travels.
+pub const PASSIVE_RANDOM_WALK_LENGTH: u32 = 3;
+/// The number of active peers included in a shuffle.
+const SHUFFLE_ACTIVE_PEERS: usize = 3;
+/// The number of passive peers included in a shuffle.
+const SHUFFLE_PASSIVE_PEERS: usize = 4;
+
+/// Membership messages to send, with the address of each recipient.
+pub type Outbox = Vec<(SocketAddr, MembershipMessage)>;
+
+/// The active and passive views of one node.
+#[derive(Debug)]
+pub struct Membership {
+    self_id: NodeId,
+    // The address peers can dial this node at, if it has one worth advertising.
+    self_addr: Option<SocketAddr>,
+    active: HashMap<NodeId, SocketAddr>,
+    passive: HashMap<NodeId, SocketAddr>,
+    // Peers asked to become neighbours that have not authenticated yet.
+    pending: HashMap<NodeId, SocketAddr>,
+    // Peers with at least one authenticated connection.
+    authenticated: HashSet<NodeId>,
+    active_capacity: usize,
+    passive_capacity: usize,
+}
+
+impl Membership {
+    pub fn new(self_id: NodeId, self_addr: Option<SocketAddr>, active_capacity: usize, passive_capacity: usize) -> Self {
+        Self {
+            self_id,
+            self_addr,
+            active: HashMap::new(),
+            passive: HashMap::new(),
+            pending: HashMap::new(),
+            authenticated: HashSet::new(),
+            active_capacity,
+            passive_capacity,
+        }
+    }
+
+    /// The neighbours that gossip is exchanged with.
+    pub fn active_view(&self) -> &HashMap<NodeId, SocketAddr> {
+        &self.active
+    }
+
+    /// The backup peers used to replace neighbours that leave.
+    pub fn passive_view(&self) -> &HashMap<NodeId, SocketAddr> {
+        &self.passive
+    }
+
+    /// Records an authenticated connection. A pending peer becomes a neighbour,
+    /// demoting another if the active view is full. Any other peer becomes a
+    /// neighbour if the active view has room, and a backup otherwise.
+    pub fn on_connected(&mut self, peer: NodeId, addr: SocketAddr) -> Outbox {
+        self.authenticated.insert(peer);
+        if let Some(known) = self.active.get_mut(&peer) {
+            *known = addr;
+        } else if self.pending.remove(&peer).is_some() {
+            return self.add_active(peer, addr);
+        } else if self.active.len() < self.active_capacity {
+            self.passive.remove(&peer);
+            self.active.insert(peer, addr);
+        } else {
+            self.add_passive(peer, addr);
+        }
+        Vec::new()
+    }
+
+    /// Asks `contact` to introduce this node to the network.
+    pub fn join(&self, contact: SocketAddr) -> Outbox {
+        vec![(contact, MembershipMessage::Join)]
+    }
+
+    /// Handles a JOIN: the new node becomes a neighbour and a random walk
+    /// starts from every other neighbour to find it more.
+    pub fn on_join(&mut self, peer: NodeId, addr: SocketAddr) -> Outbox {
+        let mut outbox = self.add_active(peer, addr);
+        let forward_join = MembershipMessage::ForwardJoin {
+            node: peer,
+            addr,
+            ttl: ACTIVE_RANDOM_WALK_LENGTH,
+        };
+        outbox.extend(
+            self.active
+                .iter()
+                .filter(|(id, _)| **id != peer)
+                .map(|(_, &neighbour)| (neighbour, forward_join.clone())),
+        );
+        outbox
+    }
+
+    /// Handles one step of a FORWARDJOIN random walk received from `sender`.
+    pub fn on_forward_join(&mut self, sender: NodeId, node: NodeId, addr: SocketAddr, ttl: u32) -> Outbox {
+        if node == self.self_id {
+            return Vec::new();
+        }
+        if ttl == PASSIVE_RANDOM_WALK_LENGTH {
+            self.add_passive(node, addr);
+        }
+        let next_hop = self
+            .active
+            .iter()
+            .filter(|(id, _)| **id != sender && **id != node)
+            .choose(&mut thread_rng())
+            .map(|(_, &neighbour)| neighbour);
+        match next_hop {
+            Some(neighbour) if ttl > 0 && self.active.len() > 1 => {
+                let forward_join = MembershipMessage::ForwardJoin { node, addr, ttl: ttl - 1 };
+                vec![(neighbour, forward_join)]
+            }
+            // The walk ends here: the joining node is asked to add this node,
+            // and becomes a neighbour once its connection authenticates.
+            _ if self.active.contains_key(&node) => Vec::new(),
+            _ => {
+                let mut outbox = self.add_pending(node, addr);
+                outbox.push((addr, MembershipMessage::Neighbor { high_priority: true }));
+                outbox
+            }
+        }
+    }
+
+    /// Handles a NEIGHBOR request. A high priority request comes from a node
+    /// with no neighbours left and is always accepted; otherwise the request is
+    /// refused with a DISCONNECT when the active view is full.
+    pub fn on_neighbor(&mut self, peer: NodeId, addr: SocketAddr, high_priority: bool) -> Outbox {
+        if high_priority || self.active.contains_key(&peer) || self.active.len() < self.active_capacity {
+            self.add_active(peer, addr)
+        } else {
+            self.add_passive(peer, addr);
+            vec![(addr, MembershipMessage::Disconnect)]
+        }
+    }
+
+    /// Handles a DISCONNECT: the peer is demoted to the passive view and
+    /// replaced by another backup.
+    pub fn on_disconnect(&mut self, peer: NodeId) -> Outbox {
+        match self.active.remove(&peer) {
+            Some(addr) => {
+                let outbox = self.heal();
+                self.add_passive(peer, addr);
+                outbox
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Handles the loss of every connection to a peer. A failed neighbour is
+    /// dropped rather than kept as a backup, and replaced from the passive view.
+    pub fn on_peer_lost(&mut self, peer: NodeId) -> Outbox {
+        self.authenticated.remove(&peer);
+        if self.active.remove(&peer).is_some() || self.pending.remove(&peer).is_some() {
+            self.heal()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Drops neighbours for which `is_connected` is false, and pending peers
+    /// that have still not authenticated, and replaces them from the passive
+    /// view.
+    pub fn retain_connected(&mut self, is_connected: impl Fn(&NodeId) -> bool) -> Outbox {
+        let before = self.active.len() + self.pending.len();
+        self.active.retain(|id, _| is_connected(id));
+        self.authenticated.retain(|id| is_connected(id));
+        self.pending.clear();
+        let mut outbox = Vec::new();
+        for _ in self.active.len()..before {
+            outbox.extend(self.heal());
+        }
+        outbox
+    }
+
+    /// Asks a random backup to become a neighbour, if there is room. It joins
+    /// the active view once its connection authenticates.
+    fn heal(&mut self) -> Outbox {
+        if self.active.len() + self.pending.len() >= self.active_capacity {
+            return Vec::new();
+        }
+        let Some((&peer, &addr)) = self.passive.iter().choose(&mut thread_rng()) else {
+            return Vec::new();
+        };
+        let high_priority = self.active.is_empty();
+        let mut outbox = self.add_pending(peer, addr);
+        outbox.push((addr, MembershipMessage::Neighbor { high_priority }));
+        outbox
+    }
+
+    /// Starts a shuffle with a random neighbour, offering a sample of both views.
+    /// A node without an address to advertise cannot be answered by the end of
+    /// the walk, and so does not shuffle.
+    pub fn start_shuffle(&self) -> Outbox {
+        let mut rng = thread_rng();
+        let Some(self_addr) = self.self_addr else {
+            return Vec::new();
+        };
+        let Some((_, &target)) = self.active.iter().choose(&mut rng) else {
+            return Vec::new();
+        };
+        let mut peers = vec![(self.self_id, self_addr)];
+        peers.extend(
+            self.active
+                .iter()
+                .map(|(&id, &addr)| (id, addr))
+                .choose_multiple(&mut rng, SHUFFLE_ACTIVE_PEERS),
+        );
+        peers.extend(
+            self.passive
+                .iter()
+                .map(|(&id, &addr)| (id, addr))
+                .choose_multiple(&mut rng, SHUFFLE_PASSIVE_PEERS),
+        );
+        let shuffle = MembershipMessage::Shuffle {
+            origin: self.self_id,
+            origin_addr: self_addr,
+            ttl: PASSIVE_RANDOM_WALK_LENGTH,
+            peers,
+        };
+        vec![(target, shuffle)]
+    }
+
+    /// Handles a SHUFFLE received from `sender`. The shuffle is forwarded until
+    /// its walk ends, and the final node answers the origin with a sample of
+    /// its passive view of the same size.
+    pub fn on_shuffle(
+        &mut self,
+        sender: NodeId,
+        origin: NodeId,
+        origin_addr: SocketAddr,
+        ttl: u32,
+        peers: Vec<(NodeId, SocketAddr)>,
+    ) -> Outbox {
+        if ttl > 0 && self.active.len() > 1 {
+            let next_hop = self
+                .active
+                .iter()
+                .filter(|(id, _)| **id != sender && **id != origin)
+                .choose(&mut thread_rng());
+            if let Some((_, &neighbour)) = next_hop {
+                let shuffle = MembershipMessage::Shuffle { origin, origin_addr, ttl: ttl - 1, peers };
+                return vec![(neighbour, shuffle)];
+            }
+        }
+        if origin == self.self_id {
+            return Vec::new();
+        }
+
+        let mut reply: Vec<_> = self.passive.iter().map(|(&id, &addr)| (id, addr)).collect();
+        reply.shuffle(&mut thread_rng());
+        reply.truncate(peers.len());
+        self.integrate(peers);
+        vec![(origin_addr, MembershipMessage::ShuffleReply { peers: reply })]
+    }
+
+    /// Handles the answer to a shuffle this node started.
+    pub fn on_shuffle_reply(&mut self, peers: Vec<(NodeId, SocketAddr)>) {
+        self.integrate(peers);
+    }
+
+    /// Adds peers learned from a shuffle to the passive view.
+    fn integrate(&mut self, peers: Vec<(NodeId, SocketAddr)>) {
+        for (id, addr) in peers {
+            self.add_passive(id, addr);
+        }
+    }
+
+    /// Marks `peer` as asked to become a neighbour, evicting a random pending
+    /// peer if as many are pending as the active view holds. A peer that has
+    /// already authenticated becomes a neighbour right away.
+    fn add_pending(&mut self, peer: NodeId, addr: SocketAddr) -> Outbox {
+        if self.authenticated.contains(&peer) {
+            return self.add_active(peer, addr);
+        }
+        if peer == self.self_id || self.active.contains_key(&peer) {
+            return Vec::new();
+        }
+        if !self.pending.contains_key(&peer) && self.pending.len() >= self.active_capacity {
+            if let Some(evicted) = self.pending.keys().copied().choose(&mut thread_rng()) {
+                self.pending.remove(&evicted);
+            }
+        }
+        self.passive.remove(&peer);
+        self.pending.insert(peer, addr);
+        Vec::new()
+    }
+
+    /// Adds an authenticated `peer` to the active view, demoting a random
+    /// neighbour with a DISCONNECT if the view is full.
+    fn add_active(&mut self, peer: NodeId, addr: SocketAddr) -> Outbox {
+        if peer == self.self_id {
+            return Vec::new();
+        }
+        if let Some(known) = self.active.get_mut(&peer) {
+            *known = addr;
+            return Vec::new();
+        }
+        let mut outbox = Vec::new();
+        if self.active.len() >= self.active_capacity {
+            let evicted = self.active.keys().copied().choose(&mut thread_rng());
+            if let Some((evicted, evicted_addr)) = evicted.and_then(|id| self.active.remove_entry(&id)) {
+                self.add_passive(evicted, evicted_addr);
+                outbox.push((evicted_addr, MembershipMessage::Disconnect));
+            }
+        }
+        self.passive.remove(&peer);
+        self.pending.remove(&peer);
+        self.active.insert(peer, addr);
+        outbox
+    }
+
+    /// Adds `peer` to the passive view, evicting a random backup if it is full.
+    fn add_passive(&mut self, peer: NodeId, addr: SocketAddr) {
+        if peer == self.self_id
+            || self.active.contains_key(&peer)
+            || self.pending.contains_key(&peer)
+            || self.passive_capacity == 0
+        {
+            return;
+        }
+        if !self.passive.contains_key(&peer) && self.passive.len() >= self.passive_capacity {
+            if let Some(evicted) = self.passive.keys().copied().choose(&mut thread_rng()) {
+                self.passive.remove(&evicted);
+            }
+        }
+        self.passive.insert(peer, addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_node_id(id: u8) -> NodeId {
+        let mut bytes = [0u8; 32];
+        bytes[0] = id;
+        NodeId(bytes)
+    }
+
+    fn create_addr(id: u8) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 6000 + id as u16))
+    }
+
+    fn create_membership(active: usize, passive: usize) -> Membership {
+        Membership::new(create_node_id(0), Some(create_addr(0)), active, passive)
+    }
+
+    #[test]
+    fn test_join_adds_neighbour_and_forwards_join() {
+        let mut membership = create_membership(3, 10);
+        membership.on_connected(create_node_id(1), create_addr(1));
+        membership.on_connected(create_node_id(2), create_addr(2));
+
+        let outbox = membership.on_join(create_node_id(3), create_addr(3));
+        assert!(membership.active_view().contains_key(&create_node_id(3)));
+        assert_eq!(outbox.len(), 2, "The join should be forwarded to both other neighbours");
+        assert!(outbox.iter().all(|(addr, message)| {
+            *addr != create_addr(3)
+                && *message == MembershipMessage::ForwardJoin {
+                    node: create_node_id(3),
+                    addr: create_addr(3),
+                    ttl: ACTIVE_RANDOM_WALK_LENGTH,
+                }
+        }));
+    }
+
+    #[test]
+    fn test_full_active_view_evicts_with_disconnect() {
+        let mut membership = create_membership(2, 10);
+        membership.on_connected(create_node_id(1), create_addr(1));
+        membership.on_connected(create_node_id(2), create_addr(2));
+        membership.on_connected(create_node_id(3), create_addr(3));
+        assert_eq!(membership.active_view().len(), 2);
+        assert!(membership.passive_view().contains_key(&create_node_id(3)));
+
+        let outbox = membership.on_join(create_node_id(4), create_addr(4));
+        assert_eq!(membership.active_view().len(), 2);
+        assert!(membership.active_view().contains_key(&create_node_id(4)));
+        let disconnects: Vec<_> = outbox
+            .iter()
+            .filter(|(_, message)| *message == MembershipMessage::Disconnect)
+            .collect();
+        assert_eq!(disconnects.len(), 1);
+        assert_eq!(membership.passive_view().len(), 2, "The evicted neighbour should become a backup");
+    }
+
+    #[test]
+    fn test_forward_join_walk_ends_in_active_view() {
+        let mut membership = create_membership(5, 10);
+        membership.on_connected(create_node_id(1), create_addr(1));
+        membership.on_connected(create_node_id(2), create_addr(2));
+        let joining = create_node_id(9);
+
+        // With hops left, the walk continues to a neighbour other than the sender.
+        let outbox = membership.on_forward_join(create_node_id(1), joining, create_addr(9), 4);
+        assert_eq!(outbox, vec![(
+            create_addr(2),
+            MembershipMessage::ForwardJoin { node: joining, addr: create_addr(9), ttl: 3 },
+        )]);
+        assert!(!membership.active_view().contains_key(&joining));
+
+        // At the passive walk length, the joining node is also kept as a backup.
+        membership.on_forward_join(create_node_id(1), joining, create_addr(9), PASSIVE_RANDOM_WALK_LENGTH);
+        assert!(membership.passive_view().contains_key(&joining));
+
+        // Once the walk ends, it is asked to become a neighbour...
+        let outbox = membership.on_forward_join(create_node_id(1), joining, create_addr(9), 0);
+        assert!(!membership.active_view().contains_key(&joining));
+        assert!(!membership.passive_view().contains_key(&joining));
+        assert_eq!(outbox, vec![(create_addr(9), MembershipMessage::Neighbor { high_priority: true })]);
+
+        // ...and becomes one when its connection authenticates.
+        assert!(membership.on_connected(joining, create_addr(9)).is_empty());
+        assert!(membership.active_view().contains_key(&joining));
+    }
+
+    #[test]
+    fn test_forward_joins_do_not_fill_the_active_view_with_unauthenticated_peers() {
+        let mut membership = create_membership(2, 10);
+        membership.on_connected(create_node_id(1), create_addr(1));
+        membership.on_connected(create_node_id(2), create_addr(2));
+
+        // A neighbour ends walks for many made-up nodes here.
+        for i in 10..50 {
+            membership.on_forward_join(create_node_id(1), create_node_id(i), create_addr(i), 0);
+        }
+        let active: Vec<_> = membership.active_view().keys().copied().collect();
+        assert_eq!(active.len(), 2);
+        assert!(active.contains(&create_node_id(1)) && active.contains(&create_node_id(2)));
+        assert!(membership.pending.len() <= 2);
+
+        // A pending node that authenticates displaces a neighbour, as a walk
+        // ending here always adds the joining node.
+        let pending = *membership.pending.keys().next().unwrap();
+        let outbox = membership.on_connected(pending, create_addr(99));
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox[0].1, MembershipMessage::Disconnect);
+        assert!(membership.active_view().contains_key(&pending));
+
+        // Pending nodes that never authenticate are given up.
+        membership.retain_connected(|_| true);
+        assert!(membership.pending.is_empty());
+    }
+
+    #[test]
+    fn test_neighbor_request_is_refused_when_full_unless_high_priority() {
+        let mut membership = create_membership(1, 10);
+        membership.on_connected(create_node_id(1), create_addr(1));
+
+        let outbox = membership.on_neighbor(create_node_id(2), create_addr(2), false);
+        assert_eq!(outbox, vec![(create_addr(2), MembershipMessage::Disconnect)]);
+        assert!(!membership.active_view().contains_key(&create_node_id(2)));
+
+        membership.on_neighbor(create_node_id(2), create_addr(2), true);
+        assert!(membership.active_view().contains_key(&create_node_id(2)));
+        assert_eq!(membership.active_view().len(), 1);
+    }
+
+    #[test]
+    fn test_lost_neighbour_is_replaced_from_passive_view() {
+        let mut membership = create_membership(1, 10);
+        membership.on_connected(create_node_id(1), create_addr(1));
+        membership.on_connected(create_node_id(2), create_addr(2));
+        assert!(membership.passive_view().contains_key(&create_node_id(2)));
+
+        let outbox = membership.on_peer_lost(create_node_id(1));
+        assert_eq!(membership.active_view().keys().collect::<Vec<_>>(), vec![&create_node_id(2)]);
+        assert!(membership.passive_view().is_empty(), "A failed neighbour should not become a backup");
+        assert_eq!(outbox, vec![(create_addr(2), MembershipMessage::Neighbor { high_priority: true })]);
+
+        // A backup only learned from a shuffle is promoted once it authenticates.
+        membership.on_shuffle_reply(vec![(create_node_id(3), create_addr(3))]);
+        let outbox = membership.on_peer_lost(create_node_id(2));
+        assert_eq!(outbox, vec![(create_addr(3), MembershipMessage::Neighbor { high_priority: true })]);
+        assert!(membership.active_view().is_empty());
+        membership.on_connected(create_node_id(3), create_addr(3));
+        assert_eq!(membership.active_view().keys().collect::<Vec<_>>(), vec![&create_node_id(3)]);
+    }
+
+    #[test]
+    fn test_disconnected_neighbour_becomes_backup() {
+        let mut membership = create_membership(2, 10);
+        membership.on_connected(create_node_id(1), create_addr(1));
+        membership.on_connected(create_node_id(2), create_addr(2));
+
+        let outbox = membership.on_disconnect(create_node_id(1));
+        assert!(outbox.is_empty(), "There is no other backup to promote");
+        assert!(!membership.active_view().contains_key(&create_node_id(1)));
+        assert!(membership.passive_view().contains_key(&create_node_id(1)));
+    }
+
+    #[test]
+    fn test_shuffle_exchanges_passive_peers() {
+        let mut origin = create_membership(2, 10);
+        origin.on_connected(create_node_id(1), create_addr(1));
+        let outbox = origin.start_shuffle();
+        let [(target, MembershipMessage::Shuffle { origin: origin_id, origin_addr, ttl, peers })] = &outbox[..] else {
+            panic!("Expected a single shuffle, got {outbox:?}");
+        };
+        assert_eq!(*target, create_addr(1));
+        assert!(peers.contains(&(create_node_id(0), create_addr(0))), "The origin should offer itself");
+
+        // The receiver has a single neighbour, so the walk ends there.
+        let mut receiver = Membership::new(create_node_id(1), Some(create_addr(1)), 1, 10);
+        receiver.on_connected(create_node_id(0), create_addr(0));
+        receiver.on_connected(create_node_id(5), create_addr(5));
+        receiver.on_connected(create_node_id(6), create_addr(6));
+        let reply = receiver.on_shuffle(create_node_id(0), *origin_id, *origin_addr, *ttl, peers.clone());
+        let [(reply_addr, MembershipMessage::ShuffleReply { peers: offered })] = &reply[..] else {
+            panic!("Expected a shuffle reply, got {reply:?}");
+        };
+        assert_eq!(*reply_addr, create_addr(0));
+        assert!(!offered.is_empty());
+
+        origin.on_shuffle_reply(offered.clone());
+        assert!(offered.iter().all(|(id, _)| {
+            *id == create_node_id(0) || origin.passive_view().contains_key(id) || origin.active_view().contains_key(id)
+        }));
+        // A node with no address to advertise does not shuffle.
+        let mut unreachable = Membership::new(create_node_id(7), None, 2, 10);
+        unreachable.on_connected(create_node_id(1), create_addr(1));
+        assert!(unreachable.start_shuffle().is_empty());
+    }
+
+    #[test]
+    fn test_passive_view_is_bounded() {
+        let mut membership = create_membership(1, 3);
+        let peers = (1..=10).map(|i| (create_node_id(i), create_addr(i))).collect();
+        membership.on_shuffle_reply(peers);
+        assert_eq!(membership.passive_view().len(), 3);
+        assert!(!membership.passive_view().contains_key(&create_node_id(0)));
+    }
+}
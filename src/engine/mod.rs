@@ -1,23 +1,51 @@
 //! src/engine/mod.rs
 //!
 //! Defines the `Engine`, the core application logic service. It maintains
-//! network state, generates telemetry, and applies the gossip protocol.
+//! network state, gossips this node's application payload, and applies the
+//! gossip protocol.
 
 use crate::{
-    config::Config,
-    domain::{GossipPayload, Identity, NetworkState, NodeId, NodeInfo, SignedMessage, TelemetryData},
-    transport::{ConnectionEvent, InboundMessage, TransportCommand},
+    config::{Config, Propagation},
+    domain::{
+        latest_record, Ban, BanReason, EngineStats, GossipPayload, Identity, MemberStatus, MessageRecord, NetworkState,
+        NodeHealth, NodeId, NodeInfo, NodeRecords, Payload, PayloadRegistry, PersistentPeer, SignedMessage,
+        SignedPeerAdvertisement, SigningEncoding, Version,
+    },
+    transport::{
+        Announcement, ConnectionEvent, ControlFrame, Direction, InboundMessage, MembershipMessage,
+        ProbeMessage, RelayedMessage, TransportCommand, WireMessage,
+    },
 };
+use rand::seq::IteratorRandom;
 use std::{
-    collections::{HashMap, HashSet},
-    net::SocketAddr,
+    collections::{hash_map::Entry, HashMap},
+    net::{IpAddr, SocketAddr},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tokio::sync::{broadcast, mpsc, watch};
-use tokio::time;
+use tokio::time::{self, Instant};
 use tokio_util::sync::CancellationToken;
 
+pub mod anti_entropy;
+pub mod membership;
+pub mod payload;
+pub mod pex;
+pub mod phi_accrual;
+pub mod plumtree;
 pub mod protocol;
+pub mod seen_cache;
+pub mod swim;
+pub mod validation;
+
+use membership::{Membership, Outbox};
+use payload::{PayloadSource, SyntheticTelemetry};
+use pex::PeerExchange;
+use phi_accrual::PhiAccrual;
+use plumtree::Plumtree;
+use protocol::{GossipStrategy, PeerView};
+use seen_cache::SeenCache;
+use swim::Swim;
+use validation::{Rejection, TimestampWindow};
 
 /// The core application logic actor.
 pub struct Engine {
@@ -26,10 +54,48 @@ pub struct Engine {
     gossip_interval: Duration,
     // NEW: Use a duration for the cleanup interval.
     cleanup_interval: Duration,
+    anti_entropy_interval: Duration,
+    shuffle_interval: Duration,
     node_ttl: Duration,
-    node_info: HashMap<crate::domain::NodeId, NodeInfo>,
+    timestamp_window: TimestampWindow,
+    // The latest accepted message per originator and payload kind, including our own.
+    records: HashMap<NodeId, NodeRecords>,
+    // Local receive times of accepted updates per node, used for pruning so
+    // that liveness does not depend on the originator's clock.
+    arrivals: PhiAccrual,
+    // Sequence number for the next message this node originates.
+    next_sequence: u64,
+    // Addresses of peers whose identity was verified by a connection handshake.
     known_peers: HashMap<crate::domain::NodeId, SocketAddr>,
-    active_peer_addrs: HashSet<SocketAddr>,
+    // Number of live authenticated connections per peer.
+    active_peers: HashMap<NodeId, usize>,
+    // Number of live connections this node dialed, which PEX keeps topped up.
+    outbound_connections: usize,
+    // The transport's connection state for each persistent peer, by address.
+    persistent_peers: HashMap<SocketAddr, PersistentPeer>,
+    // The bans the transport enforces, as last reported by it.
+    bans: Vec<Ban>,
+    // The active and passive views. Gossip is only exchanged with the active view.
+    membership: Membership,
+    // The SWIM failure detector's view of which nodes are alive.
+    swim: Swim,
+    // When each peer's last sync digest was answered. A reply can carry the
+    // whole state, so peers get at most one per half anti-entropy interval.
+    digest_replies: HashMap<NodeId, Instant>,
+    // Advertised peers that can be dialed when short of connections.
+    pex: PeerExchange,
+    // Sequence number for the next advertisement of this node's addresses.
+    next_advertisement: u64,
+    seen_cache: SeenCache,
+    // Chooses the peers each message is forwarded to.
+    strategy: Box<dyn GossipStrategy>,
+    // Produce the payloads gossiped about this node every round, one kind each.
+    payload_sources: Vec<Box<dyn PayloadSource>>,
+    // The payload kinds accepted from other nodes.
+    payloads: PayloadRegistry,
+    // The broadcast tree state; present only in Plumtree mode.
+    plumtree: Option<Plumtree>,
+    stats: EngineStats,
     inbound_rx: mpsc::Receiver<InboundMessage>,
     conn_event_rx: mpsc::Receiver<ConnectionEvent>,
     transport_tx: mpsc::Sender<TransportCommand>,
@@ -51,12 +117,50 @@ impl Engine {
             gossip_interval: Duration::from_millis(config.gossip_interval_ms),
             // MODIFICATION: Use configurable cleanup interval.
             cleanup_interval: Duration::from_millis(config.cleanup_interval_ms),
+            anti_entropy_interval: Duration::from_millis(config.anti_entropy_interval_ms),
+            shuffle_interval: Duration::from_millis(config.shuffle_interval_ms),
             node_ttl: Duration::from_millis(config.node_ttl_ms),
+            timestamp_window: TimestampWindow {
+                max_future_skew_ms: config.max_future_skew_ms,
+                max_past_skew_ms: config.max_past_skew_ms,
+            },
+            seen_cache: SeenCache::new(
+                config.seen_cache_capacity,
+                Duration::from_millis(config.seen_cache_ttl_ms),
+            ),
+            membership: Membership::new(
+                identity.node_id,
+                Self::dialable_addr(&config.listen_addrs()),
+                config.active_view_size,
+                config.passive_view_size,
+            ),
+            swim: Swim::new(
+                identity.node_id,
+                Version { epoch: identity.epoch(), sequence: 0 },
+                Duration::from_millis(config.swim_probe_timeout_ms),
+                Duration::from_millis(config.swim_suspicion_timeout_ms),
+                config.swim_indirect_probes,
+            ),
+            digest_replies: HashMap::new(),
+            pex: PeerExchange::new(identity.node_id, config.pex_candidate_pool_size)
+                .with_legacy_signatures(config.accept_legacy_signatures),
+            next_advertisement: 0,
+            strategy: protocol::strategy_from_config(&config),
+            payload_sources: vec![Box::new(SyntheticTelemetry)],
+            payloads: PayloadRegistry::default(),
+            plumtree: (config.propagation == Propagation::Plumtree)
+                .then(|| Plumtree::new(Duration::from_millis(config.plumtree_graft_timeout_ms))),
+            stats: EngineStats::default(),
             identity,
+            arrivals: PhiAccrual::new(Duration::from_millis(config.phi_min_std_deviation_ms)),
             config,
-            node_info: HashMap::new(),
+            records: HashMap::new(),
+            next_sequence: 0,
             known_peers: HashMap::new(),
-            active_peer_addrs: HashSet::new(),
+            active_peers: HashMap::new(),
+            outbound_connections: 0,
+            persistent_peers: HashMap::new(),
+            bans: Vec::new(),
             inbound_rx,
             conn_event_rx,
             transport_tx,
@@ -65,11 +169,64 @@ impl Engine {
         }
     }
 
+    /// Replaces the gossip strategy selected by `Config::peer_selection`.
+    pub fn with_strategy(mut self, strategy: Box<dyn GossipStrategy>) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Replaces the synthetic telemetry gossiped about this node.
+    pub fn with_payload_source(mut self, source: Box<dyn PayloadSource>) -> Self {
+        self.payload_sources = vec![source];
+        self
+    }
+
+    /// Replaces the synthetic telemetry with a payload from each of `sources`
+    /// every round. Each should produce a different kind, as only the latest
+    /// payload of each kind is kept.
+    pub fn with_payload_sources(mut self, sources: Vec<Box<dyn PayloadSource>>) -> Self {
+        self.payload_sources = sources;
+        self
+    }
+
+    /// Replaces the payload kinds accepted from other nodes, `TelemetryData`
+    /// only by default.
+    pub fn with_payload_registry(mut self, payloads: PayloadRegistry) -> Self {
+        self.payloads = payloads;
+        self
+    }
+
     pub async fn run(mut self, shutdown_token: CancellationToken) {
         tracing::info!(node_id = %self.identity.node_id, "Engine service started");
         let mut gossip_timer = time::interval(self.gossip_interval);
         // MODIFICATION: Use the configured duration.
         let mut cleanup_timer = time::interval(self.cleanup_interval);
+        // The first round is delayed by a full period, as there is nobody to
+        // synchronize with before the first connections are made.
+        let mut anti_entropy_timer = time::interval_at(
+            Instant::now() + self.anti_entropy_interval,
+            self.anti_entropy_interval,
+        );
+        let mut shuffle_timer = time::interval_at(
+            Instant::now() + self.shuffle_interval,
+            self.shuffle_interval,
+        );
+        let mut pex_timer = time::interval_at(
+            Instant::now() + Duration::from_millis(self.config.pex_interval_ms),
+            Duration::from_millis(self.config.pex_interval_ms),
+        );
+        let mut probe_timer = time::interval_at(
+            Instant::now() + Duration::from_millis(self.config.swim_probe_interval_ms),
+            Duration::from_millis(self.config.swim_probe_interval_ms),
+        );
+        // Checked at twice the probe timeout rate, for the same reason as grafts below.
+        let mut swim_timer = time::interval(Duration::from_millis(
+            (self.config.swim_probe_timeout_ms / 2).max(1),
+        ));
+        // Checked at twice the graft rate, so a graft is late by at most half a timeout.
+        let mut graft_timer = time::interval(Duration::from_millis(
+            (self.config.plumtree_graft_timeout_ms / 2).max(1),
+        ));
 
         loop {
             tokio::select! {
@@ -78,16 +235,39 @@ impl Engine {
                     break;
                 },
                 _ = gossip_timer.tick() => {
-                    self.gossip_self_telemetry().await;
+                    self.gossip_self_payload().await;
                 },
                 _ = cleanup_timer.tick() => {
-                    self.cleanup_stale_nodes();
+                    self.cleanup_stale_nodes().await;
+                },
+                _ = anti_entropy_timer.tick() => {
+                    self.start_anti_entropy_round().await;
+                },
+                _ = shuffle_timer.tick() => {
+                    let outbox = self.membership.start_shuffle();
+                    self.send_membership_messages(outbox).await;
+                },
+                _ = pex_timer.tick() => {
+                    self.exchange_peers().await;
+                },
+                _ = probe_timer.tick() => {
+                    let outbox = self.swim.start_probe(&self.known_peers, Instant::now());
+                    self.send_probe_messages(outbox).await;
+                    self.apply_status_changes();
+                },
+                _ = swim_timer.tick() => {
+                    let outbox = self.swim.check_timeouts(&self.known_peers, Instant::now());
+                    self.send_probe_messages(outbox).await;
+                    self.apply_status_changes();
+                },
+                _ = graft_timer.tick(), if self.plumtree.is_some() => {
+                    self.send_due_grafts().await;
                 },
                 Some(inbound) = self.inbound_rx.recv() => {
                     self.handle_inbound_message(inbound).await;
                 },
                 Some(event) = self.conn_event_rx.recv() => {
-                    self.handle_connection_event(event);
+                    self.handle_connection_event(event).await;
                 }
                 else => {
                     tracing::info!("Channel closed. Engine service shutting down.");
@@ -97,110 +277,613 @@ impl Engine {
         }
     }
 
-    fn handle_connection_event(&mut self, event: ConnectionEvent) {
+    async fn handle_connection_event(&mut self, event: ConnectionEvent) {
         match event {
-            ConnectionEvent::PeerConnected { peer_addr } => {
-                if self.active_peer_addrs.insert(peer_addr) {
-                    tracing::debug!(peer_addr = %peer_addr, "Peer connection established");
+            ConnectionEvent::PeerConnected { peer_addr, peer_id, listen_addrs, direction } => {
+                if direction == Direction::Outbound {
+                    self.outbound_connections += 1;
+                }
+                // Messages are routed to the address of the latest connection this
+                // node dialed and authenticated, as inbound connections are never
+                // used for sending. The observed address of an inbound connection
+                // is only a fallback for peers with no route yet, and an address
+                // the peer announced is routed to once a dial there authenticates.
+                let rerouted = match direction {
+                    Direction::Outbound => self.known_peers.insert(peer_id, peer_addr) != Some(peer_addr),
+                    Direction::Inbound => match self.known_peers.entry(peer_id) {
+                        Entry::Occupied(_) => false,
+                        Entry::Vacant(entry) => {
+                            entry.insert(peer_addr);
+                            true
+                        }
+                    },
+                };
+                let route = self.known_peers[&peer_id];
+                if direction == Direction::Inbound {
+                    if let Some(announced) = Self::dialable_addr(&listen_addrs).filter(|addr| *addr != peer_addr) {
+                        tracing::debug!(peer_id = %peer_id, announced = %announced, "Dialing announced address");
+                        self.send_command(TransportCommand::Connect(announced)).await;
+                    }
+                }
+                let connections = self.active_peers.entry(peer_id).or_insert(0);
+                *connections += 1;
+                if *connections > 1 && rerouted {
+                    let outbox = self.membership.on_connected(peer_id, route);
+                    self.send_membership_messages(outbox).await;
+                } else if *connections == 1 {
+                    tracing::debug!(peer_id = %peer_id, peer_addr = %peer_addr, "Peer connection established");
+                    if let Some(plumtree) = &mut self.plumtree {
+                        plumtree.add_peer(peer_id);
+                    }
+                    let outbox = self.membership.on_connected(peer_id, route);
+                    self.send_membership_messages(outbox).await;
+                    self.swim.observe(peer_id, Instant::now());
+                    self.pex.on_connected(peer_id);
+                    if self.config.bootstrap_peers.contains(&peer_addr) {
+                        let outbox = self.membership.join(peer_addr);
+                        self.send_membership_messages(outbox).await;
+                    }
                     self.publish_state();
                 }
             }
-            ConnectionEvent::PeerDisconnected { peer_addr } => {
-                if self.active_peer_addrs.remove(&peer_addr) {
-                    tracing::debug!(peer_addr = %peer_addr, "Peer connection lost");
+            ConnectionEvent::PeerDisconnected { peer_addr, peer_id, direction } => {
+                if direction == Direction::Outbound {
+                    self.outbound_connections = self.outbound_connections.saturating_sub(1);
+                }
+                let Some(connections) = self.active_peers.get_mut(&peer_id) else {
+                    return;
+                };
+                *connections -= 1;
+                if *connections == 0 {
+                    self.active_peers.remove(&peer_id);
+                    self.digest_replies.remove(&peer_id);
+                    tracing::debug!(peer_id = %peer_id, peer_addr = %peer_addr, "Peer connection lost");
+                    if let Some(plumtree) = &mut self.plumtree {
+                        plumtree.remove_peer(peer_id);
+                    }
+                    let outbox = self.membership.on_peer_lost(peer_id);
+                    self.send_membership_messages(outbox).await;
                     self.publish_state();
                 }
             }
+            ConnectionEvent::PersistentPeer(peer) => {
+                tracing::debug!(peer = %peer.addr, state = ?peer.state, failures = peer.failures, "Persistent peer state changed");
+                self.persistent_peers.insert(peer.addr, peer);
+                self.publish_state();
+            }
+            ConnectionEvent::Bans(bans) => {
+                tracing::debug!(bans = bans.len(), "Ban list changed");
+                self.bans = bans;
+                self.publish_state();
+            }
         }
     }
 
+    /// The first of `listen_addrs` that can be dialed, if any.
+    fn dialable_addr(listen_addrs: &[SocketAddr]) -> Option<SocketAddr> {
+        listen_addrs.iter().copied().find(|addr| !addr.ip().is_unspecified())
+    }
+
     async fn handle_inbound_message(&mut self, inbound: InboundMessage) {
-        if let Err(e) = inbound.message.verify() {
-            tracing::warn!(error = %e, "Received message with invalid signature. Discarding.");
-            return;
+        let InboundMessage { peer_addr, peer_id, message } = inbound;
+        let source_ip = peer_addr.ip();
+        // Replies go to the peer's route rather than the observed source
+        // address, which is that of an inbound connection that is never used
+        // for sending.
+        let route = self.known_peers.get(&peer_id).copied().unwrap_or(peer_addr);
+        let invalid_before = self.invalid_signatures();
+        match message {
+            WireMessage::Gossip(mut relayed) => {
+                let status_updates = std::mem::take(&mut relayed.status_updates);
+                self.swim.apply_updates(status_updates, Instant::now());
+                self.apply_status_changes();
+                self.refute_suspicion().await;
+                match self.accept_message(peer_addr, peer_id, &relayed) {
+                    Delivery::New => {
+                        let hops = relayed.hops.saturating_add(1);
+                        self.propagate(relayed.message, hops, Some(peer_id)).await;
+                    }
+                    Delivery::Duplicate => self.prune_redundant_sender(route, peer_id).await,
+                    Delivery::Rejected => {}
+                }
+            }
+            WireMessage::SyncDigest(digest) => {
+                self.reply_to_digest(route, peer_id, &digest).await;
+            }
+            WireMessage::SyncReply(messages) => {
+                // Repaired state is not forwarded: other peers run their own
+                // anti-entropy rounds.
+                let accepted = messages
+                    .iter()
+                    .filter(|relayed| self.accept_message(peer_addr, peer_id, relayed) == Delivery::New)
+                    .count();
+                tracing::debug!(peer = %peer_addr, received = messages.len(), accepted, "Applied anti-entropy reply");
+            }
+            WireMessage::Control(frame) => {
+                self.handle_control_frame(route, peer_id, frame).await;
+            }
+            WireMessage::Membership(message) => {
+                self.handle_membership_message(route, peer_id, message).await;
+            }
+            WireMessage::Probe(message) => {
+                self.handle_probe_message(route, message).await;
+            }
+            WireMessage::PeerExchange(advertisements) => {
+                self.handle_peer_exchange(peer_id, source_ip, advertisements);
+            }
         }
 
-        let peer_node_id = self
-            .known_peers
-            .iter()
-            .find(|(_, &addr)| addr == inbound.peer_addr)
-            .map(|(id, _)| *id);
+        // Every node verifies signatures before relaying, so an invalid one
+        // counts against the peer that sent it, not its claimed originator.
+        for _ in invalid_before..self.invalid_signatures() {
+            let strike = TransportCommand::Strike { peer_id, addr: source_ip, reason: BanReason::InvalidSignature };
+            self.send_command(strike).await;
+        }
+    }
 
-        self.known_peers
-            .insert(inbound.message.originator, inbound.peer_addr);
+    /// The number of messages and advertisements rejected for an invalid signature.
+    fn invalid_signatures(&self) -> u64 {
+        self.stats.rejected_invalid_signature + self.stats.rejected_invalid_advertisement
+    }
+
+    /// Validates a signed message and, if it is newer than what is held for its
+    /// originator, stores it.
+    fn accept_message(&mut self, peer_addr: SocketAddr, peer_id: NodeId, relayed: &RelayedMessage) -> Delivery {
+        let message = &relayed.message;
+        // Duplicates are dropped before verification. Only messages that pass every
+        // check are inserted below, so neither a forged copy nor an early delivery
+        // of a future-dated message can shadow the genuine, acceptable one.
+        let message_id = message.id();
+        if self.seen_cache.contains(&message_id, Instant::now()) {
+            self.stats.seen_cache_hits += 1;
+            tracing::trace!(originator = %message.originator, "Discarding already seen message.");
+            return Delivery::Duplicate;
+        }
+        self.stats.seen_cache_misses += 1;
 
-        let is_new = match self.node_info.get(&inbound.message.originator) {
-            Some(existing) => {
-                inbound.message.message.telemetry.timestamp_ms
-                    > existing.telemetry.timestamp_ms
+        match message.verify_with(self.config.accept_legacy_signatures) {
+            Ok(SigningEncoding::Canonical) => {}
+            Ok(encoding) => {
+                tracing::debug!(originator = %message.originator, from = %peer_addr, ?encoding, "Accepted message with a protocol version 1 signature.");
+                self.stats.accepted_legacy_signature += 1;
             }
+            Err(e) => {
+                tracing::warn!(
+                    error = %e,
+                    from = %peer_addr,
+                    reason = %Rejection::InvalidSignature,
+                    "Received message with invalid signature. Discarding."
+                );
+                self.record_rejection(peer_addr, message, Rejection::InvalidSignature);
+                return Delivery::Rejected;
+            }
+        }
+
+        let timestamp_ms = message.message.timestamp_ms;
+        if let Err(rejection) = self.timestamp_window.check(timestamp_ms, unix_time_ms()) {
+            self.record_rejection(peer_addr, message, rejection);
+            return Delivery::Rejected;
+        }
+        if let Err(e) = self.payloads.validate(&message.message.payload) {
+            tracing::warn!(
+                error = %e,
+                originator = %message.originator,
+                from = %peer_addr,
+                reason = %Rejection::InvalidPayload,
+                "Rejected message with an unacceptable application payload."
+            );
+            self.record_rejection(peer_addr, message, Rejection::InvalidPayload);
+            return Delivery::Rejected;
+        }
+        self.seen_cache.insert(message_id, Instant::now());
+
+        let existing = self.records.get(&message.originator).and_then(|records| records.get(&message.message.payload.kind));
+        let is_new = match existing {
+            Some(existing) => message.message.version > existing.message.message.version,
             None => true,
         };
+        if !is_new {
+            return Delivery::Duplicate;
+        }
 
-        if is_new {
-            tracing::info!(originator = %inbound.message.originator, "Received new information");
-            let node_info = NodeInfo {
-                telemetry: inbound.message.message.telemetry.clone(),
-                community_id: inbound.message.message.community_id,
-            };
-            self.node_info
-                .insert(inbound.message.originator, node_info);
-            
-            if let Some(id) = peer_node_id {
-                if self.animation_tx.send(id).is_err() {
-                    tracing::trace!(peer_id = %id, "No active API listeners for animation event.");
-                } else {
-                    tracing::debug!(peer_id = %id, "Sent animation event for incoming gossip.");
-                }
+        tracing::info!(originator = %message.originator, "Received new information");
+        self.store_message(MessageRecord {
+            message: message.clone(),
+            received_at_ms: unix_time_ms(),
+            from_peer: peer_id,
+            hops: relayed.hops.saturating_add(1),
+        });
+        // A newer signed message refutes a suspicion or death notice about its originator.
+        self.swim.on_signed(message.originator, message.message.version, Instant::now());
+        self.apply_status_changes();
+        self.arrivals.heartbeat(message.originator, Instant::now());
+
+        if self.animation_tx.send(peer_id).is_err() {
+            tracing::trace!(peer_id = %peer_id, "No active API listeners for animation event.");
+        } else {
+            tracing::debug!(peer_id = %peer_id, "Sent animation event for incoming gossip.");
+        }
+
+        self.publish_state();
+        Delivery::New
+    }
+
+    /// Records `record` as the latest known payload of its kind from its originator.
+    fn store_message(&mut self, record: MessageRecord) {
+        let message = &record.message;
+        let records = self.records.entry(message.originator).or_default();
+        records.insert(message.message.payload.kind.clone(), record);
+    }
+
+    /// Logs why an inbound message was refused and updates the matching counter.
+    fn record_rejection(&mut self, peer_addr: SocketAddr, message: &SignedMessage, rejection: Rejection) {
+        match rejection {
+            Rejection::InvalidSignature => self.stats.rejected_invalid_signature += 1,
+            Rejection::InvalidPayload => self.stats.rejected_invalid_payload += 1,
+            Rejection::TimestampInFuture { skew_ms } => {
+                self.stats.rejected_future_timestamp += 1;
+                tracing::warn!(
+                    originator = %message.originator,
+                    from = %peer_addr,
+                    reason = %rejection,
+                    skew_ms,
+                    "Rejected message with a timestamp too far in the future."
+                );
+            }
+            Rejection::TimestampTooOld { age_ms } => {
+                self.stats.rejected_stale_timestamp += 1;
+                tracing::debug!(
+                    originator = %message.originator,
+                    from = %peer_addr,
+                    reason = %rejection,
+                    age_ms,
+                    "Rejected message with a timestamp too far in the past."
+                );
             }
+        }
+    }
 
-            self.publish_state();
-            self.gossip_to_peers(inbound.message).await;
+    /// Sends a digest of the local state to a random connected neighbour.
+    async fn start_anti_entropy_round(&self) {
+        let target = self
+            .membership
+            .active_view()
+            .iter()
+            .filter(|(id, _)| self.active_peers.contains_key(id))
+            .choose(&mut rand::thread_rng());
+        let Some((peer_id, &peer_addr)) = target else {
+            tracing::debug!("No connected peers for anti-entropy.");
+            return;
+        };
+
+        tracing::debug!(peer_id = %peer_id, peer_addr = %peer_addr, "Starting anti-entropy round");
+        let digest = anti_entropy::digest(&self.records);
+        let command = TransportCommand::SendMessage(peer_addr, WireMessage::SyncDigest(digest));
+        if let Err(e) = self.transport_tx.send(command).await {
+            tracing::error!(error = %e, "Failed to send command to transport service");
         }
     }
 
-    async fn gossip_self_telemetry(&mut self) {
-        let timestamp_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis() as u64;
+    /// Answers a peer's digest with the messages it is missing or behind on,
+    /// unless it sent one more recently than anti-entropy rounds would.
+    async fn reply_to_digest(&mut self, peer_addr: SocketAddr, peer_id: NodeId, digest: &[(NodeId, String, Version)]) {
+        let now = Instant::now();
+        let min_gap = self.anti_entropy_interval / 2;
+        if let Some(last) = self.digest_replies.get(&peer_id) {
+            if now.duration_since(*last) < min_gap {
+                tracing::debug!(peer = %peer_addr, peer_id = %peer_id, "Ignoring sync digest sent too soon");
+                self.stats.ignored_sync_digests += 1;
+                return;
+            }
+        }
+        self.digest_replies.insert(peer_id, now);
+        for batch in anti_entropy::missing_from(&self.records, digest) {
+            tracing::debug!(peer = %peer_addr, count = batch.len(), "Sending anti-entropy reply");
+            let command = TransportCommand::SendMessage(peer_addr, WireMessage::SyncReply(batch));
+            if let Err(e) = self.transport_tx.send(command).await {
+                tracing::error!(error = %e, "Failed to send command to transport service");
+            }
+        }
+    }
+
+    /// Signs and gossips a fresh payload from each of this node's sources.
+    async fn gossip_self_payload(&mut self) {
+        let timestamp_ms = unix_time_ms();
+        let payloads: Vec<_> =
+            self.payload_sources.iter_mut().map(|source| source.next_payload(timestamp_ms)).collect();
+        for app_payload in payloads {
+            self.gossip_payload(app_payload, timestamp_ms).await;
+        }
+    }
 
+    async fn gossip_payload(&mut self, app_payload: Payload, timestamp_ms: u64) {
+        // Peers would reject a payload that is oversized, of an unregistered
+        // kind or invalid as its kind, so it is not sent at all.
+        if let Err(e) = self.payloads.validate(&app_payload) {
+            tracing::warn!(error = %e, kind = %app_payload.kind, "Not gossiping this node's payload.");
+            return;
+        }
         let payload = GossipPayload {
-            telemetry: TelemetryData {
-                timestamp_ms,
-                value: 100.0 + 50.0 * (timestamp_ms as f64 / 10000.0).sin(),
-            },
+            timestamp_ms,
             community_id: self.config.community_id,
+            version: Version {
+                epoch: self.identity.epoch(),
+                sequence: self.next_sequence,
+            },
+            payload: app_payload,
         };
+        self.next_sequence += 1;
 
         let signed_message = self.identity.sign(payload);
-        tracing::debug!("Generated new telemetry. Gossiping to peers...");
+        self.seen_cache.insert(signed_message.id(), Instant::now());
+        self.swim.on_signed(self.identity.node_id, signed_message.message.version, Instant::now());
+        tracing::debug!(kind = %signed_message.message.payload.kind, "Generated new payload. Gossiping to peers...");
 
-        let node_info = NodeInfo {
-            telemetry: signed_message.message.telemetry.clone(),
-            community_id: signed_message.message.community_id,
-        };
-        self.node_info
-            .insert(self.identity.node_id, node_info);
+        self.store_message(MessageRecord {
+            message: signed_message.clone(),
+            received_at_ms: timestamp_ms,
+            from_peer: self.identity.node_id,
+            hops: 0,
+        });
 
         self.publish_state();
 
-        self.gossip_to_peers(signed_message.clone()).await;
+        self.propagate(signed_message, 0, None).await;
+    }
 
-        for &addr in &self.config.bootstrap_peers {
-            let command = TransportCommand::SendMessage(addr, signed_message.clone());
-            if let Err(e) = self.transport_tx.send(command).await {
-                tracing::error!(error = %e, "Failed to send command to transport service for bootstrap peer");
+    /// Pushes a new message onwards: along the broadcast tree in Plumtree mode,
+    /// or to peers chosen by the gossip strategy otherwise. `from` is the peer
+    /// that delivered it, or `None` for messages this node originated.
+    async fn propagate(&mut self, message: SignedMessage, hops: u32, from: Option<NodeId>) {
+        if self.plumtree.is_some() {
+            self.push_along_tree(message, hops, from).await;
+        } else {
+            self.gossip_to_peers(message, hops).await;
+        }
+    }
+
+    /// Sends `message` in full to the eager peers and announces it to the lazy
+    /// ones, skipping the peer it came from and its originator.
+    async fn push_along_tree(&mut self, message: SignedMessage, hops: u32, from: Option<NodeId>) {
+        let Some(plumtree) = &mut self.plumtree else {
+            return;
+        };
+        if let Some(peer) = from {
+            plumtree.on_new_message(peer, message.originator, &message.message.payload.kind, message.message.version);
+        }
+
+        let address_of = |id: &NodeId| {
+            let is_target = Some(*id) != from && *id != message.originator;
+            is_target.then(|| self.membership.active_view().get(id).copied()).flatten()
+        };
+        let eager: Vec<_> = plumtree.eager_peers().filter_map(address_of).collect();
+        let lazy: Vec<_> = plumtree.lazy_peers().filter_map(address_of).collect();
+
+        for addr in eager {
+            tracing::debug!(peer_addr = %addr, "Pushing message to eager peer");
+            let relayed = RelayedMessage { message: message.clone(), hops, status_updates: self.swim.piggyback() };
+            self.send_command(TransportCommand::SendMessage(addr, WireMessage::Gossip(relayed))).await;
+        }
+        let announcement = Announcement {
+            id: message.id(),
+            originator: message.originator,
+            kind: message.message.payload.kind.clone(),
+            version: message.message.version,
+        };
+        for addr in lazy {
+            tracing::trace!(peer_addr = %addr, "Announcing message to lazy peer");
+            let frame = ControlFrame::IHave(vec![announcement.clone()]);
+            self.send_command(TransportCommand::SendControl(addr, frame)).await;
+        }
+    }
+
+    /// In Plumtree mode, prunes an eager peer that delivered a message this
+    /// node already had, removing the redundant link from the tree.
+    async fn prune_redundant_sender(&mut self, peer_addr: SocketAddr, peer_id: NodeId) {
+        let Some(plumtree) = &mut self.plumtree else {
+            return;
+        };
+        if plumtree.on_duplicate(peer_id) {
+            tracing::debug!(peer_id = %peer_id, "Pruning redundant eager peer");
+            self.send_command(TransportCommand::SendControl(peer_addr, ControlFrame::Prune)).await;
+        }
+    }
+
+    async fn handle_control_frame(&mut self, peer_addr: SocketAddr, peer_id: NodeId, frame: ControlFrame) {
+        let Some(plumtree) = &mut self.plumtree else {
+            tracing::debug!(peer = %peer_addr, "Ignoring control frame outside Plumtree mode");
+            return;
+        };
+        match frame {
+            ControlFrame::IHave(announcements) => {
+                let now = Instant::now();
+                for announcement in announcements {
+                    let is_held = self.seen_cache.contains(&announcement.id, now)
+                        || self
+                            .records
+                            .get(&announcement.originator)
+                            .and_then(|records| records.get(&announcement.kind))
+                            .is_some_and(|record| record.message.message.version >= announcement.version);
+                    if !is_held {
+                        let Announcement { id, originator, kind, version } = announcement;
+                        plumtree.on_ihave(peer_id, id, originator, &kind, version, now);
+                    }
+                }
+            }
+            ControlFrame::Graft { originator, kind, .. } => {
+                tracing::debug!(peer_id = %peer_id, "Peer grafted onto the broadcast tree");
+                plumtree.on_graft(peer_id);
+                if let Some(record) = self.records.get(&originator).and_then(|records| records.get(&kind)) {
+                    let relayed = RelayedMessage {
+                        message: record.message.clone(),
+                        hops: record.hops,
+                        status_updates: self.swim.piggyback(),
+                    };
+                    self.send_command(TransportCommand::SendMessage(peer_addr, WireMessage::Gossip(relayed))).await;
+                }
+            }
+            ControlFrame::Prune => {
+                tracing::debug!(peer_id = %peer_id, "Peer pruned from the broadcast tree");
+                plumtree.on_prune(peer_id);
             }
         }
     }
 
-    async fn gossip_to_peers(&self, message: SignedMessage) {
-        let peers_to_gossip_to = protocol::select_peers(
-            &self.known_peers,
-            message.originator,
-            self.config.gossip_factor,
-        );
+    /// Requests announced messages that did not arrive over the tree in time.
+    async fn send_due_grafts(&mut self) {
+        let Some(plumtree) = &mut self.plumtree else {
+            return;
+        };
+        for graft in plumtree.due_grafts(Instant::now()) {
+            let Some(&addr) = self.known_peers.get(&graft.peer) else {
+                continue;
+            };
+            tracing::debug!(peer_id = %graft.peer, originator = %graft.originator, "Grafting missing message");
+            let frame = ControlFrame::Graft { id: graft.id, originator: graft.originator, kind: graft.kind };
+            self.send_command(TransportCommand::SendControl(addr, frame)).await;
+        }
+    }
+
+    async fn handle_membership_message(&mut self, peer_addr: SocketAddr, peer_id: NodeId, message: MembershipMessage) {
+        tracing::trace!(peer_id = %peer_id, message = ?message, "Received membership message");
+        let outbox = match message {
+            MembershipMessage::Join => self.membership.on_join(peer_id, peer_addr),
+            MembershipMessage::ForwardJoin { node, addr, ttl } => {
+                self.membership.on_forward_join(peer_id, node, addr, ttl)
+            }
+            MembershipMessage::Neighbor { high_priority } => {
+                self.membership.on_neighbor(peer_id, peer_addr, high_priority)
+            }
+            MembershipMessage::Disconnect => self.membership.on_disconnect(peer_id),
+            MembershipMessage::Shuffle { origin, origin_addr, ttl, peers } => {
+                self.membership.on_shuffle(peer_id, origin, origin_addr, ttl, peers)
+            }
+            MembershipMessage::ShuffleReply { peers } => {
+                self.membership.on_shuffle_reply(peers);
+                Vec::new()
+            }
+        };
+        self.send_membership_messages(outbox).await;
+    }
+
+    async fn send_membership_messages(&self, outbox: Outbox) {
+        for (addr, message) in outbox {
+            self.send_command(TransportCommand::SendMessage(addr, WireMessage::Membership(message))).await;
+        }
+    }
+
+    async fn handle_probe_message(&mut self, peer_addr: SocketAddr, message: ProbeMessage) {
+        let now = Instant::now();
+        let outbox = match message {
+            ProbeMessage::Ping { seq, status_updates } => self.swim.on_ping(peer_addr, seq, status_updates, now),
+            ProbeMessage::PingReq { seq, target, target_addr: _ } => {
+                self.swim.on_ping_req(peer_addr, seq, target, &self.known_peers, now)
+            }
+            ProbeMessage::Ack { seq, target, status_updates } => self.swim.on_ack(seq, target, status_updates, now),
+        };
+        self.send_probe_messages(outbox).await;
+        self.apply_status_changes();
+        self.refute_suspicion().await;
+    }
+
+    /// Signs a fresh message right away if the failure detector raised this
+    /// node's incarnation past its last signed version to refute a suspicion,
+    /// as peers only believe incarnations they have seen signed.
+    async fn refute_suspicion(&mut self) {
+        let incarnation = self.swim.incarnation();
+        let latest = self.records.get(&self.identity.node_id).and_then(latest_record);
+        let Some(latest) = latest.map(|record| record.message.message.version) else {
+            return;
+        };
+        if incarnation > latest && incarnation.epoch == self.identity.epoch() {
+            self.next_sequence = self.next_sequence.max(incarnation.sequence);
+            self.gossip_self_payload().await;
+        }
+    }
+
+    async fn send_probe_messages(&self, outbox: swim::Outbox) {
+        for (addr, message) in outbox {
+            self.send_command(TransportCommand::SendMessage(addr, WireMessage::Probe(message))).await;
+        }
+    }
+
+    /// Sends this node's advertisement and a sample of the candidate pool to
+    /// every neighbour, then dials candidates if short of connections.
+    async fn exchange_peers(&mut self) {
+        self.pex.start_round();
+        let mut advertisements = self.pex.sample();
+        let listen_addrs = self.config.listen_addrs();
+        if !listen_addrs.is_empty() {
+            let version = Version { epoch: self.identity.epoch(), sequence: self.next_advertisement };
+            self.next_advertisement += 1;
+            advertisements.insert(0, self.identity.advertise(listen_addrs, version));
+        }
+        for &addr in self.membership.active_view().values() {
+            let message = WireMessage::PeerExchange(advertisements.clone());
+            self.send_command(TransportCommand::SendMessage(addr, message)).await;
+        }
+
+        let missing = self.config.min_outbound_connections.saturating_sub(self.outbound_connections);
+        let active_peers = &self.active_peers;
+        let dials = self.pex.select_dials(missing, |id| active_peers.contains_key(id));
+        // A dial is a NEIGHBOR request: the connection it opens is added to
+        // the views like any other once its handshake completes.
+        let high_priority = self.membership.active_view().is_empty();
+        for (node_id, addr) in dials {
+            tracing::debug!(peer_id = %node_id, peer_addr = %addr, "Dialing advertised peer");
+            let message = WireMessage::Membership(MembershipMessage::Neighbor { high_priority });
+            self.send_command(TransportCommand::SendMessage(addr, message)).await;
+        }
+    }
+
+    fn handle_peer_exchange(&mut self, peer_id: NodeId, source_ip: IpAddr, advertisements: Vec<SignedPeerAdvertisement>) {
+        let received = advertisements.len();
+        let mut added = 0;
+        for advertisement in advertisements {
+            match self.pex.insert(peer_id, source_ip, advertisement) {
+                Ok(new) => added += usize::from(new),
+                Err(e) => {
+                    self.stats.rejected_invalid_advertisement += 1;
+                    tracing::warn!(peer_id = %peer_id, error = %e, "Rejected peer advertisement");
+                }
+            }
+        }
+        tracing::debug!(peer_id = %peer_id, received, added, "Applied peer exchange");
+    }
+
+    /// Acts on the failure detector's status changes: dead nodes are removed
+    /// from the state without waiting for `node_ttl_ms`.
+    fn apply_status_changes(&mut self) {
+        let changes = self.swim.drain_changes();
+        if changes.is_empty() {
+            return;
+        }
+        for (node_id, status) in changes {
+            tracing::info!(node_id = %node_id, status = ?status, "Node status changed");
+            if status == MemberStatus::Dead {
+                self.records.remove(&node_id);
+                self.arrivals.remove(&node_id);
+            }
+        }
+        self.publish_state();
+    }
+
+    async fn send_command(&self, command: TransportCommand) {
+        if let Err(e) = self.transport_tx.send(command).await {
+            tracing::error!(error = %e, "Failed to send command to transport service");
+        }
+    }
+
+    /// Pushes `message` to a selection of active view peers. `hops` is the
+    /// distance the message has travelled from its originator to this node.
+    async fn gossip_to_peers(&mut self, message: SignedMessage, hops: u32) {
+        let view = PeerView {
+            known_peers: self.membership.active_view(),
+            active_peers: &self.active_peers,
+            nodes: &self.records,
+            local_community: self.config.community_id,
+        };
+        let peers_to_gossip_to = self.strategy.select_targets(&message, &view);
 
         if peers_to_gossip_to.is_empty() {
             tracing::debug!("No known peers to gossip to yet.");
@@ -209,53 +892,98 @@ impl Engine {
 
         for (node_id, addr) in peers_to_gossip_to {
             tracing::debug!(peer_id = %node_id, peer_addr = %addr, "Gossiping message");
-            let command = TransportCommand::SendMessage(*addr, message.clone());
+            let relayed = RelayedMessage { message: message.clone(), hops, status_updates: self.swim.piggyback() };
+            let command = TransportCommand::SendMessage(addr, WireMessage::Gossip(relayed));
             if let Err(e) = self.transport_tx.send(command).await {
                 tracing::error!(error = %e, "Failed to send command to transport service");
             }
         }
     }
 
-    fn cleanup_stale_nodes(&mut self) {
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis() as u64;
-        let ttl_ms = self.node_ttl.as_millis() as u64;
+    async fn cleanup_stale_nodes(&mut self) {
+        let now = Instant::now();
+        self.seen_cache.prune(now);
+        tracing::debug!(
+            hits = self.stats.seen_cache_hits,
+            misses = self.stats.seen_cache_misses,
+            entries = self.seen_cache.len(),
+            "Seen-message cache statistics"
+        );
 
+        // Nodes are evicted once their phi crosses the threshold, or, while
+        // their history is too short for phi, once the TTL has passed.
         let stale_nodes: Vec<_> = self
-            .node_info
-            .iter()
-            .filter(|(id, data)| {
-                **id != self.identity.node_id
-                    && data.telemetry.timestamp_ms < now_ms
-                    && (now_ms - data.telemetry.timestamp_ms) > ttl_ms
+            .arrivals
+            .nodes()
+            .filter(|id| **id != self.identity.node_id)
+            .filter(|id| match self.arrivals.phi(id, now) {
+                Some(phi) => phi >= self.config.phi_evict_threshold,
+                None => self
+                    .arrivals
+                    .last_arrival(id)
+                    .is_some_and(|at| now.saturating_duration_since(at) > self.node_ttl),
             })
-            .map(|(id, _)| *id)
+            .copied()
             .collect();
 
         if !stale_nodes.is_empty() {
             tracing::info!(count = stale_nodes.len(), "Pruning stale nodes");
             for node_id in stale_nodes {
-                self.node_info.remove(&node_id);
-                self.known_peers.remove(&node_id);
+                self.records.remove(&node_id);
+                self.arrivals.remove(&node_id);
             }
             self.publish_state();
         }
+
+        // Forget the addresses of peers that are neither connected nor alive.
+        let (records, active_peers) = (&self.records, &self.active_peers);
+        self.known_peers
+            .retain(|id, _| active_peers.contains_key(id) || records.contains_key(id));
+
+        // The failure detector only tracks nodes that can be probed or are alive.
+        let known_peers = &self.known_peers;
+        self.swim.retain(|id| known_peers.contains_key(id) || records.contains_key(id));
+
+        // Neighbours whose disconnect was missed are replaced from the passive view.
+        let outbox = self.membership.retain_connected(|id| active_peers.contains_key(id));
+        self.send_membership_messages(outbox).await;
+    }
+
+    /// The phi of every node with enough update history.
+    fn node_health(&self) -> HashMap<NodeId, NodeHealth> {
+        let now = Instant::now();
+        self.arrivals
+            .nodes()
+            .filter_map(|id| {
+                let phi = self.arrivals.phi(id, now)?;
+                let health = NodeHealth {
+                    phi: (phi * 10.0).round() / 10.0,
+                    suspected: phi >= self.config.phi_suspect_threshold,
+                };
+                Some((*id, health))
+            })
+            .collect()
     }
 
     fn publish_state(&self) {
-        let active_connections = self
-            .known_peers
-            .iter()
-            .filter(|(_, &addr)| self.active_peer_addrs.contains(&addr))
-            .map(|(id, _)| *id)
-            .collect();
+        let active_connections = self.active_peers.keys().copied().collect();
+        let mut persistent_peers: Vec<_> = self.persistent_peers.values().cloned().collect();
+        persistent_peers.sort_by_key(|peer| peer.addr);
 
         let state = NetworkState {
             self_id: Some(self.identity.node_id),
-            nodes: self.node_info.clone(),
+            nodes: self
+                .records
+                .iter()
+                .filter_map(|(id, records)| Some((*id, NodeInfo::from_records(records)?)))
+                .collect(),
             active_connections,
+            stats: self.stats,
+            messages: self.records.clone(),
+            statuses: self.swim.statuses(),
+            health: self.node_health(),
+            persistent_peers,
+            bans: self.bans.clone(),
         };
 
         if let Ok(json_state) = serde_json::to_string(&state) {
@@ -263,4 +991,23 @@ impl Engine {
         }
         let _ = self.state_tx.send(state);
     }
-}
\ No newline at end of file
+}
+
+/// The outcome of `Engine::accept_message`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivery {
+    /// The message was newer than anything held for its originator, and was stored.
+    New,
+    /// The message, or a newer one from the same originator, was already held.
+    Duplicate,
+    /// The message failed validation.
+    Rejected,
+}
+
+/// Returns the current wall-clock time in milliseconds since the Unix epoch.
+fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
@@ -0,0 +1,73 @@
+//! src/engine/payload.rs
+//!
+//! Sources of the application payload a node gossips about itself. The
+//! `Engine` asks its source for a fresh payload every gossip round, signs it,
+//! and gossips it with a new version.
+
+use crate::domain::{Metric, Payload, TelemetryData};
+use tokio::sync::watch;
+
+/// Produces the application payload this node gossips.
+pub trait PayloadSource: Send + Sync {
+    /// The payload to gossip in the round starting at `now_ms`.
+    fn next_payload(&mut self, now_ms: u64) -> Payload;
+}
+
+/// The default source: synthetic `TelemetryData` whose metrics oscillate
+/// over time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SyntheticTelemetry;
+
+impl PayloadSource for SyntheticTelemetry {
+    fn next_payload(&mut self, now_ms: u64) -> Payload {
+        let phase = now_ms as f64 / 10000.0;
+        Payload::new(
+            &TelemetryData::default()
+                .with_metric("cpu", Metric::new(50.0 + 40.0 * phase.sin(), "percent"))
+                .with_metric("mem", Metric::new((512.0 + 128.0 * phase.cos()).round() * 1024.0 * 1024.0, "bytes"))
+                .with_metric(
+                    "queue_depth",
+                    Metric::new((20.0 + 15.0 * (phase / 3.0).sin()).round(), "messages").with_label("queue", "gossip"),
+                )
+                .with_metric(
+                    "latency_p99",
+                    Metric::new(100.0 + 50.0 * phase.sin(), "ms").with_label("quantile", "0.99"),
+                ),
+        )
+    }
+}
+
+/// Gossips the latest payload sent by the embedding application, so that
+/// it can publish updates, such as changed feature flags, whenever it likes.
+impl PayloadSource for watch::Receiver<Payload> {
+    fn next_payload(&mut self, _now_ms: u64) -> Payload {
+        self.borrow_and_update().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::PayloadRegistry;
+
+    #[test]
+    fn test_watch_source_gossips_latest_published_payload() {
+        let telemetry = |value| TelemetryData::default().with_metric("cpu", Metric::new(value, "percent"));
+        let (tx, mut source) = watch::channel(Payload::new(&telemetry(1.0)));
+        assert_eq!(source.next_payload(0).decode::<TelemetryData>().unwrap(), telemetry(1.0));
+        tx.send(Payload::new(&telemetry(2.0))).unwrap();
+        assert_eq!(source.next_payload(0).decode::<TelemetryData>().unwrap(), telemetry(2.0));
+        assert_eq!(source.next_payload(0).decode::<TelemetryData>().unwrap(), telemetry(2.0));
+    }
+
+    #[test]
+    fn test_synthetic_telemetry_stays_within_bounds() {
+        for now_ms in [0, 12_345, 1_700_000_000_000] {
+            let payload = SyntheticTelemetry.next_payload(now_ms);
+            assert!(PayloadRegistry::default().validate(&payload).is_ok());
+            let telemetry: TelemetryData = payload.decode().unwrap();
+            assert_eq!(telemetry.metrics.len(), 4);
+            assert_eq!(telemetry.metrics["queue_depth"].labels["queue"], "gossip");
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! src/engine/pex.rs
+//!
+//! Implements peer exchange (PEX). Nodes periodically send their neighbours a
+//! signed advertisement of their own listen addresses, together with a sample
+//! of the advertisements they have collected. Advertisements are kept in a
+//! bounded candidate pool, from which a node that is short of connections
+//! picks peers to dial.
+//!
+//! An advertisement is only accepted if it is signed by the node it
+//! advertises, so a relaying peer cannot redirect a node to its own address.
+//! The addresses themselves are still the advertiser's claim: a node can
+//! advertise addresses it is not reachable at. Addresses that cannot name a
+//! remote node are never dialed: unspecified and multicast addresses, and
+//! loopback addresses unless relayed over loopback. Each neighbour may relay
+//! a bounded number of advertisements per exchange round.
+
+use crate::{
+    domain::{NodeId, PeerAdvertisement, SignedPeerAdvertisement},
+    error::Result,
+    transport::handshake::MAX_LISTEN_ADDRS,
+};
+use rand::{seq::IteratorRandom, thread_rng};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+/// The maximum number of collected advertisements sent in one exchange.
+pub const SAMPLE_SIZE: usize = 8;
+/// Candidates that could not be connected to after this many dials are dropped.
+pub const MAX_DIAL_ATTEMPTS: u32 = 3;
+/// The most advertisements taken from one neighbour per exchange round: its
+/// own and a full sample. Any more it relays are ignored unverified.
+pub const MAX_ADVERTISEMENTS_PER_ROUND: usize = SAMPLE_SIZE + 1;
+
+#[derive(Debug)]
+struct Candidate {
+    advertisement: SignedPeerAdvertisement,
+    // The advertised addresses worth dialing.
+    dial_addrs: Vec<SocketAddr>,
+    dial_attempts: u32,
+}
+
+/// The candidate pool of one node.
+#[derive(Debug)]
+pub struct PeerExchange {
+    self_id: NodeId,
+    candidates: HashMap<NodeId, Candidate>,
+    capacity: usize,
+    // Advertisements taken from each neighbour in the current round.
+    relayed: HashMap<NodeId, usize>,
+    // Whether advertisements signed by nodes of protocol version 1 verify.
+    accept_legacy: bool,
+}
+
+impl PeerExchange {
+    pub fn new(self_id: NodeId, capacity: usize) -> Self {
+        Self { self_id, candidates: HashMap::new(), capacity, relayed: HashMap::new(), accept_legacy: false }
+    }
+
+    /// Also accepts advertisements signed over the encoding of protocol
+    /// version 1 (see `PeerAdvertisement::legacy_signing_bytes`).
+    pub fn with_legacy_signatures(mut self, accept: bool) -> Self {
+        self.accept_legacy = accept;
+        self
+    }
+
+    /// Starts an exchange round, resetting what each neighbour may relay.
+    pub fn start_round(&mut self) {
+        self.relayed.clear();
+    }
+
+    /// The latest advertisement held for each candidate.
+    pub fn candidates(&self) -> impl Iterator<Item = &PeerAdvertisement> {
+        self.candidates.values().map(|candidate| &candidate.advertisement.advertisement)
+    }
+
+    /// Adds `advertisement`, relayed by the neighbour `relay` connected from
+    /// `relay_ip`, to the pool, evicting a random candidate if it is full.
+    /// Returns whether it was new; advertisements beyond the neighbour's
+    /// share of the round, of this node, with no address worth dialing or
+    /// more than `MAX_LISTEN_ADDRS`, or older than the one held are ignored.
+    /// Fails if the signature does not verify.
+    pub fn insert(&mut self, relay: NodeId, relay_ip: IpAddr, advertisement: SignedPeerAdvertisement) -> Result<bool> {
+        let relayed = self.relayed.entry(relay).or_insert(0);
+        if *relayed >= MAX_ADVERTISEMENTS_PER_ROUND {
+            return Ok(false);
+        }
+        *relayed += 1;
+        advertisement.verify_with(self.accept_legacy)?;
+        let PeerAdvertisement { node_id, ref listen_addrs, version } = advertisement.advertisement;
+        let dial_addrs: Vec<_> = listen_addrs
+            .iter()
+            .copied()
+            .filter(|addr| is_dialable(addr.ip(), relay_ip))
+            .collect();
+        if node_id == self.self_id || dial_addrs.is_empty() || listen_addrs.len() > MAX_LISTEN_ADDRS || self.capacity == 0 {
+            return Ok(false);
+        }
+        if let Some(held) = self.candidates.get(&node_id) {
+            if held.advertisement.advertisement.version >= version {
+                return Ok(false);
+            }
+        } else if self.candidates.len() >= self.capacity {
+            if let Some(evicted) = self.candidates.keys().copied().choose(&mut thread_rng()) {
+                self.candidates.remove(&evicted);
+            }
+        }
+        // Newer addresses deserve a fresh set of attempts.
+        self.candidates.insert(node_id, Candidate { advertisement, dial_addrs, dial_attempts: 0 });
+        Ok(true)
+    }
+
+    /// A random sample of the pool to pass on to a neighbour.
+    pub fn sample(&self) -> Vec<SignedPeerAdvertisement> {
+        self.candidates
+            .values()
+            .map(|candidate| candidate.advertisement.clone())
+            .choose_multiple(&mut thread_rng(), SAMPLE_SIZE)
+    }
+
+    /// Picks up to `count` random candidates that `is_connected` rejects, with
+    /// the address to dial each at. Successive dials of a candidate cycle
+    /// through its advertised addresses, and a candidate is dropped once it
+    /// has used up `MAX_DIAL_ATTEMPTS`.
+    pub fn select_dials(&mut self, count: usize, is_connected: impl Fn(&NodeId) -> bool) -> Vec<(NodeId, SocketAddr)> {
+        self.candidates.retain(|_, candidate| candidate.dial_attempts < MAX_DIAL_ATTEMPTS);
+        let chosen = self
+            .candidates
+            .keys()
+            .filter(|id| !is_connected(id))
+            .copied()
+            .choose_multiple(&mut thread_rng(), count);
+        chosen
+            .into_iter()
+            .filter_map(|id| {
+                let candidate = self.candidates.get_mut(&id)?;
+                let addrs = &candidate.dial_addrs;
+                let addr = addrs[candidate.dial_attempts as usize % addrs.len()];
+                candidate.dial_attempts += 1;
+                Some((id, addr))
+            })
+            .collect()
+    }
+
+    /// Records a connection to `node`, which resets its dial attempts.
+    pub fn on_connected(&mut self, node: NodeId) {
+        if let Some(candidate) = self.candidates.get_mut(&node) {
+            candidate.dial_attempts = 0;
+        }
+    }
+}
+
+/// Whether an advertised address can name a remote node. Loopback addresses
+/// only do for neighbours that are themselves on this host.
+fn is_dialable(addr: IpAddr, relay_ip: IpAddr) -> bool {
+    !addr.is_unspecified() && !addr.is_multicast() && (!addr.is_loopback() || relay_ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Identity, Version};
+
+    fn create_addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    const RELAY: NodeId = NodeId([1; 32]);
+    const LOOPBACK: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    fn advertise(identity: &Identity, sequence: u64, addrs: Vec<SocketAddr>) -> SignedPeerAdvertisement {
+        identity.advertise(addrs, Version { epoch: 1, sequence })
+    }
+
+    #[test]
+    fn test_insert_rejects_forged_advertisement() {
+        let mut pex = PeerExchange::new(Identity::new().node_id, 10);
+        let mut forged = advertise(&Identity::new(), 0, vec![create_addr(5001)]);
+        forged.advertisement.listen_addrs = vec![create_addr(6666)];
+
+        assert!(pex.insert(RELAY, LOOPBACK, forged).is_err());
+        assert_eq!(pex.candidates().count(), 0);
+    }
+
+    #[test]
+    fn test_insert_keeps_newest_advertisement() {
+        let self_identity = Identity::new();
+        let mut pex = PeerExchange::new(self_identity.node_id, 10);
+        let peer = Identity::new();
+
+        assert!(pex.insert(RELAY, LOOPBACK, advertise(&peer, 1, vec![create_addr(5001)])).unwrap());
+        assert!(!pex.insert(RELAY, LOOPBACK, advertise(&peer, 0, vec![create_addr(5002)])).unwrap());
+        assert!(pex.insert(RELAY, LOOPBACK, advertise(&peer, 2, vec![create_addr(5003)])).unwrap());
+        assert_eq!(pex.candidates().next().unwrap().listen_addrs, vec![create_addr(5003)]);
+
+        // The node's own advertisement is never a candidate.
+        assert!(!pex.insert(RELAY, LOOPBACK, advertise(&self_identity, 0, vec![create_addr(5000)])).unwrap());
+        assert_eq!(pex.candidates().count(), 1);
+    }
+
+    #[test]
+    fn test_insert_ignores_undialable_and_oversized_advertisements() {
+        let mut pex = PeerExchange::new(Identity::new().node_id, 10);
+        let public_ip: IpAddr = "203.0.113.1".parse().unwrap();
+        let addr = |ip: &str| SocketAddr::new(ip.parse().unwrap(), 5000);
+
+        for ip in ["0.0.0.0", "224.0.0.1", "::", "ff02::1"] {
+            assert!(!pex.insert(RELAY, LOOPBACK, advertise(&Identity::new(), 0, vec![addr(ip)])).unwrap(), "{ip}");
+        }
+        // Loopback addresses are only taken from neighbours on this host.
+        let local = advertise(&Identity::new(), 0, vec![create_addr(5001)]);
+        assert!(!pex.insert(NodeId([2; 32]), public_ip, local.clone()).unwrap());
+        assert!(pex.insert(RELAY, LOOPBACK, local).unwrap());
+
+        let too_many = (0..=MAX_LISTEN_ADDRS as u16).map(|port| create_addr(6000 + port)).collect();
+        assert!(!pex.insert(RELAY, LOOPBACK, advertise(&Identity::new(), 0, too_many)).unwrap());
+
+        // Undialable addresses are skipped when dialing the rest.
+        let mixed = Identity::new();
+        pex.insert(RELAY, LOOPBACK, advertise(&mixed, 0, vec![addr("0.0.0.0"), create_addr(5002)])).unwrap();
+        let dials = pex.select_dials(5, |id| *id != mixed.node_id);
+        assert_eq!(dials, vec![(mixed.node_id, create_addr(5002))]);
+    }
+
+    #[test]
+    fn test_insert_limits_advertisements_per_relay_per_round() {
+        let mut pex = PeerExchange::new(Identity::new().node_id, 100);
+        let insert = |pex: &mut PeerExchange, relay| {
+            pex.insert(relay, LOOPBACK, advertise(&Identity::new(), 0, vec![create_addr(5001)])).unwrap()
+        };
+        let added = (0..MAX_ADVERTISEMENTS_PER_ROUND + 5).filter(|_| insert(&mut pex, RELAY)).count();
+        assert_eq!(added, MAX_ADVERTISEMENTS_PER_ROUND);
+        assert!(insert(&mut pex, NodeId([2; 32])), "Other neighbours have their own share");
+
+        pex.start_round();
+        assert!(insert(&mut pex, RELAY), "The share is renewed every round");
+    }
+
+    #[test]
+    fn test_pool_is_bounded() {
+        let mut pex = PeerExchange::new(Identity::new().node_id, 3);
+        for port in 0..10 {
+            pex.insert(NodeId([port as u8; 32]), LOOPBACK, advertise(&Identity::new(), 0, vec![create_addr(5000 + port)])).unwrap();
+        }
+        assert_eq!(pex.candidates().count(), 3);
+        assert_eq!(pex.sample().len(), 3);
+    }
+
+    #[test]
+    fn test_select_dials_skips_connected_and_drops_unreachable() {
+        let mut pex = PeerExchange::new(Identity::new().node_id, 10);
+        let (connected, unreachable) = (Identity::new(), Identity::new());
+        pex.insert(RELAY, LOOPBACK, advertise(&connected, 0, vec![create_addr(5001)])).unwrap();
+        pex.insert(RELAY, LOOPBACK, advertise(&unreachable, 0, vec![create_addr(5002), create_addr(5003)])).unwrap();
+
+        let is_connected = |id: &NodeId| *id == connected.node_id;
+        let dials: Vec<_> = (0..MAX_DIAL_ATTEMPTS).flat_map(|_| pex.select_dials(5, is_connected)).collect();
+        let expected: Vec<_> = [5002, 5003, 5002].map(|port| (unreachable.node_id, create_addr(port))).into();
+        assert_eq!(dials, expected, "Dials should cycle through the advertised addresses");
+
+        assert!(pex.select_dials(5, is_connected).is_empty());
+        assert_eq!(pex.candidates().count(), 1, "Only the connected candidate should remain");
+    }
+}
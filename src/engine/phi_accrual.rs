@@ -0,0 +1,187 @@
+//! src/engine/phi_accrual.rs
+//!
+//! Implements the phi-accrual failure detector. For each node, the intervals
+//! between consecutive updates are kept in a sliding window. Instead of a
+//! binary alive/dead verdict, the detector reports phi: how unlikely the
+//! silence since the last update is, given that history, on a logarithmic
+//! scale. A phi of 1 means a 10% chance that an update is still on its way,
+//! 2 means 1%, and so on.
+
+use crate::domain::NodeId;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// The number of inter-arrival intervals kept per node.
+pub const MAX_SAMPLES: usize = 100;
+/// The number of intervals needed before phi is computed.
+pub const MIN_SAMPLES: usize = 3;
+
+/// The update history of a single node.
+#[derive(Debug)]
+struct ArrivalWindow {
+    last_arrival: Instant,
+    // Intervals between consecutive arrivals, in milliseconds.
+    intervals: VecDeque<f64>,
+}
+
+impl ArrivalWindow {
+    fn mean_and_std_deviation(&self) -> (f64, f64) {
+        let count = self.intervals.len() as f64;
+        let mean = self.intervals.iter().sum::<f64>() / count;
+        let variance = self.intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / count;
+        (mean, variance.sqrt())
+    }
+}
+
+/// Update arrival history for every known node.
+#[derive(Debug)]
+pub struct PhiAccrual {
+    windows: HashMap<NodeId, ArrivalWindow>,
+    // A floor for the standard deviation, so that perfectly regular updates
+    // do not make phi spike at the first small delay.
+    min_std_deviation_ms: f64,
+}
+
+impl PhiAccrual {
+    pub fn new(min_std_deviation: Duration) -> Self {
+        Self {
+            windows: HashMap::new(),
+            min_std_deviation_ms: min_std_deviation.as_secs_f64() * 1000.0,
+        }
+    }
+
+    /// Records an update from `node` at `now`.
+    pub fn heartbeat(&mut self, node: NodeId, now: Instant) {
+        match self.windows.get_mut(&node) {
+            Some(window) => {
+                let interval = now.saturating_duration_since(window.last_arrival);
+                if window.intervals.len() == MAX_SAMPLES {
+                    window.intervals.pop_front();
+                }
+                window.intervals.push_back(interval.as_secs_f64() * 1000.0);
+                window.last_arrival = now;
+            }
+            None => {
+                self.windows.insert(node, ArrivalWindow { last_arrival: now, intervals: VecDeque::new() });
+            }
+        }
+    }
+
+    /// Forgets the history of `node`.
+    pub fn remove(&mut self, node: &NodeId) {
+        self.windows.remove(node);
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &NodeId> {
+        self.windows.keys()
+    }
+
+    /// When the latest update from `node` arrived.
+    pub fn last_arrival(&self, node: &NodeId) -> Option<Instant> {
+        self.windows.get(node).map(|window| window.last_arrival)
+    }
+
+    /// The suspicion level of `node` at `now`, or `None` if fewer than
+    /// `MIN_SAMPLES` intervals were observed.
+    pub fn phi(&self, node: &NodeId, now: Instant) -> Option<f64> {
+        let window = self.windows.get(node)?;
+        if window.intervals.len() < MIN_SAMPLES {
+            return None;
+        }
+        let (mean, std_deviation) = window.mean_and_std_deviation();
+        let elapsed = now.saturating_duration_since(window.last_arrival).as_secs_f64() * 1000.0;
+        Some(phi(elapsed, mean, std_deviation.max(self.min_std_deviation_ms)))
+    }
+}
+
+/// Computes -log10 of the probability that an update arrives later than
+/// `elapsed`, with intervals normally distributed around `mean`. The normal
+/// CDF is approximated by a logistic function, as in Akka's detector, and
+/// evaluated as a softplus so that long silences do not overflow.
+fn phi(elapsed: f64, mean: f64, std_deviation: f64) -> f64 {
+    let y = (elapsed - mean) / std_deviation;
+    let x = y * (1.5976 + 0.070566 * y * y);
+    let softplus = if x > 0.0 { x + (-x).exp().ln_1p() } else { x.exp().ln_1p() };
+    softplus / std::f64::consts::LN_10
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_node_id(id: u8) -> NodeId {
+        let mut bytes = [0u8; 32];
+        bytes[0] = id;
+        NodeId(bytes)
+    }
+
+    /// Records `count` updates from `node`, `interval` apart, and returns the
+    /// time of the last one.
+    fn heartbeats(detector: &mut PhiAccrual, node: NodeId, start: Instant, interval: Duration, count: u32) -> Instant {
+        for i in 0..count {
+            detector.heartbeat(node, start + interval * i);
+        }
+        start + interval * (count - 1)
+    }
+
+    #[test]
+    fn test_phi_requires_enough_samples() {
+        let mut detector = PhiAccrual::new(Duration::from_millis(100));
+        let node = create_node_id(1);
+        let last = heartbeats(&mut detector, node, Instant::now(), Duration::from_secs(1), MIN_SAMPLES as u32);
+        assert!(detector.phi(&node, last).is_none());
+
+        detector.heartbeat(node, last + Duration::from_secs(1));
+        assert!(detector.phi(&node, last + Duration::from_secs(1)).is_some());
+        assert!(detector.phi(&create_node_id(2), last).is_none());
+    }
+
+    #[test]
+    fn test_phi_grows_with_silence() {
+        let mut detector = PhiAccrual::new(Duration::from_millis(100));
+        let node = create_node_id(1);
+        let interval = Duration::from_secs(1);
+        let last = heartbeats(&mut detector, node, Instant::now(), interval, 10);
+
+        let on_time = detector.phi(&node, last + interval).unwrap();
+        let late = detector.phi(&node, last + interval * 2).unwrap();
+        let silent = detector.phi(&node, last + interval * 60).unwrap();
+        assert!(on_time < 1.0, "An update due now is not suspicious, got {on_time}");
+        assert!(late > on_time);
+        assert!(silent > late && silent.is_finite(), "Long silences should give a large, finite phi");
+    }
+
+    #[test]
+    fn test_irregular_history_tolerates_longer_silence() {
+        let mut detector = PhiAccrual::new(Duration::from_millis(100));
+        let (regular, irregular) = (create_node_id(1), create_node_id(2));
+        let start = Instant::now();
+        let last = heartbeats(&mut detector, regular, start, Duration::from_secs(1), 10);
+
+        let mut at = start;
+        for i in 0..10 {
+            at += Duration::from_millis(if i % 2 == 0 { 200 } else { 1800 });
+            detector.heartbeat(irregular, at);
+        }
+
+        let silence = Duration::from_secs(2);
+        let regular_phi = detector.phi(&regular, last + silence).unwrap();
+        let irregular_phi = detector.phi(&irregular, at + silence).unwrap();
+        assert!(irregular_phi < regular_phi);
+    }
+
+    #[test]
+    fn test_window_keeps_only_recent_intervals() {
+        let mut detector = PhiAccrual::new(Duration::from_millis(1));
+        let node = create_node_id(1);
+        let start = Instant::now();
+        let last = heartbeats(&mut detector, node, start, Duration::from_millis(100), MAX_SAMPLES as u32 + 1);
+        // Once slow updates fill the window, the earlier fast ones no longer count.
+        let last = heartbeats(&mut detector, node, last, Duration::from_secs(1), MAX_SAMPLES as u32 + 1);
+        assert!(detector.phi(&node, last + Duration::from_secs(1)).unwrap() < 1.0);
+        assert_eq!(detector.windows[&node].intervals.len(), MAX_SAMPLES);
+    }
+}
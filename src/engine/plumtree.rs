@@ -0,0 +1,303 @@
+//! src/engine/plumtree.rs
+//!
+//! Implements the peer bookkeeping of Plumtree (epidemic broadcast trees).
+//! Each neighbour is either eager, receiving full messages, or lazy, receiving
+//! only announcements (IHAVE). A neighbour that delivers a duplicate is pruned
+//! to lazy, so the eager links converge on a spanning tree. When an announced
+//! message does not arrive over the tree in time, it is pulled with a GRAFT,
+//! which also adds the announcer to the tree and so repairs it after failures.
+
+use crate::domain::{MessageId, NodeId, Version};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// The most announced but missing messages tracked at once, as announcements
+/// are unsigned and cost a peer nothing to make up. Announcements of further
+/// messages are ignored until some arrive or are given up on; anti-entropy
+/// still repairs those.
+pub const MAX_MISSING: usize = 1_024;
+/// How many announcers a missing message is grafted from before it is given up on.
+pub const MAX_GRAFT_ATTEMPTS: usize = 3;
+
+/// A request to pull a missing message from a peer that announced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Graft {
+    pub peer: NodeId,
+    pub id: MessageId,
+    pub originator: NodeId,
+    pub kind: String,
+}
+
+/// A message that was announced but not yet received.
+#[derive(Debug)]
+struct Missing {
+    originator: NodeId,
+    kind: String,
+    version: Version,
+    // Peers that announced the message, in the order they did so.
+    announcers: VecDeque<NodeId>,
+    deadline: Instant,
+    // Grafts sent for the message so far.
+    attempts: usize,
+}
+
+/// The eager and lazy peer sets of one node, and the messages it is waiting for.
+#[derive(Debug)]
+pub struct Plumtree {
+    eager: HashSet<NodeId>,
+    lazy: HashSet<NodeId>,
+    missing: HashMap<MessageId, Missing>,
+    graft_timeout: Duration,
+}
+
+impl Plumtree {
+    pub fn new(graft_timeout: Duration) -> Self {
+        Self {
+            eager: HashSet::new(),
+            lazy: HashSet::new(),
+            missing: HashMap::new(),
+            graft_timeout,
+        }
+    }
+
+    /// Adds a neighbour. New neighbours start out eager; redundant links are
+    /// pruned as duplicates arrive over them.
+    pub fn add_peer(&mut self, peer: NodeId) {
+        if !self.lazy.contains(&peer) {
+            self.eager.insert(peer);
+        }
+    }
+
+    /// Forgets a neighbour that disconnected.
+    pub fn remove_peer(&mut self, peer: NodeId) {
+        self.eager.remove(&peer);
+        self.lazy.remove(&peer);
+        for missing in self.missing.values_mut() {
+            missing.announcers.retain(|announcer| *announcer != peer);
+        }
+    }
+
+    pub fn eager_peers(&self) -> impl Iterator<Item = &NodeId> {
+        self.eager.iter()
+    }
+
+    pub fn lazy_peers(&self) -> impl Iterator<Item = &NodeId> {
+        self.lazy.iter()
+    }
+
+    /// Records that `peer` delivered a new message. The peer joins the tree,
+    /// and announcements of this or older messages of the same kind from the
+    /// same originator no longer need to be pulled.
+    pub fn on_new_message(&mut self, peer: NodeId, originator: NodeId, kind: &str, version: Version) {
+        self.missing.retain(|_, missing| {
+            missing.originator != originator || missing.kind != kind || missing.version > version
+        });
+        self.on_graft(peer);
+    }
+
+    /// Records that `peer` delivered a message that was already known. Returns
+    /// whether the peer was eager, in which case it should be sent a PRUNE.
+    pub fn on_duplicate(&mut self, peer: NodeId) -> bool {
+        self.on_prune(peer)
+    }
+
+    /// Moves `peer` to the lazy set. Returns whether it was eager.
+    pub fn on_prune(&mut self, peer: NodeId) -> bool {
+        let was_eager = self.eager.remove(&peer);
+        if was_eager {
+            self.lazy.insert(peer);
+        }
+        was_eager
+    }
+
+    /// Moves `peer` to the eager set.
+    pub fn on_graft(&mut self, peer: NodeId) {
+        self.lazy.remove(&peer);
+        self.eager.insert(peer);
+    }
+
+    /// Records that `peer` announced a message this node does not hold. If the
+    /// message has not arrived `graft_timeout` after its first announcement,
+    /// `due_grafts` requests it. Ignored if `MAX_MISSING` other messages are
+    /// already awaited.
+    pub fn on_ihave(&mut self, peer: NodeId, id: MessageId, originator: NodeId, kind: &str, version: Version, now: Instant) {
+        if self.missing.len() >= MAX_MISSING && !self.missing.contains_key(&id) {
+            return;
+        }
+        let missing = self.missing.entry(id).or_insert_with(|| Missing {
+            originator,
+            kind: kind.to_string(),
+            version,
+            announcers: VecDeque::new(),
+            deadline: now + self.graft_timeout,
+            attempts: 0,
+        });
+        if !missing.announcers.contains(&peer) {
+            missing.announcers.push_back(peer);
+        }
+    }
+
+    /// Returns the grafts due at `now`: one per overdue message, sent to the
+    /// next peer that announced it, which becomes eager. Each message then
+    /// waits another `graft_timeout` before its next announcer is tried, and is
+    /// forgotten once none are left or `MAX_GRAFT_ATTEMPTS` have been made.
+    pub fn due_grafts(&mut self, now: Instant) -> Vec<Graft> {
+        let mut grafts = Vec::new();
+        let mut promoted = Vec::new();
+        self.missing.retain(|id, missing| {
+            if missing.deadline > now {
+                return true;
+            }
+            if missing.attempts == MAX_GRAFT_ATTEMPTS {
+                return false;
+            }
+            let Some(peer) = missing.announcers.pop_front() else {
+                return false;
+            };
+            grafts.push(Graft { peer, id: *id, originator: missing.originator, kind: missing.kind.clone() });
+            promoted.push(peer);
+            missing.deadline = now + self.graft_timeout;
+            missing.attempts += 1;
+            true
+        });
+        for peer in promoted {
+            self.on_graft(peer);
+        }
+        grafts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_node_id(id: u8) -> NodeId {
+        let mut bytes = [0u8; 32];
+        bytes[0] = id;
+        NodeId(bytes)
+    }
+
+    fn create_message_id(id: u8) -> MessageId {
+        MessageId([id; 32])
+    }
+
+    const VERSION: Version = Version { epoch: 1, sequence: 1 };
+    const KIND: &str = "telemetry";
+
+    #[test]
+    fn test_duplicate_delivery_prunes_eager_peer_once() {
+        let mut plumtree = Plumtree::new(Duration::from_secs(1));
+        let (a, b) = (create_node_id(1), create_node_id(2));
+        plumtree.add_peer(a);
+        plumtree.add_peer(b);
+        assert_eq!(plumtree.eager_peers().count(), 2);
+
+        plumtree.on_new_message(a, create_node_id(9), KIND, VERSION);
+        assert!(plumtree.on_duplicate(b), "First duplicate from an eager peer should prune it");
+        assert!(!plumtree.on_duplicate(b), "A lazy peer should not be pruned again");
+
+        assert_eq!(plumtree.eager_peers().collect::<Vec<_>>(), vec![&a]);
+        assert_eq!(plumtree.lazy_peers().collect::<Vec<_>>(), vec![&b]);
+
+        // A reconnecting peer keeps its lazy role.
+        plumtree.add_peer(b);
+        assert_eq!(plumtree.lazy_peers().count(), 1);
+    }
+
+    #[test]
+    fn test_missing_message_is_grafted_from_announcers_in_turn() {
+        let timeout = Duration::from_millis(100);
+        let mut plumtree = Plumtree::new(timeout);
+        let (a, b, originator) = (create_node_id(1), create_node_id(2), create_node_id(9));
+        let id = create_message_id(1);
+        plumtree.add_peer(a);
+        plumtree.add_peer(b);
+        plumtree.on_prune(a);
+        plumtree.on_prune(b);
+
+        let now = Instant::now();
+        plumtree.on_ihave(a, id, originator, KIND, VERSION, now);
+        plumtree.on_ihave(b, id, originator, KIND, VERSION, now);
+        plumtree.on_ihave(a, id, originator, KIND, VERSION, now);
+        assert!(plumtree.due_grafts(now).is_empty(), "Nothing is due before the timeout");
+
+        let grafts = plumtree.due_grafts(now + timeout);
+        assert_eq!(grafts, vec![Graft { peer: a, id, originator, kind: KIND.to_string() }]);
+        assert!(plumtree.eager_peers().any(|peer| *peer == a), "Grafted peer should become eager");
+
+        // If the grafted peer does not deliver either, the next announcer is tried.
+        let grafts = plumtree.due_grafts(now + timeout * 2);
+        assert_eq!(grafts, vec![Graft { peer: b, id, originator, kind: KIND.to_string() }]);
+        assert!(plumtree.due_grafts(now + timeout * 3).is_empty());
+        assert!(plumtree.missing.is_empty(), "Message with no announcers left should be forgotten");
+    }
+
+    #[test]
+    fn test_delivery_cancels_pending_grafts_for_older_versions_of_its_kind() {
+        let mut plumtree = Plumtree::new(Duration::from_millis(100));
+        let (a, b, originator) = (create_node_id(1), create_node_id(2), create_node_id(9));
+        let now = Instant::now();
+        let newer = Version { epoch: 1, sequence: 2 };
+        plumtree.on_ihave(a, create_message_id(1), originator, KIND, VERSION, now);
+        plumtree.on_ihave(a, create_message_id(2), originator, KIND, newer, now);
+
+        plumtree.on_ihave(a, create_message_id(3), originator, "feature_flags", VERSION, now);
+
+        plumtree.on_new_message(b, originator, KIND, VERSION);
+        let grafts = plumtree.due_grafts(now + Duration::from_millis(100));
+        let mut ids: Vec<_> = grafts.iter().map(|graft| graft.id).collect();
+        ids.sort_by_key(|id| id.0);
+        assert_eq!(ids, vec![create_message_id(2), create_message_id(3)], "Other kinds are still awaited");
+    }
+
+    #[test]
+    fn test_missing_message_is_given_up_after_max_graft_attempts() {
+        let timeout = Duration::from_millis(100);
+        let mut plumtree = Plumtree::new(timeout);
+        let (originator, id) = (create_node_id(99), create_message_id(1));
+        let now = Instant::now();
+        for peer in 0..=MAX_GRAFT_ATTEMPTS as u8 {
+            plumtree.on_ihave(create_node_id(peer), id, originator, KIND, VERSION, now);
+        }
+
+        let grafts: usize = (1..=MAX_GRAFT_ATTEMPTS as u32 + 1)
+            .map(|round| plumtree.due_grafts(now + timeout * round).len())
+            .sum();
+        assert_eq!(grafts, MAX_GRAFT_ATTEMPTS, "The last announcer should not be tried");
+        assert!(plumtree.missing.is_empty());
+    }
+
+    #[test]
+    fn test_announcements_beyond_max_missing_are_ignored() {
+        let mut plumtree = Plumtree::new(Duration::from_millis(100));
+        let (peer, originator) = (create_node_id(1), create_node_id(9));
+        let now = Instant::now();
+        for n in 0..=MAX_MISSING as u32 {
+            let mut id = [0u8; 32];
+            id[..4].copy_from_slice(&n.to_be_bytes());
+            plumtree.on_ihave(peer, MessageId(id), originator, KIND, VERSION, now);
+        }
+        assert_eq!(plumtree.missing.len(), MAX_MISSING);
+
+        // Further announcers of an awaited message are still recorded.
+        plumtree.on_ihave(create_node_id(2), MessageId([0; 32]), originator, KIND, VERSION, now);
+        assert_eq!(plumtree.missing[&MessageId([0; 32])].announcers.len(), 2);
+    }
+
+    #[test]
+    fn test_removed_peer_is_not_grafted() {
+        let timeout = Duration::from_millis(100);
+        let mut plumtree = Plumtree::new(timeout);
+        let (a, originator) = (create_node_id(1), create_node_id(9));
+        let now = Instant::now();
+        plumtree.add_peer(a);
+        plumtree.on_ihave(a, create_message_id(1), originator, KIND, VERSION, now);
+
+        plumtree.remove_peer(a);
+        assert!(plumtree.due_grafts(now + timeout).is_empty());
+        assert_eq!(plumtree.eager_peers().count(), 0);
+    }
+}
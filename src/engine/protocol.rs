@@ -2,22 +2,148 @@
 //!
 //! Implements the specific gossip propagation algorithm. By isolating this
 //! logic, the protocol can be easily analyzed, tested, and replaced.
+//!
+//! The `Engine` delegates target selection to a `GossipStrategy`. The built-in
+//! strategies are chosen with `Config::peer_selection`; other implementations
+//! can be installed with `Engine::with_strategy`.
 
-use crate::domain::NodeId;
-use rand::{seq::SliceRandom, thread_rng};
+use crate::{
+    config::{Config, PeerSelection},
+    domain::{latest_record, NodeId, NodeRecords, SignedMessage},
+};
+use rand::{seq::SliceRandom, thread_rng, Rng};
 use std::{collections::HashMap, net::SocketAddr};
 
+/// Decides which peers a message is forwarded to.
+pub trait GossipStrategy: Send + Sync {
+    /// Returns the peers `message` should be sent to, given the node's current
+    /// view of its neighbourhood.
+    fn select_targets(&mut self, message: &SignedMessage, view: &PeerView<'_>) -> Vec<(NodeId, SocketAddr)>;
+}
+
+/// The node's view of its neighbourhood, passed to a `GossipStrategy`.
+pub struct PeerView<'a> {
+    /// The neighbours in the node's active view, with their addresses.
+    pub known_peers: &'a HashMap<NodeId, SocketAddr>,
+    /// The number of live connections to each connected peer.
+    pub active_peers: &'a HashMap<NodeId, usize>,
+    /// The latest messages accepted from each node, including this one.
+    pub nodes: &'a HashMap<NodeId, NodeRecords>,
+    /// The community this node belongs to.
+    pub local_community: u32,
+}
+
+impl PeerView<'_> {
+    /// Returns the community `id` last announced, if it is known.
+    pub fn community_of(&self, id: &NodeId) -> Option<u32> {
+        self.nodes.get(id).and_then(latest_record).map(|record| record.message.message.community_id)
+    }
+
+    /// Returns whether at least one connection to `id` is live.
+    pub fn is_active(&self, id: &NodeId) -> bool {
+        self.active_peers.contains_key(id)
+    }
+}
+
+/// Builds the strategy selected by `config`.
+pub fn strategy_from_config(config: &Config) -> Box<dyn GossipStrategy> {
+    match config.peer_selection {
+        PeerSelection::Random => Box::new(RandomFanout {
+            fanout: config.gossip_factor,
+        }),
+        PeerSelection::Community => Box::new(CommunityBiased {
+            fanout: config.gossip_factor,
+            bridge_fraction: config.community_bridge_fraction,
+        }),
+        PeerSelection::RoundRobin => Box::new(RoundRobin::new(config.gossip_factor)),
+    }
+}
+
+/// Forwards each message to `fanout` known peers chosen uniformly at random.
+#[derive(Debug, Clone)]
+pub struct RandomFanout {
+    pub fanout: usize,
+}
+
+impl GossipStrategy for RandomFanout {
+    fn select_targets(&mut self, message: &SignedMessage, view: &PeerView<'_>) -> Vec<(NodeId, SocketAddr)> {
+        select_peers(view.known_peers, message.originator, self.fanout)
+            .into_iter()
+            .map(|(id, addr)| (*id, *addr))
+            .collect()
+    }
+}
+
+/// Forwards most of each fanout to peers in the message's community, and
+/// `bridge_fraction` of it to peers in other communities.
+#[derive(Debug, Clone)]
+pub struct CommunityBiased {
+    pub fanout: usize,
+    pub bridge_fraction: f64,
+}
+
+impl GossipStrategy for CommunityBiased {
+    fn select_targets(&mut self, message: &SignedMessage, view: &PeerView<'_>) -> Vec<(NodeId, SocketAddr)> {
+        select_peers_by_community(
+            view.known_peers,
+            message.originator,
+            message.message.community_id,
+            |id| view.community_of(id),
+            self.fanout,
+            self.bridge_fraction,
+        )
+        .into_iter()
+        .map(|(id, addr)| (*id, *addr))
+        .collect()
+    }
+}
+
+/// Cycles through the known peers in a fixed order, forwarding each message to
+/// the next `fanout` of them. Every peer is reached equally often, which makes
+/// propagation deterministic for a stable membership.
+#[derive(Debug, Clone)]
+pub struct RoundRobin {
+    fanout: usize,
+    cursor: usize,
+}
+
+impl RoundRobin {
+    pub fn new(fanout: usize) -> Self {
+        Self { fanout, cursor: 0 }
+    }
+}
+
+impl GossipStrategy for RoundRobin {
+    fn select_targets(&mut self, message: &SignedMessage, view: &PeerView<'_>) -> Vec<(NodeId, SocketAddr)> {
+        let mut peers: Vec<_> = view
+            .known_peers
+            .iter()
+            .filter(|(id, _)| **id != message.originator)
+            .map(|(id, addr)| (*id, *addr))
+            .collect();
+        if peers.is_empty() {
+            return Vec::new();
+        }
+        peers.sort_by_key(|(id, _)| id.0);
+
+        let count = self.fanout.min(peers.len());
+        let start = self.cursor % peers.len();
+        self.cursor = start + count;
+        peers.iter().cycle().skip(start).take(count).copied().collect()
+    }
+}
+
 /// Selects a random subset of known peers to forward a message to.
 ///
 /// # Arguments
 /// * `known_peers` - A map of all peers the node is aware of.
 /// * `exclude_originator` - The `NodeId` of the message originator, to prevent sending it back.
 /// * `gossip_factor` - The number of peers to select.
-pub fn select_peers<'a>(
-    known_peers: &'a HashMap<NodeId, SocketAddr>,
+fn select_peers(
+    known_peers: &HashMap<NodeId, SocketAddr>,
     exclude_originator: NodeId,
     gossip_factor: usize,
-) -> Vec<(&'a NodeId, &'a SocketAddr)> {
+) -> Vec<(&NodeId, &SocketAddr)> {
     let mut rng = thread_rng();
     known_peers
         .iter()
@@ -28,9 +154,61 @@ pub fn select_peers<'a>(
         .collect()
 }
 
+/// Selects up to `gossip_factor` peers, favouring those in `community_id`.
+///
+/// On average, `bridge_fraction` of the fanout goes to peers outside the
+/// community, so that information still crosses community boundaries. Peers
+/// whose community is not yet known count as outside. If either group has too
+/// few peers, the other one fills the remaining slots.
+///
+/// # Arguments
+/// * `known_peers` - A map of all peers the node is aware of.
+/// * `exclude_originator` - The `NodeId` of the message originator, to prevent sending it back.
+/// * `community_id` - The community of the message being forwarded.
+/// * `community_of` - Looks up the community a peer belongs to, if known.
+/// * `gossip_factor` - The number of peers to select.
+/// * `bridge_fraction` - The share of the fanout reserved for other communities.
+fn select_peers_by_community<F>(
+    known_peers: &HashMap<NodeId, SocketAddr>,
+    exclude_originator: NodeId,
+    community_id: u32,
+    community_of: F,
+    gossip_factor: usize,
+    bridge_fraction: f64,
+) -> Vec<(&NodeId, &SocketAddr)>
+where
+    F: Fn(&NodeId) -> Option<u32>,
+{
+    let mut rng = thread_rng();
+    let (mut inside, mut outside): (Vec<_>, Vec<_>) = known_peers
+        .iter()
+        .filter(|(id, _)| **id != exclude_originator)
+        .partition(|(id, _)| community_of(id) == Some(community_id));
+    inside.shuffle(&mut rng);
+    outside.shuffle(&mut rng);
+
+    // A fractional number of bridges is rounded up with a probability equal
+    // to its fractional part, so the configured fraction holds on average
+    // even when the fanout is small.
+    let expected_bridges = gossip_factor as f64 * bridge_fraction.clamp(0.0, 1.0);
+    let mut bridges = expected_bridges.floor() as usize;
+    if rng.gen::<f64>() < expected_bridges.fract() {
+        bridges += 1;
+    }
+
+    let bridges = bridges.min(outside.len());
+    let locals = (gossip_factor - bridges).min(inside.len());
+    let bridges = (gossip_factor - locals).min(outside.len());
+    inside.truncate(locals);
+    outside.truncate(bridges);
+    inside.extend(outside);
+    inside
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::{GossipPayload, Identity, Payload, TelemetryData, Version};
     use std::str::FromStr;
 
     // Helper to create a dummy NodeId for testing.
@@ -69,6 +247,164 @@ mod tests {
         assert_eq!(selected.len(), 3);
     }
 
+    // Builds a peer map of `count` peers per community, with ids assigned
+    // sequentially from 2, and returns it with the community of each peer.
+    fn community_peers(communities: &[u32], count: u8) -> (HashMap<NodeId, SocketAddr>, HashMap<NodeId, u32>) {
+        let mut peers = HashMap::new();
+        let mut membership = HashMap::new();
+        let mut next_id = 2;
+        for &community in communities {
+            for _ in 0..count {
+                let id = create_node_id(next_id);
+                peers.insert(id, SocketAddr::from_str("127.0.0.1:1000").unwrap());
+                membership.insert(id, community);
+                next_id += 1;
+            }
+        }
+        (peers, membership)
+    }
+
+    #[test]
+    fn test_select_peers_by_community_stays_local_without_bridges() {
+        let originator = create_node_id(1);
+        let (peers, membership) = community_peers(&[0, 1, 2], 5);
+
+        for _ in 0..100 {
+            let selected = select_peers_by_community(&peers, originator, 1, |id| membership.get(id).copied(), 3, 0.0);
+            assert_eq!(selected.len(), 3);
+            assert!(selected.iter().all(|(id, _)| membership[*id] == 1));
+        }
+    }
+
+    #[test]
+    fn test_select_peers_by_community_sends_bridge_fraction_outside() {
+        let originator = create_node_id(1);
+        let (peers, membership) = community_peers(&[0, 1], 10);
+
+        // Half of a fanout of 4 goes to the other community, every time.
+        for _ in 0..100 {
+            let selected = select_peers_by_community(&peers, originator, 0, |id| membership.get(id).copied(), 4, 0.5);
+            let bridges = selected.iter().filter(|(id, _)| membership[*id] != 0).count();
+            assert_eq!(selected.len(), 4);
+            assert_eq!(bridges, 2);
+        }
+
+        // A fraction that does not divide the fanout holds on average.
+        let trials = 2000;
+        let total_bridges: usize = (0..trials)
+            .map(|_| {
+                select_peers_by_community(&peers, originator, 0, |id| membership.get(id).copied(), 2, 0.1)
+                    .iter()
+                    .filter(|(id, _)| membership[*id] != 0)
+                    .count()
+            })
+            .sum();
+        let mean = total_bridges as f64 / trials as f64;
+        assert!((0.1..0.3).contains(&mean), "Expected about 0.2 bridges per selection, got {mean}");
+    }
+
+    #[test]
+    fn test_select_peers_by_community_fills_from_other_group() {
+        let originator = create_node_id(1);
+        let (peers, membership) = community_peers(&[0, 1], 2);
+
+        // Only two local peers exist, so the rest of the fanout crosses over.
+        let selected = select_peers_by_community(&peers, originator, 0, |id| membership.get(id).copied(), 3, 0.0);
+        assert_eq!(selected.len(), 3);
+        assert_eq!(selected.iter().filter(|(id, _)| membership[*id] == 0).count(), 2);
+
+        // With no outside peers, bridges are replaced by local peers.
+        let (peers, membership) = community_peers(&[0], 4);
+        let selected = select_peers_by_community(&peers, originator, 0, |id| membership.get(id).copied(), 3, 1.0);
+        assert_eq!(selected.len(), 3);
+    }
+
+    #[test]
+    fn test_select_peers_by_community_treats_unknown_peers_as_outside() {
+        let originator = create_node_id(1);
+        let (mut peers, membership) = community_peers(&[0], 3);
+        let unknown = create_node_id(100);
+        peers.insert(unknown, SocketAddr::from_str("127.0.0.1:1000").unwrap());
+        peers.insert(originator, SocketAddr::from_str("127.0.0.1:1001").unwrap());
+
+        for _ in 0..50 {
+            let selected = select_peers_by_community(&peers, originator, 0, |id| membership.get(id).copied(), 3, 0.0);
+            assert_eq!(selected.len(), 3);
+            assert!(selected.iter().all(|(id, _)| **id != unknown && **id != originator));
+        }
+    }
+
+    fn create_message(originator: NodeId) -> SignedMessage {
+        let mut message = Identity::new().sign(GossipPayload {
+            timestamp_ms: 0,
+            community_id: 0,
+            version: Version { epoch: 0, sequence: 0 },
+            payload: Payload::new(&TelemetryData::default()),
+        });
+        message.originator = originator;
+        message
+    }
+
+    #[test]
+    fn test_round_robin_cycles_through_all_peers() {
+        let originator = create_node_id(1);
+        let mut peers = HashMap::new();
+        for i in 2..=6 {
+            peers.insert(create_node_id(i), SocketAddr::from_str("127.0.0.1:1000").unwrap());
+        }
+        let (active_peers, nodes) = (HashMap::new(), HashMap::new());
+        let view = PeerView { known_peers: &peers, active_peers: &active_peers, nodes: &nodes, local_community: 0 };
+        let message = create_message(originator);
+
+        // Five peers with a fanout of two: every peer is picked twice in five
+        // rounds, and consecutive rounds never repeat a peer.
+        let mut strategy = RoundRobin::new(2);
+        let mut counts: HashMap<NodeId, usize> = HashMap::new();
+        let mut previous = Vec::new();
+        for _ in 0..5 {
+            let selected = strategy.select_targets(&message, &view);
+            assert_eq!(selected.len(), 2);
+            assert!(selected.iter().all(|target| !previous.contains(target)));
+            for (id, _) in &selected {
+                *counts.entry(*id).or_default() += 1;
+            }
+            previous = selected;
+        }
+        assert_eq!(counts.len(), 5);
+        assert!(counts.values().all(|&count| count == 2));
+    }
+
+    #[test]
+    fn test_round_robin_excludes_originator() {
+        let originator = create_node_id(1);
+        let mut peers = HashMap::new();
+        peers.insert(originator, SocketAddr::from_str("127.0.0.1:1001").unwrap());
+        peers.insert(create_node_id(2), SocketAddr::from_str("127.0.0.1:1002").unwrap());
+        let (active_peers, nodes) = (HashMap::new(), HashMap::new());
+        let view = PeerView { known_peers: &peers, active_peers: &active_peers, nodes: &nodes, local_community: 0 };
+
+        let mut strategy = RoundRobin::new(3);
+        let selected = strategy.select_targets(&create_message(originator), &view);
+        assert_eq!(selected, vec![(create_node_id(2), SocketAddr::from_str("127.0.0.1:1002").unwrap())]);
+    }
+
+    #[test]
+    fn test_strategy_from_config_uses_gossip_factor() {
+        let originator = create_node_id(1);
+        let mut peers = HashMap::new();
+        for i in 2..=10 {
+            peers.insert(create_node_id(i), SocketAddr::from_str("127.0.0.1:1000").unwrap());
+        }
+        let (active_peers, nodes) = (HashMap::new(), HashMap::new());
+        let view = PeerView { known_peers: &peers, active_peers: &active_peers, nodes: &nodes, local_community: 0 };
+
+        for peer_selection in [PeerSelection::Random, PeerSelection::Community, PeerSelection::RoundRobin] {
+            let config = Config { peer_selection, gossip_factor: 3, ..Config::default() };
+            let selected = strategy_from_config(&config).select_targets(&create_message(originator), &view);
+            assert_eq!(selected.len(), 3, "{peer_selection:?} should select gossip_factor peers");
+        }
+    }
+
     #[test]
     fn test_select_peers_with_no_valid_peers() {
         let originator = create_node_id(1);
@@ -0,0 +1,137 @@
+//! src/engine/seen_cache.rs
+//!
+//! A bounded, time-expiring cache of recently seen message digests. The
+//! `Engine` consults it before doing any signature verification so that the
+//! same `SignedMessage` arriving from several peers is only processed once.
+
+use crate::domain::MessageId;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Remembers `MessageId`s for at most `ttl`, holding no more than `capacity`
+/// entries. When full, the oldest entries are evicted first.
+#[derive(Debug)]
+pub struct SeenCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<MessageId, Instant>,
+    // Insertion order, used for FIFO eviction. May contain entries that have
+    // since been re-inserted; those are skipped when their timestamp no longer
+    // matches the one stored in `entries`.
+    order: VecDeque<(MessageId, Instant)>,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Returns `true` if `id` was inserted less than `ttl` ago.
+    pub fn contains(&self, id: &MessageId, now: Instant) -> bool {
+        self.entries
+            .get(id)
+            .is_some_and(|&seen_at| now.saturating_duration_since(seen_at) < self.ttl)
+    }
+
+    /// Records `id` as seen at `now`, evicting expired and excess entries.
+    pub fn insert(&mut self, id: MessageId, now: Instant) {
+        self.entries.insert(id, now);
+        self.order.push_back((id, now));
+        self.prune(now);
+    }
+
+    /// Removes all expired entries and enforces the capacity bound.
+    pub fn prune(&mut self, now: Instant) {
+        while let Some(&(id, seen_at)) = self.order.front() {
+            let expired = now.saturating_duration_since(seen_at) >= self.ttl;
+            if !expired && self.entries.len() <= self.capacity {
+                break;
+            }
+            self.order.pop_front();
+            if self.entries.get(&id) == Some(&seen_at) {
+                self.entries.remove(&id);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_message_id(id: u8) -> MessageId {
+        let mut bytes = [0u8; 32];
+        bytes[0] = id;
+        MessageId(bytes)
+    }
+
+    #[test]
+    fn test_seen_cache_reports_inserted_ids() {
+        let mut cache = SeenCache::new(10, Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.insert(create_message_id(1), now);
+
+        assert!(cache.contains(&create_message_id(1), now));
+        assert!(!cache.contains(&create_message_id(2), now));
+    }
+
+    #[test]
+    fn test_seen_cache_expires_entries_after_ttl() {
+        let mut cache = SeenCache::new(10, Duration::from_secs(60));
+        let now = Instant::now();
+        cache.insert(create_message_id(1), now);
+
+        let later = now + Duration::from_secs(61);
+        assert!(!cache.contains(&create_message_id(1), later));
+
+        cache.prune(later);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_seen_cache_evicts_oldest_when_full() {
+        let mut cache = SeenCache::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+
+        for i in 1..=3 {
+            cache.insert(create_message_id(i), now + Duration::from_millis(i as u64));
+        }
+        let now = now + Duration::from_millis(3);
+
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&create_message_id(1), now));
+        assert!(cache.contains(&create_message_id(2), now));
+        assert!(cache.contains(&create_message_id(3), now));
+    }
+
+    #[test]
+    fn test_seen_cache_reinsertion_survives_stale_order_entry() {
+        let mut cache = SeenCache::new(10, Duration::from_secs(60));
+        let now = Instant::now();
+        cache.insert(create_message_id(1), now);
+
+        // Re-insert after expiry; the stale queue entry must not evict the fresh one.
+        let later = now + Duration::from_secs(61);
+        cache.insert(create_message_id(1), later);
+
+        assert!(cache.contains(&create_message_id(1), later));
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,648 @@
+//! src/engine/swim.rs
+//!
+//! Implements the SWIM failure detector. Every protocol period, a node pings a
+//! random member. If no ack arrives within the probe timeout, it asks a few
+//! other members to ping the target on its behalf (PING-REQ). A target that
+//! answered neither way by the end of the period is suspected, and declared
+//! dead unless it refutes the suspicion within the suspicion timeout. Status
+//! changes are disseminated by piggybacking them on outgoing messages; a node
+//! that learns it is suspected refutes by raising its incarnation.
+//!
+//! Status updates are not signed, so a suspicion or death notice from a peer
+//! is only a hint: the node is probed next, and suspected and declared dead
+//! only by this node's own probes. Only the suspected node itself can refute
+//! a suspicion. PING-REQs are only served for members, at the address this
+//! node knows them by. Incarnations share the ordering of
+//! the versions nodes sign their messages with: an update naming a higher
+//! incarnation than the newest version seen signed by its node is made up and
+//! dropped, and a newer signed message revives a suspected or dead node.
+
+use crate::{
+    domain::{MemberStatus, NodeId, Version},
+    transport::{ProbeMessage, StatusUpdate},
+};
+use rand::{seq::IteratorRandom, thread_rng};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// The maximum number of status updates piggybacked on a single message.
+pub const MAX_PIGGYBACKED_UPDATES: usize = 8;
+/// Each update is piggybacked this many times per doubling of the membership,
+/// enough for it to reach every member with high probability.
+const RETRANSMIT_MULTIPLIER: u32 = 3;
+/// How many probe timeouts a node relaying a PING-REQ waits for the target's ack.
+const RELAY_TIMEOUT_FACTOR: u32 = 3;
+
+/// Probe messages to send, with the address of each recipient.
+pub type Outbox = Vec<(SocketAddr, ProbeMessage)>;
+
+#[derive(Debug)]
+struct Member {
+    status: MemberStatus,
+    incarnation: Version,
+    changed_at: Instant,
+    // The newest version seen in a message signed by the node.
+    signed: Version,
+}
+
+/// The probe of the current protocol period.
+#[derive(Debug)]
+struct Probe {
+    seq: u64,
+    target: NodeId,
+    addr: SocketAddr,
+    acked: bool,
+    // When to fall back to indirect probes; `None` once they were sent.
+    indirect_at: Option<Instant>,
+}
+
+/// A ping sent on behalf of another node, whose ack must be relayed back.
+#[derive(Debug)]
+struct Relay {
+    requester: SocketAddr,
+    seq: u64,
+    target: NodeId,
+    expires_at: Instant,
+}
+
+/// The failure detector state of one node.
+#[derive(Debug)]
+pub struct Swim {
+    self_id: NodeId,
+    incarnation: Version,
+    // The newest version this node signed a message with.
+    signed: Version,
+    members: HashMap<NodeId, Member>,
+    probe: Option<Probe>,
+    relays: HashMap<u64, Relay>,
+    next_seq: u64,
+    // Updates still to be piggybacked, with the number of sends left.
+    pending: HashMap<NodeId, (StatusUpdate, u32)>,
+    // Status changes not yet collected by `drain_changes`.
+    changes: Vec<(NodeId, MemberStatus)>,
+    // Members that peers reported as failed, to be probed first.
+    reported: HashSet<NodeId>,
+    probe_timeout: Duration,
+    suspicion_timeout: Duration,
+    indirect_probes: usize,
+}
+
+impl Swim {
+    pub fn new(
+        self_id: NodeId,
+        incarnation: Version,
+        probe_timeout: Duration,
+        suspicion_timeout: Duration,
+        indirect_probes: usize,
+    ) -> Self {
+        Self {
+            self_id,
+            incarnation,
+            signed: Version::default(),
+            members: HashMap::new(),
+            probe: None,
+            relays: HashMap::new(),
+            next_seq: 0,
+            pending: HashMap::new(),
+            changes: Vec::new(),
+            reported: HashSet::new(),
+            probe_timeout,
+            suspicion_timeout,
+            indirect_probes,
+        }
+    }
+
+    /// The status of every known node, including this one.
+    pub fn statuses(&self) -> HashMap<NodeId, MemberStatus> {
+        self.members
+            .iter()
+            .map(|(id, member)| (*id, member.status))
+            .chain([(self.self_id, MemberStatus::Alive)])
+            .collect()
+    }
+
+    pub fn status_of(&self, node: &NodeId) -> Option<MemberStatus> {
+        self.members.get(node).map(|member| member.status)
+    }
+
+    /// This node's incarnation. Peers only believe it once they have seen a
+    /// message it signed at this version or later.
+    pub fn incarnation(&self) -> Version {
+        self.incarnation
+    }
+
+    /// Adds a node seen on the network as alive, unless it is already known.
+    pub fn observe(&mut self, node: NodeId, now: Instant) {
+        if node != self.self_id {
+            self.members.entry(node).or_insert(Member {
+                status: MemberStatus::Alive,
+                incarnation: Version::default(),
+                changed_at: now,
+                signed: Version::default(),
+            });
+        }
+    }
+
+    /// Records that `node` signed a message at `version`. This bounds the
+    /// incarnations believed for it, and revives it if it was suspected or
+    /// declared dead at an older incarnation.
+    pub fn on_signed(&mut self, node: NodeId, version: Version, now: Instant) {
+        if node == self.self_id {
+            self.signed = self.signed.max(version);
+            return;
+        }
+        self.observe(node, now);
+        let member = self.members.get_mut(&node).expect("member was just observed");
+        member.signed = member.signed.max(version);
+        if member.status != MemberStatus::Alive && version > member.incarnation {
+            tracing::info!(node = %node, "Signed message refutes suspicion of node");
+            self.apply(StatusUpdate { node, status: MemberStatus::Alive, incarnation: version }, now);
+        }
+    }
+
+    /// Forgets the members for which `keep` is false.
+    pub fn retain(&mut self, keep: impl Fn(&NodeId) -> bool) {
+        self.members.retain(|id, _| keep(id));
+        self.pending.retain(|id, _| keep(id));
+        self.reported.retain(|id| keep(id));
+    }
+
+    /// Returns the status changes since the last call.
+    pub fn drain_changes(&mut self) -> Vec<(NodeId, MemberStatus)> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Starts a protocol period: suspects the previous target if it never
+    /// acknowledged its probe, then pings a member of `addresses` that is not
+    /// dead, one reported as failed by a peer if any, and a random one
+    /// otherwise.
+    pub fn start_probe(&mut self, addresses: &HashMap<NodeId, SocketAddr>, now: Instant) -> Outbox {
+        if let Some(probe) = self.probe.take() {
+            if !probe.acked {
+                tracing::debug!(node = %probe.target, "Probe unanswered, suspecting node");
+                self.suspect(probe.target, now);
+            }
+        }
+
+        let candidates = || {
+            addresses
+                .iter()
+                .filter(|(id, _)| **id != self.self_id && self.status_of(id) != Some(MemberStatus::Dead))
+        };
+        let target = candidates()
+            .find(|(id, _)| self.reported.contains(id))
+            .or_else(|| candidates().choose(&mut thread_rng()));
+        let Some((&target, &addr)) = target else {
+            return Vec::new();
+        };
+        self.reported.remove(&target);
+        self.observe(target, now);
+
+        let seq = self.next_seq();
+        self.probe = Some(Probe {
+            seq,
+            target,
+            addr,
+            acked: false,
+            indirect_at: Some(now + self.probe_timeout),
+        });
+        vec![(addr, ProbeMessage::Ping { seq, status_updates: self.piggyback() })]
+    }
+
+    /// Sends the indirect probes that are due and declares suspects whose
+    /// suspicion timed out dead.
+    pub fn check_timeouts(&mut self, addresses: &HashMap<NodeId, SocketAddr>, now: Instant) -> Outbox {
+        let mut outbox = Vec::new();
+        if let Some(probe) = &mut self.probe {
+            if !probe.acked && probe.indirect_at.is_some_and(|at| at <= now) {
+                probe.indirect_at = None;
+                let (seq, target, target_addr) = (probe.seq, probe.target, probe.addr);
+                let helpers = addresses
+                    .iter()
+                    .filter(|(id, _)| {
+                        **id != self.self_id
+                            && **id != target
+                            && self.status_of(id) == Some(MemberStatus::Alive)
+                    })
+                    .map(|(_, addr)| *addr)
+                    .choose_multiple(&mut thread_rng(), self.indirect_probes);
+                outbox.extend(
+                    helpers
+                        .into_iter()
+                        .map(|addr| (addr, ProbeMessage::PingReq { seq, target, target_addr })),
+                );
+            }
+        }
+
+        let expired: Vec<_> = self
+            .members
+            .iter()
+            .filter(|(_, member)| {
+                member.status == MemberStatus::Suspect && member.changed_at + self.suspicion_timeout <= now
+            })
+            .map(|(id, member)| StatusUpdate { node: *id, status: MemberStatus::Dead, incarnation: member.incarnation })
+            .collect();
+        for update in expired {
+            tracing::info!(node = %update.node, "Suspicion timed out, declaring node dead");
+            self.apply(update, now);
+        }
+
+        self.relays.retain(|_, relay| relay.expires_at > now);
+        outbox
+    }
+
+    /// Answers a ping from `from`.
+    pub fn on_ping(&mut self, from: SocketAddr, seq: u64, updates: Vec<StatusUpdate>, now: Instant) -> Outbox {
+        self.apply_updates(updates, now);
+        vec![(
+            from,
+            ProbeMessage::Ack { seq, target: self.self_id, status_updates: self.piggyback() },
+        )]
+    }
+
+    /// Pings `target` on behalf of `from`, remembering to relay the ack. The
+    /// target must be a member of `addresses`, and is pinged at the address
+    /// found there rather than the one the requester named, so that peers
+    /// cannot make this node send pings to arbitrary addresses.
+    pub fn on_ping_req(
+        &mut self,
+        from: SocketAddr,
+        seq: u64,
+        target: NodeId,
+        addresses: &HashMap<NodeId, SocketAddr>,
+        now: Instant,
+    ) -> Outbox {
+        let Some(&target_addr) = addresses.get(&target).filter(|_| self.members.contains_key(&target)) else {
+            tracing::debug!(node = %target, "Ignoring PING-REQ for a node that is not a member");
+            return Vec::new();
+        };
+        let relay_seq = self.next_seq();
+        self.relays.insert(relay_seq, Relay {
+            requester: from,
+            seq,
+            target,
+            expires_at: now + self.probe_timeout * RELAY_TIMEOUT_FACTOR,
+        });
+        vec![(target_addr, ProbeMessage::Ping { seq: relay_seq, status_updates: self.piggyback() })]
+    }
+
+    /// Handles an ack: relays it if it answers a ping sent on another node's
+    /// behalf, or completes the current probe.
+    pub fn on_ack(&mut self, seq: u64, target: NodeId, updates: Vec<StatusUpdate>, now: Instant) -> Outbox {
+        self.apply_updates(updates, now);
+        if self.relays.get(&seq).is_some_and(|relay| relay.target == target) {
+            let relay = self.relays.remove(&seq).expect("relay was just found");
+            return vec![(
+                relay.requester,
+                ProbeMessage::Ack { seq: relay.seq, target, status_updates: self.piggyback() },
+            )];
+        }
+        if let Some(probe) = &mut self.probe {
+            if probe.seq == seq && probe.target == target {
+                probe.acked = true;
+            }
+        }
+        Vec::new()
+    }
+
+    /// Applies piggybacked status updates. A suspicion or death notice about
+    /// this node is refuted by raising its incarnation, and one about another
+    /// member only gets it probed next. Updates naming an incarnation their
+    /// node never signed are dropped.
+    pub fn apply_updates(&mut self, updates: Vec<StatusUpdate>, now: Instant) {
+        for update in updates {
+            let signed = match self.members.get(&update.node) {
+                _ if update.node == self.self_id => self.signed,
+                Some(member) => member.signed,
+                None => Version::default(),
+            };
+            if update.incarnation > signed {
+                tracing::debug!(node = %update.node, incarnation = ?update.incarnation, "Dropping update with an unsigned incarnation");
+                continue;
+            }
+            if update.node != self.self_id {
+                if update.status == MemberStatus::Alive {
+                    self.apply(update, now);
+                } else if self.status_of(&update.node) == Some(MemberStatus::Alive) {
+                    self.reported.insert(update.node);
+                }
+            } else if update.status != MemberStatus::Alive {
+                // Peers that never heard from this node directly may hold an
+                // older incarnation, which the current one already overrides.
+                if update.incarnation >= self.incarnation {
+                    let Some(sequence) = update.incarnation.sequence.checked_add(1) else {
+                        tracing::warn!(incarnation = ?update.incarnation, "Cannot refute suspicion at the last incarnation");
+                        continue;
+                    };
+                    self.incarnation = Version { epoch: update.incarnation.epoch, sequence };
+                }
+                tracing::info!(incarnation = ?self.incarnation, "Refuting suspicion of this node");
+                self.enqueue(StatusUpdate {
+                    node: self.self_id,
+                    status: MemberStatus::Alive,
+                    incarnation: self.incarnation,
+                });
+            }
+        }
+    }
+
+    /// Returns the updates to piggyback on an outgoing message, preferring
+    /// those sent the fewest times so far.
+    pub fn piggyback(&mut self) -> Vec<StatusUpdate> {
+        let mut candidates: Vec<_> = self.pending.iter().map(|(id, (_, left))| (*id, *left)).collect();
+        candidates.sort_by_key(|(_, left)| std::cmp::Reverse(*left));
+        candidates.truncate(MAX_PIGGYBACKED_UPDATES);
+
+        let mut updates = Vec::with_capacity(candidates.len());
+        for (id, _) in candidates {
+            let Some((update, left)) = self.pending.get_mut(&id) else {
+                continue;
+            };
+            updates.push(*update);
+            *left -= 1;
+            if *left == 0 {
+                self.pending.remove(&id);
+            }
+        }
+        updates
+    }
+
+    /// Applies `update` if it supersedes what is known about its node. Higher
+    /// incarnations win; at equal incarnations, dead beats suspect beats alive.
+    fn apply(&mut self, update: StatusUpdate, now: Instant) {
+        if let Some(member) = self.members.get(&update.node) {
+            if (update.incarnation, update.status) <= (member.incarnation, member.status) {
+                return;
+            }
+        }
+        let signed = self.members.get(&update.node).map_or(Version::default(), |member| member.signed);
+        let previous = self.members.insert(update.node, Member {
+            status: update.status,
+            incarnation: update.incarnation,
+            changed_at: now,
+            signed,
+        });
+        if previous.is_none_or(|member| member.status != update.status) {
+            self.changes.push((update.node, update.status));
+        }
+        self.enqueue(update);
+    }
+
+    fn suspect(&mut self, node: NodeId, now: Instant) {
+        let Some(member) = self.members.get(&node) else {
+            return;
+        };
+        if member.status == MemberStatus::Alive {
+            let incarnation = member.incarnation;
+            self.apply(StatusUpdate { node, status: MemberStatus::Suspect, incarnation }, now);
+        }
+    }
+
+    fn enqueue(&mut self, update: StatusUpdate) {
+        let cluster_size = self.members.len() as u32 + 1;
+        let transmissions = RETRANSMIT_MULTIPLIER * (cluster_size.ilog2() + 1);
+        self.pending.insert(update.node, (update, transmissions));
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.next_seq += 1;
+        self.next_seq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_node_id(id: u8) -> NodeId {
+        let mut bytes = [0u8; 32];
+        bytes[0] = id;
+        NodeId(bytes)
+    }
+
+    fn create_addr(id: u8) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 7000 + id as u16))
+    }
+
+    const PROBE_TIMEOUT: Duration = Duration::from_millis(100);
+    const SUSPICION_TIMEOUT: Duration = Duration::from_millis(500);
+
+    fn create_swim(id: u8) -> Swim {
+        Swim::new(create_node_id(id), Version::default(), PROBE_TIMEOUT, SUSPICION_TIMEOUT, 2)
+    }
+
+    fn addresses(ids: &[u8]) -> HashMap<NodeId, SocketAddr> {
+        ids.iter().map(|id| (create_node_id(*id), create_addr(*id))).collect()
+    }
+
+    #[test]
+    fn test_unanswered_probe_leads_to_indirect_probes_suspicion_and_death() {
+        let mut swim = create_swim(0);
+        let target = create_node_id(1);
+        let now = Instant::now();
+
+        let outbox = swim.start_probe(&addresses(&[1]), now);
+        assert!(matches!(outbox[..], [(addr, ProbeMessage::Ping { .. })] if addr == create_addr(1)));
+
+        // Other members are asked to probe the silent target once the probe times out.
+        let members = addresses(&[1, 2, 3, 4]);
+        for id in [2, 3, 4] {
+            swim.observe(create_node_id(id), now);
+        }
+        assert!(swim.check_timeouts(&members, now).is_empty());
+        let outbox = swim.check_timeouts(&members, now + PROBE_TIMEOUT);
+        assert_eq!(outbox.len(), 2);
+        assert!(outbox.iter().all(|(addr, message)| *addr != create_addr(1)
+            && matches!(message, ProbeMessage::PingReq { target: t, .. } if *t == target)));
+
+        // The next period suspects it, and the suspicion expires into death.
+        swim.start_probe(&addresses(&[2]), now + PROBE_TIMEOUT * 5);
+        assert_eq!(swim.status_of(&target), Some(MemberStatus::Suspect));
+        swim.check_timeouts(&members, now + PROBE_TIMEOUT * 5 + SUSPICION_TIMEOUT);
+        assert_eq!(swim.status_of(&target), Some(MemberStatus::Dead));
+        assert_eq!(
+            swim.drain_changes(),
+            vec![(target, MemberStatus::Suspect), (target, MemberStatus::Dead)]
+        );
+    }
+
+    #[test]
+    fn test_acked_probe_keeps_target_alive() {
+        let mut swim = create_swim(0);
+        let target = create_node_id(1);
+        let now = Instant::now();
+
+        let outbox = swim.start_probe(&addresses(&[1]), now);
+        let [(_, ProbeMessage::Ping { seq, .. })] = outbox[..] else {
+            panic!("Expected a single ping");
+        };
+        swim.on_ack(seq, target, Vec::new(), now);
+
+        assert!(swim.check_timeouts(&addresses(&[1, 2]), now + PROBE_TIMEOUT).is_empty());
+        swim.start_probe(&addresses(&[1]), now + PROBE_TIMEOUT * 5);
+        assert_eq!(swim.status_of(&target), Some(MemberStatus::Alive));
+    }
+
+    #[test]
+    fn test_ping_req_relays_ack_to_requester() {
+        let mut helper = create_swim(2);
+        let (requester, target) = (create_addr(0), create_node_id(1));
+        let now = Instant::now();
+
+        // Only members are pinged on another node's behalf.
+        assert!(helper.on_ping_req(requester, 7, target, &addresses(&[1]), now).is_empty());
+        helper.observe(target, now);
+        assert!(helper.on_ping_req(requester, 7, target, &addresses(&[3]), now).is_empty());
+
+        let outbox = helper.on_ping_req(requester, 7, target, &addresses(&[1]), now);
+        let [(addr, ProbeMessage::Ping { seq, .. })] = outbox[..] else {
+            panic!("Expected a ping to the target");
+        };
+        assert_eq!(addr, create_addr(1));
+
+        let outbox = helper.on_ack(seq, target, Vec::new(), now);
+        assert!(matches!(
+            outbox[..],
+            [(addr, ProbeMessage::Ack { seq: 7, target: t, .. })] if addr == requester && t == target
+        ));
+        assert!(helper.on_ack(seq, target, Vec::new(), now).is_empty(), "An ack is relayed once");
+    }
+
+    #[test]
+    fn test_suspected_node_refutes_with_higher_incarnation() {
+        let mut swim = create_swim(0);
+        let suspicion = StatusUpdate {
+            node: create_node_id(0),
+            status: MemberStatus::Suspect,
+            incarnation: Version::default(),
+        };
+        swim.apply_updates(vec![suspicion], Instant::now());
+
+        let updates = swim.piggyback();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].status, MemberStatus::Alive);
+        assert!(updates[0].incarnation > suspicion.incarnation);
+
+        // Elsewhere, the refutation is only believed once the node has signed
+        // a message at its new incarnation, which overrides the suspicion anyway.
+        let mut peer = create_swim(1);
+        let now = Instant::now();
+        peer.start_probe(&addresses(&[0]), now);
+        peer.start_probe(&addresses(&[0]), now + PROBE_TIMEOUT * 5);
+        assert_eq!(peer.status_of(&create_node_id(0)), Some(MemberStatus::Suspect));
+        peer.apply_updates(updates.clone(), Instant::now());
+        assert_eq!(peer.status_of(&create_node_id(0)), Some(MemberStatus::Suspect));
+        peer.on_signed(create_node_id(0), swim.incarnation(), Instant::now());
+        assert_eq!(peer.status_of(&create_node_id(0)), Some(MemberStatus::Alive));
+
+        // A suspicion at an older incarnation is refuted by the current one.
+        while !swim.piggyback().is_empty() {}
+        swim.apply_updates(vec![suspicion], Instant::now());
+        assert_eq!(swim.piggyback(), updates);
+    }
+
+    #[test]
+    fn test_stale_updates_do_not_override_newer_status() {
+        let mut swim = create_swim(0);
+        let node = create_node_id(1);
+        let incarnation = Version { epoch: 1, sequence: 1 };
+        let update = |status, incarnation| StatusUpdate { node, status, incarnation };
+        let now = Instant::now();
+        swim.on_signed(node, incarnation, now);
+        swim.apply_updates(vec![update(MemberStatus::Alive, incarnation)], now);
+
+        // This node's own probes suspect the node at its signed incarnation.
+        swim.start_probe(&addresses(&[1]), now);
+        swim.start_probe(&addresses(&[1]), now + PROBE_TIMEOUT * 5);
+        swim.apply_updates(vec![update(MemberStatus::Alive, incarnation)], now);
+        assert_eq!(swim.status_of(&node), Some(MemberStatus::Suspect));
+
+        swim.check_timeouts(&addresses(&[1]), now + PROBE_TIMEOUT * 5 + SUSPICION_TIMEOUT);
+        swim.apply_updates(vec![update(MemberStatus::Alive, Version { epoch: 1, sequence: 0 })], now);
+        assert_eq!(swim.status_of(&node), Some(MemberStatus::Dead));
+
+        // A restarted node comes back with a newer signed message.
+        swim.on_signed(node, Version { epoch: 2, sequence: 0 }, now);
+        assert_eq!(swim.status_of(&node), Some(MemberStatus::Alive));
+        assert_eq!(swim.drain_changes().last(), Some(&(node, MemberStatus::Alive)));
+    }
+
+    #[test]
+    fn test_updates_above_the_signed_version_are_dropped() {
+        let mut swim = create_swim(0);
+        let (self_id, node) = (create_node_id(0), create_node_id(1));
+        let last = Version { epoch: u64::MAX, sequence: u64::MAX };
+        let now = Instant::now();
+        swim.on_signed(node, Version { epoch: 1, sequence: 3 }, now);
+
+        // Nobody signed these incarnations, so they are made up.
+        swim.apply_updates(vec![
+            StatusUpdate { node, status: MemberStatus::Dead, incarnation: last },
+            StatusUpdate { node: self_id, status: MemberStatus::Dead, incarnation: last },
+        ], now);
+        assert_eq!(swim.status_of(&node), Some(MemberStatus::Alive));
+        assert!(swim.piggyback().is_empty(), "No refutation should be sent");
+
+        // A death notice at the last possible incarnation cannot be refuted,
+        // and does not overflow it.
+        swim.on_signed(self_id, last, now);
+        swim.apply_updates(vec![StatusUpdate { node: self_id, status: MemberStatus::Dead, incarnation: last }], now);
+        assert_eq!(swim.incarnation(), Version::default());
+        assert!(swim.piggyback().is_empty());
+    }
+
+    #[test]
+    fn test_failures_reported_by_peers_are_only_probed() {
+        let mut swim = create_swim(0);
+        let (node, other) = (create_node_id(1), create_node_id(2));
+        let incarnation = Version { epoch: 1, sequence: 1 };
+        let now = Instant::now();
+        swim.on_signed(node, incarnation, now);
+        swim.on_signed(other, incarnation, now);
+
+        // An unsigned death notice neither kills the node nor is passed on...
+        swim.apply_updates(vec![StatusUpdate { node, status: MemberStatus::Dead, incarnation }], now);
+        assert_eq!(swim.status_of(&node), Some(MemberStatus::Alive));
+        assert!(swim.drain_changes().is_empty());
+        assert!(swim.piggyback().is_empty());
+
+        // ...but the node is probed next, and suspected if it does not answer.
+        let members = addresses(&[1, 2]);
+        for _ in 0..2 {
+            let outbox = swim.start_probe(&members, now);
+            assert!(matches!(outbox[..], [(addr, ProbeMessage::Ping { .. })] if addr == create_addr(1)));
+            swim.apply_updates(vec![StatusUpdate { node, status: MemberStatus::Suspect, incarnation }], now);
+        }
+        assert_eq!(swim.status_of(&node), Some(MemberStatus::Suspect));
+        assert_eq!(swim.status_of(&other), Some(MemberStatus::Alive));
+    }
+
+    #[test]
+    fn test_piggybacked_updates_are_sent_a_bounded_number_of_times() {
+        let mut swim = create_swim(0);
+        let updates: Vec<_> = (1..=MAX_PIGGYBACKED_UPDATES as u8 + 2)
+            .map(|id| StatusUpdate {
+                node: create_node_id(id),
+                status: MemberStatus::Alive,
+                incarnation: Version::default(),
+            })
+            .collect();
+        swim.apply_updates(updates, Instant::now());
+
+        assert_eq!(swim.piggyback().len(), MAX_PIGGYBACKED_UPDATES);
+        let mut sent = MAX_PIGGYBACKED_UPDATES;
+        loop {
+            let batch = swim.piggyback();
+            if batch.is_empty() {
+                break;
+            }
+            sent += batch.len();
+        }
+        assert!(sent > MAX_PIGGYBACKED_UPDATES + 2, "Updates should be retransmitted");
+        assert!(swim.pending.is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+//! src/engine/validation.rs
+//!
+//! Admission checks applied by the `Engine` to every inbound message, along
+//! with the reasons a message can be rejected.
+
+use std::fmt;
+
+/// Why the `Engine` refused to accept an inbound message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// The signature did not verify against the originator's public key.
+    InvalidSignature,
+    /// The application payload is of an unregistered kind, or does not decode
+    /// as its kind.
+    InvalidPayload,
+    /// The timestamp lies further in the future than the allowed clock skew.
+    TimestampInFuture { skew_ms: u64 },
+    /// The timestamp lies further in the past than the allowed clock skew.
+    TimestampTooOld { age_ms: u64 },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::InvalidSignature => write!(f, "invalid_signature"),
+            Rejection::InvalidPayload => write!(f, "invalid_payload"),
+            Rejection::TimestampInFuture { .. } => write!(f, "timestamp_in_future"),
+            Rejection::TimestampTooOld { .. } => write!(f, "timestamp_too_old"),
+        }
+    }
+}
+
+/// The range of timestamps, relative to the local clock, that a node accepts.
+#[derive(Clone, Copy, Debug)]
+pub struct TimestampWindow {
+    pub max_future_skew_ms: u64,
+    pub max_past_skew_ms: u64,
+}
+
+impl TimestampWindow {
+    /// Checks that `timestamp_ms` lies within the window around `now_ms`.
+    pub fn check(&self, timestamp_ms: u64, now_ms: u64) -> Result<(), Rejection> {
+        if timestamp_ms > now_ms {
+            let skew_ms = timestamp_ms - now_ms;
+            if skew_ms > self.max_future_skew_ms {
+                return Err(Rejection::TimestampInFuture { skew_ms });
+            }
+        } else {
+            let age_ms = now_ms - timestamp_ms;
+            if age_ms > self.max_past_skew_ms {
+                return Err(Rejection::TimestampTooOld { age_ms });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: TimestampWindow = TimestampWindow {
+        max_future_skew_ms: 1_000,
+        max_past_skew_ms: 5_000,
+    };
+    const NOW_MS: u64 = 1_000_000;
+
+    #[test]
+    fn test_timestamp_window_accepts_values_within_bounds() {
+        assert!(WINDOW.check(NOW_MS, NOW_MS).is_ok());
+        assert!(WINDOW.check(NOW_MS + 1_000, NOW_MS).is_ok());
+        assert!(WINDOW.check(NOW_MS - 5_000, NOW_MS).is_ok());
+    }
+
+    #[test]
+    fn test_timestamp_window_rejects_future_timestamps() {
+        assert_eq!(
+            WINDOW.check(NOW_MS + 1_001, NOW_MS),
+            Err(Rejection::TimestampInFuture { skew_ms: 1_001 })
+        );
+        assert!(matches!(
+            WINDOW.check(u64::MAX, NOW_MS),
+            Err(Rejection::TimestampInFuture { .. })
+        ));
+    }
+
+    #[test]
+    fn test_timestamp_window_rejects_old_timestamps() {
+        assert_eq!(
+            WINDOW.check(NOW_MS - 5_001, NOW_MS),
+            Err(Rejection::TimestampTooOld { age_ms: 5_001 })
+        );
+        assert!(matches!(
+            WINDOW.check(0, NOW_MS),
+            Err(Rejection::TimestampTooOld { .. })
+        ));
+    }
+}
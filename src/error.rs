@@ -10,7 +10,7 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Configuration error: {0}")]
-    Config(#[from] figment::Error),
+    Config(#[from] Box<figment::Error>),
 
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
@@ -18,12 +18,27 @@ pub enum Error {
     #[error("Failed to serialize or deserialize: {0}")]
     Serialization(#[from] bincode::Error),
 
+    #[error("Invalid message envelope: {0}")]
+    Envelope(String),
+
+    #[error("Incompatible protocol version {major}.{minor}")]
+    IncompatibleVersion { major: u8, minor: u8 },
+
+    #[error("Invalid protobuf message: {0}")]
+    Protobuf(String),
+
+    #[error("Invalid application payload: {0}")]
+    Payload(String),
+
     #[error("Cryptography error: {0}")]
     Crypto(#[from] ed25519_dalek::SignatureError),
 
     #[error("Invalid identity key file")]
     InvalidKeyFile,
 
+    #[error("Invalid identity epoch file")]
+    InvalidEpochFile,
+
     #[error("Tokio task join error: {0}")]
     TaskJoin(#[from] tokio::task::JoinError),
 
@@ -36,6 +51,9 @@ pub enum Error {
     #[error("Connection to {0} failed during establishment: {1}")]
     ConnectionEstablishFailed(SocketAddr, #[source] quinn::ConnectionError),
 
+    #[error("Peer handshake with {0} failed: {1}")]
+    Handshake(SocketAddr, String),
+
     #[error("An established connection failed: {0}")]
     Connection(#[from] quinn::ConnectionError),
 
@@ -44,4 +62,16 @@ pub enum Error {
 
     #[error("API server error: {0}")]
     ApiServer(#[from] axum::Error),
-}
\ No newline at end of file
+}
+
+impl From<figment::Error> for Error {
+    fn from(e: figment::Error) -> Self {
+        Error::Config(Box::new(e))
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(e: prost::DecodeError) -> Self {
+        Error::Protobuf(e.to_string())
+    }
+}
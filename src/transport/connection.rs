@@ -3,107 +3,253 @@
 //! Handles the logic for establishing, caching, and using QUIC connections.
 
 use crate::{
-    domain::SignedMessage,
+    config::TlsMode,
+    domain::{BanReason, BanTarget, NodeId},
     error::{Error, Result},
     // MODIFICATION: Import new types.
-    transport::{ConnectionEvent, InboundMessage, MAX_MESSAGE_SIZE},
+    transport::{
+        envelope::{self, Envelope, MessageType, WireFormat},
+        guard::{self, PeerGuard},
+        handshake::{self, LocalNode, VerifiedPeer},
+        manager::ConnectionManager,
+        tls, ConnectionEvent, Direction, InboundMessage, WireMessage, MAX_MESSAGE_SIZE,
+    },
 };
-use quinn::{Connection, Endpoint};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use quinn::{Connection, Endpoint, VarInt};
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
 // MODIFICATION: Add Semaphore.
-use tokio::sync::{mpsc, Mutex, Semaphore};
+use tokio::{
+    sync::{mpsc, Mutex, Semaphore},
+    time::Instant,
+};
+
+/// Application close code sent when a peer fails the authentication handshake.
+pub const HANDSHAKE_FAILED: VarInt = VarInt::from_u32(1);
+/// Application close code sent when a concurrent dial already connected to the peer.
+const DUPLICATE_CONNECTION: VarInt = VarInt::from_u32(2);
+/// Application close code sent when a connection is refused or evicted because
+/// the node has reached its connection limit.
+pub const CONNECTION_LIMIT: VarInt = VarInt::from_u32(3);
+/// Application close code sent to a banned peer.
+pub const BANNED: VarInt = VarInt::from_u32(4);
+/// Stream error code sent when a stream is dropped for exceeding the peer's rate limits.
+const RATE_LIMITED: VarInt = VarInt::from_u32(5);
+
+/// How long a peer has to finish sending a message once its stream is being
+/// read, so that slow senders cannot hold stream permits indefinitely.
+const STREAM_READ_TIMEOUT: Duration = Duration::from_secs(10);
 
-/// Establishes a connection to a peer and caches it.
+/// Establishes a connection to a peer, authenticates it, and caches it, which
+/// may evict another connection if the node is at its outbound limit.
 pub async fn connect_to_peer(
     endpoint: Endpoint,
-    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    local: LocalNode,
+    server_name: Arc<str>,
+    connections: Arc<Mutex<ConnectionManager>>,
     peer_addr: SocketAddr,
     // NEW: Accept event sender.
     conn_event_tx: mpsc::Sender<ConnectionEvent>,
 ) -> Result<Connection> {
     let connecting = endpoint
-        .connect(peer_addr, "localhost")
+        .connect(peer_addr, &server_name)
         .map_err(|e| Error::ConnectFailed(peer_addr, e))?;
 
     let conn = connecting
         .await
         .map_err(|e| Error::ConnectionEstablishFailed(peer_addr, e))?;
 
-    tracing::info!(peer = %peer_addr, "Successfully connected to peer");
+    let VerifiedPeer { node_id: peer_id, listen_addrs } =
+        authenticate(&conn, handshake::initiate(&conn, &local)).await?;
+    check_certificate_identity(&conn, local.tls_mode, peer_id)?;
+    tracing::info!(peer = %peer_addr, peer_id = %peer_id, format = ?wire_format(&conn), "Successfully connected to peer");
+
+    let mut conns_guard = connections.lock().await;
+    if let Some(existing) = conns_guard.get(&peer_addr) {
+        // Another task dialed the peer concurrently. Closing the connection it
+        // cached would lose the messages in flight on it, so ours is dropped.
+        drop(conns_guard);
+        conn.close(DUPLICATE_CONNECTION, b"duplicate");
+        return Ok(existing);
+    }
+    conns_guard.insert(peer_addr, conn.clone());
+    drop(conns_guard);
 
     // NEW: Send connection event.
     let _ = conn_event_tx
-        .send(ConnectionEvent::PeerConnected { peer_addr })
+        .send(ConnectionEvent::PeerConnected { peer_addr, peer_id, listen_addrs, direction: Direction::Outbound })
         .await;
 
-    connections.lock().await.insert(peer_addr, conn.clone());
+    tokio::spawn(watch_outbound_connection(
+        conn.clone(),
+        connections,
+        peer_addr,
+        peer_id,
+        conn_event_tx,
+    ));
     Ok(conn)
 }
 
+/// Runs `handshake` under a timeout, closing `conn` if it does not succeed.
+async fn authenticate(
+    conn: &Connection,
+    handshake: impl Future<Output = Result<VerifiedPeer>>,
+) -> Result<VerifiedPeer> {
+    let result = tokio::time::timeout(handshake::HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .unwrap_or_else(|_| Err(Error::Handshake(conn.remote_address(), "timed out".into())));
+    if result.is_err() {
+        conn.close(HANDSHAKE_FAILED, b"handshake failed");
+    }
+    result
+}
+
+/// In identity mode, checks that the peer's certificate names the `NodeId`
+/// that signed the handshake, closing `conn` if it does not. Otherwise a peer
+/// could pass the TLS allowlist with one identity and act as another.
+fn check_certificate_identity(conn: &Connection, tls_mode: TlsMode, peer_id: NodeId) -> Result<()> {
+    if tls_mode != TlsMode::Identity {
+        return Ok(());
+    }
+    let cert_id = tls::peer_certificate(conn).as_ref().and_then(tls::node_id_from_certificate);
+    if cert_id == Some(peer_id) {
+        return Ok(());
+    }
+    conn.close(HANDSHAKE_FAILED, b"certificate mismatch");
+    let cert_id = cert_id.map_or_else(|| "none".to_string(), |id| id.to_string());
+    Err(Error::Handshake(
+        conn.remote_address(),
+        format!("certificate identity {} does not match handshake identity {}", cert_id, peer_id),
+    ))
+}
+
+/// Waits for an outbound connection to close, then evicts it from the cache
+/// and reports the disconnect.
+async fn watch_outbound_connection(
+    conn: Connection,
+    connections: Arc<Mutex<ConnectionManager>>,
+    peer_addr: SocketAddr,
+    peer_id: NodeId,
+    conn_event_tx: mpsc::Sender<ConnectionEvent>,
+) {
+    let reason = conn.closed().await;
+    tracing::info!(peer = %peer_addr, reason = %reason, "Outbound connection closed");
+
+    // Only evict the cache entry if it has not been replaced in the meantime.
+    connections.lock().await.remove(&peer_addr, &conn);
+
+    let _ = conn_event_tx
+        .send(ConnectionEvent::PeerDisconnected { peer_addr, peer_id, direction: Direction::Outbound })
+        .await;
+}
+
 /// Gets a cached connection or creates a new one.
-async fn get_or_create_connection(
+pub async fn get_or_create_connection(
     endpoint: Endpoint,
-    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    local: LocalNode,
+    server_name: Arc<str>,
+    connections: Arc<Mutex<ConnectionManager>>,
     addr: SocketAddr,
     // NEW: Pass through event sender.
     conn_event_tx: mpsc::Sender<ConnectionEvent>,
 ) -> Result<Connection> {
-    let mut conns_guard = connections.lock().await;
-    if let Some(conn) = conns_guard.get(&addr) {
-        if conn.close_reason().is_none() {
-            return Ok(conn.clone());
-        }
-        // Connection is closed, remove it.
-        conns_guard.remove(&addr);
+    if let Some(conn) = connections.lock().await.get(&addr) {
+        return Ok(conn);
     }
-    drop(conns_guard);
-    connect_to_peer(endpoint, connections, addr, conn_event_tx).await
+    connect_to_peer(endpoint, local, server_name, connections, addr, conn_event_tx).await
 }
 
 /// Sends a single message to a peer, using the connection cache.
 pub async fn send_message_to_peer(
     endpoint: Endpoint,
-    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    local: LocalNode,
+    server_name: Arc<str>,
+    connections: Arc<Mutex<ConnectionManager>>,
     addr: SocketAddr,
-    msg: SignedMessage,
+    msg: WireMessage,
     // NEW: Accept event sender.
     conn_event_tx: mpsc::Sender<ConnectionEvent>,
 ) -> Result<()> {
-    let conn = get_or_create_connection(endpoint, connections, addr, conn_event_tx).await?;
+    let conn = get_or_create_connection(endpoint, local, server_name, connections, addr, conn_event_tx).await?;
     let mut send_stream = conn.open_uni().await?;
-    let bytes = bincode::serialize(&msg)?;
+    let bytes = envelope::encode(&msg, wire_format(&conn))?;
     send_stream.write_all(&bytes).await?;
     send_stream.finish().await?;
     tracing::trace!(peer = %addr, "Successfully sent message");
     Ok(())
 }
 
-/// Handles a single established QUIC connection, processing all incoming streams.
+/// Handles a single established QUIC connection, processing all incoming
+/// streams within the peer's rate limits.
 pub async fn handle_connection(
     conn: quinn::Connecting,
-    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    local: LocalNode,
     inbound_tx: mpsc::Sender<InboundMessage>,
     // NEW: Accept event sender and semaphore.
     conn_event_tx: mpsc::Sender<ConnectionEvent>,
     stream_semaphore: Arc<Semaphore>,
+    guard: PeerGuard,
 ) -> Result<()> {
     let connection = conn.await?;
     let peer_addr = connection.remote_address();
-    tracing::info!(peer = %peer_addr, "Accepted connection from peer");
+    let VerifiedPeer { node_id: peer_id, listen_addrs } =
+        authenticate(&connection, handshake::respond(&connection, &local)).await?;
+
+    check_certificate_identity(&connection, local.tls_mode, peer_id)?;
+    // Present only when the client authenticated with a certificate.
+    let peer_cert = tls::peer_certificate(&connection);
+    let targets = [BanTarget::Node(peer_id), BanTarget::Addr(peer_addr.ip())];
+    if guard.bans.lock().await.is_banned_any(&targets, guard::now_ms()) {
+        tracing::warn!(peer = %peer_addr, peer_id = %peer_id, "Refusing connection from banned peer");
+        connection.close(BANNED, b"banned");
+        return Ok(());
+    }
+    let format = wire_format(&connection);
+    tracing::info!(
+        peer = %peer_addr,
+        peer_id = %peer_id,
+        client_cert = peer_cert.is_some(),
+        format = ?format,
+        "Accepted connection from peer"
+    );
 
     // NEW: Send connection event.
     let _ = conn_event_tx
-        .send(ConnectionEvent::PeerConnected { peer_addr })
+        .send(ConnectionEvent::PeerConnected { peer_addr, peer_id, listen_addrs, direction: Direction::Inbound })
         .await;
 
-    connections.lock().await.insert(peer_addr, connection.clone());
-
-    loop {
+    // Inbound connections are deliberately not added to the outbound cache: the
+    // dialing side never accepts streams on its own connections, so anything we
+    // wrote back over this one would go unread.
+    let limiter = guard.limiter(peer_id).await;
+    let accept_legacy = local.accept_legacy;
+    // Every exit from the loop reports the disconnect, so the engine never
+    // counts a dead connection as live.
+    let result = loop {
         tokio::select! {
             stream = connection.accept_uni() => {
                 match stream {
                     Ok(mut recv) => {
+                        let banned = guard.bans.lock().await.is_banned_any(&targets, guard::now_ms());
+                        if banned {
+                            tracing::warn!(peer = %peer_addr, peer_id = %peer_id, "Closing connection from banned peer");
+                            connection.close(BANNED, b"banned");
+                            break Ok(());
+                        }
+                        if !limiter.streams.lock().await.try_take(1.0, Instant::now()) {
+                            tracing::debug!(peer = %peer_addr, "Dropping stream over the peer's stream rate limit");
+                            let _ = recv.stop(RATE_LIMITED);
+                            guard.strike(peer_id, peer_addr.ip(), BanReason::RateLimited, &conn_event_tx).await;
+                            continue;
+                        }
+                        let Ok(peer_permit) = limiter.permits.clone().try_acquire_owned() else {
+                            tracing::debug!(peer = %peer_addr, "Dropping stream over the peer's concurrent stream limit");
+                            let _ = recv.stop(RATE_LIMITED);
+                            guard.strike(peer_id, peer_addr.ip(), BanReason::RateLimited, &conn_event_tx).await;
+                            continue;
+                        };
                         let inbound_tx = inbound_tx.clone();
+                        let (connection, guard, conn_event_tx) = (connection.clone(), guard.clone(), conn_event_tx.clone());
+                        let limiter = limiter.clone();
                         // FIX: Acquire a permit from the semaphore before spawning a task.
                         // `acquire_owned` ties the permit lifetime to the spawned task.
                         let permit = match stream_semaphore.clone().acquire_owned().await {
@@ -114,22 +260,65 @@ pub async fn handle_connection(
                             }
                         };
                         tokio::spawn(async move {
-                            match recv.read_to_end(MAX_MESSAGE_SIZE).await {
-                                Ok(bytes) => {
-                                    match bincode::deserialize::<SignedMessage>(&bytes) {
-                                        Ok(message) => {
-                                            let inbound = InboundMessage { peer_addr, message };
-                                            if inbound_tx.send(inbound).await.is_err() {
-                                                tracing::warn!("Inbound message channel is closed.");
+                            let Ok(read) = tokio::time::timeout(STREAM_READ_TIMEOUT, recv.read_to_end(MAX_MESSAGE_SIZE)).await else {
+                                tracing::debug!(from = %peer_addr, "Dropping stream the peer did not finish in time");
+                                return;
+                            };
+                            let strike = match read {
+                                Ok(bytes) if !limiter.bytes.lock().await.try_take(bytes.len() as f64, Instant::now()) => {
+                                    tracing::debug!(from = %peer_addr, bytes = bytes.len(), "Dropping message over the peer's byte rate limit");
+                                    Some(BanReason::RateLimited)
+                                }
+                                Ok(bytes) => match Envelope::parse(&bytes, accept_legacy) {
+                                    Ok(envelope) => match MessageType::from_tag(envelope.message_type) {
+                                        Some(message_type) => match envelope.decode(message_type, format) {
+                                            Ok(message) => {
+                                                let inbound = InboundMessage { peer_addr, peer_id, message };
+                                                if inbound_tx.send(inbound).await.is_err() {
+                                                    tracing::warn!("Inbound message channel is closed.");
+                                                }
+                                                None
                                             }
+                                            Err(e) => {
+                                                tracing::error!(from = %peer_addr, message_type = ?message_type, error = %e, "Failed to deserialize message");
+                                                Some(BanReason::MalformedMessage)
+                                            }
+                                        },
+                                        None => {
+                                            tracing::debug!(from = %peer_addr, message_type = envelope.message_type, version = %envelope.version, "Ignoring message of unknown type");
+                                            None
                                         }
-                                        Err(e) => tracing::error!(from = %peer_addr, error = %e, "Failed to deserialize message"),
+                                    },
+                                    Err(Error::IncompatibleVersion { major, minor }) => {
+                                        tracing::warn!(
+                                            from = %peer_addr,
+                                            peer_id = %peer_id,
+                                            "Rejecting message from peer speaking protocol version {}.{}, incompatible with local version {}",
+                                            major, minor, envelope::PROTOCOL_VERSION
+                                        );
+                                        None
+                                    }
+                                    Err(e) => {
+                                        tracing::error!(from = %peer_addr, error = %e, "Failed to parse message envelope");
+                                        Some(BanReason::MalformedMessage)
                                     }
+                                },
+                                Err(e @ quinn::ReadToEndError::TooLong) => {
+                                    tracing::error!(from = %peer_addr, error = %e, "Failed to read from stream (potential DoS: exceeded size limit)");
+                                    Some(BanReason::MalformedMessage)
+                                }
+                                Err(e) => {
+                                    tracing::error!(from = %peer_addr, error = %e, "Failed to read from stream");
+                                    None
+                                }
+                            };
+                            if let Some(reason) = strike {
+                                if guard.strike(peer_id, peer_addr.ip(), reason, &conn_event_tx).await {
+                                    connection.close(BANNED, b"banned");
                                 }
-                                Err(e) => tracing::error!(from = %peer_addr, error = %e, "Failed to read from stream (potential DoS: exceeded size limit)"),
                             }
-                            // Permit is automatically dropped here when the task finishes.
-                            drop(permit);
+                            // Permits are automatically dropped here when the task finishes.
+                            drop((permit, peer_permit));
                         });
                     }
                     Err(e) => {
@@ -140,11 +329,32 @@ pub async fn handle_connection(
             }
             reason = connection.closed() => {
                  tracing::info!(peer = %peer_addr, reason = %reason, "Connection closed");
-                 // NEW: Send disconnect event.
-                 let _ = conn_event_tx.send(ConnectionEvent::PeerDisconnected { peer_addr }).await;
-                 connections.lock().await.remove(&peer_addr);
-                 return Ok(());
+                 break Ok(());
             }
         }
+    };
+    let _ = conn_event_tx.send(ConnectionEvent::PeerDisconnected { peer_addr, peer_id, direction: Direction::Inbound }).await;
+    result
+}
+
+/// The wire format selected by the ALPN protocol negotiated for `conn`.
+fn wire_format(conn: &Connection) -> WireFormat {
+    let protocol = conn
+        .handshake_data()
+        .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+        .and_then(|data| data.protocol);
+    WireFormat::from_alpn(protocol.as_deref())
+}
+
+/// Closes an inbound connection that is not admitted, with the close `code`
+/// and `reason`. The QUIC handshake is completed first, so that the peer is
+/// told why.
+pub async fn refuse_connection(conn: quinn::Connecting, code: VarInt, reason: &'static str) {
+    match conn.await {
+        Ok(connection) => {
+            tracing::warn!(peer = %connection.remote_address(), reason, "Refusing inbound connection");
+            connection.close(code, reason.as_bytes());
+        }
+        Err(e) => tracing::debug!(error = %e, "Refused connection failed before it was established"),
     }
-}
\ No newline at end of file
+}
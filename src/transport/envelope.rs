@@ -0,0 +1,304 @@
+//! src/transport/envelope.rs
+//!
+//! Frames every `WireMessage` sent over a stream in a versioned envelope:
+//!
+//! ```text
+//! magic (4) | major (1) | minor (1) | message type (2) | body length (4) | body
+//! ```
+//!
+//! Integers are big-endian. The body encodes the message's contents in the
+//! `WireFormat` negotiated for the connection through ALPN: bincode, or
+//! protobuf (see `proto`). Peers with a different major version cannot be
+//! understood, and their messages are rejected, except those of major version
+//! 1 while a network is upgraded from it (see `legacy`). A minor version bump may add
+//! message types and append fields to bodies, so messages of unknown types are
+//! skipped, and fields or bytes a node does not read are ignored.
+
+use crate::{
+    error::{Error, Result},
+    transport::{legacy, proto, WireMessage},
+};
+use std::fmt;
+
+/// Marks the start of every envelope.
+pub const MAGIC: [u8; 4] = *b"GSPN";
+/// The protocol version this node speaks.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 2, minor: 0 };
+/// The protocol version that predates application payloads, whose envelopes
+/// are accepted while `accept_legacy_signatures` is on.
+pub const LEGACY_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 1, minor: 0 };
+/// The size of the envelope header preceding the body.
+pub const HEADER_LEN: usize = 12;
+
+/// The ALPN protocol id of the bincode wire format.
+pub const ALPN_BINCODE: &str = "gossip/1.0";
+/// The ALPN protocol id of the protobuf wire format.
+pub const ALPN_PROTOBUF: &str = "gossip/2.0-pb";
+
+/// How envelope bodies are encoded on a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WireFormat {
+    /// Bincode-encoded Rust structs.
+    Bincode,
+    /// Protobuf messages, as described in `proto/wire.proto`.
+    Protobuf,
+}
+
+impl WireFormat {
+    /// The format selected by the ALPN protocol negotiated for a connection.
+    /// Protocols other than `ALPN_PROTOBUF`, such as `ALPN_BINCODE`, select
+    /// bincode, as does a connection that negotiated none.
+    pub fn from_alpn(protocol: Option<&[u8]>) -> Self {
+        match protocol {
+            Some(protocol) if protocol == ALPN_PROTOBUF.as_bytes() => Self::Protobuf,
+            _ => Self::Bincode,
+        }
+    }
+}
+
+/// A wire protocol version. Only peers with the same major version can talk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl ProtocolVersion {
+    pub fn is_compatible(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// The type tag of an envelope, one per `WireMessage` variant. Tags are never
+/// reused, so that older nodes can skip types they do not know.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub enum MessageType {
+    Gossip = 1,
+    SyncDigest = 2,
+    SyncReply = 3,
+    Control = 4,
+    Membership = 5,
+    Probe = 6,
+    PeerExchange = 7,
+}
+
+impl MessageType {
+    /// The message type with the tag `tag`, if this node knows it.
+    pub fn from_tag(tag: u16) -> Option<Self> {
+        Some(match tag {
+            1 => Self::Gossip,
+            2 => Self::SyncDigest,
+            3 => Self::SyncReply,
+            4 => Self::Control,
+            5 => Self::Membership,
+            6 => Self::Probe,
+            7 => Self::PeerExchange,
+            _ => return None,
+        })
+    }
+
+    pub fn tag(self) -> u16 {
+        self as u16
+    }
+
+    pub fn of(message: &WireMessage) -> Self {
+        match message {
+            WireMessage::Gossip(_) => Self::Gossip,
+            WireMessage::SyncDigest(_) => Self::SyncDigest,
+            WireMessage::SyncReply(_) => Self::SyncReply,
+            WireMessage::Control(_) => Self::Control,
+            WireMessage::Membership(_) => Self::Membership,
+            WireMessage::Probe(_) => Self::Probe,
+            WireMessage::PeerExchange(_) => Self::PeerExchange,
+        }
+    }
+
+    /// Decodes an envelope body of this type, encoded in `format`.
+    pub fn decode(self, body: &[u8], format: WireFormat) -> Result<WireMessage> {
+        if format == WireFormat::Protobuf {
+            return proto::decode(self, body);
+        }
+        Ok(match self {
+            Self::Gossip => WireMessage::Gossip(bincode::deserialize(body)?),
+            Self::SyncDigest => WireMessage::SyncDigest(bincode::deserialize(body)?),
+            Self::SyncReply => WireMessage::SyncReply(bincode::deserialize(body)?),
+            Self::Control => WireMessage::Control(bincode::deserialize(body)?),
+            Self::Membership => WireMessage::Membership(bincode::deserialize(body)?),
+            Self::Probe => WireMessage::Probe(bincode::deserialize(body)?),
+            Self::PeerExchange => WireMessage::PeerExchange(bincode::deserialize(body)?),
+        })
+    }
+}
+
+/// Encodes `message` in an envelope of the local protocol version, with its
+/// body in `format`.
+pub fn encode(message: &WireMessage, format: WireFormat) -> Result<Vec<u8>> {
+    let body = match message {
+        _ if format == WireFormat::Protobuf => proto::encode(message),
+        WireMessage::Gossip(inner) => bincode::serialize(inner)?,
+        WireMessage::SyncDigest(inner) => bincode::serialize(inner)?,
+        WireMessage::SyncReply(inner) => bincode::serialize(inner)?,
+        WireMessage::Control(inner) => bincode::serialize(inner)?,
+        WireMessage::Membership(inner) => bincode::serialize(inner)?,
+        WireMessage::Probe(inner) => bincode::serialize(inner)?,
+        WireMessage::PeerExchange(inner) => bincode::serialize(inner)?,
+    };
+    let body_len = u32::try_from(body.len()).map_err(|_| Error::Envelope("body too large".to_string()))?;
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&[PROTOCOL_VERSION.major, PROTOCOL_VERSION.minor]);
+    bytes.extend_from_slice(&MessageType::of(message).tag().to_be_bytes());
+    bytes.extend_from_slice(&body_len.to_be_bytes());
+    bytes.extend_from_slice(&body);
+    Ok(bytes)
+}
+
+/// A received envelope whose header has been checked, but whose body has not
+/// been decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Envelope<'a> {
+    pub version: ProtocolVersion,
+    /// The raw type tag, which may be one this node does not know.
+    pub message_type: u16,
+    pub body: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    /// Parses the envelope header of `bytes`. Fails with
+    /// `Error::IncompatibleVersion` if the sender speaks another major version
+    /// (other than `LEGACY_PROTOCOL_VERSION`'s, if `accept_legacy`), and with
+    /// `Error::Envelope` if the bytes are not an envelope.
+    pub fn parse(bytes: &'a [u8], accept_legacy: bool) -> Result<Self> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::Envelope(format!("{} bytes is shorter than the header", bytes.len())));
+        }
+        let (header, rest) = bytes.split_at(HEADER_LEN);
+        if header[..4] != MAGIC {
+            return Err(Error::Envelope("bad magic".to_string()));
+        }
+        let version = ProtocolVersion { major: header[4], minor: header[5] };
+        let legacy = accept_legacy && LEGACY_PROTOCOL_VERSION.is_compatible(&version);
+        if !PROTOCOL_VERSION.is_compatible(&version) && !legacy {
+            return Err(Error::IncompatibleVersion { major: version.major, minor: version.minor });
+        }
+        let message_type = u16::from_be_bytes([header[6], header[7]]);
+        let body_len = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+        let body = rest
+            .get(..body_len)
+            .ok_or_else(|| Error::Envelope(format!("body of {} bytes is truncated to {}", body_len, rest.len())))?;
+        Ok(Self { version, message_type, body })
+    }
+
+    /// Decodes the body as a message of `message_type`, encoded in `format`
+    /// by a node of the envelope's protocol version.
+    pub fn decode(&self, message_type: MessageType, format: WireFormat) -> Result<WireMessage> {
+        if LEGACY_PROTOCOL_VERSION.is_compatible(&self.version) {
+            return legacy::decode(message_type, self.body, format);
+        }
+        message_type.decode(self.body, format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{NodeId, Version},
+        transport::{MembershipMessage, ProbeMessage},
+    };
+
+    fn header(major: u8, minor: u8, tag: u16, body_len: u32) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[major, minor]);
+        bytes.extend_from_slice(&tag.to_be_bytes());
+        bytes.extend_from_slice(&body_len.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let messages = [
+            WireMessage::SyncDigest(vec![(NodeId([7; 32]), "telemetry".to_string(), Version { epoch: 1, sequence: 3 })]),
+            WireMessage::Membership(MembershipMessage::Disconnect),
+            WireMessage::Probe(ProbeMessage::Ping { seq: 9, status_updates: Vec::new() }),
+            WireMessage::PeerExchange(Vec::new()),
+        ];
+        for (message, format) in messages.iter().flat_map(|message| [(message, WireFormat::Bincode), (message, WireFormat::Protobuf)]) {
+            let bytes = encode(message, format).unwrap();
+            assert_eq!(bytes[..4], MAGIC);
+            let envelope = Envelope::parse(&bytes, false).unwrap();
+            assert_eq!(envelope.version, PROTOCOL_VERSION);
+            let message_type = MessageType::from_tag(envelope.message_type).unwrap();
+            assert_eq!(message_type, MessageType::of(message));
+            let decoded = message_type.decode(envelope.body, format).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", message));
+        }
+    }
+
+    #[test]
+    fn test_newer_minor_version_and_unknown_types_are_tolerated() {
+        // A newer minor version appending a field to a known body.
+        let mut body = bincode::serialize(&vec![(NodeId([1; 32]), "telemetry".to_string(), Version { epoch: 1, sequence: 5 })]).unwrap();
+        body.extend_from_slice(b"appended field");
+        let mut bytes = header(PROTOCOL_VERSION.major, PROTOCOL_VERSION.minor + 1, MessageType::SyncDigest.tag(), body.len() as u32);
+        bytes.extend_from_slice(&body);
+        let envelope = Envelope::parse(&bytes, false).unwrap();
+        assert_eq!(envelope.version.minor, PROTOCOL_VERSION.minor + 1);
+        let message = MessageType::from_tag(envelope.message_type).unwrap().decode(envelope.body, WireFormat::Bincode).unwrap();
+        assert!(matches!(message, WireMessage::SyncDigest(digest) if digest == vec![(NodeId([1; 32]), "telemetry".to_string(), Version { epoch: 1, sequence: 5 })]));
+
+        // A message type added by a newer minor version parses, but is not known.
+        let mut bytes = header(PROTOCOL_VERSION.major, PROTOCOL_VERSION.minor + 1, 0xbeef, 3);
+        bytes.extend_from_slice(b"new");
+        let envelope = Envelope::parse(&bytes, false).unwrap();
+        assert_eq!((envelope.message_type, envelope.body), (0xbeef, &b"new"[..]));
+        assert_eq!(MessageType::from_tag(envelope.message_type), None);
+    }
+
+    #[test]
+    fn test_wire_format_follows_negotiated_alpn() {
+        assert_eq!(WireFormat::from_alpn(Some(ALPN_PROTOBUF.as_bytes())), WireFormat::Protobuf);
+        assert_eq!(WireFormat::from_alpn(Some(ALPN_BINCODE.as_bytes())), WireFormat::Bincode);
+        assert_eq!(WireFormat::from_alpn(Some(b"custom/1.0")), WireFormat::Bincode);
+        assert_eq!(WireFormat::from_alpn(None), WireFormat::Bincode);
+    }
+
+    #[test]
+    fn test_incompatible_major_version_is_rejected() {
+        let bytes = header(PROTOCOL_VERSION.major + 1, 0, MessageType::Gossip.tag(), 0);
+        assert!(matches!(
+            Envelope::parse(&bytes, false),
+            Err(Error::IncompatibleVersion { major, minor: 0 }) if major == PROTOCOL_VERSION.major + 1
+        ));
+    }
+
+    #[test]
+    fn test_version_1_envelopes_are_accepted_only_in_compatibility_mode() {
+        let body = bincode::serialize(&vec![(NodeId([1; 32]), Version { epoch: 1, sequence: 5 })]).unwrap();
+        let mut bytes = header(LEGACY_PROTOCOL_VERSION.major, 0, MessageType::SyncDigest.tag(), body.len() as u32);
+        bytes.extend_from_slice(&body);
+        assert!(matches!(Envelope::parse(&bytes, false), Err(Error::IncompatibleVersion { major: 1, minor: 0 })));
+
+        let envelope = Envelope::parse(&bytes, true).unwrap();
+        assert_eq!(envelope.version, LEGACY_PROTOCOL_VERSION);
+        let message = envelope.decode(MessageType::SyncDigest, WireFormat::Bincode).unwrap();
+        assert!(matches!(message, WireMessage::SyncDigest(digest) if digest == vec![(NodeId([1; 32]), "telemetry".to_string(), Version { epoch: 1, sequence: 5 })]));
+    }
+
+    #[test]
+    fn test_malformed_envelopes_are_rejected() {
+        let bare = bincode::serialize(&WireMessage::PeerExchange(Vec::new())).unwrap();
+        let mut truncated = header(PROTOCOL_VERSION.major, PROTOCOL_VERSION.minor, MessageType::Gossip.tag(), 10);
+        truncated.extend_from_slice(b"short");
+        for bytes in [&b"GSP"[..], &bare, &truncated] {
+            assert!(matches!(Envelope::parse(bytes, false), Err(Error::Envelope(_))), "{:?} should be rejected", bytes);
+        }
+    }
+}
@@ -0,0 +1,417 @@
+//! src/transport/guard.rs
+//!
+//! Protects the node from abusive peers. Each peer gets token buckets, shared
+//! by all of its connections, which cap how many streams and bytes it may send
+//! per second, and a cap on how many of its streams are read at once, so one
+//! peer cannot take all of the shared stream permits.
+//!
+//! Misbehaviour, such as exceeding a rate limit, sending malformed messages,
+//! or relaying messages with invalid signatures, earns the peer a strike,
+//! counted against both its `NodeId` and its IP address. Enough strikes within
+//! the strike window get either banned for a while; addresses, which may be
+//! shared by several nodes, take more. Bans can also be placed and lifted by
+//! hand. Banned peers are refused when they connect, and their open
+//! connections are closed at their next stream.
+
+use crate::{
+    config::Config,
+    domain::{Ban, BanReason, BanTarget, NodeId},
+    transport::ConnectionEvent,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{mpsc, Mutex, Semaphore},
+    time::Instant,
+};
+
+/// A token bucket: holds up to `capacity` tokens, refilled at `rate` tokens
+/// per second.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(rate: f64, capacity: f64, now: Instant) -> Self {
+        Self { capacity, rate, tokens: capacity, last_refill: now }
+    }
+
+    /// Takes `amount` tokens if the bucket holds that many at `now`.
+    pub fn try_take(&mut self, amount: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens < amount {
+            return false;
+        }
+        self.tokens -= amount;
+        true
+    }
+
+    /// Whether the bucket has refilled to its capacity by `now`.
+    pub fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens + elapsed * self.rate >= self.capacity
+    }
+}
+
+/// The inbound traffic allowed from a single peer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimits {
+    pub streams_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// The largest message a peer may send, which the byte bucket must hold.
+    pub max_message_size: usize,
+    /// How many of a peer's streams may be read at once.
+    pub max_concurrent_streams: usize,
+}
+
+impl RateLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            streams_per_sec: config.peer_streams_per_sec as f64,
+            bytes_per_sec: config.peer_bytes_per_sec as f64,
+            max_message_size: super::MAX_MESSAGE_SIZE,
+            max_concurrent_streams: config.peer_max_concurrent_streams,
+        }
+    }
+
+    /// Fresh stream and byte buckets for a peer. Each allows a burst of one
+    /// second's worth, and the byte bucket at least one message of the
+    /// maximum size.
+    pub fn buckets(&self, now: Instant) -> (TokenBucket, TokenBucket) {
+        let byte_capacity = self.bytes_per_sec.max(self.max_message_size as f64);
+        (
+            TokenBucket::new(self.streams_per_sec, self.streams_per_sec, now),
+            TokenBucket::new(self.bytes_per_sec, byte_capacity, now),
+        )
+    }
+}
+
+/// The limits one peer is held to across all of its connections.
+#[derive(Debug)]
+pub struct PeerLimiter {
+    pub streams: Mutex<TokenBucket>,
+    pub bytes: Mutex<TokenBucket>,
+    /// Permits for the peer's streams being read.
+    pub permits: Arc<Semaphore>,
+}
+
+impl PeerLimiter {
+    fn new(limits: &RateLimits, now: Instant) -> Self {
+        let (streams, bytes) = limits.buckets(now);
+        Self {
+            streams: Mutex::new(streams),
+            bytes: Mutex::new(bytes),
+            permits: Arc::new(Semaphore::new(limits.max_concurrent_streams)),
+        }
+    }
+
+    /// Whether forgetting the limiter would change nothing: its buckets are
+    /// full and none of its permits are held.
+    async fn is_idle(&self, limits: &RateLimits, now: Instant) -> bool {
+        self.permits.available_permits() == limits.max_concurrent_streams
+            && self.streams.lock().await.is_full(now)
+            && self.bytes.lock().await.is_full(now)
+    }
+}
+
+/// When strikes turn into a ban.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BanPolicy {
+    /// The number of strikes within `strike_window` that gets a peer banned.
+    pub strike_threshold: usize,
+    /// The number of strikes within `strike_window` that gets an IP address banned.
+    pub addr_strike_threshold: usize,
+    pub strike_window: Duration,
+    /// How long automatic bans, and manual bans without a duration, last.
+    pub ban_duration: Duration,
+}
+
+impl BanPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            strike_threshold: config.ban_strike_threshold,
+            addr_strike_threshold: config.ban_addr_strike_threshold,
+            strike_window: Duration::from_millis(config.strike_window_ms),
+            ban_duration: Duration::from_millis(config.ban_duration_ms),
+        }
+    }
+}
+
+/// The active bans and recent strikes of a node. Times are in milliseconds
+/// since the Unix epoch, so that ban expiry can be reported.
+#[derive(Debug)]
+pub struct BanList {
+    policy: BanPolicy,
+    bans: HashMap<BanTarget, Ban>,
+    strikes: HashMap<BanTarget, VecDeque<u64>>,
+}
+
+impl BanList {
+    pub fn new(policy: BanPolicy) -> Self {
+        Self { policy, bans: HashMap::new(), strikes: HashMap::new() }
+    }
+
+    pub fn policy(&self) -> BanPolicy {
+        self.policy
+    }
+
+    pub fn is_banned(&self, target: &BanTarget, now_ms: u64) -> bool {
+        self.bans.get(target).is_some_and(|ban| ban.expires_at_ms > now_ms)
+    }
+
+    pub fn is_banned_any(&self, targets: &[BanTarget], now_ms: u64) -> bool {
+        targets.iter().any(|target| self.is_banned(target, now_ms))
+    }
+
+    /// Records a strike against `node` and the address `addr` it connected
+    /// from. Returns the bans placed because this strike takes either to its
+    /// threshold within the strike window.
+    pub fn strike(&mut self, node: NodeId, addr: IpAddr, reason: BanReason, now_ms: u64) -> Vec<Ban> {
+        [
+            (BanTarget::Node(node), self.policy.strike_threshold),
+            (BanTarget::Addr(addr), self.policy.addr_strike_threshold),
+        ]
+        .into_iter()
+        .filter_map(|(target, threshold)| self.strike_target(target, threshold, reason, now_ms))
+        .collect()
+    }
+
+    fn strike_target(&mut self, target: BanTarget, threshold: usize, reason: BanReason, now_ms: u64) -> Option<Ban> {
+        let window_start = now_ms.saturating_sub(self.policy.strike_window.as_millis() as u64);
+        let strikes = self.strikes.entry(target).or_default();
+        strikes.retain(|&at| at > window_start);
+        strikes.push_back(now_ms);
+        if strikes.len() < threshold {
+            return None;
+        }
+        self.strikes.remove(&target);
+        Some(self.ban(target, reason, self.policy.ban_duration, now_ms))
+    }
+
+    /// Bans `target` for `duration`, replacing any ban it already has.
+    pub fn ban(&mut self, target: BanTarget, reason: BanReason, duration: Duration, now_ms: u64) -> Ban {
+        let ban = Ban { target, reason, expires_at_ms: now_ms.saturating_add(duration.as_millis() as u64) };
+        self.bans.insert(target, ban.clone());
+        ban
+    }
+
+    /// Lifts the ban on `target`, and forgets its strikes. Returns whether it was banned.
+    pub fn unban(&mut self, target: &BanTarget) -> bool {
+        self.strikes.remove(target);
+        self.bans.remove(target).is_some()
+    }
+
+    /// Drops expired bans and strikes. Returns whether any ban expired.
+    pub fn prune(&mut self, now_ms: u64) -> bool {
+        let before = self.bans.len();
+        self.bans.retain(|_, ban| ban.expires_at_ms > now_ms);
+        let window_start = now_ms.saturating_sub(self.policy.strike_window.as_millis() as u64);
+        self.strikes.retain(|_, strikes| strikes.back().is_some_and(|&at| at > window_start));
+        self.bans.len() != before
+    }
+
+    /// The active bans, soonest to expire first.
+    pub fn bans(&self) -> Vec<Ban> {
+        let mut bans: Vec<_> = self.bans.values().cloned().collect();
+        bans.sort_by_key(|ban| ban.expires_at_ms);
+        bans
+    }
+}
+
+/// What every connection handler needs to police its peer.
+#[derive(Clone, Debug)]
+pub struct PeerGuard {
+    pub rate_limits: RateLimits,
+    pub bans: Arc<Mutex<BanList>>,
+    limiters: Arc<Mutex<HashMap<NodeId, Arc<PeerLimiter>>>>,
+}
+
+impl PeerGuard {
+    pub fn new(rate_limits: RateLimits, policy: BanPolicy) -> Self {
+        Self {
+            rate_limits,
+            bans: Arc::new(Mutex::new(BanList::new(policy))),
+            limiters: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn is_banned(&self, target: &BanTarget) -> bool {
+        self.bans.lock().await.is_banned(target, now_ms())
+    }
+
+    /// The limiter shared by all connections of `peer_id`.
+    pub async fn limiter(&self, peer_id: NodeId) -> Arc<PeerLimiter> {
+        self.limiters
+            .lock()
+            .await
+            .entry(peer_id)
+            .or_insert_with(|| Arc::new(PeerLimiter::new(&self.rate_limits, Instant::now())))
+            .clone()
+    }
+
+    /// Forgets the limiters no connection uses any more, once a fresh one
+    /// would be no more lenient.
+    pub async fn prune_limiters(&self) {
+        let now = Instant::now();
+        let mut limiters = self.limiters.lock().await;
+        let mut idle = Vec::new();
+        for (peer_id, limiter) in limiters.iter() {
+            if Arc::strong_count(limiter) == 1 && limiter.is_idle(&self.rate_limits, now).await {
+                idle.push(*peer_id);
+            }
+        }
+        for peer_id in idle {
+            limiters.remove(&peer_id);
+        }
+    }
+
+    /// Records a strike against `peer_id` connected from `addr`. Returns
+    /// whether it got the peer or its address banned, in which case the new
+    /// ban list is reported to the `Engine`.
+    pub async fn strike(
+        &self,
+        peer_id: NodeId,
+        addr: IpAddr,
+        reason: BanReason,
+        conn_event_tx: &mpsc::Sender<ConnectionEvent>,
+    ) -> bool {
+        let bans = self.bans.lock().await.strike(peer_id, addr, reason, now_ms());
+        for ban in &bans {
+            tracing::warn!(target = ?ban.target, reason = ?reason, "Banned after repeated strikes");
+        }
+        if !bans.is_empty() {
+            self.report_bans(conn_event_tx).await;
+        }
+        !bans.is_empty()
+    }
+
+    /// Sends the active bans to the `Engine`.
+    pub async fn report_bans(&self, conn_event_tx: &mpsc::Sender<ConnectionEvent>) {
+        let bans = self.bans.lock().await.bans();
+        let _ = conn_event_tx.send(ConnectionEvent::Bans(bans)).await;
+    }
+}
+
+/// The current time in milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_node_id(id: u8) -> NodeId {
+        let mut bytes = [0u8; 32];
+        bytes[0] = id;
+        NodeId(bytes)
+    }
+
+    fn policy() -> BanPolicy {
+        BanPolicy {
+            strike_threshold: 3,
+            addr_strike_threshold: 5,
+            strike_window: Duration::from_secs(10),
+            ban_duration: Duration::from_secs(60),
+        }
+    }
+
+    const ADDR: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+
+    #[test]
+    fn test_token_bucket_allows_burst_then_refills_at_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(10.0, 5.0, start);
+        assert_eq!((0..8).filter(|_| bucket.try_take(1.0, start)).count(), 5, "Only the burst should pass at once");
+
+        // Half a second refills five tokens, but never beyond the capacity.
+        assert!(bucket.try_take(5.0, start + Duration::from_millis(500)));
+        assert!(!bucket.try_take(1.0, start + Duration::from_millis(500)));
+        assert!(!bucket.try_take(6.0, start + Duration::from_secs(10)));
+        assert!(bucket.try_take(5.0, start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_byte_bucket_holds_a_maximum_size_message() {
+        let limits = RateLimits { streams_per_sec: 10.0, bytes_per_sec: 100.0, max_message_size: 1_000, max_concurrent_streams: 4 };
+        let now = Instant::now();
+        let (_, mut bytes) = limits.buckets(now);
+        assert!(bytes.try_take(1_000.0, now));
+        assert!(!bytes.try_take(1.0, now));
+    }
+
+    #[test]
+    fn test_strikes_within_window_lead_to_ban() {
+        let mut bans = BanList::new(policy());
+        let node = create_node_id(1);
+        let target = BanTarget::Node(node);
+
+        // Strikes that fall out of the window do not count.
+        assert!(bans.strike(node, ADDR, BanReason::MalformedMessage, 1_000).is_empty());
+        assert!(bans.strike(node, ADDR, BanReason::MalformedMessage, 12_000).is_empty());
+        assert!(bans.strike(node, ADDR, BanReason::MalformedMessage, 13_000).is_empty());
+        assert!(!bans.is_banned(&target, 13_000));
+
+        let ban = bans.strike(node, ADDR, BanReason::InvalidSignature, 14_000);
+        assert_eq!(ban, vec![Ban { target, reason: BanReason::InvalidSignature, expires_at_ms: 74_000 }], "The third strike should ban");
+        assert!(bans.is_banned(&target, 14_000));
+        assert!(!bans.is_banned(&BanTarget::Node(create_node_id(2)), 14_000));
+    }
+
+    #[test]
+    fn test_strikes_from_one_address_ban_it_across_node_ids() {
+        let mut bans = BanList::new(policy());
+        for id in 1..5 {
+            assert!(bans.strike(create_node_id(id), ADDR, BanReason::MalformedMessage, 1_000).is_empty());
+        }
+        let ban = bans.strike(create_node_id(5), ADDR, BanReason::MalformedMessage, 1_000);
+        assert_eq!(ban.iter().map(|ban| ban.target).collect::<Vec<_>>(), vec![BanTarget::Addr(ADDR)]);
+        assert!(!bans.is_banned(&BanTarget::Node(create_node_id(5)), 1_000), "No node took enough strikes");
+    }
+
+    #[tokio::test]
+    async fn test_limiters_are_shared_per_peer_and_pruned_once_idle() {
+        let limits = RateLimits { streams_per_sec: 2.0, bytes_per_sec: 100.0, max_message_size: 100, max_concurrent_streams: 1 };
+        let guard = PeerGuard::new(limits, policy());
+        let peer = create_node_id(1);
+
+        // A second connection of the same peer draws on the same buckets and permits.
+        let (first, second) = (guard.limiter(peer).await, guard.limiter(peer).await);
+        assert!(first.streams.lock().await.try_take(2.0, Instant::now()));
+        assert!(!second.streams.lock().await.try_take(1.0, Instant::now()));
+        let _permit = first.permits.clone().try_acquire_owned().unwrap();
+        assert!(second.permits.clone().try_acquire_owned().is_err());
+
+        drop((first, second));
+        guard.prune_limiters().await;
+        assert!(!guard.limiter(peer).await.streams.lock().await.try_take(1.0, Instant::now()), "Drained buckets are kept");
+    }
+
+    #[test]
+    fn test_bans_expire_and_can_be_lifted() {
+        let mut bans = BanList::new(policy());
+        let (node, addr) = (BanTarget::Node(create_node_id(1)), BanTarget::Addr([10, 0, 0, 1].into()));
+        bans.ban(node, BanReason::Manual, Duration::from_secs(5), 0);
+        bans.ban(addr, BanReason::Manual, Duration::from_secs(60), 0);
+        assert_eq!(bans.bans().iter().map(|ban| ban.target).collect::<Vec<_>>(), vec![node, addr]);
+
+        assert!(!bans.prune(4_999));
+        assert!(bans.prune(5_000));
+        assert!(!bans.is_banned(&node, 5_000));
+
+        assert!(bans.unban(&addr));
+        assert!(!bans.unban(&addr));
+        assert!(bans.bans().is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+//! src/transport/handshake.rs
+//!
+//! Implements the authentication handshake run on every new QUIC connection.
+//! Each side proves ownership of its ed25519 `Identity` by signing keying
+//! material exported from the connection's TLS session. Because that material
+//! is unique to the session, a proof cannot be replayed on another connection,
+//! and the connection becomes bound to the peer's verified `NodeId`.
+//!
+//! The proof also carries the addresses the peer listens on, which may differ
+//! from the connection's source address (e.g. behind NAT or when bound to
+//! `0.0.0.0`). They are covered by the signature, which is made over a
+//! canonical encoding (see `transcript`) so that other implementations can
+//! produce and check it. While `LocalNode::accept_legacy` is set, proofs of
+//! nodes of protocol version 1 (see `legacy_transcript`) are accepted too,
+//! and answered in kind.
+
+use crate::{
+    config::TlsMode,
+    domain::{self, Identity, NodeId},
+    error::{Error, Result},
+};
+use ed25519_dalek::Signature;
+use quinn::Connection;
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, time::Duration};
+
+/// Label for TLS keying material export and domain separation of signatures.
+/// The version suffix changes whenever the transcript encoding does.
+pub const HANDSHAKE_LABEL: &[u8] = b"gossip-network/handshake/v2";
+/// The label used by nodes of protocol version 1.
+const LEGACY_HANDSHAKE_LABEL: &[u8] = b"gossip-network/handshake/v1";
+/// Upper bound on the size of a single handshake message.
+const MAX_HANDSHAKE_SIZE: usize = 1_024;
+/// How long a peer has to complete the handshake before it is dropped.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The maximum number of listen addresses a proof may carry.
+pub const MAX_LISTEN_ADDRS: usize = 8;
+
+/// What this node proves and announces in every handshake.
+#[derive(Clone, Debug)]
+pub struct LocalNode {
+    pub identity: Identity,
+    /// The addresses peers should dial this node at.
+    pub listen_addrs: Vec<SocketAddr>,
+    /// How certificates are issued. In `Identity` mode, each peer's
+    /// certificate must name the `NodeId` it proves in the handshake.
+    pub tls_mode: TlsMode,
+    /// Whether peers of protocol version 1 are accepted: their handshake
+    /// proofs and envelopes.
+    pub accept_legacy: bool,
+}
+
+/// A remote peer, as authenticated by the handshake.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifiedPeer {
+    pub node_id: NodeId,
+    /// The addresses the peer listens on. Empty if it did not announce any.
+    pub listen_addrs: Vec<SocketAddr>,
+}
+
+/// The proof each side sends: its claimed identity and listen addresses, and
+/// a signature binding both to the current TLS session.
+#[derive(Debug, Serialize, Deserialize)]
+struct HandshakeProof {
+    node_id: NodeId,
+    listen_addrs: Vec<SocketAddr>,
+    signature: Signature,
+}
+
+/// The transcript encoding a proof was signed over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transcript {
+    /// `transcript`.
+    Canonical,
+    /// `legacy_transcript`, signed by nodes of protocol version 1.
+    Legacy,
+}
+
+/// Which end of the connection produced a proof. Included in the signed
+/// transcript so a peer cannot reflect our own proof back at us.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Initiator = 0,
+    Responder = 1,
+}
+
+/// Runs the handshake as the side that dialed the connection.
+pub async fn initiate(conn: &Connection, local: &LocalNode) -> Result<VerifiedPeer> {
+    let fail = |reason: String| Error::Handshake(conn.remote_address(), reason);
+
+    let (mut send, mut recv) = conn
+        .open_bi()
+        .await
+        .map_err(|e| fail(format!("failed to open stream: {e}")))?;
+    let proof = create_proof(conn, local, Role::Initiator, Transcript::Canonical)?;
+    send.write_all(&proof)
+        .await
+        .map_err(|e| fail(format!("failed to send proof: {e}")))?;
+    send.finish()
+        .await
+        .map_err(|e| fail(format!("failed to send proof: {e}")))?;
+
+    let bytes = recv
+        .read_to_end(MAX_HANDSHAKE_SIZE)
+        .await
+        .map_err(|e| fail(format!("failed to read proof: {e}")))?;
+    let (peer, _) = verify_proof(conn, local, &bytes, Role::Responder)?;
+    Ok(peer)
+}
+
+/// Runs the handshake as the side that accepted the connection.
+pub async fn respond(conn: &Connection, local: &LocalNode) -> Result<VerifiedPeer> {
+    let fail = |reason: String| Error::Handshake(conn.remote_address(), reason);
+
+    let (mut send, mut recv) = conn
+        .accept_bi()
+        .await
+        .map_err(|e| fail(format!("failed to accept stream: {e}")))?;
+    let bytes = recv
+        .read_to_end(MAX_HANDSHAKE_SIZE)
+        .await
+        .map_err(|e| fail(format!("failed to read proof: {e}")))?;
+    let (peer, transcript) = verify_proof(conn, local, &bytes, Role::Initiator)?;
+
+    let proof = create_proof(conn, local, Role::Responder, transcript)?;
+    send.write_all(&proof)
+        .await
+        .map_err(|e| fail(format!("failed to send proof: {e}")))?;
+    send.finish()
+        .await
+        .map_err(|e| fail(format!("failed to send proof: {e}")))?;
+    Ok(peer)
+}
+
+/// Derives a value both ends of `conn` agree on and no other connection
+/// shares, exported under `label`.
+fn session_binding(conn: &Connection, label: &[u8]) -> Result<[u8; 32]> {
+    let mut output = [0u8; 32];
+    conn.export_keying_material(&mut output, label, b"")
+        .map_err(|_| {
+            Error::Handshake(conn.remote_address(), "failed to export keying material".into())
+        })?;
+    Ok(output)
+}
+
+/// The bytes signed by the party playing `role`: `HANDSHAKE_LABEL`, the role
+/// as one byte, the session binding, and the listen addresses as encoded by
+/// `domain::encode_socket_addrs`.
+///
+/// ```text
+/// "gossip-network/handshake/v2" | role (u8: 0 initiator, 1 responder) | session binding (32 bytes)
+///     | address count (u32) | addresses
+/// ```
+pub fn transcript(session_binding: &[u8; 32], role: Role, listen_addrs: &[SocketAddr]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HANDSHAKE_LABEL.len() + 37 + 19 * listen_addrs.len());
+    bytes.extend_from_slice(HANDSHAKE_LABEL);
+    bytes.push(role as u8);
+    bytes.extend_from_slice(session_binding);
+    domain::encode_socket_addrs(&mut bytes, listen_addrs);
+    bytes
+}
+
+/// The bytes signed by nodes of protocol version 1: `LEGACY_HANDSHAKE_LABEL`,
+/// the role as one byte, the session binding exported under that label, and
+/// the bincode encoding of the listen addresses.
+fn legacy_transcript(session_binding: &[u8; 32], role: Role, listen_addrs: &[SocketAddr]) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(LEGACY_HANDSHAKE_LABEL.len() + 1 + session_binding.len());
+    bytes.extend_from_slice(LEGACY_HANDSHAKE_LABEL);
+    bytes.push(role as u8);
+    bytes.extend_from_slice(session_binding);
+    bytes.extend(bincode::serialize(listen_addrs)?);
+    Ok(bytes)
+}
+
+/// The bytes of `conn` signed over by the party playing `role`.
+fn signed_bytes(conn: &Connection, kind: Transcript, role: Role, listen_addrs: &[SocketAddr]) -> Result<Vec<u8>> {
+    match kind {
+        Transcript::Canonical => Ok(transcript(&session_binding(conn, HANDSHAKE_LABEL)?, role, listen_addrs)),
+        Transcript::Legacy => legacy_transcript(&session_binding(conn, LEGACY_HANDSHAKE_LABEL)?, role, listen_addrs),
+    }
+}
+
+fn create_proof(conn: &Connection, local: &LocalNode, role: Role, kind: Transcript) -> Result<Vec<u8>> {
+    let proof = HandshakeProof {
+        node_id: local.identity.node_id,
+        listen_addrs: local.listen_addrs.clone(),
+        signature: local.identity.sign_bytes(&signed_bytes(conn, kind, role, &local.listen_addrs)?),
+    };
+    Ok(bincode::serialize(&proof)?)
+}
+
+/// Verifies the proof in `bytes`, made by the party playing `role`, and
+/// returns the peer together with the transcript encoding it signed.
+fn verify_proof(conn: &Connection, local: &LocalNode, bytes: &[u8], role: Role) -> Result<(VerifiedPeer, Transcript)> {
+    let fail = |reason: &str| Error::Handshake(conn.remote_address(), reason.to_string());
+    let proof: HandshakeProof =
+        bincode::deserialize(bytes).map_err(|_| fail("malformed proof"))?;
+    if proof.node_id == local.identity.node_id {
+        return Err(fail("peer presented our own identity"));
+    }
+    if proof.listen_addrs.len() > MAX_LISTEN_ADDRS {
+        return Err(fail("too many listen addresses"));
+    }
+    let kinds: &[Transcript] = if local.accept_legacy {
+        &[Transcript::Canonical, Transcript::Legacy]
+    } else {
+        &[Transcript::Canonical]
+    };
+    for &kind in kinds {
+        let bytes = signed_bytes(conn, kind, role, &proof.listen_addrs)?;
+        if proof.node_id.verify(&bytes, &proof.signature).is_ok() {
+            return Ok((VerifiedPeer { node_id: proof.node_id, listen_addrs: proof.listen_addrs }, kind));
+        }
+    }
+    Err(fail("invalid signature"))
+}
@@ -0,0 +1,207 @@
+//! src/transport/legacy.rs
+//!
+//! Decodes the envelope bodies of nodes of protocol version 1, which are
+//! accepted during a rollout while `accept_legacy_signatures` is on. Version 1
+//! predates application payloads: its messages carry a single telemetry value
+//! (see `GossipPayloadV1`), which becomes a payload of the `telemetry` kind,
+//! and its sync digests, announcements and grafts, which name no kind, name
+//! `telemetry`. Membership, probe and peer exchange bodies are unchanged.
+
+use crate::{
+    domain::{self, AppPayload, GossipPayloadV1, MessageId, NodeId, TelemetryData, Version},
+    error::{Error, Result},
+    transport::{
+        self as wire,
+        envelope::{MessageType, WireFormat},
+        proto, WireMessage,
+    },
+};
+use ed25519_dalek::Signature;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct SignedMessage {
+    message: GossipPayloadV1,
+    originator: NodeId,
+    signature: Signature,
+}
+
+#[derive(Deserialize)]
+struct RelayedMessage {
+    message: SignedMessage,
+    hops: u32,
+    status_updates: Vec<wire::StatusUpdate>,
+}
+
+#[derive(Deserialize)]
+struct Announcement {
+    id: MessageId,
+    originator: NodeId,
+    version: Version,
+}
+
+#[derive(Deserialize)]
+enum ControlFrame {
+    IHave(Vec<Announcement>),
+    Graft { id: MessageId, originator: NodeId },
+    Prune,
+}
+
+/// Decodes a body of type `message_type` sent by a node of protocol version 1.
+pub fn decode(message_type: MessageType, body: &[u8], format: WireFormat) -> Result<WireMessage> {
+    if format == WireFormat::Protobuf {
+        // The version 1 schema differs only in fields the current one decodes
+        // (see `proto/domain.proto`) or leaves empty.
+        return proto::decode(message_type, body).map(with_telemetry_kind);
+    }
+    Ok(match message_type {
+        MessageType::Gossip => WireMessage::Gossip(bincode::deserialize::<RelayedMessage>(body)?.try_into()?),
+        MessageType::SyncDigest => WireMessage::SyncDigest(
+            bincode::deserialize::<Vec<(NodeId, Version)>>(body)?
+                .into_iter()
+                .map(|(originator, version)| (originator, telemetry_kind(), version))
+                .collect(),
+        ),
+        MessageType::SyncReply => WireMessage::SyncReply(
+            bincode::deserialize::<Vec<RelayedMessage>>(body)?
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+        ),
+        MessageType::Control => WireMessage::Control(bincode::deserialize::<ControlFrame>(body)?.into()),
+        MessageType::Membership | MessageType::Probe | MessageType::PeerExchange => message_type.decode(body, format)?,
+    })
+}
+
+fn telemetry_kind() -> String {
+    TelemetryData::KIND.to_string()
+}
+
+/// Names the `telemetry` kind wherever a protobuf body of version 1 left the
+/// kind empty.
+fn with_telemetry_kind(mut message: WireMessage) -> WireMessage {
+    let fill = |kind: &mut String| {
+        if kind.is_empty() {
+            *kind = telemetry_kind();
+        }
+    };
+    match &mut message {
+        WireMessage::SyncDigest(digest) => digest.iter_mut().for_each(|(_, kind, _)| fill(kind)),
+        WireMessage::Control(wire::ControlFrame::IHave(announcements)) => {
+            announcements.iter_mut().for_each(|announcement| fill(&mut announcement.kind))
+        }
+        WireMessage::Control(wire::ControlFrame::Graft { kind, .. }) => fill(kind),
+        _ => {}
+    }
+    message
+}
+
+impl TryFrom<RelayedMessage> for wire::RelayedMessage {
+    type Error = Error;
+
+    fn try_from(relayed: RelayedMessage) -> Result<Self> {
+        let SignedMessage { message, originator, signature } = relayed.message;
+        Ok(Self {
+            message: domain::SignedMessage { message: message.try_into()?, originator, signature },
+            hops: relayed.hops,
+            status_updates: relayed.status_updates,
+        })
+    }
+}
+
+impl From<ControlFrame> for wire::ControlFrame {
+    fn from(frame: ControlFrame) -> Self {
+        match frame {
+            ControlFrame::IHave(announcements) => Self::IHave(
+                announcements
+                    .into_iter()
+                    .map(|Announcement { id, originator, version }| wire::Announcement {
+                        id,
+                        originator,
+                        kind: telemetry_kind(),
+                        version,
+                    })
+                    .collect(),
+            ),
+            ControlFrame::Graft { id, originator } => Self::Graft { id, originator, kind: telemetry_kind() },
+            ControlFrame::Prune => Self::Prune,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Identity, SigningEncoding, TelemetryV1};
+    use prost::Message;
+
+    fn signed_v1(identity: &Identity) -> (GossipPayloadV1, Signature) {
+        let payload = GossipPayloadV1 {
+            telemetry: TelemetryV1 { timestamp_ms: 1_000, value: 117.25 },
+            community_id: 3,
+            version: Version { epoch: 2, sequence: 9 },
+        };
+        let signature = identity.sign_bytes(&payload.signing_bytes());
+        (payload, signature)
+    }
+
+    fn assert_telemetry(message: WireMessage, identity: &Identity) {
+        let WireMessage::Gossip(relayed) = message else {
+            panic!("expected a gossip message, got {:?}", message);
+        };
+        assert_eq!(relayed.hops, 2);
+        let message = relayed.message;
+        assert_eq!(message.originator, identity.node_id);
+        assert_eq!(message.message.payload.kind, "telemetry");
+        assert_eq!(message.message.payload.data, br#"{"value":117.25}"#);
+        assert_eq!(message.message.timestamp_ms, 1_000);
+        assert!(message.verify_with(false).is_err());
+        assert_eq!(message.verify_with(true).unwrap(), SigningEncoding::TelemetryV1);
+    }
+
+    #[test]
+    fn test_bincode_bodies_of_version_1_decode_as_telemetry() {
+        let identity = Identity::new();
+        let (payload, signature) = signed_v1(&identity);
+        let statuses: Vec<wire::StatusUpdate> = Vec::new();
+        let body = bincode::serialize(&((&payload, identity.node_id, signature), 2u32, statuses)).unwrap();
+        assert_telemetry(decode(MessageType::Gossip, &body, WireFormat::Bincode).unwrap(), &identity);
+
+        let (id, originator) = (MessageId([4; 32]), NodeId([5; 32]));
+        let body = bincode::serialize(&(1u32, (id, originator))).unwrap();
+        let graft = decode(MessageType::Control, &body, WireFormat::Bincode).unwrap();
+        assert!(matches!(
+            graft,
+            WireMessage::Control(wire::ControlFrame::Graft { id: i, originator: o, kind }) if i == id && o == originator && kind == "telemetry"
+        ));
+    }
+
+    #[test]
+    fn test_protobuf_bodies_of_version_1_decode_as_telemetry() {
+        let identity = Identity::new();
+        let (payload, signature) = signed_v1(&identity);
+        let body = proto::RelayedMessage {
+            message: Some(proto::SignedMessage {
+                message: Some(proto::GossipPayload {
+                    telemetry: Some(proto::TelemetryV1 { timestamp_ms: 1_000, value: 117.25 }),
+                    community_id: payload.community_id,
+                    version: Some((&payload.version).into()),
+                    ..Default::default()
+                }),
+                originator: identity.node_id.0.to_vec(),
+                signature: signature.to_bytes().to_vec(),
+            }),
+            hops: 2,
+            status_updates: Vec::new(),
+        }
+        .encode_to_vec();
+        assert_telemetry(decode(MessageType::Gossip, &body, WireFormat::Protobuf).unwrap(), &identity);
+
+        let body = proto::SyncDigest {
+            entries: vec![proto::DigestEntry { originator: vec![6; 32], version: Some((&Version::default()).into()), kind: String::new() }],
+        }
+        .encode_to_vec();
+        let digest = decode(MessageType::SyncDigest, &body, WireFormat::Protobuf).unwrap();
+        assert!(matches!(digest, WireMessage::SyncDigest(digest) if digest[0].1 == "telemetry"));
+    }
+}
@@ -0,0 +1,344 @@
+//! src/transport/manager.rs
+//!
+//! Manages the node's QUIC connections.
+//!
+//! The `ConnectionManager` caches outbound connections for reuse and enforces
+//! the connection limits. Inbound connections beyond `max_inbound_connections`
+//! are refused. When an outbound connection takes the node past
+//! `max_outbound_connections`, another one is closed, picked by the
+//! configured `EvictionPolicy`.
+//!
+//! Persistent peers, such as the bootstrap peers, are kept connected and are
+//! never evicted. Each is looked after by its own task, which dials it, waits
+//! for the connection to be lost, and dials again. Retries back off
+//! exponentially with jitter, up to a maximum interval, so that a peer that is
+//! down is not hammered, and nodes restarted together do not redial in
+//! lockstep. Every state change is reported to the `Engine`.
+
+use crate::{
+    config::{Config, EvictionPolicy},
+    domain::{DialState, PersistentPeer},
+    error::Result,
+    transport::{connection::CONNECTION_LIMIT, ConnectionEvent},
+};
+use quinn::Connection;
+use rand::Rng;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::mpsc, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+/// The connection limits of a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    pub max_outbound: usize,
+    pub max_inbound: usize,
+    pub eviction_policy: EvictionPolicy,
+}
+
+impl ConnectionLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_outbound: config.max_outbound_connections,
+            max_inbound: config.max_inbound_connections,
+            eviction_policy: config.eviction_policy,
+        }
+    }
+}
+
+/// What an outbound connection is scored by when choosing one to evict.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Usage {
+    pub established_at: Instant,
+    /// When a message was last sent over the connection.
+    pub last_used: Instant,
+    /// The connection's current round-trip time estimate.
+    pub rtt: Duration,
+}
+
+/// The connection `policy` evicts among `candidates`: the oldest, the one
+/// idle the longest, or the one with the highest latency. Ties go to the
+/// lowest address, so the choice does not depend on iteration order.
+pub fn select_eviction(
+    policy: EvictionPolicy,
+    candidates: impl IntoIterator<Item = (SocketAddr, Usage)>,
+) -> Option<SocketAddr> {
+    let candidates = candidates.into_iter();
+    let victim = match policy {
+        EvictionPolicy::Oldest => candidates.min_by_key(|&(addr, usage)| (usage.established_at, addr)),
+        EvictionPolicy::LeastRecentlyUsed => candidates.min_by_key(|&(addr, usage)| (usage.last_used, addr)),
+        EvictionPolicy::HighestLatency => {
+            candidates.min_by_key(|&(addr, usage)| (std::cmp::Reverse(usage.rtt), addr))
+        }
+    };
+    victim.map(|(addr, _)| addr)
+}
+
+#[derive(Debug)]
+struct OutboundConnection {
+    conn: Connection,
+    established_at: Instant,
+    last_used: Instant,
+}
+
+/// The outbound connection cache and inbound connection count of a node.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    limits: ConnectionLimits,
+    outbound: HashMap<SocketAddr, OutboundConnection>,
+    inbound: usize,
+    /// Peers whose connections are never evicted.
+    protected: HashSet<SocketAddr>,
+}
+
+impl ConnectionManager {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self { limits, outbound: HashMap::new(), inbound: 0, protected: HashSet::new() }
+    }
+
+    /// Exempts the connection to `addr` from eviction.
+    pub fn protect(&mut self, addr: SocketAddr) {
+        self.protected.insert(addr);
+    }
+
+    pub fn outbound_count(&self) -> usize {
+        self.outbound.len()
+    }
+
+    pub fn inbound_count(&self) -> usize {
+        self.inbound
+    }
+
+    /// The live outbound connection to `addr`, if any. Fetching it counts as
+    /// a use; a closed connection found in the cache is dropped.
+    pub fn get(&mut self, addr: &SocketAddr) -> Option<Connection> {
+        let cached = self.outbound.get_mut(addr)?;
+        if cached.conn.close_reason().is_some() {
+            self.outbound.remove(addr);
+            return None;
+        }
+        cached.last_used = Instant::now();
+        Some(cached.conn.clone())
+    }
+
+    /// Caches a new outbound connection to `addr`. If that takes the node
+    /// past `max_outbound`, other unprotected connections are evicted and
+    /// closed with `CONNECTION_LIMIT`.
+    pub fn insert(&mut self, addr: SocketAddr, conn: Connection) {
+        let now = Instant::now();
+        self.outbound.insert(addr, OutboundConnection { conn, established_at: now, last_used: now });
+        while self.outbound.len() > self.limits.max_outbound {
+            let candidates = self
+                .outbound
+                .iter()
+                .filter(|(cached_addr, _)| **cached_addr != addr && !self.protected.contains(cached_addr))
+                .map(|(&cached_addr, cached)| {
+                    let usage = Usage {
+                        established_at: cached.established_at,
+                        last_used: cached.last_used,
+                        rtt: cached.conn.rtt(),
+                    };
+                    (cached_addr, usage)
+                });
+            let Some(victim) = select_eviction(self.limits.eviction_policy, candidates) else {
+                break;
+            };
+            if let Some(evicted) = self.outbound.remove(&victim) {
+                tracing::info!(peer = %victim, policy = ?self.limits.eviction_policy, "Evicting outbound connection over the limit");
+                evicted.conn.close(CONNECTION_LIMIT, b"connection limit");
+            }
+        }
+    }
+
+    /// Removes the cached connection to `addr` if it is still `conn`, rather
+    /// than one that replaced it.
+    pub fn remove(&mut self, addr: &SocketAddr, conn: &Connection) {
+        if self.outbound.get(addr).is_some_and(|cached| cached.conn.stable_id() == conn.stable_id()) {
+            self.outbound.remove(addr);
+        }
+    }
+
+    /// Takes an inbound connection slot, unless all `max_inbound` are in use.
+    /// A slot taken must be given back with `release_inbound`.
+    pub fn try_reserve_inbound(&mut self) -> bool {
+        if self.inbound >= self.limits.max_inbound {
+            return false;
+        }
+        self.inbound += 1;
+        true
+    }
+
+    pub fn release_inbound(&mut self) {
+        self.inbound = self.inbound.saturating_sub(1);
+    }
+}
+
+/// The retry schedule for persistent peers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    /// The delay before redialing a peer whose connection was lost.
+    pub initial: Duration,
+    /// The longest delay between two attempts, before jitter.
+    pub max: Duration,
+}
+
+impl Backoff {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            initial: Duration::from_millis(config.reconnect_initial_backoff_ms),
+            max: Duration::from_millis(config.reconnect_max_backoff_ms),
+        }
+    }
+
+    /// The delay before the next attempt, after `failures` consecutive failed
+    /// attempts. It doubles with every failure up to `max`, and is drawn
+    /// uniformly from the upper half of that value.
+    pub fn delay(&self, failures: u32, rng: &mut impl Rng) -> Duration {
+        let factor = 2u32.saturating_pow(failures);
+        let ceiling = self.initial.saturating_mul(factor).min(self.max);
+        ceiling / 2 + (ceiling / 2).mul_f64(rng.gen())
+    }
+}
+
+/// Keeps `addr` connected until `shutdown_token` is cancelled. `connect`
+/// returns a live connection to the peer, dialing it if there is none.
+pub async fn maintain_peer<F, Fut>(
+    addr: SocketAddr,
+    backoff: Backoff,
+    connect: F,
+    conn_event_tx: mpsc::Sender<ConnectionEvent>,
+    shutdown_token: CancellationToken,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Connection>>,
+{
+    let report = |state: DialState, failures: u32, retry_at_ms: Option<u64>| {
+        let peer = PersistentPeer { addr, state, failures, retry_at_ms };
+        let conn_event_tx = conn_event_tx.clone();
+        async move {
+            let _ = conn_event_tx.send(ConnectionEvent::PersistentPeer(peer)).await;
+        }
+    };
+
+    let mut failures = 0;
+    loop {
+        report(DialState::Connecting, failures, None).await;
+        let attempt = tokio::select! {
+            _ = shutdown_token.cancelled() => return,
+            attempt = connect() => attempt,
+        };
+        match attempt {
+            Ok(conn) => {
+                failures = 0;
+                report(DialState::Connected, failures, None).await;
+                tokio::select! {
+                    _ = shutdown_token.cancelled() => return,
+                    _ = conn.closed() => {},
+                }
+                tracing::info!(peer = %addr, "Lost connection to persistent peer");
+            }
+            Err(e) => {
+                failures += 1;
+                tracing::warn!(peer = %addr, failures, error = %e, "Failed to connect to persistent peer");
+            }
+        }
+
+        let delay = backoff.delay(failures, &mut rand::thread_rng());
+        let retry_at = SystemTime::now() + delay;
+        let retry_at_ms = retry_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        report(DialState::BackingOff, failures, Some(retry_at_ms)).await;
+        tokio::select! {
+            _ = shutdown_token.cancelled() => return,
+            _ = tokio::time::sleep(delay) => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn create_addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    fn backoff() -> Backoff {
+        Backoff { initial: Duration::from_millis(100), max: Duration::from_secs(5) }
+    }
+
+    #[test]
+    fn test_delay_doubles_with_each_failure_within_jitter() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for failures in 0..5 {
+            let ceiling = Duration::from_millis(100 * 2u64.pow(failures));
+            for _ in 0..50 {
+                let delay = backoff().delay(failures, &mut rng);
+                assert!(delay >= ceiling / 2 && delay <= ceiling, "{delay:?} outside the jitter range for {failures} failures");
+            }
+        }
+    }
+
+    #[test]
+    fn test_delay_is_capped_at_max() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for failures in [6, 10, 32, u32::MAX] {
+            let delay = backoff().delay(failures, &mut rng);
+            assert!(delay <= backoff().max && delay >= backoff().max / 2);
+        }
+    }
+
+    #[test]
+    fn test_delay_is_jittered() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let delays: std::collections::HashSet<_> = (0..10).map(|_| backoff().delay(3, &mut rng)).collect();
+        assert!(delays.len() > 1, "Delays for the same failure count should differ");
+    }
+
+    #[test]
+    fn test_select_eviction_scores_by_policy() {
+        let start = Instant::now();
+        let usage = |age_secs: u64, idle_secs: u64, rtt_ms: u64| Usage {
+            established_at: start + Duration::from_secs(100 - age_secs),
+            last_used: start + Duration::from_secs(100 - idle_secs),
+            rtt: Duration::from_millis(rtt_ms),
+        };
+        let candidates = [
+            (create_addr(5001), usage(90, 1, 10)),
+            (create_addr(5002), usage(10, 60, 20)),
+            (create_addr(5003), usage(20, 2, 300)),
+        ];
+
+        assert_eq!(select_eviction(EvictionPolicy::Oldest, candidates), Some(create_addr(5001)));
+        assert_eq!(select_eviction(EvictionPolicy::LeastRecentlyUsed, candidates), Some(create_addr(5002)));
+        assert_eq!(select_eviction(EvictionPolicy::HighestLatency, candidates), Some(create_addr(5003)));
+        assert_eq!(select_eviction(EvictionPolicy::Oldest, []), None);
+    }
+
+    #[test]
+    fn test_select_eviction_breaks_ties_by_address() {
+        let now = Instant::now();
+        let usage = Usage { established_at: now, last_used: now, rtt: Duration::from_millis(10) };
+        let candidates = [(create_addr(5003), usage), (create_addr(5001), usage), (create_addr(5002), usage)];
+        for policy in [EvictionPolicy::Oldest, EvictionPolicy::LeastRecentlyUsed, EvictionPolicy::HighestLatency] {
+            assert_eq!(select_eviction(policy, candidates), Some(create_addr(5001)));
+        }
+    }
+
+    #[test]
+    fn test_inbound_slots_are_limited() {
+        let limits = ConnectionLimits { max_outbound: 1, max_inbound: 2, eviction_policy: EvictionPolicy::default() };
+        let mut manager = ConnectionManager::new(limits);
+        assert!(manager.try_reserve_inbound());
+        assert!(manager.try_reserve_inbound());
+        assert!(!manager.try_reserve_inbound(), "A third inbound connection should be refused");
+
+        manager.release_inbound();
+        assert!(manager.try_reserve_inbound(), "A released slot should be reusable");
+        assert_eq!(manager.inbound_count(), 2);
+    }
+}
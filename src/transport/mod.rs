@@ -4,60 +4,231 @@
 //! using the QUIC protocol.
 
 use crate::{
-    domain::SignedMessage,
+    config::{Config, TlsConfig},
+    domain::{
+        Ban, BanReason, BanTarget, Identity, MemberStatus, MessageId, NodeId, PersistentPeer, SignedMessage,
+        SignedPeerAdvertisement, Version,
+    },
     error::Result,
-    transport::{connection::handle_connection, tls::configure_tls},
+    transport::{
+        connection::handle_connection,
+        guard::{BanPolicy, PeerGuard, RateLimits},
+        handshake::LocalNode,
+        manager::{Backoff, ConnectionLimits, ConnectionManager},
+        tls::configure_tls,
+    },
 };
-use quinn::{Connection, Endpoint, TokioRuntime};
+use quinn::{Endpoint, TokioRuntime};
+use serde::{Deserialize, Serialize};
 use socket2::{Domain, Protocol, Socket, Type};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 // MODIFICATION: Add Semaphore for concurrency limiting.
 use tokio::sync::{mpsc, Mutex, Semaphore};
 use tokio_util::sync::CancellationToken;
 
 pub mod connection;
+pub mod envelope;
+pub mod guard;
+pub mod handshake;
+pub mod legacy;
+pub mod manager;
+pub mod proto;
 pub mod tls;
 
+/// How often expired bans are dropped.
+const BAN_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
 /// The maximum allowed size for a single incoming message on a QUIC stream.
 const MAX_MESSAGE_SIZE: usize = 1_024 * 1_024; // 1 MiB
 // MODIFICATION: Define a limit for concurrent inbound streams.
 const MAX_CONCURRENT_STREAMS: usize = 256;
 
+/// A signed message in transit, with the number of hops it had travelled
+/// when it was sent. The hop count and the piggybacked status updates are not
+/// covered by the signature.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RelayedMessage {
+    pub message: SignedMessage,
+    pub hops: u32,
+    pub status_updates: Vec<StatusUpdate>,
+}
+
+/// A message exchanged between peers, sent as a single QUIC stream in a
+/// versioned envelope (see `envelope`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WireMessage {
+    /// A signed telemetry update, pushed by gossip.
+    Gossip(RelayedMessage),
+    /// An anti-entropy digest: the sender's latest known version per
+    /// originator and payload kind.
+    SyncDigest(Vec<(NodeId, String, Version)>),
+    /// The reply to a digest: messages its sender was missing or behind on.
+    SyncReply(Vec<RelayedMessage>),
+    /// A broadcast-tree control frame, used in Plumtree mode.
+    Control(ControlFrame),
+    /// A partial view membership message.
+    Membership(MembershipMessage),
+    /// A SWIM failure detector probe.
+    Probe(ProbeMessage),
+    /// Peer exchange: the sender's own advertisement and a sample of the
+    /// advertisements it has collected.
+    PeerExchange(Vec<SignedPeerAdvertisement>),
+}
+
+/// The SWIM status of a node at a given incarnation, disseminated by
+/// piggybacking it on other messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusUpdate {
+    pub node: NodeId,
+    pub status: MemberStatus,
+    pub incarnation: Version,
+}
+
+/// SWIM failure detector messages. `seq` matches an ack to its probe.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProbeMessage {
+    /// Asks the receiver to acknowledge that it is alive.
+    Ping { seq: u64, status_updates: Vec<StatusUpdate> },
+    /// Asks the receiver to ping `target` on the sender's behalf and to relay
+    /// the ack.
+    PingReq { seq: u64, target: NodeId, target_addr: SocketAddr },
+    /// Answers a ping, directly or relayed, on behalf of `target`.
+    Ack { seq: u64, target: NodeId, status_updates: Vec<StatusUpdate> },
+}
+
+/// HyParView membership messages, which maintain each node's active and
+/// passive views.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MembershipMessage {
+    /// Asks the receiver to add the sender as a neighbour and to introduce it
+    /// to the rest of the network.
+    Join,
+    /// One step of the random walk that introduces a joining node.
+    ForwardJoin { node: NodeId, addr: SocketAddr, ttl: u32 },
+    /// Asks the receiver to add the sender as a neighbour.
+    Neighbor { high_priority: bool },
+    /// Tells the receiver that the sender removed it from its active view.
+    Disconnect,
+    /// Offers a sample of the origin's views, travelling on a random walk.
+    Shuffle {
+        origin: NodeId,
+        origin_addr: SocketAddr,
+        ttl: u32,
+        peers: Vec<(NodeId, SocketAddr)>,
+    },
+    /// Answers a shuffle with a sample of the receiver's passive view.
+    ShuffleReply { peers: Vec<(NodeId, SocketAddr)> },
+}
+
+/// Announces that the sender holds a message, without sending it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Announcement {
+    pub id: MessageId,
+    pub originator: NodeId,
+    /// The kind of the message's payload.
+    pub kind: String,
+    pub version: Version,
+}
+
+/// Control frames that maintain the Plumtree broadcast tree.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlFrame {
+    /// Lazy push: messages the sender received, announced by id only.
+    IHave(Vec<Announcement>),
+    /// Asks the receiver to send its latest message of `kind` from
+    /// `originator` and to add the sender back to its eager peers.
+    Graft { id: MessageId, originator: NodeId, kind: String },
+    /// Asks the receiver to move the sender to its lazy peers.
+    Prune,
+}
+
+/// Wraps a message sent directly by its originator.
+impl From<SignedMessage> for WireMessage {
+    fn from(message: SignedMessage) -> Self {
+        WireMessage::Gossip(RelayedMessage { message, hops: 0, status_updates: Vec::new() })
+    }
+}
+
 /// Commands that can be sent to the `Transport` service.
 #[derive(Debug)]
 pub enum TransportCommand {
-    SendMessage(SocketAddr, SignedMessage),
+    SendMessage(SocketAddr, WireMessage),
+    /// Sends a control frame, which carries no payload of its own.
+    SendControl(SocketAddr, ControlFrame),
+    /// Records a strike against a peer that misbehaved, and the address it
+    /// connected from.
+    Strike { peer_id: NodeId, addr: IpAddr, reason: BanReason },
+    /// Bans `target` for `duration`, or for `ban_duration_ms` if `None`.
+    Ban { target: BanTarget, duration: Option<Duration> },
+    /// Lifts the ban on `target`.
+    Unban(BanTarget),
+    /// Connects to `addr` without sending anything, so that the peer there
+    /// is authenticated and reported as connected.
+    Connect(SocketAddr),
 }
 
-/// A message received from a peer, bundled with its network address.
+/// A message received from a peer, bundled with its network address and the
+/// `NodeId` the connection was authenticated as.
 #[derive(Debug)]
 pub struct InboundMessage {
     pub peer_addr: SocketAddr,
-    pub message: SignedMessage,
+    pub peer_id: NodeId,
+    pub message: WireMessage,
+}
+
+/// Which side opened a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The peer dialed this node.
+    Inbound,
+    /// This node dialed the peer.
+    Outbound,
 }
 
 // NEW: Events sent from Transport to Engine to report connection status.
+// `peer_id` is the identity verified by the connection handshake.
 #[derive(Debug)]
 pub enum ConnectionEvent {
-    PeerConnected { peer_addr: SocketAddr },
-    PeerDisconnected { peer_addr: SocketAddr },
+    /// `peer_addr` is the connection's remote address, which for inbound
+    /// connections is the peer's source port rather than where it listens.
+    /// `listen_addrs` are the addresses the peer announced in the handshake.
+    PeerConnected { peer_addr: SocketAddr, peer_id: NodeId, listen_addrs: Vec<SocketAddr>, direction: Direction },
+    PeerDisconnected { peer_addr: SocketAddr, peer_id: NodeId, direction: Direction },
+    /// The connection state of a persistent peer changed.
+    PersistentPeer(PersistentPeer),
+    /// A ban was placed, lifted or expired. Carries every active ban.
+    Bans(Vec<Ban>),
 }
 
 /// The P2P network transport actor.
 pub struct Transport {
     endpoint: Endpoint,
+    /// This node's identity and the listen addresses announced to peers.
+    local: LocalNode,
+    /// The TLS server name expected from every peer we dial.
+    server_name: Arc<str>,
     command_rx: mpsc::Receiver<TransportCommand>,
     inbound_tx: mpsc::Sender<InboundMessage>,
     // NEW: Channel for sending connection events to the Engine.
     conn_event_tx: mpsc::Sender<ConnectionEvent>,
+    /// Peers kept connected at all times, redialed with `backoff`.
     bootstrap_peers: Vec<SocketAddr>,
-    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    backoff: Backoff,
+    /// The outbound connection cache, which also enforces the connection limits.
+    connections: Arc<Mutex<ConnectionManager>>,
     // NEW: Semaphore to limit concurrent stream handling.
     stream_semaphore: Arc<Semaphore>,
+    /// Per-peer rate limits and the ban list.
+    guard: PeerGuard,
 }
 
 impl Transport {
     pub fn new(
+        identity: Identity,
+        tls_config: &TlsConfig,
         bind_addr: SocketAddr,
         bootstrap_peers: Vec<SocketAddr>,
         command_rx: mpsc::Receiver<TransportCommand>,
@@ -65,7 +236,7 @@ impl Transport {
         // NEW: Add the connection event channel to the constructor.
         conn_event_tx: mpsc::Sender<ConnectionEvent>,
     ) -> Result<Self> {
-        let (server_config, client_config) = configure_tls()?;
+        let (server_config, client_config) = configure_tls(tls_config, &identity)?;
 
         let socket = Socket::new(
             Domain::for_address(bind_addr),
@@ -87,35 +258,87 @@ impl Transport {
 
         Ok(Self {
             endpoint,
+            local: LocalNode { identity, listen_addrs: Vec::new(), tls_mode: tls_config.mode, accept_legacy: false },
+            server_name: tls_config.server_name.as_str().into(),
             command_rx,
             inbound_tx,
             conn_event_tx,
             bootstrap_peers,
-            connections: Arc::new(Mutex::new(HashMap::new())),
+            backoff: Backoff::from_config(&Config::default()),
+            connections: Arc::new(Mutex::new(ConnectionManager::new(ConnectionLimits::from_config(&Config::default())))),
             // NEW: Initialize the semaphore.
             stream_semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_STREAMS)),
+            guard: PeerGuard::new(RateLimits::from_config(&Config::default()), BanPolicy::from_config(&Config::default())),
         })
     }
 
+    /// Sets the listen addresses announced to peers in the connection
+    /// handshake. Without any, peers reach this node at the source address of
+    /// its connections.
+    pub fn with_advertised_addrs(mut self, listen_addrs: Vec<SocketAddr>) -> Self {
+        self.local.listen_addrs = listen_addrs;
+        self
+    }
+
+    /// Also accepts peers of protocol version 1, during a rollout: their
+    /// handshakes, and envelopes of major version 1.
+    pub fn with_legacy_peers(mut self, accept: bool) -> Self {
+        self.local.accept_legacy = accept;
+        self
+    }
+
+    /// Sets the retry schedule for persistent peers.
+    pub fn with_reconnect_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the maximum number of inbound and outbound connections, and how
+    /// outbound connections over the limit are evicted.
+    pub fn with_connection_limits(self, limits: ConnectionLimits) -> Self {
+        Self { connections: Arc::new(Mutex::new(ConnectionManager::new(limits))), ..self }
+    }
+
+    /// Sets the per-peer rate limits and when strikes get a peer banned.
+    pub fn with_peer_guard(mut self, rate_limits: RateLimits, ban_policy: BanPolicy) -> Self {
+        self.guard = PeerGuard::new(rate_limits, ban_policy);
+        self
+    }
+
     /// The main run loop for the `Transport` service.
     pub async fn run(mut self, shutdown_token: CancellationToken) {
         let local_addr = self.endpoint.local_addr().unwrap();
         tracing::info!(listen_addr = %local_addr, "Transport service started");
 
-        // Initial bootstrapping connections.
+        // Bootstrap peers are kept connected for the lifetime of the node.
         for &peer_addr in &self.bootstrap_peers {
+            self.connections.lock().await.protect(peer_addr);
             let endpoint = self.endpoint.clone();
+            let local = self.local.clone();
+            let server_name = self.server_name.clone();
             let connections = self.connections.clone();
-            // NEW: Clone the event sender for the bootstrap task.
             let conn_event_tx = self.conn_event_tx.clone();
-            tokio::spawn(async move {
-                tracing::info!(peer = %peer_addr, "Attempting to connect to bootstrap peer");
-                if let Err(e) = connection::connect_to_peer(endpoint, connections, peer_addr, conn_event_tx).await {
-                    tracing::error!(peer = %peer_addr, error = %e, "Failed to connect to bootstrap peer");
-                }
-            });
+            let connect = move || {
+                connection::get_or_create_connection(
+                    endpoint.clone(),
+                    local.clone(),
+                    server_name.clone(),
+                    connections.clone(),
+                    peer_addr,
+                    conn_event_tx.clone(),
+                )
+            };
+            tracing::info!(peer = %peer_addr, "Maintaining connection to bootstrap peer");
+            tokio::spawn(manager::maintain_peer(
+                peer_addr,
+                self.backoff,
+                connect,
+                self.conn_event_tx.clone(),
+                shutdown_token.clone(),
+            ));
         }
 
+        let mut ban_sweep = tokio::time::interval(BAN_SWEEP_INTERVAL);
         loop {
             tokio::select! {
                 _ = shutdown_token.cancelled() => {
@@ -123,42 +346,99 @@ impl Transport {
                     break;
                 },
                 Some(conn) = self.endpoint.accept() => {
+                    if self.guard.is_banned(&BanTarget::Addr(conn.remote_address().ip())).await {
+                        tokio::spawn(connection::refuse_connection(conn, connection::BANNED, "banned"));
+                        continue;
+                    }
+                    if !self.connections.lock().await.try_reserve_inbound() {
+                        tokio::spawn(connection::refuse_connection(conn, connection::CONNECTION_LIMIT, "connection limit"));
+                        continue;
+                    }
                     let connections = self.connections.clone();
+                    let local = self.local.clone();
                     let inbound_tx = self.inbound_tx.clone();
                     // NEW: Clone the event sender and semaphore for the connection handler task.
                     let conn_event_tx = self.conn_event_tx.clone();
                     let stream_semaphore = self.stream_semaphore.clone();
+                    let guard = self.guard.clone();
                     tokio::spawn(async move {
-                        if let Err(e) = handle_connection(conn, connections, inbound_tx, conn_event_tx, stream_semaphore).await {
+                        if let Err(e) = handle_connection(conn, local, inbound_tx, conn_event_tx, stream_semaphore, guard).await {
                             tracing::error!(error = %e, "Connection handling failed");
                         }
+                        connections.lock().await.release_inbound();
                     });
                 },
                 Some(command) = self.command_rx.recv() => {
                     self.handle_command(command).await;
                 }
+                _ = ban_sweep.tick() => {
+                    if self.guard.bans.lock().await.prune(guard::now_ms()) {
+                        self.guard.report_bans(&self.conn_event_tx).await;
+                    }
+                    self.guard.prune_limiters().await;
+                }
                 else => {
                     tracing::info!("Command channel closed. Transport service shutting down.");
                     break;
                 }
             }
         }
+        // Closing tells peers right away, rather than after their idle timeout.
+        self.endpoint.close(quinn::VarInt::from_u32(0), b"shutdown");
         self.endpoint.wait_idle().await;
     }
 
     async fn handle_command(&self, command: TransportCommand) {
-        match command {
-            TransportCommand::SendMessage(addr, msg) => {
-                let endpoint = self.endpoint.clone();
-                let connections = self.connections.clone();
-                // NEW: Clone the event sender for message sending tasks.
-                let conn_event_tx = self.conn_event_tx.clone();
+        let (addr, msg) = match command {
+            TransportCommand::SendMessage(addr, msg) => (addr, msg),
+            TransportCommand::Connect(addr) => {
+                let connect = connection::get_or_create_connection(
+                    self.endpoint.clone(),
+                    self.local.clone(),
+                    self.server_name.clone(),
+                    self.connections.clone(),
+                    addr,
+                    self.conn_event_tx.clone(),
+                );
                 tokio::spawn(async move {
-                    if let Err(e) = connection::send_message_to_peer(endpoint, connections, addr, msg, conn_event_tx).await {
-                        tracing::warn!(peer = %addr, error = %e, "Failed to send message");
+                    if let Err(e) = connect.await {
+                        tracing::debug!(peer = %addr, error = %e, "Failed to connect");
                     }
                 });
+                return;
             }
-        }
+            TransportCommand::SendControl(addr, frame) => (addr, WireMessage::Control(frame)),
+            TransportCommand::Strike { peer_id, addr, reason } => {
+                self.guard.strike(peer_id, addr, reason, &self.conn_event_tx).await;
+                return;
+            }
+            TransportCommand::Ban { target, duration } => {
+                let mut bans = self.guard.bans.lock().await;
+                let duration = duration.unwrap_or(bans.policy().ban_duration);
+                bans.ban(target, BanReason::Manual, duration, guard::now_ms());
+                drop(bans);
+                tracing::info!(target = ?target, duration_ms = duration.as_millis() as u64, "Banned peer");
+                self.guard.report_bans(&self.conn_event_tx).await;
+                return;
+            }
+            TransportCommand::Unban(target) => {
+                if self.guard.bans.lock().await.unban(&target) {
+                    tracing::info!(target = ?target, "Lifted ban");
+                    self.guard.report_bans(&self.conn_event_tx).await;
+                }
+                return;
+            }
+        };
+        let endpoint = self.endpoint.clone();
+        let local = self.local.clone();
+        let server_name = self.server_name.clone();
+        let connections = self.connections.clone();
+        // NEW: Clone the event sender for message sending tasks.
+        let conn_event_tx = self.conn_event_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection::send_message_to_peer(endpoint, local, server_name, connections, addr, msg, conn_event_tx).await {
+                tracing::warn!(peer = %addr, error = %e, "Failed to send message");
+            }
+        });
     }
 }
\ No newline at end of file
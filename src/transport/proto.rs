@@ -0,0 +1,550 @@
+//! src/transport/proto.rs
+//!
+//! The protobuf wire format, negotiated through ALPN as `gossip/2.0-pb`. The
+//! types are generated from `proto/domain.proto` and `proto/wire.proto` by
+//! `build.rs`, and converted here to and from the domain and wire types.
+//! Converting from protobuf checks what the schema cannot express, such as
+//! required fields, node id lengths and socket addresses.
+
+use crate::{
+    domain,
+    error::{Error, Result},
+    transport::{self as wire, envelope::MessageType, WireMessage},
+};
+use ed25519_dalek::Signature;
+use prost::Message;
+use std::net::SocketAddr;
+
+include!(concat!(env!("OUT_DIR"), "/gossip.rs"));
+
+// --- Envelope bodies ---
+
+/// Encodes the body of `message` in the protobuf format.
+pub fn encode(message: &WireMessage) -> Vec<u8> {
+    match message {
+        WireMessage::Gossip(relayed) => RelayedMessage::from(relayed).encode_to_vec(),
+        WireMessage::SyncDigest(digest) => SyncDigest {
+            entries: digest
+                .iter()
+                .map(|(originator, kind, version)| DigestEntry {
+                    originator: originator.0.to_vec(),
+                    version: Some(version.into()),
+                    kind: kind.clone(),
+                })
+                .collect(),
+        }
+        .encode_to_vec(),
+        WireMessage::SyncReply(messages) => {
+            SyncReply { messages: messages.iter().map(Into::into).collect() }.encode_to_vec()
+        }
+        WireMessage::Control(frame) => ControlFrame::from(frame).encode_to_vec(),
+        WireMessage::Membership(membership) => MembershipMessage::from(membership).encode_to_vec(),
+        WireMessage::Probe(probe) => ProbeMessage::from(probe).encode_to_vec(),
+        WireMessage::PeerExchange(advertisements) => {
+            PeerExchange { advertisements: advertisements.iter().map(Into::into).collect() }.encode_to_vec()
+        }
+    }
+}
+
+/// Decodes a protobuf body of type `message_type`.
+pub fn decode(message_type: MessageType, body: &[u8]) -> Result<WireMessage> {
+    Ok(match message_type {
+        MessageType::Gossip => WireMessage::Gossip(RelayedMessage::decode(body)?.try_into()?),
+        MessageType::SyncDigest => WireMessage::SyncDigest(
+            SyncDigest::decode(body)?
+                .entries
+                .into_iter()
+                .map(|entry| Ok((node_id(entry.originator)?, entry.kind, required(entry.version, "version")?.into())))
+                .collect::<Result<_>>()?,
+        ),
+        MessageType::SyncReply => WireMessage::SyncReply(
+            SyncReply::decode(body)?.messages.into_iter().map(TryInto::try_into).collect::<Result<_>>()?,
+        ),
+        MessageType::Control => WireMessage::Control(ControlFrame::decode(body)?.try_into()?),
+        MessageType::Membership => WireMessage::Membership(MembershipMessage::decode(body)?.try_into()?),
+        MessageType::Probe => WireMessage::Probe(ProbeMessage::decode(body)?.try_into()?),
+        MessageType::PeerExchange => WireMessage::PeerExchange(
+            PeerExchange::decode(body)?
+                .advertisements
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+        ),
+    })
+}
+
+// --- Conversions ---
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::Protobuf(reason.into())
+}
+
+fn required<T>(field: Option<T>, name: &str) -> Result<T> {
+    field.ok_or_else(|| invalid(format!("missing field `{}`", name)))
+}
+
+fn node_id(bytes: Vec<u8>) -> Result<domain::NodeId> {
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| invalid("node id is not 32 bytes"))?;
+    Ok(domain::NodeId(bytes))
+}
+
+fn message_id(bytes: Vec<u8>) -> Result<domain::MessageId> {
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| invalid("message id is not 32 bytes"))?;
+    Ok(domain::MessageId(bytes))
+}
+
+fn signature(bytes: &[u8]) -> Result<Signature> {
+    Signature::from_slice(bytes).map_err(|_| invalid("signature is not 64 bytes"))
+}
+
+fn socket_addr(addr: &str) -> Result<SocketAddr> {
+    addr.parse().map_err(|_| invalid(format!("invalid socket address {:?}", addr)))
+}
+
+fn peer_addrs(peers: &[(domain::NodeId, SocketAddr)]) -> Vec<PeerAddr> {
+    peers.iter().map(|(node, addr)| PeerAddr { node: node.0.to_vec(), addr: addr.to_string() }).collect()
+}
+
+fn from_peer_addrs(peers: Vec<PeerAddr>) -> Result<Vec<(domain::NodeId, SocketAddr)>> {
+    peers.into_iter().map(|peer| Ok((node_id(peer.node)?, socket_addr(&peer.addr)?))).collect()
+}
+
+fn status_updates(updates: &[wire::StatusUpdate]) -> Vec<StatusUpdate> {
+    updates.iter().map(Into::into).collect()
+}
+
+fn from_status_updates(updates: Vec<StatusUpdate>) -> Result<Vec<wire::StatusUpdate>> {
+    updates.into_iter().map(TryInto::try_into).collect()
+}
+
+impl From<&domain::Payload> for Payload {
+    fn from(payload: &domain::Payload) -> Self {
+        Self { kind: payload.kind.clone(), data: payload.data.clone() }
+    }
+}
+
+impl From<Payload> for domain::Payload {
+    fn from(payload: Payload) -> Self {
+        Self { kind: payload.kind, data: payload.data }
+    }
+}
+
+impl From<&domain::Version> for Version {
+    fn from(version: &domain::Version) -> Self {
+        Self { epoch: version.epoch, sequence: version.sequence }
+    }
+}
+
+impl From<Version> for domain::Version {
+    fn from(version: Version) -> Self {
+        Self { epoch: version.epoch, sequence: version.sequence }
+    }
+}
+
+impl From<&domain::GossipPayload> for GossipPayload {
+    fn from(payload: &domain::GossipPayload) -> Self {
+        Self {
+            community_id: payload.community_id,
+            version: Some((&payload.version).into()),
+            timestamp_ms: payload.timestamp_ms,
+            payload: Some((&payload.payload).into()),
+            telemetry: None,
+        }
+    }
+}
+
+impl TryFrom<GossipPayload> for domain::GossipPayload {
+    type Error = Error;
+
+    fn try_from(payload: GossipPayload) -> Result<Self> {
+        let version = required(payload.version, "version")?.into();
+        if let (None, Some(telemetry)) = (&payload.payload, payload.telemetry) {
+            return domain::GossipPayloadV1 {
+                telemetry: domain::TelemetryV1 { timestamp_ms: telemetry.timestamp_ms, value: telemetry.value },
+                community_id: payload.community_id,
+                version,
+            }
+            .try_into();
+        }
+        Ok(Self {
+            timestamp_ms: payload.timestamp_ms,
+            community_id: payload.community_id,
+            version,
+            payload: required(payload.payload, "payload")?.into(),
+        })
+    }
+}
+
+impl From<&domain::SignedMessage> for SignedMessage {
+    fn from(message: &domain::SignedMessage) -> Self {
+        Self {
+            message: Some((&message.message).into()),
+            originator: message.originator.0.to_vec(),
+            signature: message.signature.to_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<SignedMessage> for domain::SignedMessage {
+    type Error = Error;
+
+    fn try_from(message: SignedMessage) -> Result<Self> {
+        Ok(Self {
+            message: required(message.message, "message")?.try_into()?,
+            originator: node_id(message.originator)?,
+            signature: signature(&message.signature)?,
+        })
+    }
+}
+
+impl From<&domain::SignedPeerAdvertisement> for SignedPeerAdvertisement {
+    fn from(signed: &domain::SignedPeerAdvertisement) -> Self {
+        let advertisement = &signed.advertisement;
+        Self {
+            advertisement: Some(PeerAdvertisement {
+                node_id: advertisement.node_id.0.to_vec(),
+                listen_addrs: advertisement.listen_addrs.iter().map(ToString::to_string).collect(),
+                version: Some((&advertisement.version).into()),
+            }),
+            signature: signed.signature.to_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<SignedPeerAdvertisement> for domain::SignedPeerAdvertisement {
+    type Error = Error;
+
+    fn try_from(signed: SignedPeerAdvertisement) -> Result<Self> {
+        let advertisement = required(signed.advertisement, "advertisement")?;
+        Ok(Self {
+            advertisement: domain::PeerAdvertisement {
+                node_id: node_id(advertisement.node_id)?,
+                listen_addrs: advertisement.listen_addrs.iter().map(|addr| socket_addr(addr)).collect::<Result<_>>()?,
+                version: required(advertisement.version, "version")?.into(),
+            },
+            signature: signature(&signed.signature)?,
+        })
+    }
+}
+
+impl From<domain::MemberStatus> for MemberStatus {
+    fn from(status: domain::MemberStatus) -> Self {
+        match status {
+            domain::MemberStatus::Alive => Self::Alive,
+            domain::MemberStatus::Suspect => Self::Suspect,
+            domain::MemberStatus::Dead => Self::Dead,
+        }
+    }
+}
+
+impl From<&wire::StatusUpdate> for StatusUpdate {
+    fn from(update: &wire::StatusUpdate) -> Self {
+        Self {
+            node: update.node.0.to_vec(),
+            status: MemberStatus::from(update.status) as i32,
+            incarnation: Some((&update.incarnation).into()),
+        }
+    }
+}
+
+impl TryFrom<StatusUpdate> for wire::StatusUpdate {
+    type Error = Error;
+
+    fn try_from(update: StatusUpdate) -> Result<Self> {
+        let status = match MemberStatus::try_from(update.status) {
+            Ok(MemberStatus::Alive) => domain::MemberStatus::Alive,
+            Ok(MemberStatus::Suspect) => domain::MemberStatus::Suspect,
+            Ok(MemberStatus::Dead) => domain::MemberStatus::Dead,
+            Ok(MemberStatus::Unspecified) | Err(_) => {
+                return Err(invalid(format!("unknown member status {}", update.status)))
+            }
+        };
+        Ok(Self {
+            node: node_id(update.node)?,
+            status,
+            incarnation: required(update.incarnation, "incarnation")?.into(),
+        })
+    }
+}
+
+impl From<&wire::RelayedMessage> for RelayedMessage {
+    fn from(relayed: &wire::RelayedMessage) -> Self {
+        Self {
+            message: Some((&relayed.message).into()),
+            hops: relayed.hops,
+            status_updates: status_updates(&relayed.status_updates),
+        }
+    }
+}
+
+impl TryFrom<RelayedMessage> for wire::RelayedMessage {
+    type Error = Error;
+
+    fn try_from(relayed: RelayedMessage) -> Result<Self> {
+        Ok(Self {
+            message: required(relayed.message, "message")?.try_into()?,
+            hops: relayed.hops,
+            status_updates: from_status_updates(relayed.status_updates)?,
+        })
+    }
+}
+
+impl From<&wire::ControlFrame> for ControlFrame {
+    fn from(frame: &wire::ControlFrame) -> Self {
+        let frame = match frame {
+            wire::ControlFrame::IHave(announcements) => control_frame::Frame::IHave(IHave {
+                announcements: announcements
+                    .iter()
+                    .map(|announcement| Announcement {
+                        id: announcement.id.0.to_vec(),
+                        originator: announcement.originator.0.to_vec(),
+                        version: Some((&announcement.version).into()),
+                        kind: announcement.kind.clone(),
+                    })
+                    .collect(),
+            }),
+            wire::ControlFrame::Graft { id, originator, kind } => control_frame::Frame::Graft(Graft {
+                id: id.0.to_vec(),
+                originator: originator.0.to_vec(),
+                kind: kind.clone(),
+            }),
+            wire::ControlFrame::Prune => control_frame::Frame::Prune(Prune {}),
+        };
+        Self { frame: Some(frame) }
+    }
+}
+
+impl TryFrom<ControlFrame> for wire::ControlFrame {
+    type Error = Error;
+
+    fn try_from(frame: ControlFrame) -> Result<Self> {
+        Ok(match required(frame.frame, "frame")? {
+            control_frame::Frame::IHave(i_have) => wire::ControlFrame::IHave(
+                i_have
+                    .announcements
+                    .into_iter()
+                    .map(|announcement| {
+                        Ok(wire::Announcement {
+                            id: message_id(announcement.id)?,
+                            originator: node_id(announcement.originator)?,
+                            kind: announcement.kind,
+                            version: required(announcement.version, "version")?.into(),
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            control_frame::Frame::Graft(graft) => wire::ControlFrame::Graft {
+                id: message_id(graft.id)?,
+                originator: node_id(graft.originator)?,
+                kind: graft.kind,
+            },
+            control_frame::Frame::Prune(Prune {}) => wire::ControlFrame::Prune,
+        })
+    }
+}
+
+impl From<&wire::MembershipMessage> for MembershipMessage {
+    fn from(message: &wire::MembershipMessage) -> Self {
+        use membership_message::Message;
+        let message = match message {
+            wire::MembershipMessage::Join => Message::Join(Join {}),
+            wire::MembershipMessage::ForwardJoin { node, addr, ttl } => {
+                Message::ForwardJoin(ForwardJoin { node: node.0.to_vec(), addr: addr.to_string(), ttl: *ttl })
+            }
+            wire::MembershipMessage::Neighbor { high_priority } => {
+                Message::Neighbor(Neighbor { high_priority: *high_priority })
+            }
+            wire::MembershipMessage::Disconnect => Message::Disconnect(Disconnect {}),
+            wire::MembershipMessage::Shuffle { origin, origin_addr, ttl, peers } => Message::Shuffle(Shuffle {
+                origin: origin.0.to_vec(),
+                origin_addr: origin_addr.to_string(),
+                ttl: *ttl,
+                peers: peer_addrs(peers),
+            }),
+            wire::MembershipMessage::ShuffleReply { peers } => {
+                Message::ShuffleReply(ShuffleReply { peers: peer_addrs(peers) })
+            }
+        };
+        Self { message: Some(message) }
+    }
+}
+
+impl TryFrom<MembershipMessage> for wire::MembershipMessage {
+    type Error = Error;
+
+    fn try_from(message: MembershipMessage) -> Result<Self> {
+        use membership_message::Message;
+        Ok(match required(message.message, "message")? {
+            Message::Join(Join {}) => wire::MembershipMessage::Join,
+            Message::ForwardJoin(join) => wire::MembershipMessage::ForwardJoin {
+                node: node_id(join.node)?,
+                addr: socket_addr(&join.addr)?,
+                ttl: join.ttl,
+            },
+            Message::Neighbor(neighbor) => wire::MembershipMessage::Neighbor { high_priority: neighbor.high_priority },
+            Message::Disconnect(Disconnect {}) => wire::MembershipMessage::Disconnect,
+            Message::Shuffle(shuffle) => wire::MembershipMessage::Shuffle {
+                origin: node_id(shuffle.origin)?,
+                origin_addr: socket_addr(&shuffle.origin_addr)?,
+                ttl: shuffle.ttl,
+                peers: from_peer_addrs(shuffle.peers)?,
+            },
+            Message::ShuffleReply(reply) => wire::MembershipMessage::ShuffleReply { peers: from_peer_addrs(reply.peers)? },
+        })
+    }
+}
+
+impl From<&wire::ProbeMessage> for ProbeMessage {
+    fn from(message: &wire::ProbeMessage) -> Self {
+        use probe_message::Message;
+        let message = match message {
+            wire::ProbeMessage::Ping { seq, status_updates: updates } => {
+                Message::Ping(Ping { seq: *seq, status_updates: status_updates(updates) })
+            }
+            wire::ProbeMessage::PingReq { seq, target, target_addr } => Message::PingReq(PingReq {
+                seq: *seq,
+                target: target.0.to_vec(),
+                target_addr: target_addr.to_string(),
+            }),
+            wire::ProbeMessage::Ack { seq, target, status_updates: updates } => Message::Ack(Ack {
+                seq: *seq,
+                target: target.0.to_vec(),
+                status_updates: status_updates(updates),
+            }),
+        };
+        Self { message: Some(message) }
+    }
+}
+
+impl TryFrom<ProbeMessage> for wire::ProbeMessage {
+    type Error = Error;
+
+    fn try_from(message: ProbeMessage) -> Result<Self> {
+        use probe_message::Message;
+        Ok(match required(message.message, "message")? {
+            Message::Ping(ping) => {
+                wire::ProbeMessage::Ping { seq: ping.seq, status_updates: from_status_updates(ping.status_updates)? }
+            }
+            Message::PingReq(req) => wire::ProbeMessage::PingReq {
+                seq: req.seq,
+                target: node_id(req.target)?,
+                target_addr: socket_addr(&req.target_addr)?,
+            },
+            Message::Ack(ack) => wire::ProbeMessage::Ack {
+                seq: ack.seq,
+                target: node_id(ack.target)?,
+                status_updates: from_status_updates(ack.status_updates)?,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Identity;
+
+    fn signed_message(identity: &Identity) -> domain::SignedMessage {
+        identity.sign(domain::GossipPayload {
+            timestamp_ms: 1_000,
+            community_id: 4,
+            version: domain::Version { epoch: 2, sequence: 7 },
+            payload: domain::Payload::new(&domain::TelemetryData::default().with_metric(
+                "latency_p99",
+                domain::Metric::new(-3.25, "ms").with_label("quantile", "0.99"),
+            )),
+        })
+    }
+
+    fn round_trip(message: &WireMessage) -> WireMessage {
+        decode(MessageType::of(message), &encode(message)).unwrap()
+    }
+
+    #[test]
+    fn test_protobuf_round_trip_preserves_every_message_type() {
+        let identity = Identity::new();
+        let node = identity.node_id;
+        let addr: SocketAddr = "[2001:db8::1]:5000".parse().unwrap();
+        let update = wire::StatusUpdate {
+            node,
+            status: domain::MemberStatus::Suspect,
+            incarnation: domain::Version { epoch: 1, sequence: 2 },
+        };
+        let relayed = wire::RelayedMessage { message: signed_message(&identity), hops: 3, status_updates: vec![update] };
+        let messages = [
+            WireMessage::Gossip(relayed.clone()),
+            WireMessage::SyncDigest(vec![(node, "telemetry".to_string(), domain::Version { epoch: 1, sequence: 9 })]),
+            WireMessage::SyncReply(vec![relayed.clone()]),
+            WireMessage::Control(wire::ControlFrame::IHave(vec![wire::Announcement {
+                id: relayed.message.id(),
+                originator: node,
+                kind: "telemetry".to_string(),
+                version: domain::Version { epoch: 2, sequence: 7 },
+            }])),
+            WireMessage::Control(wire::ControlFrame::Graft {
+                id: relayed.message.id(),
+                originator: node,
+                kind: "telemetry".to_string(),
+            }),
+            WireMessage::Control(wire::ControlFrame::Prune),
+            WireMessage::Membership(wire::MembershipMessage::Join),
+            WireMessage::Membership(wire::MembershipMessage::ForwardJoin { node, addr, ttl: 4 }),
+            WireMessage::Membership(wire::MembershipMessage::Neighbor { high_priority: true }),
+            WireMessage::Membership(wire::MembershipMessage::Shuffle {
+                origin: node,
+                origin_addr: addr,
+                ttl: 2,
+                peers: vec![(node, addr)],
+            }),
+            WireMessage::Membership(wire::MembershipMessage::ShuffleReply { peers: vec![(node, addr)] }),
+            WireMessage::Probe(wire::ProbeMessage::Ping { seq: 1, status_updates: vec![update] }),
+            WireMessage::Probe(wire::ProbeMessage::PingReq { seq: 2, target: node, target_addr: addr }),
+            WireMessage::Probe(wire::ProbeMessage::Ack { seq: 3, target: node, status_updates: Vec::new() }),
+            WireMessage::PeerExchange(vec![identity.advertise(vec![addr], domain::Version { epoch: 1, sequence: 1 })]),
+        ];
+        for message in messages {
+            assert_eq!(format!("{:?}", round_trip(&message)), format!("{:?}", message));
+        }
+    }
+
+    #[test]
+    fn test_signatures_survive_protobuf_encoding() {
+        let identity = Identity::new();
+        let WireMessage::Gossip(relayed) = round_trip(&signed_message(&identity).into()) else {
+            panic!("Expected a gossip message");
+        };
+        assert!(relayed.message.verify().is_ok());
+
+        let advertisement = identity.advertise(vec!["127.0.0.1:5000".parse().unwrap()], domain::Version::default());
+        let WireMessage::PeerExchange(advertisements) = round_trip(&WireMessage::PeerExchange(vec![advertisement])) else {
+            panic!("Expected a peer exchange");
+        };
+        assert!(advertisements[0].verify().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_protobuf_bodies_are_rejected() {
+        let identity = Identity::new();
+        let mut message = SignedMessage::from(&signed_message(&identity));
+        message.originator.pop();
+        let short_node_id = RelayedMessage { message: Some(message), ..Default::default() }.encode_to_vec();
+        let missing_message = RelayedMessage::default().encode_to_vec();
+        let bad_addr = MembershipMessage {
+            message: Some(membership_message::Message::ForwardJoin(ForwardJoin {
+                node: identity.node_id.0.to_vec(),
+                addr: "not an address".to_string(),
+                ttl: 1,
+            })),
+        }
+        .encode_to_vec();
+
+        for (message_type, body) in [
+            (MessageType::Gossip, &short_node_id[..]),
+            (MessageType::Gossip, &missing_message[..]),
+            (MessageType::Membership, &bad_addr[..]),
+            (MessageType::Probe, &b"\xff\xff\xff"[..]),
+        ] {
+            assert!(matches!(decode(message_type, body), Err(Error::Protobuf(_))), "{:?} should be rejected", message_type);
+        }
+    }
+}
@@ -1,55 +1,540 @@
 //! src/transport/tls.rs
 //!
-//! Manages the configuration of TLS for QUIC using a private PKI.
+//! Manages the configuration of TLS for QUIC, either using a private PKI or
+//! self-signed certificates derived from each node's `Identity`.
 
-use crate::error::{Error, Result};
-use quinn::{ClientConfig, ServerConfig};
-use std::{fs, sync::Arc};
+use crate::{
+    config::{TlsConfig, TlsMode},
+    domain::{Identity, NodeId},
+    error::{Error, Result},
+};
+use quinn::{ClientConfig, Connection, ServerConfig};
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, PKCS_ED25519};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    server::{AllowAnyAuthenticatedClient, ClientCertVerified, ClientCertVerifier},
+    CertificateError, ServerName, SignatureScheme,
+};
+use rustls_pemfile::Item;
+use std::{collections::HashSet, fs, path::Path, sync::Arc, time::SystemTime};
+use x509_parser::oid_registry::OID_SIG_ED25519;
 
-/// Configures TLS for the client and server using a shared private CA.
-/// Expects `ca.cert`, `node.cert`, and `node.key` files in the `certs/` directory.
-pub fn configure_tls() -> Result<(ServerConfig, ClientConfig)> {
-    // Load the certificate authority.
-    let ca_cert_der = fs::read("certs/ca.cert").map_err(|e| {
-        Error::TlsConfig(format!("Failed to read CA certificate (certs/ca.cert): {}", e))
+/// Builds the QUIC server and client configurations for the given TLS mode.
+pub fn configure_tls(config: &TlsConfig, identity: &Identity) -> Result<(ServerConfig, ClientConfig)> {
+    ServerName::try_from(config.server_name.as_str()).map_err(|_| {
+        Error::TlsConfig(format!("Invalid TLS server name: {:?}", config.server_name))
     })?;
-    let ca_cert = rustls::Certificate(ca_cert_der);
+    if config.alpn_protocols.is_empty() || config.alpn_protocols.iter().any(String::is_empty) {
+        return Err(Error::TlsConfig(
+            "ALPN protocol list must be non-empty and contain no empty entries".to_string(),
+        ));
+    }
+    let alpn_protocols: Vec<Vec<u8>> = config
+        .alpn_protocols
+        .iter()
+        .map(|protocol| protocol.as_bytes().to_vec())
+        .collect();
+
+    let (mut server_crypto_config, mut client_crypto_config) = match config.mode {
+        TlsMode::Pki => configure_pki(config)?,
+        TlsMode::Identity => configure_identity(identity, config)?,
+    };
+    client_crypto_config.alpn_protocols = alpn_protocols.clone();
+    server_crypto_config.alpn_protocols = alpn_protocols;
+    let client_config = ClientConfig::new(Arc::new(client_crypto_config));
+
+    let mut server_config = ServerConfig::with_crypto(Arc::new(server_crypto_config));
+    let transport_config = Arc::get_mut(&mut server_config.transport).unwrap();
+    transport_config.keep_alive_interval(Some(std::time::Duration::from_secs(10)));
+
+    Ok((server_config, client_config))
+}
+
+/// Configures TLS for the client and server using a shared private CA.
+/// Clients always present the node certificate; servers only demand it when
+/// `client_auth` is set.
+fn configure_pki(config: &TlsConfig) -> Result<(rustls::ServerConfig, rustls::ClientConfig)> {
+    let ca_certs = load_certs(&config.ca_cert_path, "CA certificate")?;
+    let cert_chain = load_certs(&config.cert_path, "node certificate")?;
+    let key = load_private_key(&config.key_path)?;
+    check_key_matches_cert(&key, &cert_chain[0], config)?;
+    check_cert_issued_by_ca(&cert_chain, &ca_certs, config)?;
 
-    // Configure the client to trust the CA.
     let mut root_store = rustls::RootCertStore::empty();
-    root_store.add(&ca_cert).map_err(|e| {
-        Error::TlsConfig(format!("Failed to add CA to root store: {}", e))
-    })?;
+    for ca_cert in &ca_certs {
+        root_store.add(ca_cert).map_err(|e| {
+            Error::TlsConfig(format!(
+                "Failed to add CA certificate from {} to root store: {}",
+                config.ca_cert_path.display(),
+                e
+            ))
+        })?;
+    }
 
-    let mut client_crypto_config = rustls::ClientConfig::builder()
+    // Configure the client to trust the CA.
+    let client_crypto_config = rustls::ClientConfig::builder()
         .with_safe_defaults()
-        .with_root_certificates(root_store)
-        .with_no_client_auth();
-    client_crypto_config.alpn_protocols = vec![b"gossip/1.0".to_vec()];
-    let client_config = ClientConfig::new(Arc::new(client_crypto_config));
+        .with_root_certificates(root_store.clone())
+        .with_client_auth_cert(cert_chain.clone(), key.clone())
+        .map_err(|e| Error::TlsConfig(format!("Failed to create client TLS config: {}", e)))?;
 
-    // Configure the server with its own certificate and private key.
-    let cert_chain_der = fs::read("certs/node.cert").map_err(|e| {
-        Error::TlsConfig(format!("Failed to read node certificate (certs/node.cert): {}", e))
+    // Configure the server, optionally requiring clients to chain to the CA.
+    let server_builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let server_builder = if config.client_auth {
+        server_builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(root_store).boxed())
+    } else {
+        server_builder.with_no_client_auth()
+    };
+    let server_crypto_config = server_builder
+        .with_single_cert(cert_chain, key)
+        .map_err(|e| Error::TlsConfig(format!("Failed to create server TLS config: {}", e)))?;
+
+    Ok((server_crypto_config, client_crypto_config))
+}
+
+/// Reads one or more certificates from a PEM file, or a single certificate
+/// from a DER file. `what` names the file's role in error messages.
+fn load_certs(path: &Path, what: &str) -> Result<Vec<rustls::Certificate>> {
+    let bytes = fs::read(path).map_err(|e| {
+        Error::TlsConfig(format!("Failed to read {} ({}): {}", what, path.display(), e))
     })?;
-    let key_der = fs::read("certs/node.key").map_err(|e| {
-        Error::TlsConfig(format!("Failed to read node private key (certs/node.key): {}", e))
+
+    let ders = if is_pem(&bytes) {
+        let ders = rustls_pemfile::certs(&mut bytes.as_slice()).map_err(|e| {
+            Error::TlsConfig(format!("Failed to parse PEM {} ({}): {}", what, path.display(), e))
+        })?;
+        if ders.is_empty() {
+            return Err(Error::TlsConfig(format!(
+                "No CERTIFICATE block found in PEM {} ({})",
+                what,
+                path.display()
+            )));
+        }
+        ders
+    } else {
+        vec![bytes]
+    };
+
+    for der in &ders {
+        x509_parser::parse_x509_certificate(der).map_err(|e| {
+            Error::TlsConfig(format!("Malformed {} ({}): {}", what, path.display(), e))
+        })?;
+    }
+    Ok(ders.into_iter().map(rustls::Certificate).collect())
+}
+
+/// Reads a PKCS#8, PKCS#1 (RSA) or SEC1 (EC) private key from a PEM or DER file.
+fn load_private_key(path: &Path) -> Result<rustls::PrivateKey> {
+    let bytes = fs::read(path).map_err(|e| {
+        Error::TlsConfig(format!("Failed to read node private key ({}): {}", path.display(), e))
+    })?;
+
+    let key = if is_pem(&bytes) {
+        let mut reader = bytes.as_slice();
+        let mut key = None;
+        while let Some(item) = rustls_pemfile::read_one(&mut reader).map_err(|e| {
+            Error::TlsConfig(format!("Failed to parse PEM private key ({}): {}", path.display(), e))
+        })? {
+            if let Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) = item {
+                key = Some(rustls::PrivateKey(der));
+                break;
+            }
+        }
+        key.ok_or_else(|| {
+            Error::TlsConfig(format!("No private key block found in PEM file ({})", path.display()))
+        })?
+    } else {
+        rustls::PrivateKey(bytes)
+    };
+
+    rustls::sign::any_supported_type(&key).map_err(|_| {
+        Error::TlsConfig(format!(
+            "Unsupported or malformed private key ({}); expected PKCS#8, PKCS#1 or SEC1",
+            path.display()
+        ))
+    })?;
+    Ok(key)
+}
+
+fn is_pem(bytes: &[u8]) -> bool {
+    bytes.trim_ascii_start().starts_with(b"-----BEGIN")
+}
+
+/// Proves that `key` belongs to `cert` by signing a probe with the key and
+/// verifying it against the certificate's public key.
+fn check_key_matches_cert(
+    key: &rustls::PrivateKey,
+    cert: &rustls::Certificate,
+    config: &TlsConfig,
+) -> Result<()> {
+    const PROBE: &[u8] = b"gossip-network/tls-key-check";
+    let mismatch = || {
+        Error::TlsConfig(format!(
+            "Private key ({}) does not match node certificate ({})",
+            config.key_path.display(),
+            config.cert_path.display()
+        ))
+    };
+
+    let signing_key = rustls::sign::any_supported_type(key).map_err(|_| mismatch())?;
+    let signer = signing_key
+        .choose_scheme(&[
+            SignatureScheme::ED25519,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            SignatureScheme::RSA_PSS_SHA256,
+        ])
+        .ok_or_else(mismatch)?;
+    let algorithm = match signer.scheme() {
+        SignatureScheme::ED25519 => &webpki::ED25519,
+        SignatureScheme::ECDSA_NISTP256_SHA256 => &webpki::ECDSA_P256_SHA256,
+        SignatureScheme::ECDSA_NISTP384_SHA384 => &webpki::ECDSA_P384_SHA384,
+        _ => &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    };
+    let signature = signer.sign(PROBE).map_err(|_| mismatch())?;
+
+    let end_entity = webpki::EndEntityCert::try_from(cert.0.as_slice()).map_err(|e| {
+        Error::TlsConfig(format!(
+            "Node certificate ({}) is not a valid end-entity certificate: {:?}",
+            config.cert_path.display(),
+            e
+        ))
+    })?;
+    end_entity
+        .verify_signature(algorithm, PROBE, &signature)
+        .map_err(|_| mismatch())
+}
+
+/// Checks that the node certificate chains to one of the configured CAs, so a
+/// misconfigured node fails at startup rather than on every connection.
+fn check_cert_issued_by_ca(
+    cert_chain: &[rustls::Certificate],
+    ca_certs: &[rustls::Certificate],
+    config: &TlsConfig,
+) -> Result<()> {
+    let trust_anchors = ca_certs
+        .iter()
+        .map(|ca_cert| webpki::TrustAnchor::try_from_cert_der(&ca_cert.0))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| {
+            Error::TlsConfig(format!(
+                "CA certificate ({}) cannot be used as a trust anchor: {:?}",
+                config.ca_cert_path.display(),
+                e
+            ))
+        })?;
+    let intermediates: Vec<&[u8]> = cert_chain[1..].iter().map(|cert| cert.0.as_slice()).collect();
+    let now = webpki::Time::try_from(SystemTime::now())
+        .map_err(|_| Error::TlsConfig("System clock is before the Unix epoch".to_string()))?;
+
+    let end_entity = webpki::EndEntityCert::try_from(cert_chain[0].0.as_slice()).map_err(|e| {
+        Error::TlsConfig(format!(
+            "Node certificate ({}) is not a valid end-entity certificate: {:?}",
+            config.cert_path.display(),
+            e
+        ))
     })?;
-    let cert_chain = vec![rustls::Certificate(cert_chain_der)];
-    let key = rustls::PrivateKey(key_der);
+    end_entity
+        .verify_for_usage(
+            SUPPORTED_SIG_ALGS,
+            &trust_anchors,
+            &intermediates,
+            now,
+            webpki::KeyUsage::server_auth(),
+            &[],
+        )
+        .map_err(|e| {
+            Error::TlsConfig(format!(
+                "Node certificate ({}) is not valid for CA ({}): {:?}",
+                config.cert_path.display(),
+                config.ca_cert_path.display(),
+                e
+            ))
+        })
+}
 
-    let mut server_crypto_config = rustls::ServerConfig::builder()
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Configures TLS so that each node presents a certificate derived from its
+/// `Identity`, and trusts any peer whose certificate key is a `NodeId`
+/// (restricted to `allowed_peers` when that list is non-empty). Connecting
+/// peers must always present one, whatever `client_auth` says: without it the
+/// allowlist could not be enforced on inbound connections.
+fn configure_identity(
+    identity: &Identity,
+    config: &TlsConfig,
+) -> Result<(rustls::ServerConfig, rustls::ClientConfig)> {
+    let (cert, key) = identity_certificate(identity)?;
+    let verifier = Arc::new(NodeIdVerifier::new(&config.allowed_peers));
+
+    let client_crypto_config = rustls::ClientConfig::builder()
         .with_safe_defaults()
-        .with_no_client_auth()
-        .with_single_cert(cert_chain, key)
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_client_auth_cert(vec![cert.clone()], key.clone())
+        .map_err(|e| Error::TlsConfig(format!("Failed to create client TLS config: {}", e)))?;
+
+    let server_crypto_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(vec![cert], key)
         .map_err(|e| Error::TlsConfig(format!("Failed to create server TLS config: {}", e)))?;
-    server_crypto_config.alpn_protocols = vec![b"gossip/1.0".to_vec()];
 
-    let mut server_config = ServerConfig::with_crypto(Arc::new(server_crypto_config));
-    let transport_config = Arc::get_mut(&mut server_config.transport).unwrap();
-    transport_config.keep_alive_interval(Some(std::time::Duration::from_secs(10)));
+    Ok((server_crypto_config, client_crypto_config))
+}
 
-    Ok((server_config, client_config))
+/// Generates a self-signed certificate whose key is the identity's ed25519 key.
+pub fn identity_certificate(identity: &Identity) -> Result<(rustls::Certificate, rustls::PrivateKey)> {
+    let key_der = identity.to_pkcs8_der()?;
+    let key_pair = KeyPair::from_der(&key_der)
+        .map_err(|e| Error::TlsConfig(format!("Failed to load identity key: {}", e)))?;
+
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+    params.alg = &PKCS_ED25519;
+    params.key_pair = Some(key_pair);
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, hex::encode(identity.node_id.0));
+
+    let cert_der = rcgen::Certificate::from_params(params)
+        .and_then(|cert| cert.serialize_der())
+        .map_err(|e| Error::TlsConfig(format!("Failed to generate identity certificate: {}", e)))?;
+
+    Ok((rustls::Certificate(cert_der), rustls::PrivateKey(key_der)))
+}
+
+/// Extracts the `NodeId` from a certificate carrying an ed25519 public key.
+/// Returns `None` if the certificate is malformed or uses another key type.
+pub fn node_id_from_certificate(cert: &rustls::Certificate) -> Option<NodeId> {
+    let (_, cert) = x509_parser::parse_x509_certificate(&cert.0).ok()?;
+    let spki = cert.public_key();
+    if spki.algorithm.algorithm != OID_SIG_ED25519 {
+        return None;
+    }
+    let key_bytes: [u8; 32] = spki.subject_public_key.data.as_ref().try_into().ok()?;
+    let node_id = NodeId(key_bytes);
+    // Reject byte strings that are not valid ed25519 points.
+    ed25519_dalek::VerifyingKey::from_bytes(&node_id.0).ok()?;
+    Some(node_id)
+}
+
+/// The certificate the peer presented on `conn`, if any. It has already been
+/// verified by the TLS layer by the time the connection is established.
+pub fn peer_certificate(conn: &Connection) -> Option<rustls::Certificate> {
+    let chain = conn.peer_identity()?.downcast::<Vec<rustls::Certificate>>().ok()?;
+    chain.into_iter().next()
+}
+
+/// Accepts a peer certificate if its key is a `NodeId`, optionally restricted
+/// to an allowlist. The TLS handshake itself proves possession of the key, so
+/// the certificate does not need to be signed by any authority.
+struct NodeIdVerifier {
+    allowed_peers: HashSet<NodeId>,
+}
+
+impl NodeIdVerifier {
+    fn new(allowed_peers: &[NodeId]) -> Self {
+        Self {
+            allowed_peers: allowed_peers.iter().copied().collect(),
+        }
+    }
+
+    fn verify(&self, cert: &rustls::Certificate) -> std::result::Result<NodeId, rustls::Error> {
+        let node_id = node_id_from_certificate(cert)
+            .ok_or(rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        if !self.allowed_peers.is_empty() && !self.allowed_peers.contains(&node_id) {
+            tracing::warn!(peer_id = %node_id, "Rejected TLS peer not present in the allowlist");
+            return Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ));
+        }
+        Ok(node_id)
+    }
+}
+
+impl ServerCertVerifier for NodeIdVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        self.verify(end_entity)?;
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+impl ClientCertVerifier for NodeIdVerifier {
+    fn client_auth_root_subjects(&self) -> &[rustls::DistinguishedName] {
+        // Certificates are self-signed, so there is no issuer to hint at.
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _now: SystemTime,
+    ) -> std::result::Result<ClientCertVerified, rustls::Error> {
+        self.verify(end_entity)?;
+        Ok(ClientCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, IsCa};
+
+    /// A CA, plus a node certificate it issued, generated with rcgen.
+    struct TestPki {
+        ca: rcgen::Certificate,
+        node: rcgen::Certificate,
+    }
+
+    impl TestPki {
+        fn new() -> Self {
+            let mut ca_params = CertificateParams::new(vec![]);
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+            let node = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+            Self { ca, node }
+        }
+
+        /// Writes the CA, node certificate and `key_pem` as PEM files into `dir`.
+        fn write_pem(&self, dir: &Path, key_pem: &str) -> TlsConfig {
+            let config = TlsConfig {
+                ca_cert_path: dir.join("ca.pem"),
+                cert_path: dir.join("node.pem"),
+                key_path: dir.join("node-key.pem"),
+                ..TlsConfig::default()
+            };
+            fs::write(&config.ca_cert_path, self.ca.serialize_pem().unwrap()).unwrap();
+            fs::write(&config.cert_path, self.node.serialize_pem_with_signer(&self.ca).unwrap()).unwrap();
+            fs::write(&config.key_path, key_pem).unwrap();
+            config
+        }
+    }
+
+    fn tls_error(result: Result<(ServerConfig, ClientConfig)>) -> String {
+        match result {
+            Err(Error::TlsConfig(message)) => message,
+            Err(e) => panic!("expected a TLS configuration error, got {e}"),
+            Ok(_) => panic!("expected a TLS configuration error"),
+        }
+    }
+
+    #[test]
+    fn test_identity_certificate_carries_node_id() {
+        let identity = Identity::new();
+        let (cert, _) = identity_certificate(&identity).unwrap();
+        assert_eq!(node_id_from_certificate(&cert), Some(identity.node_id));
+    }
+
+    #[test]
+    fn test_node_id_from_certificate_rejects_other_key_types() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert = rustls::Certificate(cert.serialize_der().unwrap());
+        assert_eq!(node_id_from_certificate(&cert), None);
+        assert_eq!(node_id_from_certificate(&rustls::Certificate(vec![0u8; 16])), None);
+    }
+
+    #[test]
+    fn test_verifier_enforces_allowlist() {
+        let allowed = Identity::new();
+        let stranger = Identity::new();
+        let (allowed_cert, _) = identity_certificate(&allowed).unwrap();
+        let (stranger_cert, _) = identity_certificate(&stranger).unwrap();
+
+        let open = NodeIdVerifier::new(&[]);
+        assert_eq!(open.verify(&stranger_cert).unwrap(), stranger.node_id);
+
+        let restricted = NodeIdVerifier::new(&[allowed.node_id]);
+        assert_eq!(restricted.verify(&allowed_cert).unwrap(), allowed.node_id);
+        assert!(restricted.verify(&stranger_cert).is_err());
+    }
+
+    #[test]
+    fn test_configure_pki_accepts_pem_and_der() {
+        let dir = tempfile::tempdir().unwrap();
+        let pki = TestPki::new();
+        let pem_config = pki.write_pem(dir.path(), &pki.node.serialize_private_key_pem());
+        assert!(configure_tls(&pem_config, &Identity::new()).is_ok());
+
+        let der_config = TlsConfig {
+            ca_cert_path: dir.path().join("ca.der"),
+            cert_path: dir.path().join("node.der"),
+            key_path: dir.path().join("node-key.der"),
+            ..TlsConfig::default()
+        };
+        fs::write(&der_config.ca_cert_path, pki.ca.serialize_der().unwrap()).unwrap();
+        fs::write(&der_config.cert_path, pki.node.serialize_der_with_signer(&pki.ca).unwrap()).unwrap();
+        fs::write(&der_config.key_path, pki.node.serialize_private_key_der()).unwrap();
+        assert!(configure_tls(&der_config, &Identity::new()).is_ok());
+    }
+
+    #[test]
+    fn test_configure_pki_rejects_mismatched_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let pki = TestPki::new();
+        let other = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let config = pki.write_pem(dir.path(), &other.serialize_private_key_pem());
+
+        let message = tls_error(configure_tls(&config, &Identity::new()));
+        assert!(message.contains("does not match node certificate"), "{message}");
+    }
+
+    #[test]
+    fn test_configure_pki_rejects_cert_from_another_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let pki = TestPki::new();
+        let config = pki.write_pem(dir.path(), &pki.node.serialize_private_key_pem());
+        fs::write(&config.ca_cert_path, TestPki::new().ca.serialize_pem().unwrap()).unwrap();
+
+        let message = tls_error(configure_tls(&config, &Identity::new()));
+        assert!(message.contains("is not valid for CA"), "{message}");
+    }
+
+    #[test]
+    fn test_configure_pki_reports_missing_and_malformed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let pki = TestPki::new();
+        let config = pki.write_pem(dir.path(), "-----BEGIN CERTIFICATE-----\n-----END CERTIFICATE-----\n");
+        let message = tls_error(configure_tls(&config, &Identity::new()));
+        assert!(message.contains("No private key block found"), "{message}");
+
+        fs::remove_file(&config.cert_path).unwrap();
+        let message = tls_error(configure_tls(&config, &Identity::new()));
+        assert!(message.contains("Failed to read node certificate"), "{message}");
+    }
+
+    #[test]
+    fn test_configure_tls_validates_server_name_and_alpn() {
+        let identity_config = TlsConfig { mode: TlsMode::Identity, ..TlsConfig::default() };
+        assert!(configure_tls(&identity_config, &Identity::new()).is_ok());
+
+        let bad_name = TlsConfig { server_name: "not a name".to_string(), ..identity_config.clone() };
+        assert!(tls_error(configure_tls(&bad_name, &Identity::new())).contains("server name"));
+
+        let no_alpn = TlsConfig { alpn_protocols: vec![], ..identity_config };
+        assert!(tls_error(configure_tls(&no_alpn, &Identity::new())).contains("ALPN"));
+    }
 }
 
 /*
@@ -77,16 +562,21 @@ The following steps must be completed before running the application.
      - `minica.pem` and `minica.key` (The CA)
      - `localhost/cert.pem` and `localhost/key.pem` (The node's certificate)
 
-4. Convert the PEM files to the DER format that rustls expects:
+4. Either point the `[tls]` section of `config.toml` at the PEM files directly
+   (`ca_cert_path`, `cert_path`, `key_path`), or convert them to DER at the
+   default locations:
    openssl x509 -outform der -in minica.pem -out ca.cert
    openssl x509 -outform der -in localhost/cert.pem -out node.cert
    openssl pkcs8 -topk8 -nocrypt -outform der -in localhost/key.pem -out node.key
 
-5. Verify the `certs/` directory. It should now contain:
+5. Verify the `certs/` directory. With the default paths it should contain:
    - ca.cert
    - node.cert
    - node.key
 
+   At startup the node checks that the key matches the certificate and that
+   the certificate chains to the CA, and refuses to start otherwise.
+
 For this demonstration project, all nodes in the network will share these same
 three files. In a real-world system, each node would have its own unique
 `node.cert` and `node.key` files, all signed by the same central `ca.cert`.
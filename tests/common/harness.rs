@@ -11,7 +11,12 @@
 
 use anyhow::{Context, Result};
 use futures::stream::StreamExt;
-use gossip_network::{api::protocol::WebSocketMessage, domain::NetworkState, App, Config};
+use gossip_network::{
+    api::protocol::{UpdatePayload, WebSocketMessage},
+    domain::{NetworkState, NodeHealth, NodeId},
+    transport::envelope,
+    App, Config,
+};
 use quinn::{ClientConfig, Endpoint};
 use rcgen::{Certificate, CertificateParams, DistinguishedName};
 use std::{
@@ -22,6 +27,7 @@ use std::{
     time::Duration,
 };
 use tempfile::{tempdir, TempDir};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_util::sync::CancellationToken;
 use tokio_tungstenite::{
     connect_async,
@@ -30,6 +36,9 @@ use tokio_tungstenite::{
 };
 use tracing::info;
 
+/// The admin token test nodes accept for placing and lifting bans.
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
 /// Represents a set of TLS certificates (CA and a node cert signed by it).
 #[derive(Clone)]
 pub struct CertSet {
@@ -87,6 +96,15 @@ impl TestNode {
     pub async fn spawn(
         bootstrap_peers: Vec<SocketAddr>,
         certs: &CertSet,
+    ) -> Result<Self> {
+        Self::spawn_with(bootstrap_peers, certs, |_| {}).await
+    }
+
+    /// Like `spawn`, but lets the caller adjust the generated `Config` first.
+    pub async fn spawn_with(
+        bootstrap_peers: Vec<SocketAddr>,
+        certs: &CertSet,
+        customize: impl FnOnce(&mut Config),
     ) -> Result<Self> {
         let temp_dir = tempdir().context("Failed to create temp dir")?;
         let certs_dir = temp_dir.path().join("certs");
@@ -97,7 +115,7 @@ impl TestNode {
         let p2p_addr = get_ephemeral_addr()?;
         let api_addr = get_ephemeral_addr()?;
 
-        let config = Config {
+        let mut config = Config {
             identity_path: temp_dir.path().join("identity.key"),
             p2p_addr,
             bootstrap_peers,
@@ -107,22 +125,23 @@ impl TestNode {
             // MODIFICATION: Add the new cleanup_interval_ms field.
             cleanup_interval_ms: 1000,
             community_id: 0,
-            visualizer: Some(gossip_network::config::VisualizerConfig { bind_addr: api_addr }),
+            visualizer: Some(gossip_network::config::VisualizerConfig {
+                bind_addr: api_addr,
+                admin_token: Some(ADMIN_TOKEN.to_string()),
+            }),
+            ..Config::default()
         };
+        config.tls.ca_cert_path = certs_dir.join("ca.cert");
+        config.tls.cert_path = certs_dir.join("node.cert");
+        config.tls.key_path = certs_dir.join("node.key");
+        customize(&mut config);
 
-        let shutdown_token = CancellationToken::new();
-        let app_dir = temp_dir.path().to_path_buf();
-        let app_config = config.clone();
+        let app = App::new(config.clone()).context("Failed to create app")?;
+        let shutdown_token = app.shutdown_token();
         let app_token = shutdown_token.clone();
 
         tokio::spawn(async move {
-            std::env::set_current_dir(&app_dir)
-                .expect("Failed to set current dir for spawned app");
-            if let Err(e) = App::new(app_config)
-                .expect("Failed to create app")
-                .run()
-                .await
-            {
+            if let Err(e) = app.run().await {
                 if !app_token.is_cancelled() {
                     tracing::error!(error = ?e, "Test node app failed");
                 }
@@ -131,8 +150,6 @@ impl TestNode {
 
         tokio::time::sleep(Duration::from_millis(50)).await;
         info!(p2p = %p2p_addr, api = %api_addr, "Spawned test node");
-        
-        std::env::set_current_dir(std::env::var("CARGO_MANIFEST_DIR").unwrap()).unwrap();
 
         Ok(Self {
             config,
@@ -145,7 +162,7 @@ impl TestNode {
 
     /// Creates a WebSocket client connected to this node's API.
     pub async fn ws_client(&self) -> Result<WebSocketStream<impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>> {
-        let url = format!("ws://{}", self.api_addr);
+        let url = format!("ws://{}/ws", self.api_addr);
         let mut request = url.into_client_request()?;
         request.headers_mut().append("Host", self.api_addr.to_string().parse()?);
         
@@ -153,6 +170,27 @@ impl TestNode {
         Ok(socket)
     }
 
+    /// Sends a bodyless HTTP request to the node's API, bearing the admin
+    /// token, and returns the status code.
+    pub async fn http_request(&self, method: &str, path: &str) -> Result<u16> {
+        self.http_request_with_token(method, path, Some(ADMIN_TOKEN)).await
+    }
+
+    /// Like `http_request`, but bearing `token`, if any, instead.
+    pub async fn http_request_with_token(&self, method: &str, path: &str, token: Option<&str>) -> Result<u16> {
+        let mut stream = tokio::net::TcpStream::connect(self.api_addr).await.context("HTTP connect failed")?;
+        let authorization = token.map(|token| format!("Authorization: Bearer {token}\r\n")).unwrap_or_default();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {}\r\n{authorization}Content-Length: 0\r\nConnection: close\r\n\r\n",
+            self.api_addr
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        let status = response.split_whitespace().nth(1).context("Malformed HTTP response")?;
+        Ok(status.parse()?)
+    }
+
     /// Shuts down the node gracefully.
     pub fn shutdown(&self) {
         self.shutdown_token.cancel();
@@ -164,6 +202,9 @@ fn get_ephemeral_addr() -> Result<SocketAddr> {
     Ok(listener.local_addr()?)
 }
 
+/// Consumes WebSocket messages until the reconstructed `NetworkState` satisfies
+/// `predicate`. The state is seeded from the initial snapshot and kept current
+/// by applying each subsequent delta update.
 pub async fn wait_for_state<F>(
     ws_client: &mut WebSocketStream<impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>,
     predicate: F,
@@ -173,25 +214,32 @@ where
     F: Fn(&NetworkState) -> bool,
 {
     let wait = tokio::time::timeout(timeout_duration, async {
+        let mut state = NetworkState::default();
         loop {
             let msg = ws_client.next().await
                 .context("WebSocket stream ended prematurely")?
                 .context("WebSocket message error")?;
 
-            if let Message::Text(text) = msg {
-                if let Ok(ws_msg) = serde_json::from_str::<WebSocketMessage>(&text) {
-                    if let WebSocketMessage::Snapshot(payload) = ws_msg {
-                        let state = NetworkState {
-                            self_id: Some(payload.self_id),
-                            nodes: payload.nodes,
-                            active_connections: payload.active_connections,
-                        };
-
-                        if predicate(&state) {
-                            return Ok(state);
-                        }
-                    }
+            let Message::Text(text) = msg else { continue };
+            let Ok(ws_msg) = serde_json::from_str::<WebSocketMessage>(&text) else { continue };
+            match ws_msg {
+                WebSocketMessage::Snapshot(payload) => {
+                    state = NetworkState {
+                        self_id: Some(payload.self_id),
+                        nodes: payload.nodes,
+                        active_connections: payload.active_connections,
+                        statuses: payload.statuses,
+                        health: payload.health,
+                        persistent_peers: payload.persistent_peers,
+                        bans: payload.bans,
+                        ..Default::default()
+                    };
                 }
+                WebSocketMessage::Update(update) => apply_update(&mut state, update),
+            }
+
+            if state.self_id.is_some() && predicate(&state) {
+                return Ok(state);
             }
         }
     });
@@ -199,15 +247,80 @@ where
     wait.await.context("Timeout while waiting for state condition")?
 }
 
+/// Consumes WebSocket messages for `duration` and returns the sender of each
+/// animated gossip delivery, i.e. each new message the node accepted.
+pub async fn collect_animations(
+    ws_client: &mut WebSocketStream<impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>,
+    duration: Duration,
+) -> Result<Vec<NodeId>> {
+    let mut senders = Vec::new();
+    let deadline = tokio::time::Instant::now() + duration;
+    while let Ok(msg) = tokio::time::timeout_at(deadline, ws_client.next()).await {
+        let msg = msg
+            .context("WebSocket stream ended prematurely")?
+            .context("WebSocket message error")?;
+
+        let Message::Text(text) = msg else { continue };
+        if let Ok(WebSocketMessage::Update(UpdatePayload::AnimateEdge { from_peer })) =
+            serde_json::from_str::<WebSocketMessage>(&text)
+        {
+            senders.push(from_peer);
+        }
+    }
+    Ok(senders)
+}
+
+/// Applies a single delta update to a locally reconstructed `NetworkState`.
+fn apply_update(state: &mut NetworkState, update: UpdatePayload) {
+    match update {
+        UpdatePayload::NodeAdded { id, info } | UpdatePayload::NodeUpdated { id, info } => {
+            state.nodes.insert(id, info);
+        }
+        UpdatePayload::NodeRemoved { id } => {
+            state.nodes.remove(&id);
+        }
+        UpdatePayload::ConnectionStatus { peer_id, is_connected } => {
+            state.active_connections.retain(|id| *id != peer_id);
+            if is_connected {
+                state.active_connections.push(peer_id);
+            }
+        }
+        UpdatePayload::NodeStatus { id, status } => {
+            state.statuses.insert(id, status);
+        }
+        UpdatePayload::NodeHealth { id, phi, suspected } => {
+            state.health.insert(id, NodeHealth { phi, suspected });
+        }
+        UpdatePayload::PersistentPeer(peer) => {
+            state.persistent_peers.retain(|known| known.addr != peer.addr);
+            state.persistent_peers.push(peer);
+        }
+        UpdatePayload::Bans(bans) => state.bans = bans,
+        UpdatePayload::AnimateEdge { .. } => {}
+    }
+}
+
+/// Creates a bare QUIC client that trusts `certs.ca_cert_der` and presents the
+/// node certificate from `certs` if the server asks for one. It offers only
+/// the bincode wire format.
 pub fn create_quic_client(certs: &CertSet) -> Result<Endpoint> {
+    create_quic_client_with_alpn(certs, &[envelope::ALPN_BINCODE])
+}
+
+/// Like `create_quic_client`, offering the ALPN `protocols` in order of preference.
+pub fn create_quic_client_with_alpn(certs: &CertSet, protocols: &[&str]) -> Result<Endpoint> {
     let mut root_store = rustls::RootCertStore::empty();
     root_store.add(&rustls::Certificate(certs.ca_cert_der.clone()))?;
 
-    let client_crypto = rustls::ClientConfig::builder()
+    let mut client_crypto = rustls::ClientConfig::builder()
         .with_safe_defaults()
         .with_root_certificates(root_store)
-        .with_no_client_auth();
-    
+        .with_client_auth_cert(
+            vec![rustls::Certificate(certs.node_cert_der.clone())],
+            rustls::PrivateKey(certs.node_key_der.clone()),
+        )?;
+    client_crypto.alpn_protocols = protocols.iter().map(|protocol| protocol.as_bytes().to_vec()).collect();
+
     let client_config = ClientConfig::new(Arc::new(client_crypto));
 
     let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
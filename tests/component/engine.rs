@@ -3,12 +3,26 @@
 //! In-memory component tests for the `Engine` service.
 
 use gossip_network::{
-    config::Config,
-    domain::{GossipPayload, Identity, NetworkState, SignedMessage, TelemetryData},
-    engine::Engine,
-    transport::{ConnectionEvent, InboundMessage, TransportCommand},
+    config::{Config, Propagation},
+    domain::{
+        AppPayload, Ban, BanReason, BanTarget, GossipPayload, GossipPayloadV1, Identity, MemberStatus, Metric, NetworkState,
+        NodeId, Payload, PayloadRegistry, SignedMessage, TelemetryData, TelemetryV1, Version,
+    },
+    engine::{
+        protocol::{GossipStrategy, PeerView},
+        Engine,
+    },
+    transport::{
+        Announcement, ConnectionEvent, ControlFrame, Direction, InboundMessage, MembershipMessage,
+        ProbeMessage, RelayedMessage, TransportCommand, WireMessage,
+    },
 };
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use serde::{Deserialize, Serialize};
 use test_log::test;
 use tokio::sync::{broadcast, mpsc, watch};
 use tokio::time;
@@ -40,6 +54,11 @@ where
 }
 
 fn setup_engine_harness(config: Config) -> EngineHarness {
+    setup_engine_harness_with(config, |engine| engine)
+}
+
+/// Like `setup_engine_harness`, but lets the caller adjust the `Engine` before it runs.
+fn setup_engine_harness_with(config: Config, customize: impl FnOnce(Engine) -> Engine) -> EngineHarness {
     let identity = Identity::from_file(config.identity_path.clone()).unwrap();
     let (transport_tx, transport_rx) = mpsc::channel(10);
     let (inbound_tx, inbound_rx) = mpsc::channel(10);
@@ -57,6 +76,8 @@ fn setup_engine_harness(config: Config) -> EngineHarness {
         animation_tx,
     );
 
+    let engine = customize(engine);
+
     let shutdown_token = tokio_util::sync::CancellationToken::new();
     let engine_token = shutdown_token.clone();
     tokio::spawn(engine.run(engine_token));
@@ -71,10 +92,41 @@ fn setup_engine_harness(config: Config) -> EngineHarness {
     }
 }
 
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+/// Receives transport commands until one matches `predicate`, skipping the rest.
+async fn next_command_matching<F>(transport_rx: &mut mpsc::Receiver<TransportCommand>, predicate: F) -> TransportCommand
+where
+    F: Fn(&TransportCommand) -> bool,
+{
+    time::timeout(Duration::from_secs(2), async {
+        loop {
+            let command = transport_rx.recv().await.expect("Transport channel closed");
+            if predicate(&command) {
+                return command;
+            }
+        }
+    })
+    .await
+    .expect("Timeout waiting for transport command")
+}
+
+/// Wraps `message` as if it had been delivered directly by its originator.
+fn direct_message(peer_addr: SocketAddr, message: SignedMessage) -> InboundMessage {
+    InboundMessage { peer_addr, peer_id: message.originator, message: message.into() }
+}
+
+/// Every test message gets a fresh, increasing sequence number, so messages from
+/// the same identity are always ordered by creation.
 fn create_test_message(identity: &Identity, timestamp_ms: u64) -> SignedMessage {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
     identity.sign(GossipPayload {
-        telemetry: TelemetryData { timestamp_ms, value: 42.0 },
+        timestamp_ms,
         community_id: 1,
+        version: Version { epoch: 1, sequence: SEQUENCE.fetch_add(1, Ordering::Relaxed) },
+        payload: Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(42.0, "percent"))),
     })
 }
 
@@ -98,11 +150,11 @@ async fn test_engine_prunes_stale_nodes_from_all_maps() {
     // 1. A new peer appears.
     let peer_identity = Identity::new();
     let peer_addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
-    let message = create_test_message(&peer_identity, 0);
-    inbound_tx.send(InboundMessage { peer_addr, message }).await.unwrap();
+    let message = create_test_message(&peer_identity, now_ms());
+    inbound_tx.send(direct_message(peer_addr, message)).await.unwrap();
 
-    // 2. Wait for the engine to register the new peer AND its own identity.
-    wait_for_state_change(&mut state_rx, |state| state.nodes.len() == 2).await;
+    // 2. Wait for the engine to register the new peer.
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&peer_identity.node_id)).await;
 
     // 3. Wait long enough for the peer to become stale and for the cleanup timer to fire.
     time::sleep(Duration::from_millis(150)).await;
@@ -111,6 +163,205 @@ async fn test_engine_prunes_stale_nodes_from_all_maps() {
     wait_for_state_change(&mut state_rx, |state| state.nodes.len() == 1).await;
     let final_state = state_rx.borrow();
     assert!(!final_state.nodes.contains_key(&peer_identity.node_id), "Stale peer should be pruned");
+    assert!(!final_state.messages.contains_key(&peer_identity.node_id), "Stale message should be pruned");
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_suspects_then_evicts_silent_node_by_phi_before_ttl() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        identity_path: temp_dir.path().join("id.key"),
+        gossip_interval_ms: 50,
+        cleanup_interval_ms: 50,
+        node_ttl_ms: 60_000,
+        phi_suspect_threshold: 3.0,
+        phi_evict_threshold: 30.0,
+        phi_min_std_deviation_ms: 50,
+        ..Config::default()
+    };
+    let EngineHarness {
+        inbound_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(config);
+
+    // 1. A peer sends regular updates, then falls silent.
+    let peer_identity = Identity::new();
+    let peer_addr: SocketAddr = "127.0.0.1:1235".parse().unwrap();
+    for _ in 0..6 {
+        let message = create_test_message(&peer_identity, now_ms());
+        inbound_tx.send(direct_message(peer_addr, message)).await.unwrap();
+        time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // 2. Its phi is served while it grows past the suspicion threshold...
+    wait_for_state_change(&mut state_rx, |state| {
+        state.health.get(&peer_identity.node_id).is_some_and(|health| health.suspected)
+    }).await;
+    assert!(state_rx.borrow().nodes.contains_key(&peer_identity.node_id), "Suspected nodes are kept");
+
+    // 3. ...and the node is evicted long before its TTL.
+    wait_for_state_change(&mut state_rx, |state| !state.nodes.contains_key(&peer_identity.node_id)).await;
+    assert!(!state_rx.borrow().health.contains_key(&peer_identity.node_id));
+
+    shutdown_token.cancel();
+}
+
+/// An application payload registered by the test, alongside `TelemetryData`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct FeatureFlags {
+    dark_mode: bool,
+}
+
+impl AppPayload for FeatureFlags {
+    const KIND: &'static str = "feature_flags";
+}
+
+#[test(tokio::test)]
+async fn test_engine_gossips_application_payloads_and_rejects_unregistered_kinds() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config { identity_path: temp_dir.path().join("id.key"), ..Default::default() };
+    let identity = Identity::from_file(config.identity_path.clone()).unwrap();
+    let (flags_tx, flags_rx) = watch::channel(Payload::new(&FeatureFlags { dark_mode: false }));
+    let EngineHarness {
+        inbound_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness_with(config, |engine| {
+        engine
+            .with_payload_source(Box::new(flags_rx))
+            .with_payload_registry(PayloadRegistry::empty().register::<FeatureFlags>())
+    });
+
+    // The node gossips whatever the application last published.
+    flags_tx.send(Payload::new(&FeatureFlags { dark_mode: true })).unwrap();
+    wait_for_state_change(&mut state_rx, |state| {
+        state.nodes.get(&identity.node_id).is_some_and(|info| info.payloads.get(FeatureFlags::KIND).is_some_and(|flags| flags["dark_mode"] == true))
+    }).await;
+    assert_eq!(state_rx.borrow().nodes[&identity.node_id].payloads.len(), 1);
+
+    // Telemetry is not registered on this node, so a peer's telemetry is rejected...
+    let peer_addr: SocketAddr = "127.0.0.1:7777".parse().unwrap();
+    let telemetry_identity = Identity::new();
+    inbound_tx.send(direct_message(peer_addr, create_test_message(&telemetry_identity, now_ms()))).await.unwrap();
+
+    // ...while its feature flags are accepted.
+    let flags_identity = Identity::new();
+    let flags = flags_identity.sign(GossipPayload {
+        timestamp_ms: now_ms(),
+        community_id: 1,
+        version: Version { epoch: 1, sequence: 1 },
+        payload: Payload::new(&FeatureFlags { dark_mode: false }),
+    });
+    inbound_tx.send(direct_message(peer_addr, flags)).await.unwrap();
+
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&flags_identity.node_id)).await;
+    let state = state_rx.borrow().clone();
+    assert!(!state.nodes.contains_key(&telemetry_identity.node_id));
+    assert_eq!(state.stats.rejected_invalid_payload, 1);
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_accepts_version_1_signatures_only_while_enabled() {
+    for accept_legacy_signatures in [true, false] {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config { identity_path: temp_dir.path().join("id.key"), accept_legacy_signatures, ..Default::default() };
+        let EngineHarness { inbound_tx, mut state_rx, shutdown_token, .. } = setup_engine_harness(config);
+
+        let legacy_identity = Identity::new();
+        let payload = GossipPayloadV1 {
+            telemetry: TelemetryV1 { timestamp_ms: now_ms(), value: 64.0 },
+            community_id: 1,
+            version: Version { epoch: 1, sequence: 1 },
+        };
+        let legacy = SignedMessage {
+            signature: legacy_identity.sign_bytes(&payload.legacy_signing_bytes()),
+            message: payload.try_into().unwrap(),
+            originator: legacy_identity.node_id,
+        };
+        let peer_addr: SocketAddr = "127.0.0.1:7778".parse().unwrap();
+        inbound_tx.send(direct_message(peer_addr, legacy)).await.unwrap();
+
+        wait_for_state_change(&mut state_rx, |state| {
+            state.stats.accepted_legacy_signature + state.stats.rejected_invalid_signature == 1
+        }).await;
+        let state = state_rx.borrow().clone();
+        assert_eq!(state.nodes.contains_key(&legacy_identity.node_id), accept_legacy_signatures);
+        assert_eq!(state.stats.accepted_legacy_signature, u64::from(accept_legacy_signatures));
+
+        shutdown_token.cancel();
+    }
+}
+
+#[test(tokio::test)]
+async fn test_engine_does_not_gossip_its_own_invalid_payloads() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config { identity_path: temp_dir.path().join("id.key"), gossip_interval_ms: 50, ..Default::default() };
+    let identity = Identity::from_file(config.identity_path.clone()).unwrap();
+    let invalid = TelemetryData::default().with_metric("cpu", Metric::new(f64::NAN, "percent"));
+    let (telemetry_tx, telemetry_rx) = watch::channel(Payload::new(&invalid));
+    let EngineHarness {
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness_with(config, |engine| engine.with_payload_source(Box::new(telemetry_rx)));
+
+    // A non-finite metric would reach peers as `null`, which they reject.
+    time::sleep(Duration::from_millis(200)).await;
+    assert!(!state_rx.borrow().nodes.contains_key(&identity.node_id));
+
+    telemetry_tx.send(Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(1.0, "percent")))).unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&identity.node_id)).await;
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_keeps_one_record_per_payload_kind() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config { identity_path: temp_dir.path().join("id.key"), ..Default::default() };
+    let identity = Identity::from_file(config.identity_path.clone()).unwrap();
+    let (_flags_tx, flags_rx) = watch::channel(Payload::new(&FeatureFlags { dark_mode: true }));
+    let (_telemetry_tx, telemetry_rx) = watch::channel(Payload::new(&TelemetryData::default()));
+    let EngineHarness {
+        inbound_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness_with(config, |engine| {
+        engine
+            .with_payload_sources(vec![Box::new(flags_rx), Box::new(telemetry_rx)])
+            .with_payload_registry(PayloadRegistry::default().register::<FeatureFlags>())
+    });
+
+    // Each source is gossiped under its own kind.
+    wait_for_state_change(&mut state_rx, |state| {
+        state.nodes.get(&identity.node_id).is_some_and(|info| info.payloads.len() == 2)
+    }).await;
+
+    // A peer's telemetry does not replace its feature flags, even though it is newer.
+    let peer_addr: SocketAddr = "127.0.0.1:7778".parse().unwrap();
+    let peer = Identity::new();
+    let sign = |version, payload| peer.sign(GossipPayload { timestamp_ms: now_ms(), community_id: 1, version, payload });
+    let flags = sign(Version { epoch: 1, sequence: 1 }, Payload::new(&FeatureFlags { dark_mode: false }));
+    let telemetry = sign(Version { epoch: 1, sequence: 2 }, Payload::new(&TelemetryData::default()));
+    inbound_tx.send(direct_message(peer_addr, flags)).await.unwrap();
+    inbound_tx.send(direct_message(peer_addr, telemetry)).await.unwrap();
+
+    wait_for_state_change(&mut state_rx, |state| {
+        state.messages.get(&peer.node_id).is_some_and(|records| records.len() == 2)
+    }).await;
+    let state = state_rx.borrow().clone();
+    let info = &state.nodes[&peer.node_id];
+    assert_eq!(info.payloads[FeatureFlags::KIND]["dark_mode"], false);
+    assert!(info.payloads.contains_key(TelemetryData::KIND));
+    assert_eq!(info.version, Version { epoch: 1, sequence: 2 });
 
     shutdown_token.cancel();
 }
@@ -129,19 +380,20 @@ async fn test_engine_state_freeze_via_timestamp_attack() {
     let attacker_identity = Identity::new();
     let attacker_addr: SocketAddr = "127.0.0.1:6666".parse().unwrap();
 
+    // A future-dated message must be rejected rather than freezing the node's state.
     let future_message = create_test_message(&attacker_identity, u64::MAX);
-    inbound_tx.send(InboundMessage { peer_addr: attacker_addr, message: future_message }).await.unwrap();
+    inbound_tx.send(direct_message(attacker_addr, future_message)).await.unwrap();
 
-    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&attacker_identity.node_id)).await;
-    assert_eq!(state_rx.borrow().nodes.get(&attacker_identity.node_id).unwrap().telemetry.timestamp_ms, u64::MAX);
-    
-    let valid_message = create_test_message(&attacker_identity, 1000);
-    inbound_tx.send(InboundMessage { peer_addr: attacker_addr, message: valid_message }).await.unwrap();
-    time::sleep(Duration::from_millis(50)).await;
+    // A subsequent message with a current timestamp is still accepted.
+    let valid_timestamp_ms = now_ms();
+    let valid_message = create_test_message(&attacker_identity, valid_timestamp_ms);
+    inbound_tx.send(direct_message(attacker_addr, valid_message)).await.unwrap();
 
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&attacker_identity.node_id)).await;
     let final_state = state_rx.borrow().clone();
-    assert_eq!(final_state.nodes.get(&attacker_identity.node_id).unwrap().telemetry.timestamp_ms, u64::MAX,
-        "Engine should reject the new message as it is older than the future-dated one");
+    assert_eq!(final_state.nodes.get(&attacker_identity.node_id).unwrap().timestamp_ms, valid_timestamp_ms,
+        "Engine should reject the future-dated message and accept the current one");
+    assert_eq!(final_state.stats.rejected_future_timestamp, 1);
 
     shutdown_token.cancel();
 }
@@ -151,20 +403,36 @@ async fn test_engine_routing_table_poisoning() {
     let temp_dir = tempfile::tempdir().unwrap();
     let config = Config { identity_path: temp_dir.path().join("id.key"), ..Default::default() };
     let EngineHarness {
+        _identity: identity,
         mut _transport_rx, // Needs to be mutable to be destructured.
         inbound_tx,
+        _conn_event_tx: conn_event_tx,
         mut state_rx,
         shutdown_token,
-        ..
     } = setup_engine_harness(config);
+    // Let the engine publish its own telemetry first so the initial gossip tick
+    // cannot race with the messages injected below.
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&identity.node_id)).await;
 
+    // The honest peer is directly connected, and its identity verified by the handshake.
     let honest_peer_id = Identity::new();
-    let malicious_peer_addr: SocketAddr = "127.0.0.1:6666".parse().unwrap();
+    let honest_peer_addr: SocketAddr = "127.0.0.1:5555".parse().unwrap();
+    conn_event_tx.send(ConnectionEvent::PeerConnected {
+        peer_addr: honest_peer_addr,
+        peer_id: honest_peer_id.node_id,
+        listen_addrs: Vec::new(),
+        direction: Direction::Inbound,
+    }).await.unwrap();
 
-    let message_from_a = create_test_message(&honest_peer_id, 1000);
+    // A malicious peer relays the honest peer's message, hoping the engine will
+    // associate the honest peer's NodeId with the malicious address.
+    let malicious_peer_id = Identity::new();
+    let malicious_peer_addr: SocketAddr = "127.0.0.1:6666".parse().unwrap();
+    let message_from_a = create_test_message(&honest_peer_id, now_ms());
     inbound_tx.send(InboundMessage {
         peer_addr: malicious_peer_addr,
-        message: message_from_a,
+        peer_id: malicious_peer_id.node_id,
+        message: message_from_a.into(),
     }).await.unwrap();
     
     // This test does not use `time::pause`, so we must re-enable the mutable rx variable
@@ -174,20 +442,732 @@ async fn test_engine_routing_table_poisoning() {
 
     let another_peer_id = Identity::new();
     let another_peer_addr: SocketAddr = "127.0.0.1:7777".parse().unwrap();
-    let trigger_message = create_test_message(&another_peer_id, 2000);
+    let trigger_message = create_test_message(&another_peer_id, now_ms());
 
-    inbound_tx.send(InboundMessage {
-        peer_addr: another_peer_addr,
-        message: trigger_message,
-    }).await.unwrap();
+    inbound_tx.send(direct_message(another_peer_addr, trigger_message)).await.unwrap();
 
     let command = time::timeout(Duration::from_secs(1), transport_rx.recv()).await
         .expect("Engine should have sent a gossip command")
         .unwrap();
 
-    let TransportCommand::SendMessage(addr, msg) = command;
-    assert_eq!(addr, malicious_peer_addr, "Address should be the malicious peer's address");
-    assert_eq!(msg.originator, another_peer_id.node_id, "Message should be the trigger message");
+    let TransportCommand::SendMessage(addr, WireMessage::Gossip(msg)) = command else {
+        panic!("Expected a gossip message");
+    };
+    assert_eq!(addr, honest_peer_addr, "Address should be the honest peer's verified address");
+    assert_eq!(msg.message.originator, another_peer_id.node_id, "Message should be the trigger message");
+    assert_eq!(msg.hops, 1, "Forwarded message should count the hop from its originator");
     
     shutdown_token.cancel();
-}
\ No newline at end of file
+}
+
+#[test(tokio::test)]
+async fn test_engine_discards_duplicate_messages_via_seen_cache() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config { identity_path: temp_dir.path().join("id.key"), ..Default::default() };
+    let EngineHarness {
+        _identity: identity,
+        inbound_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(config);
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&identity.node_id)).await;
+
+    // The same message arrives via three different peers.
+    let peer_identity = Identity::new();
+    let message = create_test_message(&peer_identity, now_ms());
+    for peer_addr in ["127.0.0.1:1001", "127.0.0.1:1002", "127.0.0.1:1003"] {
+        inbound_tx.send(direct_message(peer_addr.parse().unwrap(), message.clone())).await.unwrap();
+    }
+
+    // A fresh message forces a state publication after the duplicates were handled.
+    let marker_identity = Identity::new();
+    inbound_tx.send(direct_message(
+        "127.0.0.1:1004".parse().unwrap(),
+        create_test_message(&marker_identity, now_ms()),
+    )).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&marker_identity.node_id)).await;
+
+    let stats = state_rx.borrow().stats;
+    assert_eq!(stats.seen_cache_hits, 2, "Both duplicates should be served from the cache");
+    assert_eq!(stats.seen_cache_misses, 2, "Only the first copy and the marker should be processed");
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_accepts_future_dated_message_once_within_window() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        identity_path: temp_dir.path().join("id.key"),
+        max_future_skew_ms: 100,
+        ..Default::default()
+    };
+    let EngineHarness {
+        inbound_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(config);
+
+    // Dated ahead of the window at first, the same message is redelivered once
+    // the clocks agree, and must not be discarded as already seen.
+    let peer_identity = Identity::new();
+    let peer_addr: SocketAddr = "127.0.0.1:6667".parse().unwrap();
+    let message = create_test_message(&peer_identity, now_ms() + 400);
+    inbound_tx.send(direct_message(peer_addr, message.clone())).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.stats.rejected_future_timestamp == 1).await;
+
+    time::sleep(Duration::from_millis(400)).await;
+    inbound_tx.send(direct_message(peer_addr, message)).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&peer_identity.node_id)).await;
+    assert_eq!(state_rx.borrow().stats.seen_cache_hits, 0);
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_orders_updates_by_version_not_timestamp() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config { identity_path: temp_dir.path().join("id.key"), ..Default::default() };
+    let EngineHarness {
+        _identity: identity,
+        inbound_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(config);
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&identity.node_id)).await;
+
+    let peer_identity = Identity::new();
+    let peer_addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+    let sign = |sequence: u64, timestamp_ms: u64| {
+        peer_identity.sign(GossipPayload {
+            timestamp_ms,
+            community_id: 1,
+            version: Version { epoch: 1, sequence },
+            payload: Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(sequence as f64, "percent"))),
+        })
+    };
+    let now = now_ms();
+
+    inbound_tx.send(direct_message(peer_addr, sign(1, now))).await.unwrap();
+
+    // The peer's clock jumps back by ten seconds; its next message must still be accepted.
+    inbound_tx.send(direct_message(peer_addr, sign(2, now - 10_000))).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| {
+        state.nodes.get(&peer_identity.node_id).is_some_and(|info| info.version.sequence == 2)
+    }).await;
+
+    // An older sequence number loses even with a newer timestamp.
+    inbound_tx.send(direct_message(peer_addr, sign(0, now + 1_000))).await.unwrap();
+    let marker_identity = Identity::new();
+    inbound_tx.send(direct_message(
+        "127.0.0.1:1235".parse().unwrap(),
+        create_test_message(&marker_identity, now_ms()),
+    )).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&marker_identity.node_id)).await;
+
+    let state = state_rx.borrow().clone();
+    let peer_info = state.nodes.get(&peer_identity.node_id).unwrap();
+    assert_eq!(peer_info.version, Version { epoch: 1, sequence: 2 });
+    assert_eq!(peer_info.timestamp_ms, now - 10_000);
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_replies_to_sync_digest_with_missing_messages() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config { identity_path: temp_dir.path().join("id.key"), ..Default::default() };
+    let EngineHarness {
+        _identity: identity,
+        _transport_rx: mut transport_rx,
+        inbound_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(config);
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&identity.node_id)).await;
+
+    let peer_identity = Identity::new();
+    inbound_tx.send(direct_message("127.0.0.1:1001".parse().unwrap(), create_test_message(&peer_identity, now_ms()))).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&peer_identity.node_id)).await;
+
+    // A requester that knows nothing is sent everything.
+    let requester_addr: SocketAddr = "127.0.0.1:2002".parse().unwrap();
+    inbound_tx.send(InboundMessage {
+        peer_addr: requester_addr,
+        peer_id: Identity::new().node_id,
+        message: WireMessage::SyncDigest(vec![]),
+    }).await.unwrap();
+
+    let command = time::timeout(Duration::from_secs(1), transport_rx.recv()).await
+        .expect("Engine should have replied to the digest")
+        .unwrap();
+    let TransportCommand::SendMessage(addr, WireMessage::SyncReply(messages)) = command else {
+        panic!("Expected a sync reply");
+    };
+    assert_eq!(addr, requester_addr);
+    let mut originators: Vec<_> = messages.iter().map(|m| m.message.originator.0).collect();
+    originators.sort();
+    let mut expected = vec![identity.node_id.0, peer_identity.node_id.0];
+    expected.sort();
+    assert_eq!(originators, expected);
+
+    // The same requester asking again right away is ignored rather than
+    // being sent the whole state a second time.
+    let requester_id = Identity::new().node_id;
+    for _ in 0..2 {
+        inbound_tx.send(InboundMessage {
+            peer_addr: requester_addr,
+            peer_id: requester_id,
+            message: WireMessage::SyncDigest(vec![]),
+        }).await.unwrap();
+    }
+    next_command_matching(&mut transport_rx, |command| matches!(command, TransportCommand::SendMessage(_, WireMessage::SyncReply(_)))).await;
+    time::sleep(Duration::from_millis(50)).await;
+    assert!(transport_rx.try_recv().is_err(), "Engine should reply to one of the two digests only");
+
+    // A requester that is already up to date is sent nothing.
+    let digest = messages.iter().map(|m| (m.message.originator, m.message.message.payload.kind.clone(), m.message.message.version)).collect();
+    inbound_tx.send(InboundMessage {
+        peer_addr: requester_addr,
+        peer_id: Identity::new().node_id,
+        message: WireMessage::SyncDigest(digest),
+    }).await.unwrap();
+    time::sleep(Duration::from_millis(50)).await;
+    assert!(transport_rx.try_recv().is_err(), "Engine should not reply to an up-to-date digest");
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_runs_anti_entropy_with_connected_peers() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        identity_path: temp_dir.path().join("id.key"),
+        anti_entropy_interval_ms: 100,
+        ..Default::default()
+    };
+    let EngineHarness {
+        _identity: identity,
+        _transport_rx: mut transport_rx,
+        inbound_tx,
+        _conn_event_tx: conn_event_tx,
+        mut state_rx,
+        shutdown_token,
+    } = setup_engine_harness(config);
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&identity.node_id)).await;
+
+    let peer_identity = Identity::new();
+    let peer_addr: SocketAddr = "127.0.0.1:3003".parse().unwrap();
+    conn_event_tx.send(ConnectionEvent::PeerConnected {
+        peer_addr,
+        peer_id: peer_identity.node_id,
+        listen_addrs: Vec::new(),
+        direction: Direction::Inbound,
+    }).await.unwrap();
+
+    // The periodic round sends our digest to the connected peer.
+    let command = time::timeout(Duration::from_secs(1), transport_rx.recv()).await
+        .expect("Engine should have started an anti-entropy round")
+        .unwrap();
+    let TransportCommand::SendMessage(addr, WireMessage::SyncDigest(digest)) = command else {
+        panic!("Expected a sync digest");
+    };
+    assert_eq!(addr, peer_addr);
+    assert!(digest.iter().any(|(id, kind, _)| *id == identity.node_id && kind == TelemetryData::KIND));
+
+    // The peer's reply is applied, but not forwarded as fresh gossip.
+    let missing_identity = Identity::new();
+    inbound_tx.send(InboundMessage {
+        peer_addr,
+        peer_id: peer_identity.node_id,
+        message: WireMessage::SyncReply(vec![RelayedMessage {
+            message: create_test_message(&missing_identity, now_ms()),
+            hops: 2,
+            status_updates: Vec::new(),
+        }]),
+    }).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&missing_identity.node_id)).await;
+
+    // The full signed message is retained along with how it was delivered.
+    let record = state_rx.borrow().messages[&missing_identity.node_id][TelemetryData::KIND].clone();
+    assert!(record.message.verify().is_ok());
+    assert_eq!(record.from_peer, peer_identity.node_id);
+    assert_eq!(record.hops, 3);
+    assert!(record.received_at_ms > 0);
+    while let Ok(command) = transport_rx.try_recv() {
+        assert!(
+            matches!(command, TransportCommand::SendMessage(_, WireMessage::SyncDigest(_))),
+            "Repaired state should not be re-gossiped"
+        );
+    }
+
+    shutdown_token.cancel();
+}
+
+/// A strategy that sends every message to a single, fixed peer.
+struct FixedTarget(NodeId, SocketAddr);
+
+impl GossipStrategy for FixedTarget {
+    fn select_targets(&mut self, _message: &SignedMessage, _view: &PeerView<'_>) -> Vec<(NodeId, SocketAddr)> {
+        vec![(self.0, self.1)]
+    }
+}
+
+#[test(tokio::test)]
+async fn test_engine_gossips_with_custom_strategy() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        identity_path: temp_dir.path().join("id.key"),
+        ..Config::default()
+    };
+    let target_addr: SocketAddr = "127.0.0.1:4004".parse().unwrap();
+    let target_id = Identity::new().node_id;
+    let EngineHarness {
+        _transport_rx: mut transport_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness_with(config, |engine| {
+        engine.with_strategy(Box::new(FixedTarget(target_id, target_addr)))
+    });
+
+    // The engine knows no peers, so only the custom strategy can produce a target
+    // for its first telemetry message.
+    let command = time::timeout(Duration::from_secs(1), transport_rx.recv()).await
+        .expect("Engine should have gossiped its telemetry")
+        .unwrap();
+    let TransportCommand::SendMessage(addr, WireMessage::Gossip(_)) = command else {
+        panic!("Expected a gossip message");
+    };
+    assert_eq!(addr, target_addr);
+
+    shutdown_token.cancel();
+}
+
+fn plumtree_config(temp_dir: &tempfile::TempDir) -> Config {
+    Config {
+        identity_path: temp_dir.path().join("id.key"),
+        propagation: Propagation::Plumtree,
+        plumtree_graft_timeout_ms: 100,
+        ..Config::default()
+    }
+}
+
+#[test(tokio::test)]
+async fn test_engine_plumtree_prunes_redundant_eager_peer() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let EngineHarness {
+        _transport_rx: mut transport_rx,
+        inbound_tx,
+        _conn_event_tx: conn_event_tx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(plumtree_config(&temp_dir));
+
+    let (peer_a, peer_b) = (Identity::new(), Identity::new());
+    let addr_a: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+    let addr_b: SocketAddr = "127.0.0.1:5002".parse().unwrap();
+    for (peer, addr) in [(&peer_a, addr_a), (&peer_b, addr_b)] {
+        conn_event_tx.send(ConnectionEvent::PeerConnected { peer_addr: addr, peer_id: peer.node_id, listen_addrs: Vec::new(), direction: Direction::Inbound }).await.unwrap();
+    }
+
+    // Both peers start out eager, so a message relayed by A is pushed on to B.
+    let message = create_test_message(&Identity::new(), now_ms());
+    let relayed = |peer: &Identity, addr| InboundMessage {
+        peer_addr: addr,
+        peer_id: peer.node_id,
+        message: WireMessage::Gossip(RelayedMessage { message: message.clone(), hops: 1, status_updates: Vec::new() }),
+    };
+    inbound_tx.send(relayed(&peer_a, addr_a)).await.unwrap();
+    next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(addr, WireMessage::Gossip(_)) if *addr == addr_b)
+    }).await;
+
+    // B then delivers the same message, which makes its link redundant.
+    inbound_tx.send(relayed(&peer_b, addr_b)).await.unwrap();
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendControl(..))
+    }).await;
+    let TransportCommand::SendControl(addr, ControlFrame::Prune) = command else {
+        panic!("Expected a prune, got {command:?}");
+    };
+    assert_eq!(addr, addr_b);
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_plumtree_grafts_announced_message() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let EngineHarness {
+        _transport_rx: mut transport_rx,
+        inbound_tx,
+        _conn_event_tx: conn_event_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(plumtree_config(&temp_dir));
+
+    let peer = Identity::new();
+    let peer_addr: SocketAddr = "127.0.0.1:5003".parse().unwrap();
+    conn_event_tx.send(ConnectionEvent::PeerConnected { peer_addr, peer_id: peer.node_id, listen_addrs: Vec::new(), direction: Direction::Inbound }).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.active_connections.contains(&peer.node_id)).await;
+
+    // Once pruned, the peer only receives announcements, so a message relayed by
+    // another node reaches it in full only if it grafts.
+    let control = |frame| InboundMessage { peer_addr, peer_id: peer.node_id, message: WireMessage::Control(frame) };
+    inbound_tx.send(control(ControlFrame::Prune)).await.unwrap();
+    let relayed_message = create_test_message(&Identity::new(), now_ms());
+    inbound_tx.send(InboundMessage {
+        peer_addr: "127.0.0.1:5004".parse().unwrap(),
+        peer_id: Identity::new().node_id,
+        message: WireMessage::Gossip(RelayedMessage {
+            message: relayed_message.clone(),
+            hops: 1,
+            status_updates: Vec::new(),
+        }),
+    }).await.unwrap();
+    inbound_tx.send(control(ControlFrame::Graft {
+        id: relayed_message.id(),
+        originator: relayed_message.originator,
+        kind: relayed_message.message.payload.kind.clone(),
+    })).await.unwrap();
+
+    // The engine's own payload may have been pushed before the prune, so wait
+    // for the relayed message specifically.
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(addr, WireMessage::Gossip(gossip))
+            if *addr == peer_addr && gossip.message.id() == relayed_message.id())
+    }).await;
+    let TransportCommand::SendMessage(_, WireMessage::Gossip(relayed)) = command else {
+        unreachable!();
+    };
+    assert_eq!(relayed.hops, 2);
+
+    // The peer announces a message that never arrives, so it is pulled once the
+    // graft timeout expires.
+    let missing = create_test_message(&Identity::new(), now_ms());
+    let announcement = Announcement {
+        id: missing.id(),
+        originator: missing.originator,
+        kind: missing.message.payload.kind.clone(),
+        version: missing.message.version,
+    };
+    inbound_tx.send(control(ControlFrame::IHave(vec![announcement.clone()]))).await.unwrap();
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendControl(_, ControlFrame::Graft { .. }))
+    }).await;
+    let TransportCommand::SendControl(addr, ControlFrame::Graft { id, originator, kind }) = command else {
+        unreachable!();
+    };
+    assert_eq!(addr, peer_addr);
+    assert_eq!((id, originator, kind), (announcement.id, announcement.originator, announcement.kind));
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_gossips_over_active_view_and_heals_from_passive_view() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        identity_path: temp_dir.path().join("id.key"),
+        gossip_interval_ms: 100,
+        active_view_size: 1,
+        ..Config::default()
+    };
+    let EngineHarness {
+        _transport_rx: mut transport_rx,
+        _conn_event_tx: conn_event_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(config);
+
+    // A fills the single active view slot, so B is only kept as a backup.
+    let (peer_a, peer_b) = (Identity::new(), Identity::new());
+    let addr_a: SocketAddr = "127.0.0.1:5011".parse().unwrap();
+    let addr_b: SocketAddr = "127.0.0.1:5012".parse().unwrap();
+    for (peer, addr) in [(&peer_a, addr_a), (&peer_b, addr_b)] {
+        conn_event_tx.send(ConnectionEvent::PeerConnected { peer_addr: addr, peer_id: peer.node_id, listen_addrs: Vec::new(), direction: Direction::Inbound }).await.unwrap();
+    }
+    wait_for_state_change(&mut state_rx, |state| state.active_connections.len() == 2).await;
+
+    for _ in 0..3 {
+        let command = next_command_matching(&mut transport_rx, |command| {
+            matches!(command, TransportCommand::SendMessage(_, WireMessage::Gossip(_)))
+        }).await;
+        let TransportCommand::SendMessage(addr, _) = command else {
+            unreachable!();
+        };
+        assert_eq!(addr, addr_a, "Gossip should only be sent to the active view");
+    }
+
+    // Losing A leaves the active view empty, so B is promoted with a high
+    // priority NEIGHBOR request.
+    conn_event_tx.send(ConnectionEvent::PeerDisconnected { peer_addr: addr_a, peer_id: peer_a.node_id, direction: Direction::Inbound }).await.unwrap();
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(_, WireMessage::Membership(_)))
+    }).await;
+    let TransportCommand::SendMessage(addr, WireMessage::Membership(message)) = command else {
+        unreachable!();
+    };
+    assert_eq!(addr, addr_b);
+    assert_eq!(message, MembershipMessage::Neighbor { high_priority: true });
+
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(_, WireMessage::Gossip(_)))
+    }).await;
+    let TransportCommand::SendMessage(addr, _) = command else {
+        unreachable!();
+    };
+    assert_eq!(addr, addr_b, "Gossip should follow the healed active view");
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_swim_answers_pings_and_declares_silent_peer_dead() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        identity_path: temp_dir.path().join("id.key"),
+        swim_probe_interval_ms: 100,
+        swim_probe_timeout_ms: 50,
+        swim_suspicion_timeout_ms: 300,
+        ..Config::default()
+    };
+    let EngineHarness {
+        _identity: identity,
+        _transport_rx: mut transport_rx,
+        inbound_tx,
+        _conn_event_tx: conn_event_tx,
+        mut state_rx,
+        shutdown_token,
+    } = setup_engine_harness(config);
+
+    // A ping is answered with an ack on behalf of this node.
+    let (responsive, silent) = (Identity::new(), Identity::new());
+    let responsive_addr: SocketAddr = "127.0.0.1:5021".parse().unwrap();
+    inbound_tx.send(InboundMessage {
+        peer_addr: responsive_addr,
+        peer_id: responsive.node_id,
+        message: WireMessage::Probe(ProbeMessage::Ping { seq: 42, status_updates: Vec::new() }),
+    }).await.unwrap();
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(_, WireMessage::Probe(ProbeMessage::Ack { .. })))
+    }).await;
+    let TransportCommand::SendMessage(addr, WireMessage::Probe(ProbeMessage::Ack { seq, target, .. })) = command else {
+        unreachable!();
+    };
+    assert_eq!((addr, seq, target), (responsive_addr, 42, identity.node_id));
+
+    // A connected peer that never acks is probed, suspected, then declared dead
+    // and removed from the state.
+    let silent_addr: SocketAddr = "127.0.0.1:5022".parse().unwrap();
+    inbound_tx.send(direct_message(silent_addr, create_test_message(&silent, now_ms()))).await.unwrap();
+    conn_event_tx.send(ConnectionEvent::PeerConnected { peer_addr: silent_addr, peer_id: silent.node_id, listen_addrs: Vec::new(), direction: Direction::Inbound }).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.nodes.contains_key(&silent.node_id)).await;
+
+    next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(addr, WireMessage::Probe(ProbeMessage::Ping { .. })) if *addr == silent_addr)
+    }).await;
+    wait_for_state_change(&mut state_rx, |state| {
+        state.statuses.get(&silent.node_id) == Some(&MemberStatus::Suspect)
+    }).await;
+    wait_for_state_change(&mut state_rx, |state| {
+        state.statuses.get(&silent.node_id) == Some(&MemberStatus::Dead) && !state.nodes.contains_key(&silent.node_id)
+    }).await;
+    assert_eq!(state_rx.borrow().statuses.get(&identity.node_id), Some(&MemberStatus::Alive));
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_exchanges_signed_advertisements_and_dials_candidates() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        identity_path: temp_dir.path().join("id.key"),
+        gossip_interval_ms: 100,
+        pex_interval_ms: 100,
+        min_outbound_connections: 3,
+        ..Config::default()
+    };
+    let p2p_addr = config.p2p_addr;
+    let EngineHarness {
+        _identity: identity,
+        _transport_rx: mut transport_rx,
+        inbound_tx,
+        _conn_event_tx: conn_event_tx,
+        mut state_rx,
+        shutdown_token,
+    } = setup_engine_harness(config);
+
+    let neighbour = Identity::new();
+    let neighbour_addr: SocketAddr = "127.0.0.1:5031".parse().unwrap();
+    conn_event_tx.send(ConnectionEvent::PeerConnected { peer_addr: neighbour_addr, peer_id: neighbour.node_id, listen_addrs: Vec::new(), direction: Direction::Inbound }).await.unwrap();
+
+    // The neighbour passes on one genuine advertisement and one whose address
+    // was swapped after signing.
+    let (advertised, forged) = (Identity::new(), Identity::new());
+    let advertised_addr: SocketAddr = "127.0.0.1:5032".parse().unwrap();
+    let forged_addr: SocketAddr = "127.0.0.1:5033".parse().unwrap();
+    let mut forged_advertisement = forged.advertise(vec!["127.0.0.1:5034".parse().unwrap()], Version::default());
+    forged_advertisement.advertisement.listen_addrs = vec![forged_addr];
+    inbound_tx.send(InboundMessage {
+        peer_addr: neighbour_addr,
+        peer_id: neighbour.node_id,
+        message: WireMessage::PeerExchange(vec![
+            advertised.advertise(vec![advertised_addr], Version::default()),
+            forged_advertisement,
+        ]),
+    }).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.stats.rejected_invalid_advertisement == 1).await;
+
+    // Neighbours receive the node's own signed advertisement...
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(addr, WireMessage::PeerExchange(_)) if *addr == neighbour_addr)
+    }).await;
+    let TransportCommand::SendMessage(_, WireMessage::PeerExchange(advertisements)) = command else {
+        unreachable!();
+    };
+    let own = &advertisements[0];
+    assert!(own.verify().is_ok());
+    assert_eq!((own.advertisement.node_id, own.advertisement.listen_addrs.clone()), (identity.node_id, vec![p2p_addr]));
+
+    // ...and, being short of connections, the node dials the genuine candidate.
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(_, WireMessage::Membership(MembershipMessage::Neighbor { .. })))
+    }).await;
+    let TransportCommand::SendMessage(addr, _) = command else { unreachable!() };
+    assert_eq!(addr, advertised_addr, "Only the genuine advertisement should be dialed");
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_routes_to_announced_address_once_authenticated_there() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        identity_path: temp_dir.path().join("id.key"),
+        anti_entropy_interval_ms: 100,
+        ..Config::default()
+    };
+    let EngineHarness {
+        _transport_rx: mut transport_rx,
+        inbound_tx,
+        _conn_event_tx: conn_event_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(config);
+
+    // The peer connects from one address, and its handshake announces another.
+    let peer = Identity::new();
+    let source_addr: SocketAddr = "127.0.0.1:49152".parse().unwrap();
+    let listen_addr: SocketAddr = "127.0.0.1:5041".parse().unwrap();
+    conn_event_tx.send(ConnectionEvent::PeerConnected {
+        peer_addr: source_addr,
+        peer_id: peer.node_id,
+        listen_addrs: vec!["0.0.0.0:5041".parse().unwrap(), listen_addr],
+        direction: Direction::Inbound,
+    }).await.unwrap();
+    let command = next_command_matching(&mut transport_rx, |command| matches!(command, TransportCommand::Connect(_))).await;
+    assert!(matches!(command, TransportCommand::Connect(addr) if addr == listen_addr), "The announced address should be dialed");
+
+    // Until that dial authenticates the peer, nothing is sent to the announced address.
+    inbound_tx.send(InboundMessage {
+        peer_addr: source_addr,
+        peer_id: peer.node_id,
+        message: WireMessage::Probe(ProbeMessage::Ping { seq: 7, status_updates: Vec::new() }),
+    }).await.unwrap();
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(_, WireMessage::Probe(ProbeMessage::Ack { .. })))
+    }).await;
+    assert!(matches!(command, TransportCommand::SendMessage(addr, _) if addr == source_addr));
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(_, WireMessage::SyncDigest(_)))
+    }).await;
+    assert!(matches!(command, TransportCommand::SendMessage(addr, _) if addr == source_addr));
+
+    // Once it has, the peer is routed to there.
+    conn_event_tx.send(ConnectionEvent::PeerConnected {
+        peer_addr: listen_addr,
+        peer_id: peer.node_id,
+        listen_addrs: vec![listen_addr],
+        direction: Direction::Outbound,
+    }).await.unwrap();
+    next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(addr, WireMessage::SyncDigest(_)) if *addr == listen_addr)
+    }).await;
+    wait_for_state_change(&mut state_rx, |state| state.active_connections.contains(&peer.node_id)).await;
+
+    // A later inbound connection does not take the route back, and replies to
+    // messages arriving over it go to the announced address too.
+    let other_source: SocketAddr = "127.0.0.1:49153".parse().unwrap();
+    conn_event_tx.send(ConnectionEvent::PeerConnected {
+        peer_addr: other_source,
+        peer_id: peer.node_id,
+        listen_addrs: vec![listen_addr],
+        direction: Direction::Inbound,
+    }).await.unwrap();
+    inbound_tx.send(InboundMessage {
+        peer_addr: other_source,
+        peer_id: peer.node_id,
+        message: WireMessage::Probe(ProbeMessage::Ping { seq: 8, status_updates: Vec::new() }),
+    }).await.unwrap();
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(_, WireMessage::Probe(ProbeMessage::Ack { seq: 8, .. })))
+    }).await;
+    assert!(matches!(command, TransportCommand::SendMessage(addr, _) if addr == listen_addr));
+    inbound_tx.send(InboundMessage {
+        peer_addr: other_source,
+        peer_id: peer.node_id,
+        message: WireMessage::SyncDigest(Vec::new()),
+    }).await.unwrap();
+    let command = next_command_matching(&mut transport_rx, |command| {
+        matches!(command, TransportCommand::SendMessage(_, WireMessage::SyncReply(_)))
+    }).await;
+    assert!(matches!(command, TransportCommand::SendMessage(addr, _) if addr == listen_addr));
+
+    shutdown_token.cancel();
+}
+
+#[test(tokio::test)]
+async fn test_engine_strikes_peer_relaying_invalid_signature_and_publishes_bans() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config { identity_path: temp_dir.path().join("id.key"), ..Config::default() };
+    let EngineHarness {
+        _transport_rx: mut transport_rx,
+        inbound_tx,
+        _conn_event_tx: conn_event_tx,
+        mut state_rx,
+        shutdown_token,
+        ..
+    } = setup_engine_harness(config);
+
+    // A relaying peer passes on a message whose payload was altered after signing.
+    let (originator, relayer) = (Identity::new(), Identity::new());
+    let mut forged = create_test_message(&originator, now_ms());
+    forged.message.payload = Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(-1.0, "percent")));
+    inbound_tx.send(InboundMessage {
+        peer_addr: "127.0.0.1:5051".parse().unwrap(),
+        peer_id: relayer.node_id,
+        message: forged.into(),
+    }).await.unwrap();
+
+    // The strike goes against the relaying peer, not the claimed originator.
+    let command = next_command_matching(&mut transport_rx, |command| matches!(command, TransportCommand::Strike { .. })).await;
+    let TransportCommand::Strike { peer_id, addr, reason } = command else { unreachable!() };
+    assert_eq!((peer_id, addr, reason), (relayer.node_id, "127.0.0.1".parse().unwrap(), BanReason::InvalidSignature));
+
+    // The bans the transport reports are published as they are.
+    let ban = Ban { target: BanTarget::Node(relayer.node_id), reason, expires_at_ms: now_ms() + 60_000 };
+    conn_event_tx.send(ConnectionEvent::Bans(vec![ban.clone()])).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.bans == vec![ban.clone()]).await;
+    conn_event_tx.send(ConnectionEvent::Bans(Vec::new())).await.unwrap();
+    wait_for_state_change(&mut state_rx, |state| state.bans.is_empty()).await;
+
+    shutdown_token.cancel();
+}
@@ -2,4 +2,5 @@
 //!
 //! Declares modules for component-level tests.
 
-mod engine;
\ No newline at end of file
+mod engine;
+mod signing;
\ No newline at end of file
@@ -0,0 +1,149 @@
+//! tests/component/signing.rs
+//!
+//! Checks the canonical signing encodings of payloads, peer advertisements and
+//! handshake transcripts against the published test vectors in
+//! `tests/vectors/`, which other implementations test against too, as well as
+//! the telemetry encoding of protocol version 1 still accepted during a rollout.
+
+use gossip_network::{
+    domain::{
+        GossipPayload, GossipPayloadV1, Identity, NodeId, Payload, PeerAdvertisement, SignedMessage,
+        SignedPeerAdvertisement, SigningEncoding, TelemetryV1, Version, PAYLOAD_SIGNING_CONTEXT,
+        PEER_ADVERTISEMENT_SIGNING_CONTEXT, TELEMETRY_SIGNING_CONTEXT,
+    },
+    transport::handshake::{self, Role, HANDSHAKE_LABEL},
+};
+use serde_json::Value;
+use std::net::SocketAddr;
+
+const VECTORS: &str = include_str!("../vectors/payload_signing_v1.json");
+const ADVERTISEMENT_VECTORS: &str = include_str!("../vectors/peer_advertisement_signing_v1.json");
+const HANDSHAKE_VECTORS: &str = include_str!("../vectors/handshake_transcript_v2.json");
+const TELEMETRY_V1_VECTORS: &str = include_str!("../vectors/telemetry_signing_v1.json");
+
+fn hex_field(vector: &Value, field: &str) -> Vec<u8> {
+    hex::decode(vector[field].as_str().unwrap()).unwrap()
+}
+
+fn payload(vector: &Value) -> GossipPayload {
+    let payload = &vector["payload"];
+    GossipPayload {
+        timestamp_ms: payload["timestamp_ms"].as_u64().unwrap(),
+        community_id: payload["community_id"].as_u64().unwrap() as u32,
+        version: Version { epoch: payload["epoch"].as_u64().unwrap(), sequence: payload["sequence"].as_u64().unwrap() },
+        payload: Payload { kind: payload["kind"].as_str().unwrap().to_string(), data: hex_field(payload, "data") },
+    }
+}
+
+fn identity(vector: &Value) -> Identity {
+    let secret_key: [u8; 32] = hex_field(vector, "secret_key").try_into().unwrap();
+    let identity = Identity::from_secret_key(secret_key);
+    assert_eq!(identity.node_id.0.to_vec(), hex_field(vector, "node_id"));
+    identity
+}
+
+fn listen_addrs(addrs: &Value) -> Vec<SocketAddr> {
+    addrs.as_array().unwrap().iter().map(|addr| addr.as_str().unwrap().parse().unwrap()).collect()
+}
+
+#[test]
+fn test_canonical_signing_encoding_matches_published_vectors() {
+    let document: Value = serde_json::from_str(VECTORS).unwrap();
+    assert_eq!(document["context"].as_str().unwrap().as_bytes(), PAYLOAD_SIGNING_CONTEXT);
+
+    let vectors = document["vectors"].as_array().unwrap();
+    assert!(!vectors.is_empty());
+    for vector in vectors {
+        let description = vector["description"].as_str().unwrap();
+        let secret_key: [u8; 32] = hex_field(vector, "secret_key").try_into().unwrap();
+        let identity = Identity::from_secret_key(secret_key);
+        assert_eq!(identity.node_id.0.to_vec(), hex_field(vector, "node_id"), "{}", description);
+
+        let payload = payload(vector);
+        assert_eq!(payload.signing_bytes(), hex_field(vector, "signing_bytes"), "{}", description);
+
+        // Ed25519 signatures are deterministic, so signing reproduces the vector.
+        let signed = identity.sign(payload.clone());
+        assert_eq!(signed.signature.to_bytes().to_vec(), hex_field(vector, "signature"), "{}", description);
+
+        // And a signature produced elsewhere verifies here.
+        let signature: [u8; 64] = hex_field(vector, "signature").try_into().unwrap();
+        let node_id: [u8; 32] = hex_field(vector, "node_id").try_into().unwrap();
+        let received = SignedMessage { message: payload, originator: NodeId(node_id), signature: signature.into() };
+        assert!(received.verify().is_ok(), "{}", description);
+    }
+}
+
+#[test]
+fn test_version_1_telemetry_signatures_verify_in_compatibility_mode() {
+    let document: Value = serde_json::from_str(TELEMETRY_V1_VECTORS).unwrap();
+    assert_eq!(document["context"].as_str().unwrap().as_bytes(), TELEMETRY_SIGNING_CONTEXT);
+
+    let vectors = document["vectors"].as_array().unwrap();
+    assert!(!vectors.is_empty());
+    for vector in vectors {
+        let description = vector["description"].as_str().unwrap();
+        let identity = identity(vector);
+        let fields = &vector["payload"];
+        let value_bits = u64::from_str_radix(fields["value_bits"].as_str().unwrap(), 16).unwrap();
+        let payload = GossipPayloadV1 {
+            telemetry: TelemetryV1 { timestamp_ms: fields["timestamp_ms"].as_u64().unwrap(), value: f64::from_bits(value_bits) },
+            community_id: fields["community_id"].as_u64().unwrap() as u32,
+            version: Version { epoch: fields["epoch"].as_u64().unwrap(), sequence: fields["sequence"].as_u64().unwrap() },
+        };
+        assert_eq!(payload.signing_bytes(), hex_field(vector, "signing_bytes"), "{}", description);
+        assert_eq!(identity.sign_bytes(&payload.signing_bytes()).to_bytes().to_vec(), hex_field(vector, "signature"), "{}", description);
+
+        // Carried as a telemetry payload, the signature verifies only in compatibility mode.
+        let signature: [u8; 64] = hex_field(vector, "signature").try_into().unwrap();
+        let received =
+            SignedMessage { message: payload.try_into().unwrap(), originator: identity.node_id, signature: signature.into() };
+        assert!(received.verify_with(false).is_err(), "{}", description);
+        assert_eq!(received.verify_with(true).unwrap(), SigningEncoding::TelemetryV1, "{}", description);
+    }
+}
+
+#[test]
+fn test_peer_advertisement_encoding_matches_published_vectors() {
+    let document: Value = serde_json::from_str(ADVERTISEMENT_VECTORS).unwrap();
+    assert_eq!(document["context"].as_str().unwrap().as_bytes(), PEER_ADVERTISEMENT_SIGNING_CONTEXT);
+
+    for vector in document["vectors"].as_array().unwrap() {
+        let description = vector["description"].as_str().unwrap();
+        let identity = identity(vector);
+        let fields = &vector["advertisement"];
+        let listen_addrs = listen_addrs(&fields["listen_addrs"]);
+        let version = Version { epoch: fields["epoch"].as_u64().unwrap(), sequence: fields["sequence"].as_u64().unwrap() };
+
+        let signed = identity.advertise(listen_addrs.clone(), version);
+        assert_eq!(signed.advertisement.signing_bytes(), hex_field(vector, "signing_bytes"), "{}", description);
+        assert_eq!(signed.signature.to_bytes().to_vec(), hex_field(vector, "signature"), "{}", description);
+
+        let signature: [u8; 64] = hex_field(vector, "signature").try_into().unwrap();
+        let advertisement = PeerAdvertisement { node_id: identity.node_id, listen_addrs, version };
+        let received = SignedPeerAdvertisement { advertisement, signature: signature.into() };
+        assert!(received.verify().is_ok(), "{}", description);
+    }
+}
+
+#[test]
+fn test_handshake_transcript_matches_published_vectors() {
+    let document: Value = serde_json::from_str(HANDSHAKE_VECTORS).unwrap();
+    assert_eq!(document["label"].as_str().unwrap().as_bytes(), HANDSHAKE_LABEL);
+
+    for vector in document["vectors"].as_array().unwrap() {
+        let description = vector["description"].as_str().unwrap();
+        let identity = identity(vector);
+        let role = match vector["role"].as_str().unwrap() {
+            "initiator" => Role::Initiator,
+            _ => Role::Responder,
+        };
+        let binding: [u8; 32] = hex_field(vector, "session_binding").try_into().unwrap();
+
+        let transcript = handshake::transcript(&binding, role, &listen_addrs(&vector["listen_addrs"]));
+        assert_eq!(transcript, hex_field(vector, "transcript"), "{}", description);
+        let signature = identity.sign_bytes(&transcript);
+        assert_eq!(signature.to_bytes().to_vec(), hex_field(vector, "signature"), "{}", description);
+        assert!(identity.node_id.verify(&transcript, &signature).is_ok(), "{}", description);
+    }
+}
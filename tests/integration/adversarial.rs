@@ -4,11 +4,15 @@
 
 use gossip_network::{
     config::Config,
-    domain::{GossipPayload, Identity, NetworkState, SignedMessage, TelemetryData},
+    domain::{GossipPayload, Identity, Metric, NetworkState, Payload, SignedMessage, TelemetryData, Version},
     engine::Engine,
-    transport::{ConnectionEvent, InboundMessage, TransportCommand},
+    transport::{ConnectionEvent, Direction, InboundMessage, TransportCommand, WireMessage},
+};
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use std::{net::SocketAddr, time::Duration};
 use test_log::test;
 use tokio::sync::{broadcast, mpsc, watch};
 use tokio::time;
@@ -71,10 +75,24 @@ fn setup_engine_harness(config: Config) -> EngineHarness {
     }
 }
 
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+/// Wraps `message` as if it had been delivered directly by its originator.
+fn direct_message(peer_addr: SocketAddr, message: SignedMessage) -> InboundMessage {
+    InboundMessage { peer_addr, peer_id: message.originator, message: message.into() }
+}
+
+/// Every test message gets a fresh, increasing sequence number, so messages from
+/// the same identity are always ordered by creation.
 fn create_test_message(identity: &Identity, timestamp_ms: u64) -> SignedMessage {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
     identity.sign(GossipPayload {
-        telemetry: TelemetryData { timestamp_ms, value: 42.0 },
+        timestamp_ms,
         community_id: 1,
+        version: Version { epoch: 1, sequence: SEQUENCE.fetch_add(1, Ordering::Relaxed) },
+        payload: Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(42.0, "percent"))),
     })
 }
 
@@ -83,41 +101,62 @@ async fn test_engine_prunes_stale_nodes_from_all_maps() {
     let temp_dir = tempfile::tempdir().unwrap();
     let config = Config {
         identity_path: temp_dir.path().join("id.key"),
-        node_ttl_ms: 1000,
+        node_ttl_ms: 200,
+        cleanup_interval_ms: 50,
         ..Config::default()
     };
     let mut harness = setup_engine_harness(config);
-    time::pause();
+    let self_id = harness._identity.node_id;
+    // Let the engine publish its own telemetry first so the initial gossip tick
+    // cannot race with the messages injected below.
+    wait_for_state_change(&mut harness, |state| state.nodes.contains_key(&self_id)).await;
 
     let peer_identity = Identity::new();
     let peer_addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
-    let message = create_test_message(&peer_identity, time::Instant::now().elapsed().as_millis() as u64);
-
-    harness.inbound_tx.send(InboundMessage { peer_addr, message }).await.unwrap();
-
-    wait_for_state_change(&mut harness, |state| state.nodes.len() == 1).await;
-    // MODIFICATION: Introduce a scope to limit the lifetime of the `state` borrow.
-    {
-        let state = harness.state_rx.borrow();
-        assert!(state.nodes.contains_key(&peer_identity.node_id), "Peer should be added to state");
-    } // `state` is dropped here, releasing the immutable borrow.
+    let message = create_test_message(&peer_identity, now_ms());
 
-    harness.inbound_tx.send(InboundMessage {
-        peer_addr: "127.0.0.1:9999".parse().unwrap(),
-        message: create_test_message(&Identity::new(), 0)
+    harness._conn_event_tx.send(ConnectionEvent::PeerConnected {
+        peer_addr,
+        peer_id: peer_identity.node_id,
+        listen_addrs: Vec::new(),
+        direction: Direction::Inbound,
     }).await.unwrap();
-    assert!(harness.transport_rx.try_recv().is_ok(), "Engine should know peer address to gossip");
+    harness.inbound_tx.send(direct_message(peer_addr, message)).await.unwrap();
 
-    time::advance(Duration::from_secs(62)).await;
+    wait_for_state_change(&mut harness, |state| state.nodes.contains_key(&peer_identity.node_id)).await;
 
-    wait_for_state_change(&mut harness, |state| state.nodes.is_empty()).await;
-    let final_state = harness.state_rx.borrow();
-    assert!(final_state.nodes.is_empty(), "Stale peer should be pruned from node_info");
-    
-    harness.inbound_tx.send(InboundMessage {
-        peer_addr: "127.0.0.1:9999".parse().unwrap(),
-        message: create_test_message(&Identity::new(), 0)
+    let trigger_identity = Identity::new();
+    harness.inbound_tx.send(direct_message(
+        "127.0.0.1:9999".parse().unwrap(),
+        create_test_message(&trigger_identity, now_ms()),
+    )).await.unwrap();
+    let command = time::timeout(Duration::from_secs(1), harness.transport_rx.recv()).await
+        .expect("Engine should know peer address to gossip")
+        .unwrap();
+    let TransportCommand::SendMessage(addr, _) = command else {
+        panic!("Expected a gossip message");
+    };
+    assert_eq!(addr, peer_addr);
+
+    harness._conn_event_tx.send(ConnectionEvent::PeerDisconnected {
+        peer_addr,
+        peer_id: peer_identity.node_id,
+        direction: Direction::Inbound,
     }).await.unwrap();
+    wait_for_state_change(&mut harness, |state| {
+        !state.nodes.contains_key(&peer_identity.node_id)
+            && !state.nodes.contains_key(&trigger_identity.node_id)
+    }).await;
+    {
+        let final_state = harness.state_rx.borrow();
+        assert_eq!(final_state.nodes.len(), 1, "Stale peers should be pruned from node_info");
+    }
+
+    harness.inbound_tx.send(direct_message(
+        "127.0.0.1:9999".parse().unwrap(),
+        create_test_message(&Identity::new(), now_ms()),
+    )).await.unwrap();
+    time::sleep(Duration::from_millis(20)).await;
     assert!(harness.transport_rx.try_recv().is_err(), "Engine should not gossip to a pruned peer");
 
     harness.shutdown_token.cancel();
@@ -133,58 +172,68 @@ async fn test_engine_state_freeze_via_timestamp_attack() {
     let attacker_addr: SocketAddr = "127.0.0.1:6666".parse().unwrap();
 
     let future_message = create_test_message(&attacker_identity, u64::MAX);
-    harness.inbound_tx.send(InboundMessage { peer_addr: attacker_addr, message: future_message }).await.unwrap();
+    harness.inbound_tx.send(direct_message(attacker_addr, future_message)).await.unwrap();
 
-    wait_for_state_change(&mut harness, |state| !state.nodes.is_empty()).await;
-    {
-        let state = harness.state_rx.borrow();
-        assert_eq!(state.nodes.get(&attacker_identity.node_id).unwrap().telemetry.timestamp_ms, u64::MAX);
-    }
-    
-    let valid_message = create_test_message(&attacker_identity, 1000);
-    harness.inbound_tx.send(InboundMessage { peer_addr: attacker_addr, message: valid_message }).await.unwrap();
-    time::sleep(Duration::from_millis(10)).await;
+    let valid_timestamp_ms = now_ms();
+    let valid_message = create_test_message(&attacker_identity, valid_timestamp_ms);
+    harness.inbound_tx.send(direct_message(attacker_addr, valid_message)).await.unwrap();
 
+    wait_for_state_change(&mut harness, |state| state.nodes.contains_key(&attacker_identity.node_id)).await;
     let final_state = harness.state_rx.borrow().clone();
-    assert_eq!(final_state.nodes.get(&attacker_identity.node_id).unwrap().telemetry.timestamp_ms, u64::MAX,
-        "Engine should reject the new message as it is older than the future-dated one");
+    assert_eq!(final_state.nodes.get(&attacker_identity.node_id).unwrap().timestamp_ms, valid_timestamp_ms,
+        "Engine should reject the future-dated message and accept the current one");
+    assert_eq!(final_state.stats.rejected_future_timestamp, 1);
 
     harness.shutdown_token.cancel();
 }
 
 #[test(tokio::test)]
 async fn test_engine_routing_table_poisoning() {
-    time::pause();
     let temp_dir = tempfile::tempdir().unwrap();
     let config = Config { identity_path: temp_dir.path().join("id.key"), ..Default::default() };
     let mut harness = setup_engine_harness(config);
+    let self_id = harness._identity.node_id;
+    wait_for_state_change(&mut harness, |state| state.nodes.contains_key(&self_id)).await;
 
+    // The honest peer is directly connected, and its identity verified by the handshake.
     let honest_peer_id = Identity::new();
-    let malicious_peer_addr: SocketAddr = "127.0.0.1:6666".parse().unwrap();
+    let honest_peer_addr: SocketAddr = "127.0.0.1:5555".parse().unwrap();
+    harness._conn_event_tx.send(ConnectionEvent::PeerConnected {
+        peer_addr: honest_peer_addr,
+        peer_id: honest_peer_id.node_id,
+        listen_addrs: Vec::new(),
+        direction: Direction::Inbound,
+    }).await.unwrap();
 
-    let message_from_a = create_test_message(&honest_peer_id, 1000);
+    // A malicious peer relays the honest peer's message, hoping the engine will
+    // associate the honest peer's NodeId with the malicious address.
+    let malicious_peer_id = Identity::new();
+    let malicious_peer_addr: SocketAddr = "127.0.0.1:6666".parse().unwrap();
+    let message_from_a = create_test_message(&honest_peer_id, now_ms());
     harness.inbound_tx.send(InboundMessage {
         peer_addr: malicious_peer_addr,
-        message: message_from_a,
+        peer_id: malicious_peer_id.node_id,
+        message: message_from_a.into(),
     }).await.unwrap();
-    time::sleep(Duration::from_millis(10)).await;
+    wait_for_state_change(&mut harness, |state| state.nodes.contains_key(&honest_peer_id.node_id)).await;
 
     let another_peer_id = Identity::new();
     let another_peer_addr: SocketAddr = "127.0.0.1:7777".parse().unwrap();
-    let trigger_message = create_test_message(&another_peer_id, 2000);
+    let trigger_message = create_test_message(&another_peer_id, now_ms());
 
-    harness.inbound_tx.send(InboundMessage {
-        peer_addr: another_peer_addr,
-        message: trigger_message,
-    }).await.unwrap();
+    harness.inbound_tx.send(direct_message(another_peer_addr, trigger_message)).await.unwrap();
 
     let command = time::timeout(Duration::from_secs(1), harness.transport_rx.recv()).await
         .expect("Engine should have sent a gossip command")
         .unwrap();
 
-    let TransportCommand::SendMessage(addr, msg) = command;
-    assert_eq!(addr, malicious_peer_addr, "Address should be the malicious peer's address");
-    assert_eq!(msg.originator, another_peer_id.node_id, "Message should be the trigger message");
-    
+    let TransportCommand::SendMessage(addr, WireMessage::Gossip(msg)) = command else {
+        panic!("Expected a gossip message");
+    };
+    assert_eq!(addr, honest_peer_addr, "Relayed messages must not poison the routing table");
+    assert_eq!(msg.message.originator, another_peer_id.node_id, "Message should be the trigger message");
+    time::sleep(Duration::from_millis(20)).await;
+    assert!(harness.transport_rx.try_recv().is_err(), "Engine should not gossip to the relaying peer's address");
+
     harness.shutdown_token.cancel();
-}
\ No newline at end of file
+}
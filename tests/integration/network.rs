@@ -5,7 +5,21 @@
 //! propagated through the gossip protocol on the "happy path".
 
 use crate::common::harness::{self, TestNode};
-use std::time::Duration;
+use gossip_network::{
+    config::TlsMode,
+    domain::{
+        BanReason, BanTarget, DialState, GossipPayload, GossipPayloadV1, Identity, Metric, NetworkState, NodeId, Payload,
+        TelemetryData, TelemetryV1, Version,
+    },
+    transport::{
+        connection, envelope,
+        handshake::{self, LocalNode},
+        tls, WireMessage,
+    },
+};
+use ed25519_dalek::Signature;
+use quinn::ConnectionError;
+use std::{net::SocketAddr, time::Duration};
 use test_log::test;
 
 #[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
@@ -39,10 +53,505 @@ async fn test_state_propagation_across_two_nodes() {
 
         assert_eq!(final_state.nodes.len(), 2, "Node B should know about 2 nodes");
 
+        // Each node's telemetry metrics arrive over the WebSocket with their units and labels.
+        for info in final_state.nodes.values() {
+            assert_eq!(info.payloads.keys().collect::<Vec<_>>(), vec!["telemetry"]);
+            let telemetry: TelemetryData = serde_json::from_value(info.payloads["telemetry"].clone()).unwrap();
+            assert_eq!(telemetry.metrics["cpu"].unit, "percent");
+            assert_eq!(telemetry.metrics["latency_p99"].labels["quantile"], "0.99");
+        }
+
         // --- 6. Cleanup ---
         node_a.shutdown();
         node_b.shutdown();
     }).await;
 
     assert!(result.is_ok(), "Test timed out");
-}
\ No newline at end of file
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_identity_tls_authenticates_peers_by_node_id() {
+    let test_timeout = Duration::from_secs(15);
+    let result = tokio::time::timeout(test_timeout, async {
+        // Identity mode ignores the CA material, so the certificates on disk are
+        // never used; each node presents a certificate derived from its own key.
+        let unused_certs = harness::generate_certs("localhost");
+        let identity_tls = |allowed_peers| {
+            move |config: &mut gossip_network::Config| {
+                config.tls.mode = TlsMode::Identity;
+                config.tls.client_auth = true;
+                config.tls.allowed_peers = allowed_peers;
+            }
+        };
+
+        let node_a = TestNode::spawn_with(vec![], &unused_certs, identity_tls(vec![]))
+            .await
+            .expect("Failed to spawn node A");
+        let mut ws_client_a = node_a.ws_client().await.expect("Failed to connect ws client to A");
+        let state_a = harness::wait_for_state(&mut ws_client_a, |_| true, Duration::from_secs(2))
+            .await
+            .expect("Failed to observe node A's state");
+        let node_a_id = state_a.self_id.unwrap();
+
+        // Node B only trusts Node A, and so joins the network.
+        let node_b = TestNode::spawn_with(vec![node_a.p2p_addr], &unused_certs, identity_tls(vec![node_a_id]))
+            .await
+            .expect("Failed to spawn node B");
+        let mut ws_client_b = node_b.ws_client().await.expect("Failed to connect ws client to B");
+        harness::wait_for_state(&mut ws_client_b, |state| state.nodes.contains_key(&node_a_id), Duration::from_secs(5))
+            .await
+            .expect("Node B should accept Node A's identity certificate");
+
+        // Node C trusts someone else entirely, and so refuses Node A's certificate.
+        let node_c = TestNode::spawn_with(vec![node_a.p2p_addr], &unused_certs, identity_tls(vec![Identity::new().node_id]))
+            .await
+            .expect("Failed to spawn node C");
+        let mut ws_client_c = node_c.ws_client().await.expect("Failed to connect ws client to C");
+        let joined = harness::wait_for_state(&mut ws_client_c, |state| state.nodes.contains_key(&node_a_id), Duration::from_secs(2)).await;
+        assert!(joined.is_err(), "Node C should reject a peer outside its allowlist");
+
+        node_a.shutdown();
+        node_b.shutdown();
+        node_c.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_identity_tls_binds_client_certificates_to_handshake_identity() {
+    let result = tokio::time::timeout(Duration::from_secs(15), async {
+        let unused_certs = harness::generate_certs("localhost");
+        let trusted = Identity::new();
+        let trusted_id = trusted.node_id;
+        // Client authentication is left off; identity mode requires it regardless.
+        let node = TestNode::spawn_with(vec![], &unused_certs, move |config| {
+            config.tls.mode = TlsMode::Identity;
+            config.tls.allowed_peers = vec![trusted_id];
+        })
+        .await
+        .unwrap();
+
+        // Connects with a certificate derived from `cert_identity`, then proves
+        // `handshake_identity`, and reports how the node closed the connection.
+        let connect = |cert_identity: Identity, handshake_identity: Identity| async move {
+            let tls_config = gossip_network::config::TlsConfig { mode: TlsMode::Identity, ..Default::default() };
+            let (_, client_config) = tls::configure_tls(&tls_config, &cert_identity).unwrap();
+            let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+            endpoint.set_default_client_config(client_config);
+            let conn = match endpoint.connect(node.p2p_addr, "localhost").unwrap().await {
+                Ok(conn) => conn,
+                Err(e) => return Some(e),
+            };
+            let peer = LocalNode { identity: handshake_identity, listen_addrs: Vec::new(), tls_mode: TlsMode::Identity, accept_legacy: false };
+            let _ = handshake::initiate(&conn, &peer).await;
+            tokio::time::timeout(Duration::from_secs(1), conn.closed()).await.ok()
+        };
+
+        assert!(connect(trusted.clone(), trusted.clone()).await.is_none(), "An allowed peer should be let in");
+        assert!(
+            connect(Identity::new(), Identity::new()).await.is_some(),
+            "The allowlist should apply to inbound connections"
+        );
+        let mismatch = connect(trusted.clone(), Identity::new()).await;
+        assert!(
+            matches!(&mismatch, Some(ConnectionError::ApplicationClosed(close)) if close.error_code == connection::HANDSHAKE_FAILED),
+            "A handshake proving another identity than the certificate should fail, got {mismatch:?}"
+        );
+
+        node.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_mutual_tls_rejects_clients_from_untrusted_ca() {
+    let test_timeout = Duration::from_secs(15);
+    let result = tokio::time::timeout(test_timeout, async {
+        // Two independent trust domains.
+        let trusted_certs = harness::generate_certs("localhost");
+        let rogue_certs = harness::generate_certs("localhost");
+
+        let node_a = TestNode::spawn_with(vec![], &trusted_certs, |config| config.tls.client_auth = true)
+            .await
+            .expect("Failed to spawn node A");
+
+        // TLS 1.3 clients finish their side of the handshake before the server has
+        // checked the client certificate, so a rejection shows up as a prompt close.
+        let is_rejected = |certs: harness::CertSet| async move {
+            let endpoint = harness::create_quic_client(&certs).expect("Failed to create QUIC client");
+            match endpoint.connect(node_a.p2p_addr, "localhost").unwrap().await {
+                Err(_) => true,
+                Ok(conn) => tokio::time::timeout(Duration::from_secs(1), conn.closed()).await.is_ok(),
+            }
+        };
+
+        // A client holding a certificate from the trusted CA is let in.
+        assert!(!is_rejected(trusted_certs.clone()).await, "Node A should accept a client from its own CA");
+
+        // A client that trusts Node A's CA, but whose certificate the rogue CA issued, is not.
+        let impostor_certs = harness::CertSet {
+            ca_cert_der: trusted_certs.ca_cert_der.clone(),
+            ..rogue_certs
+        };
+        assert!(is_rejected(impostor_certs).await, "Node A should reject a client from an untrusted CA");
+
+        node_a.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_bootstrap_peer_is_redialed_after_restart() {
+    let result = tokio::time::timeout(Duration::from_secs(30), async {
+        let certs = harness::generate_certs("localhost");
+        let node_a = TestNode::spawn(vec![], &certs).await.unwrap();
+        let a_addr = node_a.p2p_addr;
+        let node_b = TestNode::spawn_with(vec![a_addr], &certs, |config| {
+            config.reconnect_initial_backoff_ms = 100;
+            config.reconnect_max_backoff_ms = 1_000;
+        })
+        .await
+        .unwrap();
+
+        let bootstrap_state = move |state: &NetworkState| {
+            state.persistent_peers.iter().find(|peer| peer.addr == a_addr).map(|peer| peer.state)
+        };
+        let mut ws_client = node_b.ws_client().await.unwrap();
+        harness::wait_for_state(
+            &mut ws_client,
+            |state| bootstrap_state(state) == Some(DialState::Connected),
+            Duration::from_secs(5),
+        ).await.expect("B should report its bootstrap peer as connected");
+
+        // B notices that A went away and starts backing off...
+        node_a.shutdown();
+        let mut ws_client = node_b.ws_client().await.unwrap();
+        harness::wait_for_state(
+            &mut ws_client,
+            |state| bootstrap_state(state).is_some_and(|dial_state| dial_state != DialState::Connected),
+            Duration::from_secs(5),
+        ).await.expect("B should notice the lost bootstrap peer");
+
+        // ...and reconnects by itself once A is back on the same address.
+        let node_a_restarted = TestNode::spawn_with(vec![], &certs, |config| config.p2p_addr = a_addr).await.unwrap();
+        let mut ws_client_a = node_a_restarted.ws_client().await.unwrap();
+        let restarted_id = harness::wait_for_state(
+            &mut ws_client_a,
+            |state| state.self_id.is_some(),
+            Duration::from_secs(5),
+        ).await.unwrap().self_id.unwrap();
+        let mut ws_client = node_b.ws_client().await.unwrap();
+        harness::wait_for_state(
+            &mut ws_client,
+            |state| bootstrap_state(state) == Some(DialState::Connected) && state.nodes.contains_key(&restarted_id),
+            Duration::from_secs(20),
+        ).await.expect("B should reconnect to the restarted bootstrap peer");
+
+        node_a_restarted.shutdown();
+        node_b.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_inbound_connections_over_the_limit_are_refused() {
+    let result = tokio::time::timeout(Duration::from_secs(30), async {
+        let certs = harness::generate_certs("localhost");
+        let node_a = TestNode::spawn_with(vec![], &certs, |config| config.max_inbound_connections = 1).await.unwrap();
+        let a_addr = node_a.p2p_addr;
+        let bootstrap_state = move |state: &NetworkState| {
+            state.persistent_peers.iter().find(|peer| peer.addr == a_addr).cloned()
+        };
+
+        // B takes A's only inbound slot.
+        let node_b = TestNode::spawn(vec![a_addr], &certs).await.unwrap();
+        let mut ws_client_b = node_b.ws_client().await.unwrap();
+        let b_id = harness::wait_for_state(
+            &mut ws_client_b,
+            |state| bootstrap_state(state).is_some_and(|peer| peer.state == DialState::Connected),
+            Duration::from_secs(5),
+        ).await.expect("B should connect to A").self_id.unwrap();
+
+        // C is turned away, and keeps backing off rather than connecting.
+        let node_c = TestNode::spawn_with(vec![a_addr], &certs, |config| {
+            config.reconnect_initial_backoff_ms = 100;
+            config.reconnect_max_backoff_ms = 500;
+        })
+        .await
+        .unwrap();
+        let mut ws_client_c = node_c.ws_client().await.unwrap();
+        let state_c = harness::wait_for_state(
+            &mut ws_client_c,
+            |state| bootstrap_state(state).is_some_and(|peer| peer.failures >= 2),
+            Duration::from_secs(10),
+        ).await.expect("C's connection attempts should be refused");
+        assert!(state_c.active_connections.is_empty());
+
+        let mut ws_client_a = node_a.ws_client().await.unwrap();
+        let state_a = harness::wait_for_state(
+            &mut ws_client_a,
+            |state| state.active_connections.contains(&b_id),
+            Duration::from_secs(5),
+        ).await.unwrap();
+        assert_eq!(state_a.active_connections.len(), 1, "Only B should be connected to A");
+
+        node_a.shutdown();
+        node_b.shutdown();
+        node_c.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_misbehaving_peer_is_banned_until_unbanned_through_api() {
+    let result = tokio::time::timeout(Duration::from_secs(30), async {
+        let certs = harness::generate_certs("localhost");
+        let node = TestNode::spawn_with(vec![], &certs, |config| config.ban_strike_threshold = 3).await.unwrap();
+        let client = harness::create_quic_client(&certs).unwrap();
+        let peer = LocalNode { identity: Identity::new(), listen_addrs: Vec::new(), tls_mode: TlsMode::Pki, accept_legacy: false };
+        let peer_id = peer.identity.node_id;
+        let connect = || async {
+            let conn = client.connect(node.p2p_addr, "localhost").unwrap().await.unwrap();
+            handshake::initiate(&conn, &peer).await.unwrap();
+            conn
+        };
+        let closed_as_banned = |reason: ConnectionError| {
+            matches!(reason, ConnectionError::ApplicationClosed(close) if close.error_code == connection::BANNED)
+        };
+
+        // An authenticated peer sends streams that are not wire messages.
+        let conn = connect().await;
+        for _ in 0..3 {
+            let mut send = conn.open_uni().await.unwrap();
+            send.write_all(b"not a wire message").await.unwrap();
+            send.finish().await.unwrap();
+        }
+        assert!(closed_as_banned(conn.closed().await), "The third strike should ban the peer");
+
+        let mut ws_client = node.ws_client().await.unwrap();
+        let state = harness::wait_for_state(&mut ws_client, |state| !state.bans.is_empty(), Duration::from_secs(5))
+            .await
+            .expect("The ban should be published");
+        assert_eq!((state.bans[0].target, state.bans[0].reason), (BanTarget::Node(peer_id), BanReason::MalformedMessage));
+        assert!(closed_as_banned(connect().await.closed().await), "A banned peer should be refused");
+
+        // Lifting the ban through the API takes the admin token, and lets the peer back in.
+        let path = format!("/api/bans/{}", hex::encode(peer_id.0));
+        assert_eq!(node.http_request_with_token("DELETE", &path, None).await.unwrap(), 401);
+        assert_eq!(node.http_request_with_token("DELETE", &path, Some("wrong")).await.unwrap(), 401);
+        let status = node.http_request("DELETE", &format!("/api/bans/{}", hex::encode(peer_id.0))).await.unwrap();
+        assert_eq!(status, 202);
+        let mut ws_client = node.ws_client().await.unwrap();
+        harness::wait_for_state(&mut ws_client, |state| state.bans.is_empty(), Duration::from_secs(5))
+            .await
+            .expect("The ban should be lifted");
+        let conn = connect().await;
+        let mut ws_client = node.ws_client().await.unwrap();
+        harness::wait_for_state(
+            &mut ws_client,
+            |state| state.active_connections.contains(&peer_id),
+            Duration::from_secs(5),
+        ).await.expect("The unbanned peer should be connected");
+
+        // Banning its address through the API closes the connection at its next stream.
+        let status = node.http_request("POST", "/api/bans/127.0.0.1?duration_ms=60000").await.unwrap();
+        assert_eq!(status, 202);
+        let mut ws_client = node.ws_client().await.unwrap();
+        harness::wait_for_state(&mut ws_client, |state| !state.bans.is_empty(), Duration::from_secs(5))
+            .await
+            .expect("The address ban should be published");
+        conn.open_uni().await.unwrap().finish().await.unwrap();
+        assert!(closed_as_banned(conn.closed().await), "A peer at a banned address should be disconnected");
+        assert_eq!(node.http_request("POST", "/api/bans/not-a-target").await.unwrap(), 400);
+
+        node.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_unknown_message_types_and_incompatible_versions_are_not_struck() {
+    let result = tokio::time::timeout(Duration::from_secs(20), async {
+        let certs = harness::generate_certs("localhost");
+        let node = TestNode::spawn_with(vec![], &certs, |config| config.ban_strike_threshold = 1).await.unwrap();
+        let client = harness::create_quic_client(&certs).unwrap();
+        let peer = LocalNode { identity: Identity::new(), listen_addrs: Vec::new(), tls_mode: TlsMode::Pki, accept_legacy: false };
+        let conn = client.connect(node.p2p_addr, "localhost").unwrap().await.unwrap();
+        handshake::initiate(&conn, &peer).await.unwrap();
+        let frame = |major: u8, minor: u8, message_type: u16, body: &[u8]| {
+            let mut bytes = envelope::MAGIC.to_vec();
+            bytes.extend_from_slice(&[major, minor]);
+            bytes.extend_from_slice(&message_type.to_be_bytes());
+            bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(body);
+            bytes
+        };
+
+        // A type from a newer minor version, and a message from an incompatible
+        // major version, are dropped without striking the peer.
+        let version = envelope::PROTOCOL_VERSION;
+        for bytes in [
+            frame(version.major, version.minor + 1, 0xbeef, b"from the future"),
+            frame(version.major + 1, 0, envelope::MessageType::SyncDigest.tag(), b"unreadable"),
+            envelope::encode(&WireMessage::SyncDigest(Vec::new()), envelope::WireFormat::Bincode).unwrap(),
+        ] {
+            let mut send = conn.open_uni().await.unwrap();
+            send.write_all(&bytes).await.unwrap();
+            send.finish().await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(conn.close_reason().is_none(), "Tolerated messages should not get the peer banned");
+
+        // Whereas a single malformed envelope does, with a strike threshold of one.
+        let mut send = conn.open_uni().await.unwrap();
+        send.write_all(&frame(version.major, 0, envelope::MessageType::SyncDigest.tag(), b"x")).await.unwrap();
+        send.finish().await.unwrap();
+        assert!(matches!(
+            conn.closed().await,
+            ConnectionError::ApplicationClosed(close) if close.error_code == connection::BANNED
+        ));
+
+        node.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_bincode_and_protobuf_peers_interoperate_in_a_mixed_cluster() {
+    let result = tokio::time::timeout(Duration::from_secs(20), async {
+        let certs = harness::generate_certs("localhost");
+        // Node A offers both wire formats, as upgraded nodes do by default.
+        let node_a = TestNode::spawn(vec![], &certs).await.unwrap();
+        // Node B predates protobuf and only speaks bincode.
+        let node_b = TestNode::spawn_with(vec![node_a.p2p_addr], &certs, |config| {
+            config.tls.alpn_protocols = vec![envelope::ALPN_BINCODE.to_string()];
+        })
+        .await
+        .unwrap();
+        let mut ws_client_b = node_b.ws_client().await.unwrap();
+        harness::wait_for_state(&mut ws_client_b, |state| state.nodes.len() == 2, Duration::from_secs(5))
+            .await
+            .expect("Node B should exchange telemetry with node A over bincode");
+
+        // A client preferring protobuf gets it from node A.
+        let client = harness::create_quic_client_with_alpn(&certs, &[envelope::ALPN_PROTOBUF, envelope::ALPN_BINCODE]).unwrap();
+        let conn = client.connect(node_a.p2p_addr, "localhost").unwrap().await.unwrap();
+        let protocol = conn
+            .handshake_data()
+            .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+            .and_then(|data| data.protocol);
+        assert_eq!(protocol.as_deref(), Some(envelope::ALPN_PROTOBUF.as_bytes()));
+        let peer = LocalNode { identity: Identity::new(), listen_addrs: Vec::new(), tls_mode: TlsMode::Pki, accept_legacy: false };
+        handshake::initiate(&conn, &peer).await.unwrap();
+
+        // Its protobuf-encoded telemetry reaches node B, relayed by node A in bincode.
+        let timestamp_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        let message = peer.identity.sign(GossipPayload {
+            timestamp_ms,
+            community_id: 0,
+            version: Version { epoch: 1, sequence: 1 },
+            payload: Payload::new(&TelemetryData::default().with_metric("cpu", Metric::new(1.5, "percent"))),
+        });
+        let mut send = conn.open_uni().await.unwrap();
+        send.write_all(&envelope::encode(&message.into(), envelope::WireFormat::Protobuf).unwrap()).await.unwrap();
+        send.finish().await.unwrap();
+        let mut ws_client_b = node_b.ws_client().await.unwrap();
+        harness::wait_for_state(
+            &mut ws_client_b,
+            |state| state.nodes.contains_key(&peer.identity.node_id),
+            Duration::from_secs(5),
+        )
+        .await
+        .expect("Telemetry sent over protobuf should reach the bincode-only node");
+
+        node_a.shutdown();
+        node_b.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
+
+/// Runs the connection handshake as a node of protocol version 1 did, and
+/// checks that the responder answered in kind.
+async fn initiate_v1_handshake(conn: &quinn::Connection, identity: &Identity) -> Result<(), String> {
+    const LABEL: &[u8] = b"gossip-network/handshake/v1";
+    let mut binding = [0u8; 32];
+    conn.export_keying_material(&mut binding, LABEL, b"").map_err(|_| "export failed".to_string())?;
+    let transcript = |role: u8, listen_addrs: &[SocketAddr]| {
+        let mut bytes = LABEL.to_vec();
+        bytes.push(role);
+        bytes.extend_from_slice(&binding);
+        bytes.extend(bincode::serialize(listen_addrs).unwrap());
+        bytes
+    };
+    let listen_addrs: Vec<SocketAddr> = Vec::new();
+    let proof = bincode::serialize(&(identity.node_id, &listen_addrs, identity.sign_bytes(&transcript(0, &listen_addrs)))).unwrap();
+    let (mut send, mut recv) = conn.open_bi().await.map_err(|e| e.to_string())?;
+    send.write_all(&proof).await.map_err(|e| e.to_string())?;
+    send.finish().await.map_err(|e| e.to_string())?;
+    let bytes = recv.read_to_end(1_024).await.map_err(|e| e.to_string())?;
+    let (node_id, listen_addrs, signature): (NodeId, Vec<SocketAddr>, Signature) =
+        bincode::deserialize(&bytes).map_err(|e| e.to_string())?;
+    node_id.verify(&transcript(1, &listen_addrs), &signature).map_err(|e| e.to_string())
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_version_1_peers_are_accepted_during_a_rollout() {
+    let result = tokio::time::timeout(Duration::from_secs(20), async {
+        let certs = harness::generate_certs("localhost");
+        let node_a = TestNode::spawn(vec![], &certs).await.unwrap();
+        let node_b = TestNode::spawn(vec![node_a.p2p_addr], &certs).await.unwrap();
+        let client = harness::create_quic_client(&certs).unwrap();
+        let peer = Identity::new();
+        let conn = client.connect(node_a.p2p_addr, "localhost").unwrap().await.unwrap();
+        initiate_v1_handshake(&conn, &peer).await.expect("Node A should accept a version 1 handshake");
+
+        // A version 1 message, signed over the telemetry encoding and framed
+        // in an envelope of major version 1.
+        let timestamp_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        let payload = GossipPayloadV1 {
+            telemetry: TelemetryV1 { timestamp_ms, value: 21.5 },
+            community_id: 0,
+            version: Version { epoch: 1, sequence: 1 },
+        };
+        let signature = peer.sign_bytes(&payload.signing_bytes());
+        let body = bincode::serialize(&((&payload, peer.node_id, signature), 0u32, Vec::<()>::new())).unwrap();
+        let mut bytes = envelope::MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&envelope::MessageType::Gossip.tag().to_be_bytes());
+        bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&body);
+        let mut send = conn.open_uni().await.unwrap();
+        send.write_all(&bytes).await.unwrap();
+        send.finish().await.unwrap();
+
+        // Node A relays it to node B with its original signature.
+        let mut ws_client_b = node_b.ws_client().await.unwrap();
+        let state = harness::wait_for_state(
+            &mut ws_client_b,
+            |state| state.nodes.contains_key(&peer.node_id),
+            Duration::from_secs(5),
+        )
+        .await
+        .expect("The version 1 telemetry should reach node B");
+        assert_eq!(state.nodes[&peer.node_id].payloads["telemetry"], serde_json::json!({ "value": 21.5 }));
+
+        // Once the flag is off, version 1 peers are refused.
+        let node_c = TestNode::spawn_with(vec![], &certs, |config| config.accept_legacy_signatures = false).await.unwrap();
+        let conn = client.connect(node_c.p2p_addr, "localhost").unwrap().await.unwrap();
+        assert!(initiate_v1_handshake(&conn, &peer).await.is_err(), "Node C should refuse a version 1 handshake");
+
+        node_a.shutdown();
+        node_b.shutdown();
+        node_c.shutdown();
+    }).await;
+
+    assert!(result.is_ok(), "Test timed out");
+}
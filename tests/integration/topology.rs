@@ -7,17 +7,21 @@
 // partitions.
 
 use crate::common::harness::{self, TestNode};
-use std::time::Duration;
+use gossip_network::{
+    config::{PeerSelection, Propagation},
+    domain::MemberStatus,
+};
+use std::{collections::HashMap, time::Duration};
 use test_log::test;
 
 #[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
 async fn test_network_partition_and_healing() {
-    // This test creates a 3-node line topology (A <-> B <-> C) and validates:
-    // 1. State from A correctly propagates through B to C.
-    // 2. When B is shut down, the network is partitioned, and state from A
-    //    no longer reaches C.
-    // 3. When B is brought back online, the network "heals," and propagation resumes.
-    // This demonstrates the brittleness of the static peer discovery model (Issue 3.1).
+    // This test bootstraps a 3-node line topology (A <-> B <-> C) and validates:
+    // 1. State from A correctly propagates to C.
+    // 2. When B is shut down, the network is not partitioned: C's join was
+    //    forwarded to A, which added C to its active view, so C prunes only B
+    //    and keeps receiving A's state.
+    // 3. When B is brought back online, propagation includes it again.
 
     let test_timeout = Duration::from_secs(25);
     let result = tokio::time::timeout(test_timeout, async {
@@ -28,6 +32,12 @@ async fn test_network_partition_and_healing() {
         let node_b = TestNode::spawn(vec![node_a.p2p_addr], &trusted_certs).await.unwrap();
         let node_c = TestNode::spawn(vec![node_b.p2p_addr], &trusted_certs).await.unwrap();
 
+        let mut ws_client_a = node_a.ws_client().await.unwrap();
+        let node_a_id = harness::wait_for_state(
+            &mut ws_client_a,
+            |state| state.self_id.is_some(),
+            Duration::from_secs(5),
+        ).await.unwrap().self_id.unwrap();
         let mut ws_client_c = node_c.ws_client().await.unwrap();
 
         // Verify that Node C learns about all 3 nodes via gossip through B.
@@ -40,8 +50,8 @@ async fn test_network_partition_and_healing() {
         assert_eq!(initial_state.nodes.len(), 3);
         tracing::info!("Phase 1: Success. Node C sees the full network.");
 
-        // --- Phase 2: Partition the network by shutting down Node B ---
-        tracing::info!("Phase 2: Shutting down Node B to partition the network...");
+        // --- Phase 2: Shut down Node B ---
+        tracing::info!("Phase 2: Shutting down Node B...");
         node_b.shutdown();
         tokio::time::sleep(Duration::from_millis(500)).await; // Give time for connections to drop.
         
@@ -49,16 +59,20 @@ async fn test_network_partition_and_healing() {
         drop(ws_client_c);
         let mut ws_client_c_partitioned = node_c.ws_client().await.unwrap();
 
-        // Node C's state should eventually time out Node A and B. We wait for it
-        // to only know about itself. The TTL is 5 seconds in the test harness.
-        tracing::info!("Phase 2: Waiting for Node C to prune stale nodes...");
-        let partitioned_state = harness::wait_for_state(
+        // Node C's state should eventually time out Node B, while A stays alive
+        // through their direct link. The TTL is 5 seconds in the test harness.
+        tracing::info!("Phase 2: Waiting for Node C to prune Node B...");
+        let remaining_state = harness::wait_for_state(
             &mut ws_client_c_partitioned,
-            |state| state.nodes.len() == 1,
+            |state| state.nodes.len() == 2,
             Duration::from_secs(8),
-        ).await.expect("Node C should prune stale nodes A and B");
-        assert_eq!(partitioned_state.nodes.len(), 1, "Node C should only know about itself");
-        tracing::info!("Phase 2: Success. Network is partitioned.");
+        ).await.expect("Node C should prune stale Node B");
+        assert!(remaining_state.nodes.contains_key(&node_a_id), "Node C should still receive Node A's state");
+        assert!(
+            remaining_state.active_connections.contains(&node_a_id),
+            "Node C should be connected to Node A directly"
+        );
+        tracing::info!("Phase 2: Success. Network is still connected.");
 
         // --- Phase 3: Heal the network by restarting Node B ---
         tracing::info!("Phase 3: Restarting Node B to heal the network...");
@@ -89,4 +103,167 @@ async fn test_network_partition_and_healing() {
 
     }).await;
     assert!(result.is_ok(), "Test timed out");
-}
\ No newline at end of file
+}
+
+/// Spawns a fully meshed network of two communities with `nodes_per_community`
+/// nodes each, waits until every node knows its own community, and returns how
+/// many new messages the nodes then accept from the other community within
+/// `window`.
+async fn count_cross_community_deliveries(
+    peer_selection: PeerSelection,
+    nodes_per_community: usize,
+    window: Duration,
+) -> usize {
+    let certs = harness::generate_certs("localhost");
+    let mut nodes: Vec<TestNode> = Vec::new();
+    for i in 0..nodes_per_community * 2 {
+        let bootstrap = nodes.iter().map(|node| node.p2p_addr).collect();
+        let node = TestNode::spawn_with(bootstrap, &certs, |config| {
+            config.community_id = (i % 2) as u32;
+            config.peer_selection = peer_selection;
+            config.community_bridge_fraction = 0.0;
+            // A fanout of one keeps every forward within a three-node community.
+            config.gossip_factor = 1;
+            config.gossip_interval_ms = 500;
+            // Keep anti-entropy out of the measurement.
+            config.anti_entropy_interval_ms = 60_000;
+        })
+        .await
+        .unwrap();
+        nodes.push(node);
+    }
+
+    // Warm up until every node has heard from all members of its community,
+    // so that community-aware selection knows which peers are local.
+    let mut communities = HashMap::new();
+    for node in &nodes {
+        let community_id = node.config.community_id;
+        let mut ws_client = node.ws_client().await.unwrap();
+        let state = harness::wait_for_state(
+            &mut ws_client,
+            |state| state.nodes.values().filter(|info| info.community_id == community_id).count() == nodes_per_community,
+            Duration::from_secs(10),
+        ).await.expect("Node should learn about its own community");
+        communities.insert(state.self_id.unwrap(), community_id);
+    }
+
+    // Fresh clients only observe deliveries made during the window.
+    let mut ws_clients = Vec::new();
+    for node in &nodes {
+        ws_clients.push((node.ws_client().await.unwrap(), node.config.community_id));
+    }
+    let counts = futures::future::join_all(ws_clients.iter_mut().map(|(ws_client, community_id)| {
+        let communities = &communities;
+        async move {
+            harness::collect_animations(ws_client, window)
+                .await
+                .unwrap()
+                .iter()
+                .filter(|sender| communities.get(*sender) != Some(community_id))
+                .count()
+        }
+    }))
+    .await;
+
+    for node in &nodes {
+        node.shutdown();
+    }
+    counts.into_iter().sum()
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_community_peer_selection_reduces_cross_community_gossip() {
+    // Two interleaved communities of three nodes share a full mesh. With random
+    // selection, more than half of each fanout crosses to the other community.
+    // With community-aware selection and no bridges, gossip stays within the
+    // community once every node knows its neighbours.
+    let test_timeout = Duration::from_secs(50);
+    let result = tokio::time::timeout(test_timeout, async {
+        let window = Duration::from_secs(2);
+        let random = count_cross_community_deliveries(PeerSelection::Random, 3, window).await;
+        let community = count_cross_community_deliveries(PeerSelection::Community, 3, window).await;
+        tracing::info!(random, community, "Cross-community deliveries");
+
+        assert!(random >= 10, "Random selection should gossip across communities, got {random}");
+        assert!(
+            community * 5 <= random,
+            "Community selection should mostly stay local: {community} cross-community deliveries vs {random} with random selection"
+        );
+    }).await;
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_plumtree_propagates_across_line_topology() {
+    // In a line A <-> B <-> C every link is part of the spanning tree, so state
+    // from A must be pushed eagerly through B to reach C.
+    let test_timeout = Duration::from_secs(15);
+    let result = tokio::time::timeout(test_timeout, async {
+        let certs = harness::generate_certs("localhost");
+        let plumtree = |config: &mut gossip_network::Config| config.propagation = Propagation::Plumtree;
+        let node_a = TestNode::spawn_with(vec![], &certs, plumtree).await.unwrap();
+        let node_b = TestNode::spawn_with(vec![node_a.p2p_addr], &certs, plumtree).await.unwrap();
+        let node_c = TestNode::spawn_with(vec![node_b.p2p_addr], &certs, plumtree).await.unwrap();
+
+        let mut ws_client_c = node_c.ws_client().await.unwrap();
+        let state = harness::wait_for_state(
+            &mut ws_client_c,
+            |state| state.nodes.len() == 3,
+            Duration::from_secs(10),
+        ).await.expect("Node C should learn about all 3 nodes over the broadcast tree");
+        assert_eq!(state.nodes.len(), 3);
+
+        node_a.shutdown();
+        node_b.shutdown();
+        node_c.shutdown();
+    }).await;
+    assert!(result.is_ok(), "Test timed out");
+}
+
+#[test(tokio::test(flavor = "multi_thread", worker_threads = 4))]
+async fn test_swim_declares_failed_node_dead_before_ttl() {
+    // With a one-minute TTL, a crashed node would linger in the state. The
+    // failure detector notices it within a few protocol periods instead.
+    let test_timeout = Duration::from_secs(20);
+    let result = tokio::time::timeout(test_timeout, async {
+        let certs = harness::generate_certs("localhost");
+        let fast_swim = |config: &mut gossip_network::Config| {
+            config.node_ttl_ms = 60_000;
+            config.swim_probe_interval_ms = 200;
+            config.swim_probe_timeout_ms = 100;
+            config.swim_suspicion_timeout_ms = 1_000;
+        };
+        let node_a = TestNode::spawn_with(vec![], &certs, fast_swim).await.unwrap();
+        let node_b = TestNode::spawn_with(vec![node_a.p2p_addr], &certs, fast_swim).await.unwrap();
+        let node_c = TestNode::spawn_with(vec![node_a.p2p_addr], &certs, fast_swim).await.unwrap();
+
+        let mut ws_client_c = node_c.ws_client().await.unwrap();
+        let node_c_id = harness::wait_for_state(
+            &mut ws_client_c,
+            |state| state.self_id.is_some(),
+            Duration::from_secs(5),
+        ).await.unwrap().self_id.unwrap();
+
+        let mut ws_client_a = node_a.ws_client().await.unwrap();
+        harness::wait_for_state(
+            &mut ws_client_a,
+            |state| state.nodes.contains_key(&node_c_id),
+            Duration::from_secs(10),
+        ).await.expect("Node A should learn about Node C");
+
+        node_c.shutdown();
+        // `wait_for_state` rebuilds the state from a snapshot, so a new client is needed.
+        drop(ws_client_a);
+        let mut ws_client_a = node_a.ws_client().await.unwrap();
+        let state = harness::wait_for_state(
+            &mut ws_client_a,
+            |state| state.statuses.get(&node_c_id) == Some(&MemberStatus::Dead),
+            Duration::from_secs(8),
+        ).await.expect("Node A should declare the crashed Node C dead");
+        assert!(!state.nodes.contains_key(&node_c_id), "A dead node should be removed from the state");
+
+        node_a.shutdown();
+        node_b.shutdown();
+    }).await;
+    assert!(result.is_ok(), "Test timed out");
+}